@@ -0,0 +1,182 @@
+//! This module contains lints we run over torrent files.
+//!
+//! None of these problems stop us from parsing a torrent, but they usually
+//! indicate that the torrent was created by a buggy program, or is trying to
+//! do something sneaky, like writing files outside of the download directory.
+use std::fmt;
+pub use typhoon::core::Severity;
+use typhoon::{
+    bencoding::Bencoding,
+    core::{Torrent, ValidationIssue},
+};
+
+/// Represents a single problem found when checking a torrent.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lint {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Lint {
+    fn warning(message: String) -> Self {
+        Lint {
+            severity: Severity::Warning,
+            message,
+        }
+    }
+}
+
+impl From<ValidationIssue> for Lint {
+    fn from(issue: ValidationIssue) -> Self {
+        Lint {
+            severity: issue.severity(),
+            message: issue.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+/// Run all of our lints over a torrent, the bencoding it was parsed from, and the raw
+/// bytes of that bencoding.
+pub fn check_torrent(input: &[u8], bencoding: &Bencoding, torrent: &Torrent) -> Vec<Lint> {
+    let mut lints = Vec::new();
+
+    // Non-canonical data gets re-encoded differently, which changes the info hash
+    if let Ok(issues) = Bencoding::canonical_issues(input) {
+        for issue in issues {
+            lints.push(Lint::warning(format!("non-canonical bencoding {}", issue)));
+        }
+    }
+
+    if let Bencoding::Dict(map) = bencoding {
+        if !map.contains_key(&b"announce"[..]) && !map.contains_key(&b"announce-list"[..]) {
+            lints.push(Lint::warning("torrent has no announce URL".to_owned()));
+        }
+    }
+
+    lints.extend(check_padding(torrent));
+
+    lints.extend(torrent.validate().into_iter().map(Lint::from));
+
+    lints
+}
+
+// Padding files exist to align the next real file with the start of a piece,
+// so any other kind of padding file is useless, or a sign of a buggy creator.
+fn check_padding(torrent: &Torrent) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    let mut end = 0;
+    for (i, file) in torrent.files.iter().enumerate() {
+        end += file.length;
+        if !file.attributes.padding {
+            continue;
+        }
+        let name = file.name.display();
+        if torrent.piece_length > 0 && end % torrent.piece_length != 0 {
+            lints.push(Lint::warning(format!(
+                "padding file {} doesn't end on a piece boundary",
+                name
+            )));
+        }
+        match torrent.files.get(i + 1) {
+            Some(next) if !next.attributes.padding => {}
+            Some(_) => lints.push(Lint::warning(format!(
+                "padding file {} is followed by another padding file",
+                name
+            ))),
+            None => lints.push(Lint::warning(format!(
+                "padding file {} is the last file",
+                name
+            ))),
+        }
+    }
+    lints
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{convert::TryFrom, path::PathBuf};
+    use typhoon::{bencoding::Dict, core::FileInfo};
+
+    #[test]
+    fn checking_finds_problems() {
+        let input = b"d4:infod6:lengthi0e4:name1:f12:piece lengthi1000e6:pieces0:ee";
+        let mut torrent = Torrent::try_from(&Bencoding::decode(input).unwrap()).unwrap();
+        torrent.files = vec![
+            FileInfo::new("dir/../../etc/passwd", 10),
+            FileInfo {
+                symlink_path: Some(PathBuf::from("dir/../..")),
+                ..FileInfo::new("dir/link", 0)
+            },
+        ]
+        .into_boxed_slice();
+        let lints = check_torrent(input, &Bencoding::Dict(Dict::new()), &torrent);
+        let severities: Vec<Severity> = lints.iter().map(|l| l.severity).collect();
+        assert_eq!(
+            vec![
+                Severity::Warning,
+                Severity::Warning,
+                Severity::Error,
+                Severity::Warning,
+                Severity::Warning
+            ],
+            severities
+        );
+    }
+
+    #[test]
+    fn checking_finds_non_canonical_bencoding() {
+        let input = b"d4:infod6:lengthi0e4:name1:f12:piece lengthi016384e6:pieces0:e1:ai0ee";
+        let bencoding = Bencoding::decode(input).unwrap();
+        let torrent = Torrent::try_from(&bencoding).unwrap();
+        let messages: Vec<String> = check_torrent(input, &bencoding, &torrent)
+            .into_iter()
+            .map(|lint| lint.message)
+            .collect();
+        assert_eq!(
+            vec![
+                "non-canonical bencoding at byte 44: number has leading zeros",
+                "non-canonical bencoding at byte 62: dictionary key isn't in sorted order",
+                "torrent has no announce URL",
+            ],
+            messages
+        );
+    }
+
+    #[test]
+    fn checking_finds_misplaced_padding() {
+        let input = b"d4:infod6:lengthi0e4:name1:f12:piece lengthi16e6:pieces0:ee";
+        let mut torrent = Torrent::try_from(&Bencoding::decode(input).unwrap()).unwrap();
+        let padding = |name: &str, length| {
+            let mut file = FileInfo::new(name, length);
+            file.attributes.padding = true;
+            file
+        };
+        torrent.files = vec![
+            FileInfo::new("a", 10),
+            padding(".pad/6", 6),
+            FileInfo::new("b", 10),
+            padding(".pad/4", 4),
+            padding(".pad/2", 2),
+        ]
+        .into_boxed_slice();
+        let messages: Vec<String> = check_padding(&torrent)
+            .into_iter()
+            .map(|lint| lint.message)
+            .collect();
+        assert_eq!(
+            vec![
+                "padding file .pad/4 doesn't end on a piece boundary",
+                "padding file .pad/4 is followed by another padding file",
+                "padding file .pad/2 is the last file",
+            ],
+            messages
+        );
+    }
+}
@@ -0,0 +1,353 @@
+//! This module compares the metadata of two torrents.
+//!
+//! This is useful when a torrent gets re-issued, and we want to know exactly
+//! what changed between the two versions. The info hashes come first, since
+//! they tell us whether the two torrents share a swarm at all. Keys we don't
+//! parse into a `Torrent` are compared in their bencoded form.
+use std::{collections::HashMap, fmt};
+use typhoon::{
+    bencoding::{self, Bencoding},
+    core::{format_time, FileInfo, HttpSource, Torrent, TrackerAddr},
+};
+
+/// Represents a single difference between two torrents.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Difference {
+    /// Some piece of metadata changed from one value to another.
+    Changed {
+        field: &'static str,
+        old: String,
+        new: String,
+    },
+    /// An item in some list of metadata only appears in the old torrent.
+    Removed { field: &'static str, value: String },
+    /// An item in some list of metadata only appears in the new torrent.
+    Added { field: &'static str, value: String },
+    /// A tracker only appears in the old torrent.
+    RemovedTracker(u8, TrackerAddr),
+    /// A tracker only appears in the new torrent.
+    AddedTracker(u8, TrackerAddr),
+    /// A file only appears in the old torrent.
+    RemovedFile(FileInfo),
+    /// A file only appears in the new torrent.
+    AddedFile(FileInfo),
+    /// A file appears in both torrents, but with a different length.
+    ResizedFile { file: FileInfo, new_length: usize },
+    /// Some of the piece hashes at the same index differ between the two torrents.
+    PieceHashes(usize),
+    /// Some of the files in both torrents have different v2 merkle roots.
+    PieceRoots(usize),
+    /// Some bencoded data we don't parse differs, at the path in this entry.
+    Other(String),
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::Changed { field, old, new } => write!(f, "~ {}: {} -> {}", field, old, new),
+            Difference::Removed { field, value } => write!(f, "- {}: {}", field, value),
+            Difference::Added { field, value } => write!(f, "+ {}: {}", field, value),
+            Difference::RemovedTracker(tier, addr) => {
+                write!(f, "- tracker (tier {}): {}", tier, addr)
+            }
+            Difference::AddedTracker(tier, addr) => {
+                write!(f, "+ tracker (tier {}): {}", tier, addr)
+            }
+            Difference::RemovedFile(file) => {
+                write!(f, "- file: {} ({} bytes)", file.name.display(), file.length)
+            }
+            Difference::AddedFile(file) => {
+                write!(f, "+ file: {} ({} bytes)", file.name.display(), file.length)
+            }
+            Difference::ResizedFile { file, new_length } => write!(
+                f,
+                "~ file: {} ({} -> {} bytes)",
+                file.name.display(),
+                file.length,
+                new_length
+            ),
+            Difference::PieceHashes(count) => write!(f, "~ piece hashes: {} differ", count),
+            Difference::PieceRoots(count) => write!(f, "~ piece roots: {} files differ", count),
+            Difference::Other(entry) => write!(f, "{}", entry),
+        }
+    }
+}
+
+// The bencoded keys whose differences are already reported through the parsed torrent.
+const PARSED_KEYS: &[&str] = &[
+    "announce",
+    "announce-list",
+    "comment",
+    "created by",
+    "creation date",
+    "encoding",
+    "httpseeds",
+    "info.collections",
+    "info.file tree",
+    "info.files",
+    "info.length",
+    "info.meta version",
+    "info.name",
+    "info.piece length",
+    "info.pieces",
+    "info.private",
+    "info.similar",
+    "info.source",
+    "nodes",
+    "piece layers",
+    "signatures",
+    "url-list",
+];
+
+fn is_parsed(path: &str) -> bool {
+    PARSED_KEYS.iter().any(|key| {
+        path.strip_prefix(key)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.') || rest.starts_with('['))
+    })
+}
+
+fn changed<T: PartialEq + fmt::Debug>(
+    out: &mut Vec<Difference>,
+    field: &'static str,
+    old: &T,
+    new: &T,
+) {
+    if old != new {
+        out.push(Difference::Changed {
+            field,
+            old: format!("{:?}", old),
+            new: format!("{:?}", new),
+        });
+    }
+}
+
+// Report the items only appearing in one of two lists, described as a field and a value.
+fn list_changes<T: PartialEq>(
+    out: &mut Vec<Difference>,
+    old: &[T],
+    new: &[T],
+    describe: impl Fn(&T) -> (&'static str, String),
+) {
+    for item in old.iter().filter(|item| !new.contains(item)) {
+        let (field, value) = describe(item);
+        out.push(Difference::Removed { field, value });
+    }
+    for item in new.iter().filter(|item| !old.contains(item)) {
+        let (field, value) = describe(item);
+        out.push(Difference::Added { field, value });
+    }
+}
+
+/// Find all of the differences between an old and a new version of a torrent.
+///
+/// The bencoding each torrent was parsed from is used to find differences in
+/// the keys that parsing skips over.
+pub fn diff_torrents(
+    old_bencoding: &Bencoding,
+    old: &Torrent,
+    new_bencoding: &Bencoding,
+    new: &Torrent,
+) -> Vec<Difference> {
+    let mut out = Vec::new();
+    if old.info_hash != new.info_hash {
+        out.push(Difference::Changed {
+            field: "info hash",
+            old: old.info_hash.to_string(),
+            new: new.info_hash.to_string(),
+        });
+    }
+    let v2_hash = |torrent: &Torrent| {
+        torrent
+            .info_hash_v2
+            .map_or_else(|| "none".to_owned(), |hash| hash.to_string())
+    };
+    if old.info_hash_v2 != new.info_hash_v2 {
+        out.push(Difference::Changed {
+            field: "v2 info hash",
+            old: v2_hash(old),
+            new: v2_hash(new),
+        });
+    }
+    changed(&mut out, "version", &old.version, &new.version);
+    changed(
+        &mut out,
+        "piece length",
+        &old.piece_length,
+        &new.piece_length,
+    );
+    changed(&mut out, "private", &old.private, &new.private);
+    changed(&mut out, "source", &old.source, &new.source);
+    changed(
+        &mut out,
+        "creation date",
+        &old.creation.map(format_time),
+        &new.creation.map(format_time),
+    );
+    changed(&mut out, "comment", &old.comment, &new.comment);
+    changed(&mut out, "created by", &old.created_by, &new.created_by);
+    changed(&mut out, "encoding", &old.encoding, &new.encoding);
+
+    for (tier, addr) in old.trackers.iter() {
+        if !new.trackers.iter().any(|(_, a)| a == addr) {
+            out.push(Difference::RemovedTracker(*tier, addr.clone()));
+        }
+    }
+    for (tier, addr) in new.trackers.iter() {
+        if !old.trackers.iter().any(|(_, a)| a == addr) {
+            out.push(Difference::AddedTracker(*tier, addr.clone()));
+        }
+    }
+    list_changes(
+        &mut out,
+        &old.http_sources,
+        &new.http_sources,
+        |source| match source {
+            HttpSource::WebSeed(url) => ("web seed", url.clone()),
+            HttpSource::HttpSeed(url) => ("http seed", url.clone()),
+        },
+    );
+    list_changes(&mut out, &old.dht_nodes, &new.dht_nodes, |(host, port)| {
+        ("DHT node", format!("{}:{}", host, port))
+    });
+    list_changes(&mut out, &old.similar, &new.similar, |hash| {
+        ("similar torrent", hash.to_string())
+    });
+    list_changes(&mut out, &old.collections, &new.collections, |name| {
+        ("collection", name.clone())
+    });
+    // A signature changing shows up as the old one being removed, and the new one added
+    list_changes(&mut out, &old.signatures, &new.signatures, |signature| {
+        ("signature", signature.signer.clone())
+    });
+
+    for file in old.files.iter() {
+        match new.files.iter().find(|f| f.name == file.name) {
+            None => out.push(Difference::RemovedFile(file.clone())),
+            Some(other) if other.length != file.length => out.push(Difference::ResizedFile {
+                file: file.clone(),
+                new_length: other.length,
+            }),
+            Some(_) => {}
+        }
+    }
+    for file in new.files.iter() {
+        if !old.files.iter().any(|f| f.name == file.name) {
+            out.push(Difference::AddedFile(file.clone()));
+        }
+    }
+
+    changed(
+        &mut out,
+        "piece count",
+        &old.piece_hashes.len(),
+        &new.piece_hashes.len(),
+    );
+    let differing = old
+        .piece_hashes
+        .iter()
+        .zip(new.piece_hashes.iter())
+        .filter(|(a, b)| a != b)
+        .count();
+    if differing > 0 {
+        out.push(Difference::PieceHashes(differing));
+    }
+    if let (Some(old_v2), Some(new_v2)) = (&old.v2, &new.v2) {
+        let roots: HashMap<_, _> = old_v2
+            .files
+            .iter()
+            .map(|file| (&file.name, file.pieces_root))
+            .collect();
+        let differing = new_v2
+            .files
+            .iter()
+            .filter(|file| {
+                roots
+                    .get(&file.name)
+                    .is_some_and(|root| *root != file.pieces_root)
+            })
+            .count();
+        if differing > 0 {
+            out.push(Difference::PieceRoots(differing));
+        }
+    }
+
+    for entry in bencoding::diff(old_bencoding, new_bencoding) {
+        if !is_parsed(entry.path()) {
+            out.push(Difference::Other(entry.to_string()));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::TryFrom;
+    use typhoon::bencoding::Bencoding;
+
+    const INPUT: &[u8] =
+        b"d8:announce16:udp://tracker:804:infod6:lengthi0e4:name1:f12:piece lengthi16384e6:pieces0:ee";
+
+    fn torrent(files: Vec<FileInfo>) -> Torrent {
+        let mut torrent = Torrent::try_from(&Bencoding::decode(INPUT).unwrap()).unwrap();
+        torrent.files = files.into_boxed_slice();
+        torrent
+    }
+
+    fn diff(old: &Torrent, new: &Torrent) -> Vec<Difference> {
+        let bencoding = Bencoding::decode(INPUT).unwrap();
+        diff_torrents(&bencoding, old, &bencoding, new)
+    }
+
+    fn file(name: &str, length: usize) -> FileInfo {
+        FileInfo::new(name, length)
+    }
+
+    #[test]
+    fn identical_torrents_have_no_differences() {
+        let a = torrent(vec![file("a", 1)]);
+        assert_eq!(Vec::<Difference>::new(), diff(&a, &a.clone()));
+    }
+
+    #[test]
+    fn diffing_files_works() {
+        let old = torrent(vec![file("a", 1), file("b", 2)]);
+        let mut new = torrent(vec![file("b", 3), file("c", 4)]);
+        new.private = true;
+        let expected = vec![
+            Difference::Changed {
+                field: "private",
+                old: "false".to_owned(),
+                new: "true".to_owned(),
+            },
+            Difference::RemovedFile(file("a", 1)),
+            Difference::ResizedFile {
+                file: file("b", 2),
+                new_length: 3,
+            },
+            Difference::AddedFile(file("c", 4)),
+        ];
+        assert_eq!(expected, diff(&old, &new));
+    }
+
+    #[test]
+    fn info_hashes_and_unparsed_keys_are_reported() {
+        let old_bencoding =
+            Bencoding::decode(b"d4:infod6:lengthi1e4:name1:f12:piece lengthi16384e6:pieces0:ee")
+                .unwrap();
+        let new_bencoding = Bencoding::decode(
+            b"d8:url-list9:http://ws4:infod6:lengthi1e4:name1:f12:piece lengthi16384e6:pieces0:1:xi1eee",
+        )
+        .unwrap();
+        let old = Torrent::try_from(&old_bencoding).unwrap();
+        let new = Torrent::try_from(&new_bencoding).unwrap();
+        let lines: Vec<_> = diff_torrents(&old_bencoding, &old, &new_bencoding, &new)
+            .iter()
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(3, lines.len());
+        assert!(lines[0].starts_with(&format!("~ info hash: {} -> ", old.info_hash)));
+        assert_eq!("+ web seed: http://ws", lines[1]);
+        assert_eq!("+ info.x", lines[2]);
+    }
+}
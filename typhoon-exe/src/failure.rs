@@ -0,0 +1,142 @@
+//! This module describes the ways in which a command can fail.
+//!
+//! Each kind of failure gets its own exit code, so that scripts wrapping us
+//! can tell what went wrong without having to parse our error messages.
+use std::{fmt, io};
+use typhoon::bencoding::BencodingError;
+
+/// The exit code used when a command succeeds.
+pub const SUCCESS: i32 = 0;
+
+/// Represents the broad category a failure falls into.
+///
+/// The discriminant of each variant is the exit code we use for it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureKind {
+    /// One of the files we were given doesn't exist.
+    FileNotFound = 2,
+    /// A file wasn't valid bencoding.
+    Bencoding = 3,
+    /// A file was valid bencoding, but didn't describe a valid torrent.
+    TorrentSchema = 4,
+    /// We couldn't read a file for some other reason, like missing permissions.
+    Io = 5,
+    /// Checking a torrent found problems serious enough to fail.
+    CheckFailed = 8,
+}
+
+impl FailureKind {
+    /// The exit code the process should use for this kind of failure.
+    pub fn exit_code(self) -> i32 {
+        self as i32
+    }
+
+    /// A short machine readable name for this kind of failure.
+    pub fn name(self) -> &'static str {
+        match self {
+            FailureKind::FileNotFound => "file_not_found",
+            FailureKind::Bencoding => "bencoding",
+            FailureKind::TorrentSchema => "torrent_schema",
+            FailureKind::Io => "io",
+            FailureKind::CheckFailed => "check_failed",
+        }
+    }
+}
+
+/// Represents a failure that happened while running a command.
+///
+/// We keep the error message as a string, since some of our errors borrow from
+/// data that doesn't live as long as the failure itself.
+#[derive(Clone, Debug)]
+pub struct Failure {
+    pub kind: FailureKind,
+    pub message: String,
+}
+
+impl Failure {
+    pub fn new(kind: FailureKind, message: impl fmt::Display) -> Self {
+        Failure {
+            kind,
+            message: message.to_string(),
+        }
+    }
+
+    /// Write out this failure to standard error, either as text, or as JSON.
+    pub fn report(&self, json: bool) {
+        if json {
+            eprintln!(
+                "{{\"error\":\"{}\",\"code\":{},\"message\":\"{}\"}}",
+                self.kind.name(),
+                self.kind.exit_code(),
+                escape_json(&self.message)
+            );
+        } else {
+            eprintln!("{}", self);
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let context = match self.kind {
+            FailureKind::FileNotFound => "File not found",
+            FailureKind::Bencoding => "Error decoding file",
+            FailureKind::TorrentSchema => "Error reading torrent data",
+            FailureKind::Io => "Error reading file",
+            FailureKind::CheckFailed => "Check failed",
+        };
+        write!(f, "{}:\n{}", context, self.message)
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(error: io::Error) -> Self {
+        let kind = match error.kind() {
+            io::ErrorKind::NotFound => FailureKind::FileNotFound,
+            _ => FailureKind::Io,
+        };
+        Failure::new(kind, error)
+    }
+}
+
+impl From<BencodingError> for Failure {
+    fn from(error: BencodingError) -> Self {
+        Failure::new(FailureKind::Bencoding, error)
+    }
+}
+
+// Escape a string so that it can be placed inside of a JSON string literal.
+fn escape_json(string: &str) -> String {
+    let mut out = String::with_capacity(string.len());
+    for c in string.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn io_errors_are_classified_by_kind() {
+        let missing = Failure::from(io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(FailureKind::FileNotFound, missing.kind);
+        let denied = Failure::from(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert_eq!(FailureKind::Io, denied.kind);
+        assert_eq!(5, denied.kind.exit_code());
+    }
+
+    #[test]
+    fn escaping_json_works() {
+        assert_eq!("a\\\"b\\\\c\\nd\\u0001", escape_json("a\"b\\c\nd\u{1}"));
+    }
+}
@@ -1,8 +1,32 @@
 extern crate structopt;
-use std::{convert::TryFrom, fs, io, path::PathBuf};
+use std::{convert::TryFrom, fs, path::PathBuf, process};
 use structopt::StructOpt;
 extern crate typhoon;
-use typhoon::{bencoding::Bencoding, core::Torrent};
+use typhoon::{
+    bencoding::{Bencoding, PrettyOptions},
+    core::Torrent,
+    library::Library,
+};
+
+mod check;
+use check::{check_torrent, Severity};
+mod diff;
+use diff::diff_torrents;
+mod failure;
+use failure::{Failure, FailureKind};
+mod select;
+use select::{select_files, Selection};
+
+#[derive(Debug, StructOpt)]
+struct Opt {
+    /// Report errors as JSON objects on standard error.
+    ///
+    /// Each object contains the kind of error, the exit code, and a message.
+    #[structopt(long, global = true)]
+    json_errors: bool,
+    #[structopt(subcommand)]
+    command: Command,
+}
 
 #[derive(Debug, StructOpt)]
 enum Command {
@@ -18,28 +42,212 @@ enum Command {
         /// This will work on any bencoded file, not just torrents
         #[structopt(short, long)]
         bencoding: bool,
+        /// Print long strings in full when printing bencoding.
+        ///
+        /// By default, strings longer than 64 bytes are cut short.
+        #[structopt(long)]
+        full_strings: bool,
+        /// Print the bencoding as JSON, for use with tools like jq.
+        ///
+        /// Strings that aren't UTF-8 are written as "hex:" followed by their bytes in hex.
+        #[structopt(long)]
+        json: bool,
+        /// Print the indexed list of files in this torrent, and exit.
+        ///
+        /// This is implied by `--files` and `--exclude`.
+        #[structopt(long)]
+        list_files: bool,
+        /// Print statistics about the files and trackers of this torrent.
+        #[structopt(long)]
+        stats: bool,
+        /// Only list the files matching these indices, ranges, or globs.
+        ///
+        /// For example, `0,2-4,*.mkv` selects the first file, the third through fifth
+        /// files, as well as every file ending in `.mkv`.
+        #[structopt(long)]
+        files: Option<Selection>,
+        /// Don't list the files matching these indices, ranges, or globs.
+        #[structopt(long)]
+        exclude: Option<Selection>,
+    },
+    /// Compare the metadata of two torrent files
+    Diff {
+        /// The original torrent file.
+        old: PathBuf,
+        /// The torrent file to compare against the original.
+        new: PathBuf,
+    },
+    /// Check a torrent file for common problems
+    Check {
+        /// The torrent file to check.
+        #[structopt(short, long)]
+        file: PathBuf,
+        /// Fail if any warnings are found, and not just errors.
+        #[structopt(long)]
+        deny_warnings: bool,
+    },
+    /// Find duplicate and overlapping torrents in a directory
+    Dedupe {
+        /// The directory to scan for .torrent files, recursively.
+        dir: PathBuf,
     },
 }
 
-fn main() -> io::Result<()> {
-    let command = Command::from_args();
-    match command {
-        Command::Parse { file, bencoding } => {
-            let bytes = fs::read(file)?;
-            match Bencoding::decode(&bytes) {
-                Ok(bencoded_data) => {
-                    if bencoding {
-                        println!("{}", bencoded_data);
-                    } else {
-                        match Torrent::try_from(&bencoded_data) {
-                            Ok(torrent) => println!("{:?}", torrent),
-                            Err(e) => println!("Error reading torrent data:\n{}", e),
-                        }
-                    }
+fn read_torrent(file: PathBuf) -> Result<(Bencoding, Torrent), Failure> {
+    let bytes = fs::read(file)?;
+    let bencoded_data = Bencoding::decode(&bytes)?;
+    let torrent = Torrent::try_from(&bencoded_data)
+        .map_err(|e| Failure::new(FailureKind::TorrentSchema, e))?;
+    Ok((bencoded_data, torrent))
+}
+
+// What to print about a parsed torrent.
+enum ParseOutput {
+    Summary,
+    Stats,
+    Files,
+}
+
+fn parse(
+    file: PathBuf,
+    bencoding: bool,
+    full_strings: bool,
+    json: bool,
+    output: ParseOutput,
+    files: Option<Selection>,
+    exclude: Option<Selection>,
+) -> Result<(), Failure> {
+    let bytes = fs::read(file)?;
+    let bencoded_data = Bencoding::decode(&bytes)?;
+    if json {
+        println!("{}", bencoded_data.to_json());
+        return Ok(());
+    }
+    if bencoding {
+        let options = PrettyOptions {
+            truncate: if full_strings { None } else { Some(64) },
+            ..PrettyOptions::default()
+        };
+        println!("{}", bencoded_data.pretty(&options));
+        return Ok(());
+    }
+    let torrent = Torrent::try_from(&bencoded_data)
+        .map_err(|e| Failure::new(FailureKind::TorrentSchema, e))?;
+    match output {
+        ParseOutput::Summary => println!("{}", torrent),
+        ParseOutput::Stats => println!("{}", torrent.stats()),
+        ParseOutput::Files => {
+            let selected = select_files(&torrent.files, files.as_ref(), exclude.as_ref());
+            for (index, info) in torrent.files.iter().enumerate() {
+                if selected[index] {
+                    println!("{}\t{}\t{}", index, info.length, info.name.display());
                 }
-                Err(e) => println!("Error decoding file:\n{}", e),
             }
         }
     }
     Ok(())
 }
+
+fn diff(old: PathBuf, new: PathBuf) -> Result<(), Failure> {
+    let (old_bencoding, old) = read_torrent(old)?;
+    let (new_bencoding, new) = read_torrent(new)?;
+    let differences = diff_torrents(&old_bencoding, &old, &new_bencoding, &new);
+    if differences.is_empty() {
+        println!("The torrents have the same metadata");
+    }
+    for difference in differences {
+        println!("{}", difference);
+    }
+    Ok(())
+}
+
+fn check(file: PathBuf, deny_warnings: bool) -> Result<(), Failure> {
+    let bytes = fs::read(file)?;
+    let bencoded_data = Bencoding::decode(&bytes)?;
+    let torrent = Torrent::try_from(&bencoded_data)
+        .map_err(|e| Failure::new(FailureKind::TorrentSchema, e))?;
+    let lints = check_torrent(&bytes, &bencoded_data, &torrent);
+    for lint in &lints {
+        println!("{}", lint);
+    }
+    let errors = lints
+        .iter()
+        .filter(|l| l.severity == Severity::Error)
+        .count();
+    let warnings = lints.len() - errors;
+    if errors > 0 || (deny_warnings && warnings > 0) {
+        return Err(Failure::new(
+            FailureKind::CheckFailed,
+            format!("found {} errors and {} warnings", errors, warnings),
+        ));
+    }
+    Ok(())
+}
+
+fn dedupe(dir: PathBuf) -> Result<(), Failure> {
+    let library = Library::scan(&dir)?;
+    for (path, error) in &library.failures {
+        eprintln!("Skipping {}: {}", path.display(), error);
+    }
+    for group in library.duplicates() {
+        println!("Duplicates of {}:", group[0].info_hash);
+        for entry in group {
+            println!("  {}", entry.path.display());
+        }
+    }
+    for group in library.variants() {
+        println!("Same content with different info hashes:");
+        for entry in group {
+            println!("  {} {}", entry.info_hash, entry.path.display());
+        }
+    }
+    for overlap in library.overlaps() {
+        println!(
+            "{} and {} share {} files:",
+            overlap.first.path.display(),
+            overlap.second.path.display(),
+            overlap.files.len()
+        );
+        for file in overlap.files {
+            println!("  {}", file.name.display());
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    let result = match opt.command {
+        Command::Parse {
+            file,
+            bencoding,
+            full_strings,
+            json,
+            list_files,
+            stats,
+            files,
+            exclude,
+        } => {
+            let list_files = list_files || files.is_some() || exclude.is_some();
+            let output = match (list_files, stats) {
+                (true, _) => ParseOutput::Files,
+                (false, true) => ParseOutput::Stats,
+                (false, false) => ParseOutput::Summary,
+            };
+            parse(file, bencoding, full_strings, json, output, files, exclude)
+        }
+        Command::Diff { old, new } => diff(old, new),
+        Command::Check {
+            file,
+            deny_warnings,
+        } => check(file, deny_warnings),
+        Command::Dedupe { dir } => dedupe(dir),
+    };
+    match result {
+        Ok(()) => process::exit(failure::SUCCESS),
+        Err(failure) => {
+            failure.report(opt.json_errors);
+            process::exit(failure.kind.exit_code());
+        }
+    }
+}
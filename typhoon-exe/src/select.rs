@@ -0,0 +1,178 @@
+//! This module handles choosing a subset of the files in a torrent.
+//!
+//! Users can pick files either by their index in the torrent's file list,
+//! by ranges of indices, or by glob patterns matched against the file's path.
+use std::{error, fmt, num::ParseIntError, str::FromStr};
+use typhoon::core::FileInfo;
+
+/// An error occurring when parsing a file selection from the command line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelectionError(String);
+
+impl fmt::Display for SelectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid file selection: {}", self.0)
+    }
+}
+
+impl error::Error for SelectionError {}
+
+impl From<ParseIntError> for SelectionError {
+    fn from(error: ParseIntError) -> Self {
+        SelectionError(error.to_string())
+    }
+}
+
+/// A single item in a comma separated file selection.
+#[derive(Clone, Debug, PartialEq)]
+enum Selector {
+    /// Select the file at this index.
+    Index(usize),
+    /// Select the files in this inclusive range of indices.
+    Range(usize, usize),
+    /// Select the files whose path matches this glob.
+    Glob(String),
+}
+
+impl Selector {
+    fn matches(&self, index: usize, file: &FileInfo) -> bool {
+        match self {
+            Selector::Index(i) => *i == index,
+            Selector::Range(lo, hi) => *lo <= index && index <= *hi,
+            Selector::Glob(pattern) => glob_matches(pattern, &file.name.to_string_lossy()),
+        }
+    }
+}
+
+impl FromStr for Selector {
+    type Err = SelectionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let is_numeric = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+        if is_numeric(s) {
+            return Ok(Selector::Index(s.parse()?));
+        }
+        if let Some((lo, hi)) = s.split_once('-') {
+            if is_numeric(lo) && is_numeric(hi) {
+                let (lo, hi) = (lo.parse()?, hi.parse()?);
+                if lo > hi {
+                    return Err(SelectionError(format!("range {} is backwards", s)));
+                }
+                return Ok(Selector::Range(lo, hi));
+            }
+        }
+        if s.is_empty() {
+            return Err(SelectionError("empty selector".to_owned()));
+        }
+        Ok(Selector::Glob(s.to_owned()))
+    }
+}
+
+/// A list of selectors, parsed from something like `0,2-4,*.mkv`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Selection(Vec<Selector>);
+
+impl Selection {
+    fn matches(&self, index: usize, file: &FileInfo) -> bool {
+        self.0.iter().any(|selector| selector.matches(index, file))
+    }
+}
+
+impl FromStr for Selection {
+    type Err = SelectionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let selectors: Result<Vec<_>, _> = s.split(',').map(|part| part.trim().parse()).collect();
+        selectors.map(Selection)
+    }
+}
+
+/// Figure out which files should be kept, given what to include and exclude.
+///
+/// Having no include selection means that every file is included, before
+/// applying the exclusions.
+pub fn select_files(
+    files: &[FileInfo],
+    include: Option<&Selection>,
+    exclude: Option<&Selection>,
+) -> Vec<bool> {
+    files
+        .iter()
+        .enumerate()
+        .map(|(index, file)| {
+            let included = include.is_none_or(|s| s.matches(index, file));
+            let excluded = exclude.is_some_and(|s| s.matches(index, file));
+            included && !excluded
+        })
+        .collect()
+}
+
+// Check whether or not some text matches a glob pattern.
+//
+// We support `*`, matching any sequence of characters (including separators),
+// and `?`, matching exactly one character.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // The position of the last star we saw, and the text position it was matched at
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    backtrack = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn file(name: &str) -> FileInfo {
+        FileInfo::new(name, 0)
+    }
+
+    #[test]
+    fn glob_matching_works() {
+        assert!(glob_matches("*.mkv", "show/episode 01.mkv"));
+        assert!(glob_matches("show/e??.srt", "show/e01.srt"));
+        assert!(!glob_matches("*.mkv", "show/episode 01.nfo"));
+        assert!(glob_matches("*", ""));
+    }
+
+    #[test]
+    fn parsing_selections_works() {
+        let expected = Selection(vec![
+            Selector::Index(0),
+            Selector::Range(2, 4),
+            Selector::Glob("*.mkv".to_owned()),
+        ]);
+        assert_eq!(Ok(expected), "0, 2-4,*.mkv".parse());
+        assert!("4-2".parse::<Selection>().is_err());
+    }
+
+    #[test]
+    fn selecting_files_works() {
+        let files = [file("a.mkv"), file("b.nfo"), file("c.mkv"), file("d.srt")];
+        let include: Selection = "*.mkv,3".parse().unwrap();
+        let exclude: Selection = "c*".parse().unwrap();
+        let selected = select_files(&files, Some(&include), Some(&exclude));
+        assert_eq!(vec![true, false, false, true], selected);
+    }
+}
@@ -0,0 +1,74 @@
+/*
+ * C API for parsing torrent files and magnet links with typhoon.
+ *
+ * Strings are returned as a pointer and a length, and are not NUL terminated.
+ * Every pointer returned by an accessor stays valid until the handle it came
+ * from is released, with typhoon_torrent_free or typhoon_magnet_free.
+ */
+#ifndef TYPHOON_H
+#define TYPHOON_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum TyphoonStatus {
+    TYPHOON_OK = 0,
+    TYPHOON_NULL_POINTER = 1,
+    TYPHOON_BENCODING = 2,
+    TYPHOON_TORRENT = 3,
+    TYPHOON_OUT_OF_BOUNDS = 4,
+    TYPHOON_MAGNET = 5,
+} TyphoonStatus;
+
+typedef struct TyphoonTorrent TyphoonTorrent;
+typedef struct TyphoonMagnet TyphoonMagnet;
+
+const char *typhoon_last_error_message(void);
+
+TyphoonStatus typhoon_torrent_parse(const uint8_t *data, size_t len, TyphoonTorrent **out);
+void typhoon_torrent_free(TyphoonTorrent *torrent);
+
+size_t typhoon_torrent_piece_length(const TyphoonTorrent *torrent);
+size_t typhoon_torrent_piece_count(const TyphoonTorrent *torrent);
+bool typhoon_torrent_is_private(const TyphoonTorrent *torrent);
+bool typhoon_torrent_creation_date(const TyphoonTorrent *torrent, int64_t *out);
+const uint8_t *typhoon_torrent_comment(const TyphoonTorrent *torrent, size_t *len);
+const uint8_t *typhoon_torrent_created_by(const TyphoonTorrent *torrent, size_t *len);
+
+size_t typhoon_torrent_file_count(const TyphoonTorrent *torrent);
+TyphoonStatus typhoon_torrent_file(const TyphoonTorrent *torrent, size_t index,
+                                   const uint8_t **path, size_t *path_len, uint64_t *length);
+
+size_t typhoon_torrent_tracker_count(const TyphoonTorrent *torrent);
+TyphoonStatus typhoon_torrent_tracker(const TyphoonTorrent *torrent, size_t index,
+                                      const uint8_t **url, size_t *url_len, uint8_t *tier);
+
+TyphoonStatus typhoon_magnet_parse(const uint8_t *data, size_t len, TyphoonMagnet **out);
+void typhoon_magnet_free(TyphoonMagnet *magnet);
+
+bool typhoon_magnet_info_hash(const TyphoonMagnet *magnet, uint8_t out[20]);
+bool typhoon_magnet_info_hash_v2(const TyphoonMagnet *magnet, uint8_t out[32]);
+const uint8_t *typhoon_magnet_name(const TyphoonMagnet *magnet, size_t *len);
+
+size_t typhoon_magnet_tracker_count(const TyphoonMagnet *magnet);
+TyphoonStatus typhoon_magnet_tracker(const TyphoonMagnet *magnet, size_t index,
+                                     const uint8_t **url, size_t *url_len);
+
+size_t typhoon_magnet_web_seed_count(const TyphoonMagnet *magnet);
+TyphoonStatus typhoon_magnet_web_seed(const TyphoonMagnet *magnet, size_t index,
+                                      const uint8_t **url, size_t *url_len);
+
+size_t typhoon_magnet_peer_count(const TyphoonMagnet *magnet);
+TyphoonStatus typhoon_magnet_peer(const TyphoonMagnet *magnet, size_t index,
+                                  const uint8_t **addr, size_t *addr_len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
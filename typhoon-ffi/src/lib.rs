@@ -0,0 +1,640 @@
+//! This crate exposes a C API over the torrent parsing in `typhoon`.
+//!
+//! The API is built around opaque handles, which are created by parsing either the bytes
+//! of a `.torrent` file, into a `TyphoonTorrent`, or a magnet link, into a `TyphoonMagnet`.
+//! These are then queried through accessor functions.
+//! The matching declarations live in `include/typhoon.h`.
+//!
+//! Strings are returned as a pointer and a length, without a terminating NUL byte,
+//! since bencoded strings are really just sequences of bytes. Every pointer returned
+//! by an accessor stays valid until the handle it came from is freed.
+use std::{
+    cell::RefCell,
+    convert::TryFrom,
+    ffi::CString,
+    os::raw::c_char,
+    ptr, slice, str,
+    time::{self, SystemTime},
+};
+use typhoon::{
+    bencoding::Bencoding,
+    core::{MagnetLink, Torrent},
+};
+
+/// The status codes returned by the functions in this API.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TyphoonStatus {
+    /// The function succeeded.
+    Ok = 0,
+    /// One of the pointers passed to the function was null.
+    NullPointer = 1,
+    /// The input wasn't valid bencoding.
+    Bencoding = 2,
+    /// The input was valid bencoding, but not a valid torrent.
+    Torrent = 3,
+    /// An index was past the end of the list it was indexing.
+    OutOfBounds = 4,
+    /// The input wasn't a valid magnet link.
+    Magnet = 5,
+}
+
+/// A parsed torrent, along with the strings we hand out pointers to.
+pub struct TyphoonTorrent {
+    torrent: Torrent,
+    file_paths: Vec<String>,
+    tracker_urls: Vec<String>,
+}
+
+impl TyphoonTorrent {
+    fn new(torrent: Torrent) -> Self {
+        let file_paths = torrent
+            .files
+            .iter()
+            .map(|f| {
+                let components: Vec<_> = f
+                    .name
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect();
+                components.join("/")
+            })
+            .collect();
+        let tracker_urls = torrent
+            .trackers
+            .iter()
+            .map(|(_, addr)| addr.to_string())
+            .collect();
+        TyphoonTorrent {
+            torrent,
+            file_paths,
+            tracker_urls,
+        }
+    }
+}
+
+/// A parsed magnet link, along with the strings we hand out pointers to.
+pub struct TyphoonMagnet {
+    magnet: MagnetLink,
+    tracker_urls: Vec<String>,
+}
+
+impl TyphoonMagnet {
+    fn new(magnet: MagnetLink) -> Self {
+        let tracker_urls = magnet.trackers.iter().map(|t| t.to_string()).collect();
+        TyphoonMagnet {
+            magnet,
+            tracker_urls,
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    // Interior NUL bytes can't be represented in a C string, so we drop them
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+// Get the parsed torrent behind a handle.
+unsafe fn inner<'a>(torrent: *const TyphoonTorrent) -> &'a Torrent {
+    &(*torrent).torrent
+}
+
+// Write out a string as a pointer and length, returning null if the string is absent.
+unsafe fn write_str(string: Option<&str>, len: *mut usize) -> *const u8 {
+    let (data, size) = match string {
+        Some(s) => (s.as_ptr(), s.len()),
+        None => (ptr::null(), 0),
+    };
+    if !len.is_null() {
+        *len = size;
+    }
+    data
+}
+
+// Write out the string at some index in a list, as a pointer and length.
+unsafe fn write_item(
+    list: &[String],
+    index: usize,
+    out: *mut *const u8,
+    len: *mut usize,
+) -> TyphoonStatus {
+    let item = match list.get(index) {
+        Some(item) => item,
+        None => return TyphoonStatus::OutOfBounds,
+    };
+    if !out.is_null() {
+        *out = write_str(Some(item), len);
+    }
+    TyphoonStatus::Ok
+}
+
+// Copy out the bytes of a hash, returning false if it's absent.
+unsafe fn write_hash(hash: Option<&[u8]>, out: *mut u8) -> bool {
+    match hash {
+        Some(hash) if !out.is_null() => {
+            ptr::copy_nonoverlapping(hash.as_ptr(), out, hash.len());
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Get a message describing the last error that happened on this thread.
+///
+/// This returns null if no error has happened yet. The string is NUL terminated,
+/// and stays valid until the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn typhoon_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
+
+/// Parse the bytes of a `.torrent` file into a new torrent handle.
+///
+/// On success, `out` points to a torrent that must be released with `typhoon_torrent_free`.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_torrent_parse(
+    data: *const u8,
+    len: usize,
+    out: *mut *mut TyphoonTorrent,
+) -> TyphoonStatus {
+    if data.is_null() || out.is_null() {
+        return TyphoonStatus::NullPointer;
+    }
+    let bytes = slice::from_raw_parts(data, len);
+    let bencoding = match Bencoding::decode(bytes) {
+        Ok(b) => b,
+        Err(e) => {
+            set_last_error(e.to_string());
+            return TyphoonStatus::Bencoding;
+        }
+    };
+    match Torrent::try_from(&bencoding) {
+        Ok(torrent) => {
+            *out = Box::into_raw(Box::new(TyphoonTorrent::new(torrent)));
+            TyphoonStatus::Ok
+        }
+        Err(e) => {
+            set_last_error(e.to_string());
+            TyphoonStatus::Torrent
+        }
+    }
+}
+
+/// Release a torrent created by `typhoon_torrent_parse`.
+///
+/// # Safety
+///
+/// `torrent` must be null, or a pointer returned by `typhoon_torrent_parse`
+/// that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_torrent_free(torrent: *mut TyphoonTorrent) {
+    if !torrent.is_null() {
+        drop(Box::from_raw(torrent));
+    }
+}
+
+/// Get the number of bytes in each piece of a torrent.
+///
+/// # Safety
+///
+/// `torrent` must be a valid torrent handle.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_torrent_piece_length(torrent: *const TyphoonTorrent) -> usize {
+    inner(torrent).piece_length
+}
+
+/// Get the number of pieces the files of a torrent are split into.
+///
+/// # Safety
+///
+/// `torrent` must be a valid torrent handle.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_torrent_piece_count(torrent: *const TyphoonTorrent) -> usize {
+    inner(torrent).num_pieces()
+}
+
+/// Check whether or not a torrent is private.
+///
+/// # Safety
+///
+/// `torrent` must be a valid torrent handle.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_torrent_is_private(torrent: *const TyphoonTorrent) -> bool {
+    inner(torrent).private
+}
+
+/// Get the creation date of a torrent, as seconds since the UNIX epoch.
+///
+/// Dates before 1970 are negative. This returns false, leaving `out` untouched,
+/// if the torrent has no creation date.
+///
+/// # Safety
+///
+/// `torrent` must be a valid torrent handle, and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_torrent_creation_date(
+    torrent: *const TyphoonTorrent,
+    out: *mut i64,
+) -> bool {
+    let seconds =
+        inner(torrent)
+            .creation
+            .map(|t: SystemTime| match t.duration_since(time::UNIX_EPOCH) {
+                Ok(after) => after.as_secs() as i64,
+                Err(e) => -(e.duration().as_secs() as i64),
+            });
+    match seconds {
+        Some(s) if !out.is_null() => {
+            *out = s;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Get the comment of a torrent, as UTF-8 bytes.
+///
+/// This returns null if the torrent has no comment.
+///
+/// # Safety
+///
+/// `torrent` must be a valid torrent handle, and `len` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_torrent_comment(
+    torrent: *const TyphoonTorrent,
+    len: *mut usize,
+) -> *const u8 {
+    write_str(inner(torrent).comment.as_deref(), len)
+}
+
+/// Get a description of the program that created a torrent, as UTF-8 bytes.
+///
+/// This returns null if the torrent doesn't say what created it.
+///
+/// # Safety
+///
+/// `torrent` must be a valid torrent handle, and `len` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_torrent_created_by(
+    torrent: *const TyphoonTorrent,
+    len: *mut usize,
+) -> *const u8 {
+    write_str(inner(torrent).created_by.as_deref(), len)
+}
+
+/// Get the number of files in a torrent.
+///
+/// # Safety
+///
+/// `torrent` must be a valid torrent handle.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_torrent_file_count(torrent: *const TyphoonTorrent) -> usize {
+    inner(torrent).files.len()
+}
+
+/// Get the path and length of the file at a given index.
+///
+/// The path is written out as UTF-8 bytes, with `/` separating its components on every
+/// platform. Bytes that aren't valid UTF-8 are replaced with U+FFFD.
+///
+/// # Safety
+///
+/// `torrent` must be a valid torrent handle, and the other pointers must be null
+/// or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_torrent_file(
+    torrent: *const TyphoonTorrent,
+    index: usize,
+    path: *mut *const u8,
+    path_len: *mut usize,
+    length: *mut u64,
+) -> TyphoonStatus {
+    let torrent = &*torrent;
+    let (name, file) = match (
+        torrent.file_paths.get(index),
+        torrent.torrent.files.get(index),
+    ) {
+        (Some(name), Some(file)) => (name, file),
+        _ => return TyphoonStatus::OutOfBounds,
+    };
+    if !path.is_null() {
+        *path = write_str(Some(name), path_len);
+    }
+    if !length.is_null() {
+        *length = file.length as u64;
+    }
+    TyphoonStatus::Ok
+}
+
+/// Get the number of trackers in a torrent.
+///
+/// # Safety
+///
+/// `torrent` must be a valid torrent handle.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_torrent_tracker_count(torrent: *const TyphoonTorrent) -> usize {
+    inner(torrent).trackers.len()
+}
+
+/// Get the URL and tier of the tracker at a given index.
+///
+/// Trackers in lower tiers should be tried before trackers in higher tiers.
+///
+/// # Safety
+///
+/// `torrent` must be a valid torrent handle, and the other pointers must be null
+/// or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_torrent_tracker(
+    torrent: *const TyphoonTorrent,
+    index: usize,
+    url: *mut *const u8,
+    url_len: *mut usize,
+    tier: *mut u8,
+) -> TyphoonStatus {
+    let torrent = &*torrent;
+    let (address, &(t, _)) = match (
+        torrent.tracker_urls.get(index),
+        torrent.torrent.trackers.get(index),
+    ) {
+        (Some(address), Some(tracker)) => (address, tracker),
+        _ => return TyphoonStatus::OutOfBounds,
+    };
+    if !url.is_null() {
+        *url = write_str(Some(address), url_len);
+    }
+    if !tier.is_null() {
+        *tier = t;
+    }
+    TyphoonStatus::Ok
+}
+
+/// Parse a magnet link into a new magnet handle.
+///
+/// The link is given as UTF-8 bytes, which don't need to be NUL terminated.
+/// On success, `out` points to a magnet link that must be released with `typhoon_magnet_free`.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_magnet_parse(
+    data: *const u8,
+    len: usize,
+    out: *mut *mut TyphoonMagnet,
+) -> TyphoonStatus {
+    if data.is_null() || out.is_null() {
+        return TyphoonStatus::NullPointer;
+    }
+    let link = match str::from_utf8(slice::from_raw_parts(data, len)) {
+        Ok(link) => link,
+        Err(e) => {
+            set_last_error(e.to_string());
+            return TyphoonStatus::Magnet;
+        }
+    };
+    match link.parse::<MagnetLink>() {
+        Ok(magnet) => {
+            *out = Box::into_raw(Box::new(TyphoonMagnet::new(magnet)));
+            TyphoonStatus::Ok
+        }
+        Err(e) => {
+            set_last_error(e.to_string());
+            TyphoonStatus::Magnet
+        }
+    }
+}
+
+/// Release a magnet link created by `typhoon_magnet_parse`.
+///
+/// # Safety
+///
+/// `magnet` must be null, or a pointer returned by `typhoon_magnet_parse`
+/// that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_magnet_free(magnet: *mut TyphoonMagnet) {
+    if !magnet.is_null() {
+        drop(Box::from_raw(magnet));
+    }
+}
+
+/// Copy the 20 byte v1 info hash of a magnet link into `out`.
+///
+/// This returns false, leaving `out` untouched, if the link has no v1 info hash.
+///
+/// # Safety
+///
+/// `magnet` must be a valid magnet handle, and `out` must be valid for writing 20 bytes.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_magnet_info_hash(
+    magnet: *const TyphoonMagnet,
+    out: *mut u8,
+) -> bool {
+    let hash = (*magnet).magnet.info_hash.as_ref();
+    write_hash(hash.map(|h| &h.as_bytes()[..]), out)
+}
+
+/// Copy the 32 byte v2 info hash of a magnet link into `out`.
+///
+/// This returns false, leaving `out` untouched, if the link has no v2 info hash.
+///
+/// # Safety
+///
+/// `magnet` must be a valid magnet handle, and `out` must be valid for writing 32 bytes.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_magnet_info_hash_v2(
+    magnet: *const TyphoonMagnet,
+    out: *mut u8,
+) -> bool {
+    let hash = (*magnet).magnet.info_hash_v2.as_ref();
+    write_hash(hash.map(|h| &h.as_bytes()[..]), out)
+}
+
+/// Get the display name of a magnet link, as UTF-8 bytes.
+///
+/// This returns null if the link has no name.
+///
+/// # Safety
+///
+/// `magnet` must be a valid magnet handle, and `len` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_magnet_name(
+    magnet: *const TyphoonMagnet,
+    len: *mut usize,
+) -> *const u8 {
+    write_str((*magnet).magnet.name.as_deref(), len)
+}
+
+/// Get the number of trackers in a magnet link.
+///
+/// # Safety
+///
+/// `magnet` must be a valid magnet handle.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_magnet_tracker_count(magnet: *const TyphoonMagnet) -> usize {
+    (*magnet).tracker_urls.len()
+}
+
+/// Get the URL of the tracker at a given index.
+///
+/// # Safety
+///
+/// `magnet` must be a valid magnet handle, and the other pointers must be null
+/// or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_magnet_tracker(
+    magnet: *const TyphoonMagnet,
+    index: usize,
+    url: *mut *const u8,
+    url_len: *mut usize,
+) -> TyphoonStatus {
+    write_item(&(*magnet).tracker_urls, index, url, url_len)
+}
+
+/// Get the number of web seeds in a magnet link.
+///
+/// # Safety
+///
+/// `magnet` must be a valid magnet handle.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_magnet_web_seed_count(magnet: *const TyphoonMagnet) -> usize {
+    (*magnet).magnet.web_seeds.len()
+}
+
+/// Get the URL of the web seed at a given index.
+///
+/// # Safety
+///
+/// `magnet` must be a valid magnet handle, and the other pointers must be null
+/// or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_magnet_web_seed(
+    magnet: *const TyphoonMagnet,
+    index: usize,
+    url: *mut *const u8,
+    url_len: *mut usize,
+) -> TyphoonStatus {
+    write_item(&(*magnet).magnet.web_seeds, index, url, url_len)
+}
+
+/// Get the number of peer addresses in a magnet link.
+///
+/// # Safety
+///
+/// `magnet` must be a valid magnet handle.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_magnet_peer_count(magnet: *const TyphoonMagnet) -> usize {
+    (*magnet).magnet.peers.len()
+}
+
+/// Get the address of the peer at a given index, like `10.0.0.1:6881`.
+///
+/// # Safety
+///
+/// `magnet` must be a valid magnet handle, and the other pointers must be null
+/// or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn typhoon_magnet_peer(
+    magnet: *const TyphoonMagnet,
+    index: usize,
+    addr: *mut *const u8,
+    addr_len: *mut usize,
+) -> TyphoonStatus {
+    write_item(&(*magnet).magnet.peers, index, addr, addr_len)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parsing_through_the_c_api_works() {
+        let input = b"d13:announce-listll9:udp://a:1ee4:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces20:AAAAAAAAAAAAAAAAAAAAee";
+        let mut torrent = ptr::null_mut();
+        unsafe {
+            let status = typhoon_torrent_parse(input.as_ptr(), input.len(), &mut torrent);
+            assert_eq!(TyphoonStatus::Ok, status);
+            assert_eq!(1, typhoon_torrent_piece_count(torrent));
+            let (mut url, mut url_len, mut tier) = (ptr::null(), 0, 0);
+            let status = typhoon_torrent_tracker(torrent, 0, &mut url, &mut url_len, &mut tier);
+            assert_eq!(TyphoonStatus::Ok, status);
+            assert_eq!(b"udp://a:1", slice::from_raw_parts(url, url_len));
+            let (mut path, mut path_len, mut length) = (ptr::null(), 0, 0);
+            let status = typhoon_torrent_file(torrent, 0, &mut path, &mut path_len, &mut length);
+            assert_eq!(TyphoonStatus::Ok, status);
+            assert_eq!(b"f", slice::from_raw_parts(path, path_len));
+            assert_eq!(5, length);
+            let status = typhoon_torrent_file(torrent, 1, &mut path, &mut path_len, &mut length);
+            assert_eq!(TyphoonStatus::OutOfBounds, status);
+            typhoon_torrent_free(torrent);
+        }
+    }
+
+    #[test]
+    fn creation_dates_before_1970_are_negative() {
+        let input = b"d13:creation datei-86400e4:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces20:AAAAAAAAAAAAAAAAAAAAee";
+        let mut torrent = ptr::null_mut();
+        unsafe {
+            let status = typhoon_torrent_parse(input.as_ptr(), input.len(), &mut torrent);
+            assert_eq!(TyphoonStatus::Ok, status);
+            let mut date = 0;
+            assert!(typhoon_torrent_creation_date(torrent, &mut date));
+            assert_eq!(-86400, date);
+            typhoon_torrent_free(torrent);
+        }
+    }
+
+    #[test]
+    fn parsing_magnets_through_the_c_api_works() {
+        let link = format!(
+            "magnet:?xt=urn:btih:{}&dn=a+b&tr=udp%3A%2F%2Ft%3A1&x.pe=10.0.0.1:6881",
+            "ab".repeat(20)
+        );
+        let mut magnet = ptr::null_mut();
+        unsafe {
+            let status = typhoon_magnet_parse(link.as_ptr(), link.len(), &mut magnet);
+            assert_eq!(TyphoonStatus::Ok, status);
+            let mut hash = [0; 20];
+            assert!(typhoon_magnet_info_hash(magnet, hash.as_mut_ptr()));
+            assert_eq!([0xAB; 20], hash);
+            assert!(!typhoon_magnet_info_hash_v2(magnet, [0; 32].as_mut_ptr()));
+            let mut len = 0;
+            let name = typhoon_magnet_name(magnet, &mut len);
+            assert_eq!(b"a b", slice::from_raw_parts(name, len));
+            assert_eq!(1, typhoon_magnet_tracker_count(magnet));
+            let (mut url, mut url_len) = (ptr::null(), 0);
+            let status = typhoon_magnet_tracker(magnet, 0, &mut url, &mut url_len);
+            assert_eq!(TyphoonStatus::Ok, status);
+            assert_eq!(b"udp://t:1", slice::from_raw_parts(url, url_len));
+            assert_eq!(0, typhoon_magnet_web_seed_count(magnet));
+            let status = typhoon_magnet_web_seed(magnet, 0, &mut url, &mut url_len);
+            assert_eq!(TyphoonStatus::OutOfBounds, status);
+            assert_eq!(1, typhoon_magnet_peer_count(magnet));
+            let status = typhoon_magnet_peer(magnet, 0, &mut url, &mut url_len);
+            assert_eq!(TyphoonStatus::Ok, status);
+            assert_eq!(b"10.0.0.1:6881", slice::from_raw_parts(url, url_len));
+            typhoon_magnet_free(magnet);
+        }
+        let invalid = b"http://example.com";
+        let status = unsafe { typhoon_magnet_parse(invalid.as_ptr(), invalid.len(), &mut magnet) };
+        assert_eq!(TyphoonStatus::Magnet, status);
+    }
+
+    #[test]
+    fn parsing_errors_are_reported() {
+        let input = b"i1";
+        let mut torrent = ptr::null_mut();
+        let status = unsafe { typhoon_torrent_parse(input.as_ptr(), input.len(), &mut torrent) };
+        assert_eq!(TyphoonStatus::Bencoding, status);
+        assert!(!typhoon_last_error_message().is_null());
+    }
+}
@@ -0,0 +1,81 @@
+//! Throughput benchmarks for decoding bencoding, and extracting torrents.
+//!
+//! These use a small timing loop rather than a benchmarking framework,
+//! and can be run with `cargo bench -p typhoon`.
+use std::{convert::TryFrom, hint::black_box, time::Instant};
+use typhoon::{
+    bencoding::{Arena, Bencoding, BencodingReader, BencodingRef},
+    core::Torrent,
+};
+
+// Build the bencoding for a multi file torrent with many pieces.
+fn torrent_bytes(files: usize, pieces: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(b"d13:announce-listll30:udp://tracker.example.com:1337ee");
+    out.extend_from_slice(b"7:comment22:a benchmarking torrent");
+    out.extend_from_slice(b"4:infod5:filesl");
+    for i in 0..files {
+        let name = format!("episode {:05}.mkv", i);
+        out.extend_from_slice(format!("d6:lengthi{}e4:pathl", 1_000_000 + i).as_bytes());
+        out.extend_from_slice(format!("6:season{}:{}ee", name.len(), name).as_bytes());
+    }
+    out.extend_from_slice(b"e4:name4:show12:piece lengthi262144e");
+    out.extend_from_slice(format!("6:pieces{}:", pieces * 20).as_bytes());
+    out.extend((0..pieces * 20).map(|i| (i % 251) as u8));
+    out.extend_from_slice(b"ee");
+    out
+}
+
+// Build the bencoding for a long list of integers.
+fn int_list_bytes(count: usize) -> Vec<u8> {
+    let mut out = b"l".to_vec();
+    for i in 0..count {
+        out.extend_from_slice(format!("i{}e", (i as i64 * 7919) - 500_000).as_bytes());
+    }
+    out.push(b'e');
+    out
+}
+
+fn bench<F: FnMut()>(name: &str, bytes: usize, iterations: u32, mut f: F) {
+    f();
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    let elapsed = start.elapsed();
+    let per_iteration = elapsed / iterations;
+    let throughput = (bytes as f64 * f64::from(iterations)) / elapsed.as_secs_f64() / 1e6;
+    println!(
+        "{:<32} {:>12?}/iter {:>10.1} MB/s",
+        name, per_iteration, throughput
+    );
+}
+
+fn main() {
+    let torrent = torrent_bytes(20_000, 20_000);
+    let ints = int_list_bytes(200_000);
+
+    bench("decode torrent", torrent.len(), 50, || {
+        black_box(Bencoding::decode(black_box(&torrent)).unwrap());
+    });
+    bench("decode torrent (borrowed)", torrent.len(), 50, || {
+        black_box(BencodingRef::decode(black_box(&torrent)).unwrap());
+    });
+    let mut arena = Arena::new();
+    bench("decode torrent (arena)", torrent.len(), 50, || {
+        black_box(Bencoding::decode_in(&mut arena, black_box(&torrent)).unwrap());
+    });
+    bench("decode int list", ints.len(), 50, || {
+        black_box(Bencoding::decode(black_box(&ints)).unwrap());
+    });
+    bench("validate int list", ints.len(), 50, || {
+        black_box(Bencoding::is_canonical(black_box(&ints))).unwrap();
+    });
+    bench("read torrent events", torrent.len(), 50, || {
+        black_box(BencodingReader::new(black_box(&torrent)).count());
+    });
+    let decoded = Bencoding::decode(&torrent).unwrap();
+    bench("extract torrent", torrent.len(), 50, || {
+        black_box(Torrent::try_from(black_box(&decoded)).unwrap());
+    });
+}
@@ -0,0 +1,10 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use typhoon::bencoding::Bencoding;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(bencoding) = Bencoding::decode(data) {
+        // Displaying the result shouldn't panic either
+        let _ = bencoding.to_string();
+    }
+});
@@ -0,0 +1,8 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use typhoon::tracker::Peer;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Peer::parse_compact(data);
+    let _ = Peer::parse_compact_v6(data);
+});
@@ -0,0 +1,18 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use std::str;
+use typhoon::core::MagnetLink;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(input) = str::from_utf8(data) {
+        match input.parse::<MagnetLink>() {
+            // Links we manage to parse should survive a round trip
+            Ok(magnet) => {
+                let _ = magnet.to_string().parse::<MagnetLink>();
+            }
+            Err(e) => {
+                let _ = e.to_string();
+            }
+        }
+    }
+});
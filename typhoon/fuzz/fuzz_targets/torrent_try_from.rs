@@ -0,0 +1,12 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use std::convert::TryFrom;
+use typhoon::{bencoding::Bencoding, core::Torrent};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(bencoding) = Bencoding::decode(data) {
+        if let Err(e) = Torrent::try_from(&bencoding) {
+            let _ = e.to_string();
+        }
+    }
+});
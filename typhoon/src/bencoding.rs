@@ -1,21 +1,216 @@
-use std::{collections::HashMap, convert, error, fmt, str};
+mod arena;
+#[cfg(any(feature = "cbor", feature = "msgpack"))]
+mod binary;
+mod build;
+mod diff;
+mod edit;
+mod json;
+mod lenient;
+mod path;
+mod pretty;
+mod read;
+mod visit;
+pub use arena::{Arena, ArenaNode};
+#[cfg(any(feature = "cbor", feature = "msgpack"))]
+pub use binary::BinaryError;
+pub use build::{DictBuilder, ListBuilder};
+pub use diff::{diff, DiffEntry};
+pub use json::JsonError;
+pub use path::PathError;
+pub use pretty::{Pretty, PrettyOptions};
+pub use read::ReadError;
+pub use visit::{walk, Visitor};
+
+use std::{
+    convert::{self, TryFrom},
+    error, fmt, io,
+    iter::FromIterator,
+    marker::PhantomData,
+    ops::Range,
+    str,
+};
+
+/// Describes the kind of token we were expecting when parsing failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Expected {
+    /// An ASCII digit, as part of an integer or a string length.
+    Digit,
+    /// A specific byte, like the `e` ending a list, or the `:` after a string length.
+    Byte(u8),
+    /// The start of any bencoded element.
+    Element,
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expected::Digit => write!(f, "a digit"),
+            Expected::Byte(b) => write!(f, "'{}'", *b as char),
+            Expected::Element => write!(f, "a bencoded element"),
+        }
+    }
+}
 
 /// Represents an error that occurs while parsing bencoded data.
 ///
-/// For now, this isn't very useful, and just contains a formatted string
-/// produced by our parsing functions. This could be extended into a richer
-/// enum for each of the different points of failure, along with context.
-///
-/// This is a fine enough solution since this is usually just presented to the user
-/// directly.
+/// Each variant contains the offset, in bytes, into the input where parsing failed,
+/// which lets users pinpoint where exactly a file is corrupted.
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct BencodingError(String);
+pub enum BencodingError {
+    /// We reached the end of the input while expecting more.
+    UnexpectedEof { offset: usize, expected: Expected },
+    /// We found a byte that didn't match what we expected.
+    UnexpectedByte {
+        offset: usize,
+        expected: Expected,
+        found: u8,
+    },
+    /// A byte string claims to be longer than the rest of the input.
+    ///
+    /// The offset points at the start of the length prefix.
+    InvalidLength { offset: usize, length: usize },
+    /// An integer, or a string length, doesn't fit in 64 bits.
+    ///
+    /// The offset points at the first digit of the integer.
+    IntegerOverflow { offset: usize },
+    /// Lists and dictionaries are nested more deeply than we allow.
+    TooDeep { offset: usize, limit: usize },
+    /// The input contains more elements than we allow.
+    ///
+    /// The offset points at the first element past the limit.
+    TooManyElements { offset: usize, limit: usize },
+    /// A byte string is longer than we allow.
+    ///
+    /// The offset points at the start of the length prefix.
+    StringTooLong {
+        offset: usize,
+        length: usize,
+        limit: usize,
+    },
+    /// There is more data after the end of the root element.
+    TrailingData { offset: usize },
+    /// An integer, or a string length, has superfluous leading zeros.
+    ///
+    /// This is only reported when decoding strictly.
+    LeadingZero { offset: usize },
+    /// The integer `i-0e`, which should be written as `i0e`.
+    ///
+    /// This is only reported when decoding strictly.
+    NegativeZero { offset: usize },
+    /// A dictionary key isn't greater than the key preceding it.
+    ///
+    /// This is only reported when decoding strictly.
+    UnsortedKey { offset: usize },
+    /// A dictionary key appears more than once.
+    ///
+    /// This is only reported when decoding strictly.
+    DuplicateKey { offset: usize },
+}
+
+impl BencodingError {
+    /// The offset, in bytes, into the input where parsing failed.
+    pub fn offset(&self) -> usize {
+        use BencodingError::*;
+        match *self {
+            UnexpectedEof { offset, .. }
+            | UnexpectedByte { offset, .. }
+            | InvalidLength { offset, .. }
+            | IntegerOverflow { offset }
+            | TooDeep { offset, .. }
+            | TooManyElements { offset, .. }
+            | StringTooLong { offset, .. }
+            | TrailingData { offset }
+            | LeadingZero { offset }
+            | NegativeZero { offset }
+            | UnsortedKey { offset }
+            | DuplicateKey { offset } => offset,
+        }
+    }
+}
 
 impl error::Error for BencodingError {}
 
 impl fmt::Display for BencodingError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
+        use BencodingError::*;
+        write!(f, "at byte {}: ", self.offset())?;
+        match self {
+            UnexpectedEof { expected, .. } => {
+                write!(f, "expected {}, but reached the end of input", expected)
+            }
+            UnexpectedByte {
+                expected, found, ..
+            } if found.is_ascii_graphic() => {
+                write!(f, "expected {}, but found '{}'", expected, *found as char)
+            }
+            UnexpectedByte {
+                expected, found, ..
+            } => write!(f, "expected {}, but found byte 0x{:02X}", expected, found),
+            InvalidLength { length, .. } => {
+                write!(f, "string of length {} runs past the end of input", length)
+            }
+            IntegerOverflow { .. } => write!(f, "integer doesn't fit in 64 bits"),
+            TooDeep { limit, .. } => write!(f, "elements are nested more than {} deep", limit),
+            TooManyElements { limit, .. } => write!(f, "more than {} elements", limit),
+            StringTooLong { length, limit, .. } => write!(
+                f,
+                "string of length {} is longer than the limit of {}",
+                length, limit
+            ),
+            TrailingData { .. } => write!(f, "unexpected data after the end of the element"),
+            LeadingZero { .. } => write!(f, "number has leading zeros"),
+            NegativeZero { .. } => write!(f, "negative zero isn't a valid integer"),
+            UnsortedKey { .. } => write!(f, "dictionary key isn't in sorted order"),
+            DuplicateKey { .. } => write!(f, "dictionary key appears more than once"),
+        }
+    }
+}
+
+/// Represents the ways in which encoded data can fail to be canonical.
+///
+/// This is returned by `Bencoding::is_canonical`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CanonicalError {
+    /// The data isn't valid bencoding in the first place.
+    Invalid(BencodingError),
+    /// The data is valid bencoding, but isn't encoded canonically.
+    ///
+    /// This contains the first difference from the canonical encoding, like a
+    /// number with leading zeros, a key out of order, or data after the end.
+    NonCanonical(BencodingError),
+}
+
+impl CanonicalError {
+    /// The offset, in bytes, into the input where the problem is.
+    pub fn offset(&self) -> usize {
+        match self {
+            CanonicalError::Invalid(e) | CanonicalError::NonCanonical(e) => e.offset(),
+        }
+    }
+}
+
+impl From<BencodingError> for CanonicalError {
+    fn from(error: BencodingError) -> Self {
+        use BencodingError::*;
+        match error {
+            LeadingZero { .. }
+            | NegativeZero { .. }
+            | UnsortedKey { .. }
+            | DuplicateKey { .. }
+            | TrailingData { .. } => CanonicalError::NonCanonical(error),
+            _ => CanonicalError::Invalid(error),
+        }
+    }
+}
+
+impl error::Error for CanonicalError {}
+
+impl fmt::Display for CanonicalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CanonicalError::Invalid(e) => write!(f, "invalid bencoding {}", e),
+            CanonicalError::NonCanonical(e) => write!(f, "non canonical bencoding {}", e),
+        }
     }
 }
 
@@ -53,7 +248,7 @@ pub enum Bencoding {
     ///
     /// The keys of this map are subject to the same caveats as byte sequence elements in this
     /// enum. In practice though, non UTF-8 map keys don't seem to appear.
-    Dict(HashMap<Box<[u8]>, Bencoding>),
+    Dict(Dict),
 }
 
 impl fmt::Display for Bencoding {
@@ -72,7 +267,7 @@ impl fmt::Display for Bencoding {
 
         match self {
             Bencoding::Int(i) => write!(f, "{}", i),
-            Bencoding::ByteString(b) => fmt_bytestring(&b, f),
+            Bencoding::ByteString(b) => fmt_bytestring(b, f),
             Bencoding::List(items) => {
                 write!(f, "[")?;
                 for item in items.iter() {
@@ -81,10 +276,10 @@ impl fmt::Display for Bencoding {
                 }
                 write!(f, "]")
             }
-            Bencoding::Dict(map) => {
+            Bencoding::Dict(dict) => {
                 write!(f, "{{")?;
-                for (key, value) in map.iter() {
-                    fmt_bytestring(&key, f)?;
+                for (key, value) in dict.iter() {
+                    fmt_bytestring(key, f)?;
                     write!(f, ": ")?;
                     value.fmt(f)?;
                     write!(f, ", ")?;
@@ -95,97 +290,919 @@ impl fmt::Display for Bencoding {
     }
 }
 
+/// Represents the entries of a bencoded dictionary.
+///
+/// Entries are kept in the order they were inserted, or appeared in the input,
+/// which lets us tell whether a dictionary was sorted like the specification requires.
+/// Alongside them we keep an index sorted by key, for lookups and for canonical encoding.
+///
+/// Two dictionaries are equal if they contain the same entries, regardless of their order.
+#[derive(Clone, Debug, Default)]
+pub struct Dict {
+    entries: Vec<(Box<[u8]>, Bencoding)>,
+    // Indices into `entries`, sorted by key, with ties in the order of insertion.
+    sorted: Vec<usize>,
+}
+
+impl Dict {
+    /// Create an empty dictionary.
+    pub fn new() -> Self {
+        Dict::default()
+    }
+
+    /// The number of entries in this dictionary.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check whether or not this dictionary has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // The position in `sorted` just past every entry whose key is at most `key`.
+    fn upper_bound(&self, key: &[u8]) -> usize {
+        self.sorted.partition_point(|&i| &*self.entries[i].0 <= key)
+    }
+
+    // The index in `entries` of a key, picking the last entry if it appears more than once.
+    fn find(&self, key: &[u8]) -> Option<usize> {
+        let end = self.upper_bound(key);
+        let last = *self.sorted[..end].last()?;
+        if &*self.entries[last].0 == key {
+            Some(last)
+        } else {
+            None
+        }
+    }
+
+    /// Look up the value associated with a key.
+    ///
+    /// If the key appears more than once, the last value is returned.
+    pub fn get(&self, key: &[u8]) -> Option<&Bencoding> {
+        self.find(key).map(|i| &self.entries[i].1)
+    }
+
+    /// Look up the value associated with a key, allowing it to be modified.
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut Bencoding> {
+        let i = self.find(key)?;
+        Some(&mut self.entries[i].1)
+    }
+
+    /// Check whether or not this dictionary contains a key.
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.find(key).is_some()
+    }
+
+    /// Associate a value with a key, returning the previous value, if any.
+    ///
+    /// Replacing a value keeps the position of its key, while new keys go at the end.
+    pub fn insert(&mut self, key: impl Into<Box<[u8]>>, value: Bencoding) -> Option<Bencoding> {
+        let key = key.into();
+        if let Some(i) = self.find(&key) {
+            return Some(std::mem::replace(&mut self.entries[i].1, value));
+        }
+        self.push(key, value);
+        None
+    }
+
+    /// Remove a key from this dictionary, returning its value, if any.
+    ///
+    /// If the key appears more than once, every entry is removed, and the last value returned.
+    pub fn remove(&mut self, key: &[u8]) -> Option<Bencoding> {
+        self.find(key)?;
+        let mut removed = None;
+        let entries = std::mem::take(&mut self.entries);
+        for (k, v) in entries {
+            if &*k == key {
+                removed = Some(v);
+            } else {
+                self.entries.push((k, v));
+            }
+        }
+        // Removing entries shifts the ones after them, so we rebuild the index from scratch
+        let mut sorted: Vec<usize> = (0..self.entries.len()).collect();
+        sorted.sort_by(|&a, &b| self.entries[a].0.cmp(&self.entries[b].0));
+        self.sorted = sorted;
+        removed
+    }
+
+    // Add an entry at the end, without checking if its key is already present.
+    fn push(&mut self, key: Box<[u8]>, value: Bencoding) {
+        let position = self.upper_bound(&key);
+        self.sorted.insert(position, self.entries.len());
+        self.entries.push((key, value));
+    }
+
+    /// Iterate over the entries of this dictionary, in their original order.
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &Bencoding)> {
+        self.entries.iter().map(|(k, v)| (&**k, v))
+    }
+
+    /// Iterate over the entries of this dictionary, sorted by key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (&[u8], &Bencoding)> {
+        self.sorted.iter().map(move |&i| {
+            let (k, v) = &self.entries[i];
+            (&**k, v)
+        })
+    }
+
+    /// Iterate over the keys of this dictionary, in their original order.
+    pub fn keys(&self) -> impl Iterator<Item = &[u8]> {
+        self.entries.iter().map(|(k, _)| &**k)
+    }
+}
+
+impl PartialEq for Dict {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter_sorted().eq(other.iter_sorted())
+    }
+}
+
+impl Eq for Dict {}
+
+/// Collecting entries keeps them in order, including any duplicate keys.
+impl<K: Into<Box<[u8]>>> FromIterator<(K, Bencoding)> for Dict {
+    fn from_iter<I: IntoIterator<Item = (K, Bencoding)>>(iter: I) -> Self {
+        let mut dict = Dict::new();
+        for (key, value) in iter {
+            dict.push(key.into(), value);
+        }
+        dict
+    }
+}
+
+/// Represents bencoded data that borrows from the input it was decoded from.
+///
+/// This mirrors `Bencoding`, except that byte strings and dictionary keys point
+/// directly into the original input, instead of being copied out. For large files,
+/// like torrents with hundreds of thousands of pieces, this avoids most of the
+/// allocations involved in decoding.
+///
+/// This can be converted into the owned representation with `to_bencoding`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BencodingRef<'a> {
+    /// Represents an integer.
+    Int(i64),
+    /// Represents a sequence of bytes, borrowed from the input.
+    ByteString(&'a [u8]),
+    /// Represents an ordered sequence of bencoded elements.
+    List(Vec<BencodingRef<'a>>),
+    /// Represents a mapping from byte sequences to bencoded elements.
+    ///
+    /// Like `Dict`, the entries are kept in the order they appeared in the input,
+    /// duplicate keys included.
+    Dict(Vec<(&'a [u8], BencodingRef<'a>)>),
+}
+
+impl<'a> BencodingRef<'a> {
+    /// Try and decode a sequence of bytes as bencoded data, without copying strings.
+    pub fn decode(input: &'a [u8]) -> Result<Self, BencodingError> {
+        parse(input, &ParseOptions::default())
+    }
+
+    /// Try and decode a sequence of bytes without copying, with custom limits on what we accept.
+    pub fn decode_with(input: &'a [u8], options: &ParseOptions) -> Result<Self, BencodingError> {
+        parse(input, options)
+    }
+
+    /// Copy this data into the owned representation of bencoding.
+    pub fn to_bencoding(&self) -> Bencoding {
+        match self {
+            BencodingRef::Int(i) => Bencoding::Int(*i),
+            BencodingRef::ByteString(b) => Bencoding::ByteString((*b).into()),
+            BencodingRef::List(items) => {
+                Bencoding::List(items.iter().map(BencodingRef::to_bencoding).collect())
+            }
+            BencodingRef::Dict(entries) => Bencoding::Dict(
+                entries
+                    .iter()
+                    .map(|&(k, ref v)| (k, v.to_bencoding()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl<'a> From<&BencodingRef<'a>> for Bencoding {
+    fn from(bencoding: &BencodingRef<'a>) -> Self {
+        bencoding.to_bencoding()
+    }
+}
+
+/// Represents bencoded data, along with the range of input bytes each element came from.
+///
+/// Some parts of Bittorrent depend on the exact bytes of an element, rather than
+/// its value. For example, the info hash of a torrent is the hash of its info
+/// dictionary, exactly as it appears in the file. Slicing the input with the span of
+/// that dictionary recovers those bytes, even if the file wasn't encoded canonically.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Spanned<'a> {
+    /// The range of bytes in the input this element was decoded from.
+    pub span: Range<usize>,
+    /// The value of this element.
+    pub value: SpannedValue<'a>,
+}
+
+/// Represents the value of a `Spanned` element.
+///
+/// This mirrors `BencodingRef`, except that every nested element carries its own span.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpannedValue<'a> {
+    /// Represents an integer.
+    Int(i64),
+    /// Represents a sequence of bytes, borrowed from the input.
+    ByteString(&'a [u8]),
+    /// Represents an ordered sequence of bencoded elements.
+    List(Vec<Spanned<'a>>),
+    /// Represents a mapping from byte sequences to bencoded elements, in their original order.
+    Dict(Vec<(&'a [u8], Spanned<'a>)>),
+}
+
+impl<'a> Spanned<'a> {
+    /// Try and decode a sequence of bytes, keeping track of where each element starts and ends.
+    pub fn decode(input: &'a [u8]) -> Result<Self, BencodingError> {
+        parse(input, &ParseOptions::default())
+    }
+
+    /// Look up the value of a key, if this element is a dictionary containing it.
+    ///
+    /// If the key appears more than once, the last value is returned, like `Dict::get`.
+    pub fn get(&self, key: &[u8]) -> Option<&Spanned<'a>> {
+        match &self.value {
+            SpannedValue::Dict(entries) => entries
+                .iter()
+                .rev()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Copy this data into the owned representation of bencoding, dropping the spans.
+    pub fn to_bencoding(&self) -> Bencoding {
+        match &self.value {
+            SpannedValue::Int(i) => Bencoding::Int(*i),
+            SpannedValue::ByteString(b) => Bencoding::ByteString((*b).into()),
+            SpannedValue::List(items) => {
+                Bencoding::List(items.iter().map(Spanned::to_bencoding).collect())
+            }
+            SpannedValue::Dict(entries) => Bencoding::Dict(
+                entries
+                    .iter()
+                    .map(|&(k, ref v)| (k, v.to_bencoding()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Controls how much work the parser is willing to do, and what it accepts.
+///
+/// The default options accept any well formed bencoding nested at most 512 levels deep.
+/// When decoding untrusted input, tighter limits bound the memory used by the parser,
+/// since the decoded data can be much larger than the input it came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// How deeply lists and dictionaries can be nested.
+    ///
+    /// Parsing doesn't use the call stack, but dropping or comparing the decoded data does,
+    /// so very large limits can still exhaust the stack once the data is decoded.
+    pub max_depth: usize,
+    /// The maximum number of elements, including nested ones, in the input.
+    pub max_elements: usize,
+    /// The maximum length of a byte string, including dictionary keys.
+    pub max_string_length: usize,
+    /// Only accept the canonical encoding of each element.
+    ///
+    /// See `Bencoding::decode_strict` for what this rejects.
+    pub strict: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            max_depth: 512,
+            max_elements: usize::MAX,
+            max_string_length: usize::MAX,
+            strict: false,
+        }
+    }
+}
+
 // A type synonym for the result of parsing bencoded data.
 type BencodingResult = Result<Bencoding, BencodingError>;
 
-impl Bencoding {
-    /// Try and decode a sequence of bytes as bencoded data.
-    pub fn decode(input: &[u8]) -> BencodingResult {
-        fn int_digits(lexer: &mut Lexer) -> Result<i64, BencodingError> {
-            let head = *lexer.peek().ok_or(BencodingError(
-                "Tried to parse integer from empty input".to_owned(),
-            ))?;
-            let mut acc = as_digit(head).ok_or(BencodingError(
-                "Tried to parse integer without any valid digits".to_owned(),
-            ))?;
-            lexer.next();
-            while let Some(&chr) = lexer.peek() {
-                match as_digit(chr) {
-                    None => break,
-                    Some(digit) => {
-                        lexer.next();
-                        acc = 10 * acc + digit;
-                    }
-                }
+// The different representations our parser can build, with `B` being the type of
+// byte strings the input gives us.
+//
+// This lets us share a single parser between the owned and borrowed versions of bencoding.
+trait Node<B>: Sized {
+    fn int(int: i64) -> Self;
+    fn bytestring(bytes: B) -> Self;
+    fn list(items: Vec<Self>) -> Self;
+    fn dict(entries: Vec<(B, Self)>) -> Self;
+
+    // Called once each element is complete, with the range of input it was parsed from.
+    fn with_span(self, _span: Range<usize>) -> Self {
+        self
+    }
+}
+
+impl<B: Into<Box<[u8]>>> Node<B> for Bencoding {
+    fn int(int: i64) -> Self {
+        Bencoding::Int(int)
+    }
+
+    fn bytestring(bytes: B) -> Self {
+        Bencoding::ByteString(bytes.into())
+    }
+
+    fn list(items: Vec<Self>) -> Self {
+        Bencoding::List(items.into_boxed_slice())
+    }
+
+    fn dict(entries: Vec<(B, Self)>) -> Self {
+        Bencoding::Dict(entries.into_iter().collect())
+    }
+}
+
+// When only checking input, we don't need to build anything.
+impl<B> Node<B> for () {
+    fn int(_: i64) -> Self {}
+
+    fn bytestring(_: B) -> Self {}
+
+    fn list(_: Vec<Self>) -> Self {}
+
+    fn dict(_: Vec<(B, Self)>) -> Self {}
+}
+
+impl<'a> Node<&'a [u8]> for BencodingRef<'a> {
+    fn int(int: i64) -> Self {
+        BencodingRef::Int(int)
+    }
+
+    fn bytestring(bytes: &'a [u8]) -> Self {
+        BencodingRef::ByteString(bytes)
+    }
+
+    fn list(items: Vec<Self>) -> Self {
+        BencodingRef::List(items)
+    }
+
+    fn dict(entries: Vec<(&'a [u8], Self)>) -> Self {
+        BencodingRef::Dict(entries)
+    }
+}
+
+// Spans are only known once an element is complete, so they start out empty.
+impl<'a> Node<&'a [u8]> for Spanned<'a> {
+    fn int(int: i64) -> Self {
+        SpannedValue::Int(int).into()
+    }
+
+    fn bytestring(bytes: &'a [u8]) -> Self {
+        SpannedValue::ByteString(bytes).into()
+    }
+
+    fn list(items: Vec<Self>) -> Self {
+        SpannedValue::List(items).into()
+    }
+
+    fn dict(entries: Vec<(&'a [u8], Self)>) -> Self {
+        SpannedValue::Dict(entries).into()
+    }
+
+    fn with_span(self, span: Range<usize>) -> Self {
+        Spanned { span, ..self }
+    }
+}
+
+impl<'a> From<SpannedValue<'a>> for Spanned<'a> {
+    fn from(value: SpannedValue<'a>) -> Self {
+        Spanned { span: 0..0, value }
+    }
+}
+
+fn int_digits(lexer: &mut Lexer, negative: bool) -> Result<i64, BencodingError> {
+    let start = lexer.pos;
+    let (digits, value) = lexer.digits(negative);
+    if digits.is_empty() {
+        return Err(lexer.unexpected(Expected::Digit));
+    }
+    if digits.len() > 1 && digits[0] == b'0' {
+        lexer.non_canonical(BencodingError::LeadingZero { offset: start })?;
+    }
+    value.ok_or(BencodingError::IntegerOverflow { offset: start })
+}
+
+// Parse the rest of an integer, after its leading `i`.
+fn integer(lexer: &mut Lexer) -> Result<i64, BencodingError> {
+    let start = lexer.pos;
+    let negative = lexer.peek() == Some(&b'-');
+    if negative {
+        lexer.next();
+    }
+    let int = int_digits(lexer, negative)?;
+    if negative && int == 0 {
+        lexer.non_canonical(BencodingError::NegativeZero { offset: start })?;
+    }
+    lexer.expect(b'e')?;
+    Ok(int)
+}
+
+// Parse a byte string, including its length prefix.
+fn bytestring<'a>(lexer: &mut Lexer<'a>) -> Result<&'a [u8], BencodingError> {
+    limited_bytestring(lexer, usize::MAX)
+}
+
+// Parse a byte string, failing if it's longer than a given limit.
+fn limited_bytestring<'a>(lexer: &mut Lexer<'a>, limit: usize) -> Result<&'a [u8], BencodingError> {
+    let start = lexer.pos;
+    // On 32 bit platforms, lengths that don't fit in a usize can't fit in the input either
+    let count = usize::try_from(int_digits(lexer, false)?).unwrap_or(usize::MAX);
+    if count > limit {
+        return Err(BencodingError::StringTooLong {
+            offset: start,
+            length: count,
+            limit,
+        });
+    }
+    // The string needs to fit in what's left after the `:`
+    if count >= lexer.remaining() {
+        return Err(BencodingError::InvalidLength {
+            offset: start,
+            length: count,
+        });
+    }
+    lexer.expect(b':')?;
+    let slice = lexer.take(count).ok_or(BencodingError::InvalidLength {
+        offset: start,
+        length: count,
+    })?;
+    Ok(slice)
+}
+
+// Where the parser reads its input from, either a slice or a reader.
+//
+// The parser only needs whole integers and byte strings, along with single bytes
+// to tell which element comes next.
+trait Source {
+    type Bytes: AsRef<[u8]>;
+    type Error: From<BencodingError>;
+
+    // How many bytes we've consumed so far.
+    fn offset(&self) -> usize;
+    fn peek_byte(&mut self) -> Result<Option<u8>, Self::Error>;
+    fn skip_byte(&mut self) -> Result<(), Self::Error>;
+    // Read the rest of an integer, after its leading `i`.
+    fn read_integer(&mut self) -> Result<i64, Self::Error>;
+    fn read_bytestring(&mut self, limit: usize) -> Result<Self::Bytes, Self::Error>;
+    // Create an error for finding something other than an element at the current position.
+    fn unexpected_element(&mut self) -> Self::Error;
+    // Report dictionary keys that aren't in canonical order, which is only checked in strict mode.
+    fn non_canonical(&mut self, error: BencodingError) -> Result<(), Self::Error>;
+}
+
+impl<'a> Source for Lexer<'a> {
+    type Bytes = &'a [u8];
+    type Error = BencodingError;
+
+    #[inline]
+    fn offset(&self) -> usize {
+        self.pos
+    }
+
+    #[inline]
+    fn peek_byte(&mut self) -> Result<Option<u8>, BencodingError> {
+        Ok(self.peek().copied())
+    }
+
+    #[inline]
+    fn skip_byte(&mut self) -> Result<(), BencodingError> {
+        self.next();
+        Ok(())
+    }
+
+    #[inline]
+    fn read_integer(&mut self) -> Result<i64, BencodingError> {
+        integer(self)
+    }
+
+    #[inline]
+    fn read_bytestring(&mut self, limit: usize) -> Result<&'a [u8], BencodingError> {
+        limited_bytestring(self, limit)
+    }
+
+    #[inline]
+    fn unexpected_element(&mut self) -> BencodingError {
+        self.unexpected(Expected::Element)
+    }
+
+    #[inline]
+    fn non_canonical(&mut self, error: BencodingError) -> Result<(), BencodingError> {
+        Lexer::non_canonical(self, error)
+    }
+}
+
+// What the parser puts the elements it decodes into.
+//
+// Containers are opened before their contents and closed after them, which lets
+// builders either assemble a tree of nodes, or lay elements out flat, like arenas do.
+trait Builder<B> {
+    // A complete element.
+    type Node;
+    // A list or dictionary we're in the middle of parsing.
+    type Frame;
+
+    fn int(&mut self, int: i64, span: Range<usize>) -> Self::Node;
+    fn bytestring(&mut self, bytes: B, span: Range<usize>) -> Self::Node;
+    fn open(&mut self, dict: bool, start: usize) -> Self::Frame;
+    fn is_dict(&self, frame: &Self::Frame) -> bool;
+    // The last key we've seen in a dictionary, if any.
+    fn last_key<'f>(&'f self, frame: &'f Self::Frame) -> Option<&'f [u8]>;
+    // Set the key of the next value in a dictionary.
+    fn key(&mut self, frame: &mut Self::Frame, key: B, span: Range<usize>);
+    fn push(&mut self, frame: &mut Self::Frame, node: Self::Node);
+    fn close(&mut self, frame: Self::Frame, end: usize) -> Self::Node;
+}
+
+// A list or dictionary we're in the middle of assembling into a tree.
+enum Frame<B, N> {
+    List {
+        start: usize,
+        items: Vec<N>,
+    },
+    Dict {
+        start: usize,
+        entries: Vec<(B, N)>,
+        // The key of the value we're currently parsing.
+        key: Option<B>,
+    },
+}
+
+// Assembles elements into a tree of nodes.
+struct Tree<N>(PhantomData<N>);
+
+impl<N> Tree<N> {
+    fn new() -> Self {
+        Tree(PhantomData)
+    }
+}
+
+impl<B: AsRef<[u8]>, N: Node<B>> Builder<B> for Tree<N> {
+    type Node = N;
+    type Frame = Frame<B, N>;
+
+    #[inline]
+    fn int(&mut self, int: i64, span: Range<usize>) -> N {
+        N::int(int).with_span(span)
+    }
+
+    #[inline]
+    fn bytestring(&mut self, bytes: B, span: Range<usize>) -> N {
+        N::bytestring(bytes).with_span(span)
+    }
+
+    #[inline]
+    fn open(&mut self, dict: bool, start: usize) -> Frame<B, N> {
+        if dict {
+            Frame::Dict {
+                start,
+                entries: Vec::new(),
+                key: None,
+            }
+        } else {
+            Frame::List {
+                start,
+                items: Vec::new(),
             }
-            Ok(acc)
         }
+    }
 
-        fn int(lexer: &mut Lexer) -> BencodingResult {
-            let negate = if let Some(b'-') = lexer.peek() {
-                lexer.next();
-                -1
-            } else {
-                1
-            };
-            let int = int_digits(lexer)?;
-            lexer.expect(b'e')?;
-            Ok(Bencoding::Int(negate * int))
+    #[inline]
+    fn is_dict(&self, frame: &Frame<B, N>) -> bool {
+        matches!(frame, Frame::Dict { .. })
+    }
+
+    #[inline]
+    fn last_key<'f>(&'f self, frame: &'f Frame<B, N>) -> Option<&'f [u8]> {
+        match frame {
+            Frame::Dict { entries, .. } => entries.last().map(|(key, _)| key.as_ref()),
+            Frame::List { .. } => None,
         }
+    }
 
-        fn bytestring(lexer: &mut Lexer) -> Result<Box<[u8]>, BencodingError> {
-            let count = int_digits(lexer)? as usize;
-            lexer.expect(b':')?;
-            let slice = lexer.take(count).ok_or(BencodingError(format!(
-                "Unable to take {} bytes from input",
-                count
-            )))?;
-            Ok(slice.to_vec().into_boxed_slice())
+    #[inline]
+    fn key(&mut self, frame: &mut Frame<B, N>, new: B, _span: Range<usize>) {
+        if let Frame::Dict { key, .. } = frame {
+            *key = Some(new);
         }
+    }
 
-        fn list(lexer: &mut Lexer) -> BencodingResult {
-            let mut inner = Vec::new();
-            while let Ok(item) = root(lexer) {
-                inner.push(item);
+    #[inline]
+    fn push(&mut self, frame: &mut Frame<B, N>, node: N) {
+        match frame {
+            Frame::List { items, .. } => items.push(node),
+            Frame::Dict { entries, key, .. } => {
+                if let Some(key) = key.take() {
+                    entries.push((key, node));
+                }
             }
-            lexer.expect(b'e')?;
-            Ok(Bencoding::List(inner.into_boxed_slice()))
         }
+    }
 
-        fn dict(lexer: &mut Lexer) -> BencodingResult {
-            let mut inner = HashMap::new();
-            while let Ok(key) = bytestring(lexer) {
-                let item = root(lexer)?;
-                inner.insert(key, item);
-            }
-            lexer.expect(b'e')?;
-            Ok(Bencoding::Dict(inner))
+    #[inline]
+    fn close(&mut self, frame: Frame<B, N>, end: usize) -> N {
+        match frame {
+            Frame::List { start, items } => N::list(items).with_span(start..end),
+            Frame::Dict { start, entries, .. } => N::dict(entries).with_span(start..end),
         }
+    }
+}
 
-        fn root(lexer: &mut Lexer) -> BencodingResult {
-            match lexer.peek() {
-                None => Err(BencodingError(
-                    "Tried to parse bencoded data from empty input".to_owned(),
-                )),
+// Parse the element at the start of a source, handing what we decode to a builder.
+//
+// Instead of recursing for each level of nesting, we keep the containers we're
+// inside of in an explicit stack, so that deeply nested input can't overflow the call stack.
+// The stack is passed in so that arenas can reuse its memory between inputs.
+fn parse_from<S: Source, K: Builder<S::Bytes>>(
+    source: &mut S,
+    builder: &mut K,
+    options: &ParseOptions,
+    stack: &mut Vec<K::Frame>,
+) -> Result<K::Node, S::Error> {
+    stack.clear();
+    let mut elements = 0;
+    loop {
+        let node = if !stack.is_empty() && source.peek_byte()? == Some(b'e') {
+            source.skip_byte()?;
+            match stack.pop() {
+                Some(frame) => builder.close(frame, source.offset()),
+                None => unreachable!(),
+            }
+        } else {
+            if let Some(frame) = stack.last_mut() {
+                if builder.is_dict(frame) {
+                    let start = source.offset();
+                    let key = source.read_bytestring(options.max_string_length)?;
+                    if let (true, Some(last)) = (options.strict, builder.last_key(frame)) {
+                        if key.as_ref() == last {
+                            source.non_canonical(BencodingError::DuplicateKey { offset: start })?;
+                        } else if key.as_ref() < last {
+                            source.non_canonical(BencodingError::UnsortedKey { offset: start })?;
+                        }
+                    }
+                    builder.key(frame, key, start..source.offset());
+                }
+            }
+            let start = source.offset();
+            elements += 1;
+            if elements > options.max_elements {
+                return Err(BencodingError::TooManyElements {
+                    offset: start,
+                    limit: options.max_elements,
+                }
+                .into());
+            }
+            match source.peek_byte()? {
                 Some(b'i') => {
-                    lexer.next();
-                    int(lexer)
+                    source.skip_byte()?;
+                    let int = source.read_integer()?;
+                    builder.int(int, start..source.offset())
                 }
-                Some(b'l') => {
-                    lexer.next();
-                    list(lexer)
+                Some(c) if c == b'l' || c == b'd' => {
+                    if stack.len() >= options.max_depth {
+                        return Err(BencodingError::TooDeep {
+                            offset: start,
+                            limit: options.max_depth,
+                        }
+                        .into());
+                    }
+                    source.skip_byte()?;
+                    let frame = builder.open(c == b'd', start);
+                    stack.push(frame);
+                    continue;
                 }
-                Some(b'd') => {
-                    lexer.next();
-                    dict(lexer)
+                Some(c) if as_digit(c).is_some() => {
+                    let bytes = source.read_bytestring(options.max_string_length)?;
+                    builder.bytestring(bytes, start..source.offset())
                 }
-                Some(&c) if as_digit(c).is_some() => bytestring(lexer).map(Bencoding::ByteString),
-                Some(c) => Err(BencodingError(format!("Unknown type of element {}", c))),
+                _ => return Err(source.unexpected_element()),
             }
+        };
+        match stack.last_mut() {
+            None => return Ok(node),
+            Some(frame) => builder.push(frame, node),
         }
+    }
+}
+
+fn parse<'a, N: Node<&'a [u8]>>(
+    input: &'a [u8],
+    options: &ParseOptions,
+) -> Result<N, BencodingError> {
+    let (root, end) = parse_prefix(input, options)?;
+    if end < input.len() {
+        return Err(BencodingError::TrailingData { offset: end });
+    }
+    Ok(root)
+}
 
+// Parse the element at the start of the input, returning it along with where it ends.
+fn parse_prefix<'a, N: Node<&'a [u8]>>(
+    input: &'a [u8],
+    options: &ParseOptions,
+) -> Result<(N, usize), BencodingError> {
+    let mut lexer = Lexer::new(input);
+    lexer.strict = options.strict;
+    let root = parse_from(&mut lexer, &mut Tree::new(), options, &mut Vec::new())?;
+    Ok((root, lexer.pos))
+}
+
+impl Bencoding {
+    /// Try and decode a sequence of bytes as bencoded data.
+    pub fn decode(input: &[u8]) -> BencodingResult {
+        parse(input, &ParseOptions::default())
+    }
+
+    /// Try and decode a sequence of bytes, only accepting the canonical encoding.
+    ///
+    /// Besides what `decode` checks, this rejects numbers with leading zeros, `i-0e`,
+    /// as well as dictionaries whose keys are duplicated or not in sorted order.
+    /// Data accepted by this function is encoded back into exactly the same bytes.
+    pub fn decode_strict(input: &[u8]) -> BencodingResult {
+        let options = ParseOptions {
+            strict: true,
+            ..ParseOptions::default()
+        };
+        parse(input, &options)
+    }
+
+    /// Try and decode a sequence of bytes, with custom limits on what we accept.
+    pub fn decode_with(input: &[u8], options: &ParseOptions) -> BencodingResult {
+        parse(input, options)
+    }
+
+    /// Decode the element at the start of some bytes, returning the bytes following it.
+    ///
+    /// Unlike `decode`, data after the end of the element isn't an error. This is useful
+    /// for messages which contain a bencoded element followed by some other payload.
+    pub fn parse_prefix(input: &[u8]) -> Result<(Bencoding, &[u8]), BencodingError> {
+        let (root, end) = parse_prefix(input, &ParseOptions::default())?;
+        Ok((root, &input[end..]))
+    }
+
+    /// Check that some data is the canonical encoding of a single element.
+    ///
+    /// This means that dictionary keys are sorted and unique, numbers have no superfluous
+    /// leading zeros, and nothing follows the element. Canonical data is the only encoding
+    /// of its value, which is what makes hashes of it, like info hashes, stable.
+    ///
+    /// This doesn't build the decoded data, so it's cheaper than `decode_strict`.
+    pub fn is_canonical(input: &[u8]) -> Result<(), CanonicalError> {
+        let options = ParseOptions {
+            max_depth: usize::MAX,
+            strict: true,
+            ..ParseOptions::default()
+        };
+        parse::<()>(input, &options).map_err(CanonicalError::from)
+    }
+
+    /// Find every place where some data isn't the canonical encoding of its value.
+    ///
+    /// Unlike `is_canonical`, this doesn't stop at the first problem, returning
+    /// each leading zero, `i-0e`, and duplicate or unsorted key, in the order they appear.
+    /// Data that isn't valid bencoding at all is still an error.
+    pub fn canonical_issues(input: &[u8]) -> Result<Vec<BencodingError>, BencodingError> {
+        let options = ParseOptions {
+            max_depth: usize::MAX,
+            strict: true,
+            ..ParseOptions::default()
+        };
         let mut lexer = Lexer::new(input);
-        root(&mut lexer)
+        lexer.strict = true;
+        lexer.issues = Some(Vec::new());
+        parse_from::<_, Tree<()>>(&mut lexer, &mut Tree::new(), &options, &mut Vec::new())?;
+        if lexer.pos < input.len() {
+            return Err(BencodingError::TrailingData { offset: lexer.pos });
+        }
+        Ok(lexer.issues.unwrap_or_default())
+    }
+
+    /// Encode this data as a sequence of bytes.
+    ///
+    /// The keys of each dictionary are written in sorted order, as the specification requires.
+    /// If a key appears more than once, only its last value is written.
+    /// This means that decoding well formed bencoding and then encoding it again produces
+    /// the same bytes.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_to(true, &mut out)
+            .expect("writing to a Vec can't fail");
+        out
+    }
+
+    /// Encode this data directly into a writer, in the same way as `encode`.
+    ///
+    /// This avoids building up the whole encoding in memory first, which matters
+    /// for large structures like torrents with many pieces. Since we write the encoding
+    /// out in many small pieces, unbuffered writers should be wrapped in an `io::BufWriter`.
+    pub fn encode_to_writer<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        self.encode_to(true, &mut writer)
+    }
+
+    /// Encode this data as a sequence of bytes, keeping dictionaries in their original order.
+    ///
+    /// Unlike `encode`, this writes out every entry of each dictionary, duplicates included,
+    /// in the order they were decoded or inserted in. Decoding a file, modifying part of it,
+    /// and then encoding it with this function leaves the rest of the file untouched,
+    /// as long as its integers and string lengths didn't have superfluous leading zeros.
+    /// For torrents, this means that editing trackers won't change the info hash,
+    /// even if the info dictionary wasn't sorted.
+    pub fn encode_in_order(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_to(false, &mut out)
+            .expect("writing to a Vec can't fail");
+        out
+    }
+
+    /// The number of bytes that `encode` would produce, without encoding anything.
+    ///
+    /// This is useful to allocate buffers ahead of time, or to check that a message
+    /// fits in some size limit before encoding it.
+    pub fn encoded_len(&self) -> usize {
+        fn digits(mut n: u64) -> usize {
+            let mut count = 1;
+            while n >= 10 {
+                n /= 10;
+                count += 1;
+            }
+            count
+        }
+
+        fn bytestring(string: &[u8]) -> usize {
+            digits(string.len() as u64) + 1 + string.len()
+        }
+
+        match self {
+            Bencoding::Int(i) => 2 + usize::from(*i < 0) + digits(i.unsigned_abs()),
+            Bencoding::ByteString(b) => bytestring(b),
+            Bencoding::List(items) => 2 + items.iter().map(Bencoding::encoded_len).sum::<usize>(),
+            Bencoding::Dict(dict) => {
+                let mut len = 2;
+                let mut entries = dict.iter_sorted().peekable();
+                while let Some((key, value)) = entries.next() {
+                    // Like `encode`, only the last value of a duplicate key counts
+                    if entries.peek().is_some_and(|(next, _)| *next == key) {
+                        continue;
+                    }
+                    len += bytestring(key) + value.encoded_len();
+                }
+                len
+            }
+        }
+    }
+
+    fn encode_to<W: io::Write>(&self, sorted: bool, out: &mut W) -> io::Result<()> {
+        fn bytestring<W: io::Write>(string: &[u8], out: &mut W) -> io::Result<()> {
+            write!(out, "{}:", string.len())?;
+            out.write_all(string)
+        }
+
+        match self {
+            Bencoding::Int(i) => write!(out, "i{}e", i),
+            Bencoding::ByteString(b) => bytestring(b, out),
+            Bencoding::List(items) => {
+                out.write_all(b"l")?;
+                for item in items.iter() {
+                    item.encode_to(sorted, out)?;
+                }
+                out.write_all(b"e")
+            }
+            Bencoding::Dict(dict) if sorted => {
+                out.write_all(b"d")?;
+                let mut entries = dict.iter_sorted().peekable();
+                while let Some((key, value)) = entries.next() {
+                    // Duplicate keys are next to each other, with the last value coming last
+                    if entries.peek().is_some_and(|(next, _)| *next == key) {
+                        continue;
+                    }
+                    bytestring(key, out)?;
+                    value.encode_to(sorted, out)?;
+                }
+                out.write_all(b"e")
+            }
+            Bencoding::Dict(dict) => {
+                out.write_all(b"d")?;
+                for (key, value) in dict.iter() {
+                    bytestring(key, out)?;
+                    value.encode_to(sorted, out)?;
+                }
+                out.write_all(b"e")
+            }
+        }
     }
 }
 
@@ -197,16 +1214,184 @@ impl convert::TryFrom<&[u8]> for Bencoding {
     }
 }
 
+/// An event produced while reading through bencoded data.
+///
+/// See `BencodingReader` for how these events are produced.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event<'a> {
+    /// An integer.
+    Int(i64),
+    /// A byte string, borrowed from the input.
+    ///
+    /// This is produced for values, and never for dictionary keys.
+    ByteString(&'a [u8]),
+    /// The start of a list, whose elements follow, until a matching `End`.
+    ListStart,
+    /// The start of a dictionary, whose entries follow, until a matching `End`.
+    DictStart,
+    /// The key of the next entry in a dictionary, which is followed by the value.
+    DictKey(&'a [u8]),
+    /// The end of the most recently started list or dictionary.
+    End,
+}
+
+// The kind of container the reader is inside of.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Container {
+    List,
+    // We keep track of whether or not the next thing should be a key.
+    Dict { key_next: bool },
+}
+
+/// A pull parser over bencoded data, producing a stream of events.
+///
+/// Unlike `Bencoding::decode`, this never builds up the structure of the data
+/// in memory. This makes it possible to look for a handful of keys in a large
+/// input, skipping over everything else with `skip_value`.
+///
+/// The reader stops after the first complete value in the input. After an error,
+/// no more events are produced.
+#[derive(Debug)]
+pub struct BencodingReader<'a> {
+    lexer: Lexer<'a>,
+    stack: Vec<Container>,
+    done: bool,
+}
+
+impl<'a> BencodingReader<'a> {
+    /// Create a new reader over some input.
+    pub fn new(input: &'a [u8]) -> Self {
+        BencodingReader {
+            lexer: Lexer::new(input),
+            stack: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// How many lists or dictionaries we're currently inside of.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// The offset in the input of the next byte this reader will look at.
+    ///
+    /// Taking the offset before and after `skip_value` gives the range of bytes
+    /// making up that value.
+    pub fn offset(&self) -> usize {
+        self.lexer.pos
+    }
+
+    /// Skip over the next value in the input, including all of its contents.
+    ///
+    /// Inside a dictionary, this should be called right after reading a `DictKey`,
+    /// to skip over the value associated with that key.
+    pub fn skip_value(&mut self) -> Result<(), BencodingError> {
+        let start = self.depth();
+        loop {
+            match self.next() {
+                None => return Err(self.lexer.unexpected(Expected::Element)),
+                Some(Err(e)) => return Err(e),
+                Some(Ok(Event::End)) if self.depth() < start => {
+                    // The end marker has already been consumed, so we point back at it
+                    let offset = self.lexer.pos - 1;
+                    return Err(self.lexer.unexpected_at(offset, Expected::Element));
+                }
+                Some(Ok(Event::DictKey(_))) => {}
+                Some(Ok(_)) if self.depth() == start => return Ok(()),
+                Some(Ok(_)) => {}
+            }
+        }
+    }
+
+    fn next_event(&mut self) -> Result<Option<Event<'a>>, BencodingError> {
+        if let Some(Container::Dict { key_next }) = self.stack.last_mut() {
+            match (*key_next, self.lexer.peek()) {
+                (true, Some(&c)) if c != b'e' => {
+                    *key_next = false;
+                    return bytestring(&mut self.lexer).map(|key| Some(Event::DictKey(key)));
+                }
+                (false, Some(b'e')) => return Err(self.lexer.unexpected(Expected::Element)),
+                _ => *key_next = true,
+            }
+        }
+        let event = match self.lexer.peek() {
+            None => return Err(self.lexer.unexpected(Expected::Element)),
+            Some(b'e') if !self.stack.is_empty() => {
+                self.lexer.next();
+                self.stack.pop();
+                Event::End
+            }
+            Some(b'i') => {
+                self.lexer.next();
+                Event::Int(integer(&mut self.lexer)?)
+            }
+            Some(b'l') => {
+                self.lexer.next();
+                self.stack.push(Container::List);
+                Event::ListStart
+            }
+            Some(b'd') => {
+                self.lexer.next();
+                self.stack.push(Container::Dict { key_next: true });
+                Event::DictStart
+            }
+            Some(&c) if as_digit(c).is_some() => Event::ByteString(bytestring(&mut self.lexer)?),
+            Some(_) => return Err(self.lexer.unexpected(Expected::Element)),
+        };
+        if self.stack.is_empty() {
+            self.done = true;
+        }
+        Ok(Some(event))
+    }
+}
+
+impl<'a> Iterator for BencodingReader<'a> {
+    type Item = Result<Event<'a>, BencodingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_event();
+        if result.is_err() {
+            self.done = true;
+        }
+        result.transpose()
+    }
+}
+
 #[derive(Debug)]
 struct Lexer<'a> {
     input: &'a [u8],
     pos: usize,
+    // Whether or not to reject non-canonical numbers
+    strict: bool,
+    // If set, non-canonical data is recorded here instead of being rejected.
+    issues: Option<Vec<BencodingError>>,
 }
 
 impl<'a> Lexer<'a> {
     #[inline]
     fn new(input: &'a [u8]) -> Self {
-        Lexer { input, pos: 0 }
+        Lexer {
+            input,
+            pos: 0,
+            strict: false,
+            issues: None,
+        }
+    }
+
+    // Report data that isn't canonically encoded, which is an error in strict mode.
+    #[inline]
+    fn non_canonical(&mut self, error: BencodingError) -> Result<(), BencodingError> {
+        match &mut self.issues {
+            Some(issues) => {
+                issues.push(error);
+                Ok(())
+            }
+            None if self.strict => Err(error),
+            None => Ok(()),
+        }
     }
 
     #[inline]
@@ -221,9 +1406,53 @@ impl<'a> Lexer<'a> {
         self.input.get(self.pos)
     }
 
+    // Consume the longest run of ASCII digits at the current position, along with
+    // their value, negated if asked, or `None` if that doesn't fit in an i64.
+    //
+    // Numbers are scanned and accumulated in a single pass. Since 18 digits always
+    // fit in an i64, only the digits after those need to check for overflow.
+    // Negative values are accumulated as such, since `i64::MIN` has no positive twin.
+    #[inline]
+    fn digits(&mut self, negative: bool) -> (&'a [u8], Option<i64>) {
+        let rest = &self.input[self.pos.min(self.input.len())..];
+        let mut acc: i64 = 0;
+        let mut count = 0;
+        while let Some(&chr) = rest.get(count) {
+            if !chr.is_ascii_digit() {
+                break;
+            }
+            let digit = i64::from(chr - b'0');
+            let digit = if negative { -digit } else { digit };
+            acc = if count < 18 {
+                acc * 10 + digit
+            } else {
+                match acc.checked_mul(10).and_then(|acc| acc.checked_add(digit)) {
+                    Some(acc) => acc,
+                    None => {
+                        count += rest[count..]
+                            .iter()
+                            .take_while(|c| c.is_ascii_digit())
+                            .count();
+                        self.pos += count;
+                        return (&rest[..count], None);
+                    }
+                }
+            };
+            count += 1;
+        }
+        self.pos += count;
+        (&rest[..count], Some(acc))
+    }
+
+    // The number of bytes left to consume.
+    #[inline]
+    fn remaining(&self) -> usize {
+        self.input.len().saturating_sub(self.pos)
+    }
+
     #[inline]
     fn take(&mut self, count: usize) -> Option<&'a [u8]> {
-        let top = self.pos + count;
+        let top = self.pos.checked_add(count)?;
         if top > self.input.len() {
             None
         } else {
@@ -240,21 +1469,32 @@ impl<'a> Lexer<'a> {
                 self.next();
                 Ok(())
             }
-            Some(bad) => Err(BencodingError(format!(
-                "Expected {} but found {}",
-                target, bad
-            ))),
-            None => Err(BencodingError(format!(
-                "Expected {} but reached the end of input",
-                target
-            ))),
+            _ => Err(self.unexpected(Expected::Byte(target))),
+        }
+    }
+
+    // Create an error for finding something other than what we expected at the current position.
+    #[inline]
+    fn unexpected(&self, expected: Expected) -> BencodingError {
+        self.unexpected_at(self.pos, expected)
+    }
+
+    #[inline]
+    fn unexpected_at(&self, offset: usize, expected: Expected) -> BencodingError {
+        match self.input.get(offset) {
+            None => BencodingError::UnexpectedEof { offset, expected },
+            Some(&found) => BencodingError::UnexpectedByte {
+                offset,
+                expected,
+                found,
+            },
         }
     }
 }
 
 // Check that an ASCII character is between '0' and '9'
 fn as_digit(chr: u8) -> Option<i64> {
-    if b'0' <= chr && chr <= b'9' {
+    if chr.is_ascii_digit() {
         Some(chr as i64 - 48)
     } else {
         None
@@ -263,9 +1503,11 @@ fn as_digit(chr: u8) -> Option<i64> {
 
 #[cfg(test)]
 mod test {
-    use std::collections::HashMap;
 
-    use super::{as_digit, Bencoding};
+    use super::{
+        as_digit, Bencoding, BencodingError, BencodingReader, BencodingRef, CanonicalError, Dict,
+        Event, Expected, ParseOptions, Spanned, SpannedValue,
+    };
 
     #[test]
     fn as_digit_test() {
@@ -310,10 +1552,391 @@ mod test {
     fn parsing_basic_dicts_works() {
         let input = b"d1:Ai1e1:Bi2ee";
         let output = Bencoding::decode(input);
-        let mut map = HashMap::new();
-        map.insert(b"A".to_vec().into_boxed_slice(), Bencoding::Int(1));
-        map.insert(b"B".to_vec().into_boxed_slice(), Bencoding::Int(2));
-        let expected = Bencoding::Dict(map);
+        let mut dict = Dict::new();
+        dict.insert(&b"A"[..], Bencoding::Int(1));
+        dict.insert(&b"B"[..], Bencoding::Int(2));
+        let expected = Bencoding::Dict(dict);
         assert_eq!(Ok(expected), output);
     }
+
+    #[test]
+    fn dicts_keep_their_original_order() {
+        let dict = match Bencoding::decode(b"d1:bi1e1:ai2e1:bi3ee") {
+            Ok(Bencoding::Dict(dict)) => dict,
+            other => panic!("expected a dictionary, found {:?}", other),
+        };
+        let keys: Vec<_> = dict.keys().collect();
+        assert_eq!(vec![&b"b"[..], b"a", b"b"], keys);
+        let sorted: Vec<_> = dict.iter_sorted().map(|(k, v)| (k, v.clone())).collect();
+        assert_eq!(
+            vec![
+                (&b"a"[..], Bencoding::Int(2)),
+                (b"b", Bencoding::Int(1)),
+                (b"b", Bencoding::Int(3))
+            ],
+            sorted
+        );
+        // The last duplicate wins, like it would when inserting
+        assert_eq!(Some(&Bencoding::Int(3)), dict.get(b"b"));
+        assert_eq!(None, dict.get(b"c"));
+
+        let mut other = Dict::new();
+        other.insert(&b"a"[..], Bencoding::Int(2));
+        assert_eq!(None, other.insert(&b"b"[..], Bencoding::Int(1)));
+        assert_ne!(dict, other);
+        other.push(b"b"[..].into(), Bencoding::Int(3));
+        assert_eq!(dict, other);
+    }
+
+    #[test]
+    fn encoding_roundtrips() {
+        let input = b"d4:infod5:filesli1ei-2ee4:name3:dire3:zzz0:1:ai3ee";
+        let output = Bencoding::decode(input).unwrap().encode();
+        assert_eq!(
+            b"d1:ai3e4:infod5:filesli1ei-2ee4:name3:dire3:zzz0:e".to_vec(),
+            output
+        );
+    }
+
+    #[test]
+    fn parsing_prefixes_returns_the_remainder() {
+        assert_eq!(
+            Ok((
+                Bencoding::List(Box::new([Bencoding::Int(1)])),
+                &b"\x00\x01rest"[..]
+            )),
+            Bencoding::parse_prefix(b"li1ee\x00\x01rest")
+        );
+        assert_eq!(
+            Ok((Bencoding::ByteString(b"ab"[..].into()), &b""[..])),
+            Bencoding::parse_prefix(b"2:ab")
+        );
+        assert!(Bencoding::parse_prefix(b"li1e").is_err());
+    }
+
+    #[test]
+    fn encoded_lengths_match_encoding() {
+        let inputs: &[&[u8]] = &[
+            b"i0e",
+            b"i-10e",
+            b"i-9223372036854775807e",
+            b"10:0123456789",
+            b"d1:ali1ei-2ee1:b0:1:ai3ee",
+            b"llleee",
+        ];
+        for input in inputs {
+            let bencoding = Bencoding::decode(input).unwrap();
+            assert_eq!(bencoding.encode().len(), bencoding.encoded_len());
+        }
+        let min = Bencoding::Int(i64::MIN);
+        assert_eq!(min.encode().len(), min.encoded_len());
+    }
+
+    #[test]
+    fn checking_canonical_encodings_works() {
+        assert_eq!(Ok(()), Bencoding::is_canonical(b"d1:ai1e1:bli-2eee"));
+        assert_eq!(
+            Err(CanonicalError::NonCanonical(BencodingError::UnsortedKey {
+                offset: 7
+            })),
+            Bencoding::is_canonical(b"d1:bi1e1:ai2ee")
+        );
+        assert_eq!(
+            Err(CanonicalError::NonCanonical(BencodingError::TrailingData {
+                offset: 3
+            })),
+            Bencoding::is_canonical(b"i1ei2e")
+        );
+        assert!(matches!(
+            Bencoding::is_canonical(b"i1"),
+            Err(CanonicalError::Invalid(_))
+        ));
+        // Being canonical doesn't depend on how deeply elements are nested
+        let mut input = vec![b'l'; 100_000];
+        input.extend(vec![b'e'; 100_000]);
+        assert_eq!(Ok(()), Bencoding::is_canonical(&input));
+    }
+
+    #[test]
+    fn finding_every_canonical_issue_works() {
+        assert_eq!(
+            Ok(Vec::new()),
+            Bencoding::canonical_issues(b"d1:ai1e1:bli-2eee")
+        );
+        assert_eq!(
+            Ok(vec![
+                BencodingError::LeadingZero { offset: 5 },
+                BencodingError::UnsortedKey { offset: 8 },
+                BencodingError::NegativeZero { offset: 12 },
+                BencodingError::DuplicateKey { offset: 15 },
+            ]),
+            Bencoding::canonical_issues(b"d1:bi01e1:ai-0e1:ai1ee")
+        );
+        assert_eq!(
+            Err(BencodingError::TrailingData { offset: 3 }),
+            Bencoding::canonical_issues(b"i1ei2e")
+        );
+    }
+
+    #[test]
+    fn encoding_to_a_writer_works() {
+        let bencoding = Bencoding::decode(b"d1:bi-1e1:al0:ee").unwrap();
+        let mut out = Vec::new();
+        bencoding.encode_to_writer(&mut out).unwrap();
+        assert_eq!(bencoding.encode(), out);
+        // The writer only has room for part of the encoding
+        let mut small = [0u8; 4];
+        assert!(bencoding.encode_to_writer(&mut small[..]).is_err());
+    }
+
+    #[test]
+    fn parsing_borrowed_bencoding_works() {
+        let input = b"d1:Al4:AAAAi-3ee1:B0:e";
+        let output = BencodingRef::decode(input).unwrap();
+        let entries = vec![
+            (
+                &b"A"[..],
+                BencodingRef::List(vec![
+                    BencodingRef::ByteString(b"AAAA"),
+                    BencodingRef::Int(-3),
+                ]),
+            ),
+            (&b"B"[..], BencodingRef::ByteString(b"")),
+        ];
+        assert_eq!(BencodingRef::Dict(entries), output);
+        assert_eq!(Bencoding::decode(input), Ok(output.to_bencoding()));
+        // Unsorted and duplicate keys are kept, like they are with `Bencoding`
+        let input = b"d1:bi1e1:ai2e1:bi3ee";
+        let output = BencodingRef::decode(input).unwrap();
+        let entries = vec![
+            (&b"b"[..], BencodingRef::Int(1)),
+            (&b"a"[..], BencodingRef::Int(2)),
+            (&b"b"[..], BencodingRef::Int(3)),
+        ];
+        assert_eq!(BencodingRef::Dict(entries), output);
+        let owned = Bencoding::decode(input).unwrap();
+        assert_eq!(
+            owned.encode_in_order(),
+            output.to_bencoding().encode_in_order()
+        );
+        let spanned = Spanned::decode(input).unwrap();
+        assert_eq!(&b"i3e"[..], &input[spanned.get(b"b").unwrap().span.clone()]);
+        assert_eq!(
+            owned.encode_in_order(),
+            spanned.to_bencoding().encode_in_order()
+        );
+    }
+
+    #[test]
+    fn parsing_spans_works() {
+        // The keys of the info dictionary aren't sorted, so re-encoding wouldn't match
+        let input = b"d4:infod1:bi1e1:a2:xye3:numi7ee";
+        let output = Spanned::decode(input).unwrap();
+        assert_eq!(0..input.len(), output.span);
+        let info = output.get(b"info").unwrap();
+        assert_eq!(&b"d1:bi1e1:a2:xye"[..], &input[info.span.clone()]);
+        assert_eq!(&b"2:xy"[..], &input[info.get(b"a").unwrap().span.clone()]);
+        let num = output.get(b"num").unwrap();
+        assert_eq!(SpannedValue::Int(7), num.value);
+        assert_eq!(&b"i7e"[..], &input[num.span.clone()]);
+        assert_eq!(Bencoding::decode(input), Ok(output.to_bencoding()));
+    }
+
+    #[test]
+    fn strict_parsing_rejects_non_canonical_data() {
+        let canonical = b"d1:ai-3e1:bli0e3:xyzee";
+        assert_eq!(
+            Bencoding::decode(canonical),
+            Bencoding::decode_strict(canonical)
+        );
+        let cases: [(&[u8], BencodingError); 5] = [
+            (b"i03e", BencodingError::LeadingZero { offset: 1 }),
+            (b"i-0e", BencodingError::NegativeZero { offset: 1 }),
+            (b"02:ab", BencodingError::LeadingZero { offset: 0 }),
+            (b"d1:bi1e1:ai2ee", BencodingError::UnsortedKey { offset: 7 }),
+            (
+                b"d1:ai1e1:ai2ee",
+                BencodingError::DuplicateKey { offset: 7 },
+            ),
+        ];
+        for (input, error) in cases.iter() {
+            assert!(Bencoding::decode(input).is_ok());
+            assert_eq!(Err(error.clone()), Bencoding::decode_strict(input));
+        }
+        assert_eq!(
+            Err(BencodingError::TrailingData { offset: 3 }),
+            Bencoding::decode_strict(b"i1ei2e")
+        );
+    }
+
+    #[test]
+    fn reading_events_works() {
+        let input = b"d1:Ali1e0:e1:Bdee";
+        let events: Result<Vec<_>, _> = BencodingReader::new(input).collect();
+        let expected = vec![
+            Event::DictStart,
+            Event::DictKey(b"A"),
+            Event::ListStart,
+            Event::Int(1),
+            Event::ByteString(b""),
+            Event::End,
+            Event::DictKey(b"B"),
+            Event::DictStart,
+            Event::End,
+            Event::End,
+        ];
+        assert_eq!(Ok(expected), events);
+    }
+
+    #[test]
+    fn skipping_values_works() {
+        let input = b"d6:piecesl3:AAA3:BBBe4:name4:showe";
+        let mut reader = BencodingReader::new(input);
+        assert_eq!(Some(Ok(Event::DictStart)), reader.next());
+        assert_eq!(Some(Ok(Event::DictKey(b"pieces"))), reader.next());
+        assert_eq!(9, reader.offset());
+        assert_eq!(Ok(()), reader.skip_value());
+        assert_eq!(21, reader.offset());
+        assert_eq!(Some(Ok(Event::DictKey(b"name"))), reader.next());
+        assert_eq!(Some(Ok(Event::ByteString(b"show"))), reader.next());
+        assert_eq!(Some(Ok(Event::End)), reader.next());
+        assert_eq!(None, reader.next());
+    }
+
+    #[test]
+    fn reading_malformed_input_fails() {
+        let events: Vec<_> = BencodingReader::new(b"li1e").collect();
+        assert!(events.last().unwrap().is_err());
+        let events: Vec<_> = BencodingReader::new(b"di1ei2ee").collect();
+        assert!(events.last().unwrap().is_err());
+        let events: Vec<_> = BencodingReader::new(b"d1:Ae").collect();
+        assert!(events.last().unwrap().is_err());
+    }
+
+    #[test]
+    fn parsing_overflowing_integers_fails() {
+        assert!(Bencoding::decode(b"i99999999999999999999e").is_err());
+        assert!(Bencoding::decode(b"99999999999999999999:A").is_err());
+        assert!(Bencoding::decode(b"9223372036854775807:A").is_err());
+        assert_eq!(
+            Err(BencodingError::IntegerOverflow { offset: 1 }),
+            Bencoding::decode(b"i9223372036854775808e")
+        );
+        // Only the digits past the first 18 are checked for overflow
+        assert_eq!(
+            Ok(Bencoding::Int(i64::MAX)),
+            Bencoding::decode(b"i9223372036854775807e")
+        );
+        assert_eq!(
+            Ok(Bencoding::Int(-999_999_999_999_999_999)),
+            Bencoding::decode(b"i-999999999999999999e")
+        );
+        assert_eq!(
+            Ok(Bencoding::Int(i64::MIN)),
+            Bencoding::decode(b"i-9223372036854775808e")
+        );
+        assert_eq!(
+            Err(BencodingError::IntegerOverflow { offset: 2 }),
+            Bencoding::decode(b"i-9223372036854775809e")
+        );
+        assert_eq!(
+            Ok(Bencoding::Int(12)),
+            Bencoding::decode(b"i000000000000000000000012e")
+        );
+    }
+
+    #[test]
+    fn parsing_oversized_lengths_fails_early() {
+        // We shouldn't need to look for the `:` to know this string doesn't fit
+        assert_eq!(
+            Err(BencodingError::InvalidLength {
+                offset: 1,
+                length: 4_000_000_000
+            }),
+            Bencoding::decode(b"l4000000000")
+        );
+        assert_eq!(
+            Err(BencodingError::InvalidLength {
+                offset: 0,
+                length: 3
+            }),
+            Bencoding::decode(b"3:ab")
+        );
+    }
+
+    #[test]
+    fn parsing_deeply_nested_lists_fails() {
+        let mut input = vec![b'l'; 100_000];
+        input.extend(vec![b'e'; 100_000]);
+        assert!(Bencoding::decode(&input).is_err());
+    }
+
+    #[test]
+    fn parsing_respects_limits() {
+        let options = ParseOptions {
+            max_depth: 5_000,
+            ..ParseOptions::default()
+        };
+        let mut input = vec![b'l'; 5_000];
+        input.extend(vec![b'e'; 5_000]);
+        assert!(Bencoding::decode_with(&input, &options).is_ok());
+
+        let options = ParseOptions {
+            max_depth: 1,
+            max_elements: 3,
+            max_string_length: 2,
+            strict: false,
+        };
+        assert!(Bencoding::decode_with(b"d2:abi1ee", &options).is_ok());
+        assert_eq!(
+            Err(BencodingError::TooDeep {
+                offset: 1,
+                limit: 1
+            }),
+            Bencoding::decode_with(b"lleee", &options)
+        );
+        assert_eq!(
+            Err(BencodingError::TooManyElements {
+                offset: 7,
+                limit: 3
+            }),
+            Bencoding::decode_with(b"li1ei2ei3ee", &options)
+        );
+        assert_eq!(
+            Err(BencodingError::StringTooLong {
+                offset: 1,
+                length: 3,
+                limit: 2
+            }),
+            Bencoding::decode_with(b"d3:abci1ee", &options)
+        );
+    }
+
+    #[test]
+    fn parsing_errors_contain_context() {
+        let expected = BencodingError::UnexpectedByte {
+            offset: 6,
+            expected: Expected::Byte(b'e'),
+            found: b'x',
+        };
+        assert_eq!(Err(expected), Bencoding::decode(b"li1ei2x"));
+        let expected = BencodingError::UnexpectedEof {
+            offset: 4,
+            expected: Expected::Element,
+        };
+        assert_eq!(Err(expected), Bencoding::decode(b"d1:A"));
+        let expected = BencodingError::InvalidLength {
+            offset: 0,
+            length: 5,
+        };
+        assert_eq!(Err(expected), Bencoding::decode(b"5:AB"));
+        let expected = BencodingError::TrailingData { offset: 3 };
+        assert_eq!(Err(expected), Bencoding::decode(b"i1ei2e"));
+    }
+
+    #[test]
+    fn parsing_unterminated_lists_fails() {
+        assert!(Bencoding::decode(b"li1ei2e").is_err());
+        assert!(Bencoding::decode(b"l4:abce").is_err());
+    }
 }
@@ -0,0 +1,310 @@
+//! This module contains a decoding mode that reuses its memory between inputs.
+//!
+//! Decoding into `Bencoding`, or even `BencodingRef`, allocates at least once for
+//! each list and dictionary. When decoding many files in a row, like an indexer
+//! going through a large collection of torrents, these allocations can end up
+//! dominating the time spent.
+//!
+//! Instead, an `Arena` stores every element in a single flat buffer, with each
+//! list or dictionary followed by its contents. Byte strings stay in the input,
+//! and are only referred to by their position. Once an arena has grown large
+//! enough for the inputs it sees, decoding into it doesn't allocate at all.
+use super::{parse_from, Bencoding, BencodingError, Builder, Lexer, ParseOptions};
+use std::ops::Range;
+
+// A single element in the flat buffer of an arena.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Slot {
+    Int(i64),
+    ByteString { start: usize, end: usize },
+    // Containers store the index just past their last descendant, which is also
+    // where their next sibling starts.
+    List { next: usize },
+    Dict { next: usize },
+}
+
+/// Holds decoded elements, reusing its memory between decodings.
+///
+/// Data is decoded into an arena with `Bencoding::decode_in`.
+#[derive(Clone, Debug, Default)]
+pub struct Arena {
+    slots: Vec<Slot>,
+    // The containers we're inside of while decoding.
+    stack: Vec<Open>,
+}
+
+// A container we're in the middle of decoding.
+#[derive(Clone, Debug)]
+struct Open {
+    slot: usize,
+    // The position of the last key we saw, if this is a dictionary.
+    last_key: Option<Range<usize>>,
+}
+
+impl Arena {
+    /// Create a new arena, without allocating any memory yet.
+    pub fn new() -> Self {
+        Arena::default()
+    }
+}
+
+/// Represents an element decoded into an arena.
+///
+/// This borrows both the arena and the input, and is cheap to copy around.
+#[derive(Clone, Copy, Debug)]
+pub struct ArenaNode<'r> {
+    slots: &'r [Slot],
+    input: &'r [u8],
+    index: usize,
+}
+
+impl<'r> ArenaNode<'r> {
+    // The index of the slot following this element and all of its contents.
+    fn next(&self) -> usize {
+        match self.slots[self.index] {
+            Slot::List { next } | Slot::Dict { next } => next,
+            _ => self.index + 1,
+        }
+    }
+
+    fn child(&self, index: usize) -> ArenaNode<'r> {
+        ArenaNode { index, ..*self }
+    }
+
+    // Iterate over the direct children of a container, or nothing for other elements.
+    fn children(&self) -> impl Iterator<Item = ArenaNode<'r>> {
+        let node = *self;
+        let end = match self.slots[self.index] {
+            Slot::List { next } | Slot::Dict { next } => next,
+            _ => self.index + 1,
+        };
+        let mut index = self.index + 1;
+        std::iter::from_fn(move || {
+            if index >= end {
+                return None;
+            }
+            let child = node.child(index);
+            index = child.next();
+            Some(child)
+        })
+    }
+
+    /// Get the value of this element, if it's an integer.
+    pub fn as_int(&self) -> Option<i64> {
+        match self.slots[self.index] {
+            Slot::Int(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    /// Get the bytes of this element, if it's a byte string.
+    pub fn as_bytes(&self) -> Option<&'r [u8]> {
+        match self.slots[self.index] {
+            Slot::ByteString { start, end } => Some(&self.input[start..end]),
+            _ => None,
+        }
+    }
+
+    /// Check whether or not this element is a list.
+    pub fn is_list(&self) -> bool {
+        matches!(self.slots[self.index], Slot::List { .. })
+    }
+
+    /// Check whether or not this element is a dictionary.
+    pub fn is_dict(&self) -> bool {
+        matches!(self.slots[self.index], Slot::Dict { .. })
+    }
+
+    /// Iterate over the items of this element, if it's a list.
+    pub fn items(&self) -> Option<impl Iterator<Item = ArenaNode<'r>>> {
+        if self.is_list() {
+            Some(self.children())
+        } else {
+            None
+        }
+    }
+
+    /// Iterate over the entries of this element, in order, if it's a dictionary.
+    pub fn entries(&self) -> Option<impl Iterator<Item = (&'r [u8], ArenaNode<'r>)>> {
+        if !self.is_dict() {
+            return None;
+        }
+        let mut children = self.children();
+        Some(std::iter::from_fn(move || {
+            let key = children.next()?.as_bytes()?;
+            Some((key, children.next()?))
+        }))
+    }
+
+    /// Look up the value of a key, if this element is a dictionary containing it.
+    ///
+    /// This is a linear search, and the last value is returned for duplicate keys.
+    pub fn get(&self, key: &[u8]) -> Option<ArenaNode<'r>> {
+        self.entries()?
+            .filter(|(k, _)| *k == key)
+            .map(|(_, v)| v)
+            .last()
+    }
+
+    /// Copy this element out of the arena, into the owned representation of bencoding.
+    pub fn to_bencoding(&self) -> Bencoding {
+        match self.slots[self.index] {
+            Slot::Int(i) => Bencoding::Int(i),
+            Slot::ByteString { start, end } => Bencoding::ByteString(self.input[start..end].into()),
+            Slot::List { .. } => {
+                Bencoding::List(self.children().map(|c| c.to_bencoding()).collect())
+            }
+            Slot::Dict { .. } => Bencoding::Dict(
+                self.entries()
+                    .into_iter()
+                    .flatten()
+                    .map(|(k, v)| (k, v.to_bencoding()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl Bencoding {
+    /// Decode a sequence of bytes into an arena, clearing whatever it held before.
+    ///
+    /// The limits and strictness of the default `ParseOptions` apply.
+    pub fn decode_in<'r>(
+        arena: &'r mut Arena,
+        input: &'r [u8],
+    ) -> Result<ArenaNode<'r>, BencodingError> {
+        Bencoding::decode_in_with(arena, input, &ParseOptions::default())
+    }
+
+    /// Decode a sequence of bytes into an arena, with custom limits on what we accept.
+    pub fn decode_in_with<'r>(
+        arena: &'r mut Arena,
+        input: &'r [u8],
+        options: &ParseOptions,
+    ) -> Result<ArenaNode<'r>, BencodingError> {
+        fill(arena, input, options)?;
+        Ok(ArenaNode {
+            slots: &arena.slots,
+            input,
+            index: 0,
+        })
+    }
+}
+
+// Lays elements out in the flat buffer of an arena, as they get decoded.
+struct Fill<'r> {
+    slots: &'r mut Vec<Slot>,
+    input: &'r [u8],
+}
+
+impl<'r> Builder<&'r [u8]> for Fill<'r> {
+    // Elements are already in the buffer once they're complete.
+    type Node = ();
+    type Frame = Open;
+
+    fn int(&mut self, int: i64, _span: Range<usize>) {
+        self.slots.push(Slot::Int(int));
+    }
+
+    fn bytestring(&mut self, bytes: &'r [u8], span: Range<usize>) {
+        self.slots.push(Slot::ByteString {
+            start: span.end - bytes.len(),
+            end: span.end,
+        });
+    }
+
+    fn open(&mut self, dict: bool, _start: usize) -> Open {
+        self.slots.push(if dict {
+            Slot::Dict { next: 0 }
+        } else {
+            Slot::List { next: 0 }
+        });
+        Open {
+            slot: self.slots.len() - 1,
+            last_key: None,
+        }
+    }
+
+    fn is_dict(&self, open: &Open) -> bool {
+        matches!(self.slots[open.slot], Slot::Dict { .. })
+    }
+
+    fn last_key<'f>(&'f self, open: &'f Open) -> Option<&'f [u8]> {
+        open.last_key.clone().map(|key| &self.input[key])
+    }
+
+    fn key(&mut self, open: &mut Open, key: &'r [u8], span: Range<usize>) {
+        let key = span.end - key.len()..span.end;
+        self.slots.push(Slot::ByteString {
+            start: key.start,
+            end: key.end,
+        });
+        open.last_key = Some(key);
+    }
+
+    fn push(&mut self, _open: &mut Open, _node: ()) {}
+
+    fn close(&mut self, open: Open, _end: usize) {
+        let end = self.slots.len();
+        if let Slot::List { next } | Slot::Dict { next } = &mut self.slots[open.slot] {
+            *next = end;
+        }
+    }
+}
+
+// Decode an input into the flat buffer of an arena.
+fn fill(arena: &mut Arena, input: &[u8], options: &ParseOptions) -> Result<(), BencodingError> {
+    arena.slots.clear();
+    let mut lexer = Lexer::new(input);
+    lexer.strict = options.strict;
+    let mut builder = Fill {
+        slots: &mut arena.slots,
+        input,
+    };
+    parse_from(&mut lexer, &mut builder, options, &mut arena.stack)?;
+    if lexer.pos < input.len() {
+        return Err(BencodingError::TrailingData { offset: lexer.pos });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decoding_into_an_arena_works() {
+        let mut arena = Arena::new();
+        let input = b"d4:infod5:filesli1ei-2ee4:name3:dire3:zzz0:1:ai3ee";
+        let root = Bencoding::decode_in(&mut arena, input).unwrap();
+        assert_eq!(Bencoding::decode(input), Ok(root.to_bencoding()));
+        let info = root.get(b"info").unwrap();
+        let files: Vec<_> = info.get(b"files").unwrap().items().unwrap().collect();
+        assert_eq!(Some(-2), files[1].as_int());
+        assert_eq!(Some(&b"dir"[..]), info.get(b"name").unwrap().as_bytes());
+        assert_eq!(Some(3), root.get(b"a").unwrap().as_int());
+        assert!(root.get(b"b").is_none());
+        let keys: Vec<_> = root.entries().unwrap().map(|(k, _)| k).collect();
+        assert_eq!(vec![&b"info"[..], b"zzz", b"a"], keys);
+    }
+
+    #[test]
+    fn arenas_can_be_reused() {
+        let mut arena = Arena::new();
+        assert!(Bencoding::decode_in(&mut arena, b"l1:ae").is_ok());
+        let root = Bencoding::decode_in(&mut arena, b"i5e").unwrap();
+        assert_eq!(Some(5), root.as_int());
+        assert_eq!(
+            Err(BencodingError::TrailingData { offset: 2 }),
+            Bencoding::decode_in(&mut arena, b"lel").map(|_| ())
+        );
+        let options = ParseOptions {
+            strict: true,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            Err(BencodingError::UnsortedKey { offset: 7 }),
+            Bencoding::decode_in_with(&mut arena, b"d1:bi1e1:ai2ee", &options).map(|_| ())
+        );
+    }
+}
@@ -0,0 +1,495 @@
+//! This module converts bencoded data to and from CBOR and MessagePack.
+//!
+//! Both formats have a direct equivalent for each kind of element:
+//!
+//! - Integers become integers, using the smallest encoding that fits.
+//! - Byte strings become binary strings, since they aren't necessarily UTF-8.
+//! - Lists become arrays, and dictionaries become maps with binary string keys.
+//!
+//! Dictionary entries are written in their original order, duplicates included,
+//! so converting bencoding and back again always produces the data we started with.
+//! When converting back, text strings are accepted wherever binary strings are,
+//! but values with no equivalent in bencoding, like floats or `null`, are rejected.
+//!
+//! CBOR support is enabled by the `cbor` feature, and MessagePack support by the
+//! `msgpack` feature. Both are enabled by default.
+use super::{Bencoding, Dict};
+use std::{convert::TryFrom, error, fmt};
+
+// How deeply arrays and maps can be nested before we give up.
+const MAX_DEPTH: usize = 512;
+
+/// Represents an error that occurs while converting CBOR or MessagePack into bencoding.
+///
+/// Each variant contains the offset, in bytes, into the input where conversion failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BinaryError {
+    /// The input ended in the middle of a value.
+    UnexpectedEof { offset: usize },
+    /// The input contains a value with no equivalent in bencoding.
+    ///
+    /// This is the case for floating point numbers, booleans, `null`, and tagged or
+    /// extension values, as well as map keys which aren't strings.
+    Unsupported { offset: usize },
+    /// An integer doesn't fit in 64 bits.
+    IntegerOverflow { offset: usize },
+    /// Arrays and maps are nested more deeply than we allow.
+    TooDeep { offset: usize },
+    /// There's more data after the end of the value.
+    TrailingData { offset: usize },
+}
+
+impl BinaryError {
+    /// The offset, in bytes, into the input where conversion failed.
+    pub fn offset(&self) -> usize {
+        use BinaryError::*;
+        match *self {
+            UnexpectedEof { offset }
+            | Unsupported { offset }
+            | IntegerOverflow { offset }
+            | TooDeep { offset }
+            | TrailingData { offset } => offset,
+        }
+    }
+}
+
+impl error::Error for BinaryError {}
+
+impl fmt::Display for BinaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use BinaryError::*;
+        write!(f, "at byte {}: ", self.offset())?;
+        match self {
+            UnexpectedEof { .. } => write!(f, "unexpected end of input"),
+            Unsupported { .. } => write!(f, "value can't be represented in bencoding"),
+            IntegerOverflow { .. } => write!(f, "integer doesn't fit in 64 bits"),
+            TooDeep { .. } => write!(f, "values are nested more than {} deep", MAX_DEPTH),
+            TrailingData { .. } => write!(f, "unexpected data after the end of the value"),
+        }
+    }
+}
+
+type BinaryResult<T> = Result<T, BinaryError>;
+
+// Reads the big endian pieces that both formats are made of.
+struct Reader<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> BinaryResult<u8> {
+        let byte = *self
+            .input
+            .get(self.pos)
+            .ok_or(BinaryError::UnexpectedEof { offset: self.pos })?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn take(&mut self, count: u64) -> BinaryResult<&'a [u8]> {
+        let count = self.length(count)?;
+        let slice = &self.input[self.pos..self.pos + count];
+        self.pos += count;
+        Ok(slice)
+    }
+
+    // Read an unsigned integer spread over a given number of bytes.
+    fn uint(&mut self, size: u64) -> BinaryResult<u64> {
+        Ok(self
+            .take(size)?
+            .iter()
+            .fold(0, |acc, &b| (acc << 8) | u64::from(b)))
+    }
+
+    // Check that a number of bytes, or of elements, can fit in the rest of the input.
+    //
+    // Each element takes up at least a byte, so this stops a bogus length from making
+    // us allocate more memory than the input could ever fill.
+    fn length(&self, count: u64) -> BinaryResult<usize> {
+        match usize::try_from(count) {
+            Ok(count) if count <= self.input.len() - self.pos => Ok(count),
+            _ => Err(BinaryError::UnexpectedEof {
+                offset: self.input.len(),
+            }),
+        }
+    }
+
+    fn signed(&self, int: u64, offset: usize) -> BinaryResult<i64> {
+        i64::try_from(int).map_err(|_| BinaryError::IntegerOverflow { offset })
+    }
+
+    fn finish(&self) -> BinaryResult<()> {
+        if self.pos < self.input.len() {
+            return Err(BinaryError::TrailingData { offset: self.pos });
+        }
+        Ok(())
+    }
+}
+
+// Build a dictionary, making sure that every key is a string.
+fn dict_entry(
+    key: Bencoding,
+    value: Bencoding,
+    offset: usize,
+) -> BinaryResult<(Box<[u8]>, Bencoding)> {
+    match key {
+        Bencoding::ByteString(key) => Ok((key, value)),
+        _ => Err(BinaryError::Unsupported { offset }),
+    }
+}
+
+#[cfg(feature = "cbor")]
+mod cbor {
+    use super::*;
+
+    fn write_head(major: u8, arg: u64, out: &mut Vec<u8>) {
+        let major = major << 5;
+        match arg {
+            0..=23 => out.push(major | arg as u8),
+            24..=0xFF => out.extend_from_slice(&[major | 24, arg as u8]),
+            0x100..=0xFFFF => {
+                out.push(major | 25);
+                out.extend_from_slice(&(arg as u16).to_be_bytes());
+            }
+            0x1_0000..=0xFFFF_FFFF => {
+                out.push(major | 26);
+                out.extend_from_slice(&(arg as u32).to_be_bytes());
+            }
+            _ => {
+                out.push(major | 27);
+                out.extend_from_slice(&arg.to_be_bytes());
+            }
+        }
+    }
+
+    pub(super) fn write_element(bencoding: &Bencoding, out: &mut Vec<u8>) {
+        match bencoding {
+            Bencoding::Int(i) if *i >= 0 => write_head(0, *i as u64, out),
+            // Negative integers are stored as -1 - n
+            Bencoding::Int(i) => write_head(1, !*i as u64, out),
+            Bencoding::ByteString(bytes) => {
+                write_head(2, bytes.len() as u64, out);
+                out.extend_from_slice(bytes);
+            }
+            Bencoding::List(items) => {
+                write_head(4, items.len() as u64, out);
+                for item in items.iter() {
+                    write_element(item, out);
+                }
+            }
+            Bencoding::Dict(dict) => {
+                write_head(5, dict.len() as u64, out);
+                for (key, value) in dict.iter() {
+                    write_head(2, key.len() as u64, out);
+                    out.extend_from_slice(key);
+                    write_element(value, out);
+                }
+            }
+        }
+    }
+
+    pub(super) fn read_element(reader: &mut Reader, depth: usize) -> BinaryResult<Bencoding> {
+        let start = reader.pos;
+        let head = reader.byte()?;
+        let arg = match head & 0x1F {
+            info @ 0..=23 => u64::from(info),
+            24 => reader.uint(1)?,
+            25 => reader.uint(2)?,
+            26 => reader.uint(4)?,
+            27 => reader.uint(8)?,
+            // Indefinite lengths, and reserved values
+            _ => return Err(BinaryError::Unsupported { offset: start }),
+        };
+        match head >> 5 {
+            0 => Ok(Bencoding::Int(reader.signed(arg, start)?)),
+            1 => Ok(Bencoding::Int(!reader.signed(arg, start)?)),
+            2 | 3 => Ok(Bencoding::ByteString(reader.take(arg)?.into())),
+            major @ 4 | major @ 5 => {
+                if depth >= MAX_DEPTH {
+                    return Err(BinaryError::TooDeep { offset: start });
+                }
+                let count = reader.length(arg)?;
+                if major == 4 {
+                    let mut items = Vec::with_capacity(count);
+                    for _ in 0..count {
+                        items.push(read_element(reader, depth + 1)?);
+                    }
+                    return Ok(Bencoding::List(items.into_boxed_slice()));
+                }
+                let mut entries = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let offset = reader.pos;
+                    let key = read_element(reader, depth + 1)?;
+                    let value = read_element(reader, depth + 1)?;
+                    entries.push(dict_entry(key, value, offset)?);
+                }
+                Ok(Bencoding::Dict(entries.into_iter().collect::<Dict>()))
+            }
+            // Tags, floats, and simple values like booleans
+            _ => Err(BinaryError::Unsupported { offset: start }),
+        }
+    }
+}
+
+#[cfg(feature = "msgpack")]
+mod msgpack {
+    use super::*;
+
+    // Write a length, using the fixed size marker if there is one, and a 16 or 32 bit length otherwise.
+    fn write_length(len: usize, fixed: Option<u8>, markers: [u8; 3], out: &mut Vec<u8>) {
+        match (fixed, len) {
+            (Some(fixed), 0..=15) => out.push(fixed | len as u8),
+            (_, 0..=0xFF) if markers[0] != 0 => out.extend_from_slice(&[markers[0], len as u8]),
+            (_, 0..=0xFFFF) => {
+                out.push(markers[1]);
+                out.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            _ => {
+                let len = u32::try_from(len).expect("MessagePack lengths can't exceed 32 bits");
+                out.push(markers[2]);
+                out.extend_from_slice(&len.to_be_bytes());
+            }
+        }
+    }
+
+    fn write_bytes(bytes: &[u8], out: &mut Vec<u8>) {
+        write_length(bytes.len(), None, [0xC4, 0xC5, 0xC6], out);
+        out.extend_from_slice(bytes);
+    }
+
+    fn write_int(i: i64, out: &mut Vec<u8>) {
+        match i {
+            0..=0x7F => out.push(i as u8),
+            -32..=-1 => out.push(i as u8),
+            0x80..=0xFF => out.extend_from_slice(&[0xCC, i as u8]),
+            0x100..=0xFFFF => {
+                out.push(0xCD);
+                out.extend_from_slice(&(i as u16).to_be_bytes());
+            }
+            0x1_0000..=0xFFFF_FFFF => {
+                out.push(0xCE);
+                out.extend_from_slice(&(i as u32).to_be_bytes());
+            }
+            _ if i > 0 => {
+                out.push(0xCF);
+                out.extend_from_slice(&(i as u64).to_be_bytes());
+            }
+            -0x80..=-33 => out.extend_from_slice(&[0xD0, i as u8]),
+            -0x8000..=-0x81 => {
+                out.push(0xD1);
+                out.extend_from_slice(&(i as i16).to_be_bytes());
+            }
+            -0x8000_0000..=-0x8001 => {
+                out.push(0xD2);
+                out.extend_from_slice(&(i as i32).to_be_bytes());
+            }
+            _ => {
+                out.push(0xD3);
+                out.extend_from_slice(&i.to_be_bytes());
+            }
+        }
+    }
+
+    pub(super) fn write_element(bencoding: &Bencoding, out: &mut Vec<u8>) {
+        match bencoding {
+            Bencoding::Int(i) => write_int(*i, out),
+            Bencoding::ByteString(bytes) => write_bytes(bytes, out),
+            Bencoding::List(items) => {
+                write_length(items.len(), Some(0x90), [0, 0xDC, 0xDD], out);
+                for item in items.iter() {
+                    write_element(item, out);
+                }
+            }
+            Bencoding::Dict(dict) => {
+                write_length(dict.len(), Some(0x80), [0, 0xDE, 0xDF], out);
+                for (key, value) in dict.iter() {
+                    write_bytes(key, out);
+                    write_element(value, out);
+                }
+            }
+        }
+    }
+
+    pub(super) fn read_element(reader: &mut Reader, depth: usize) -> BinaryResult<Bencoding> {
+        let start = reader.pos;
+        let marker = reader.byte()?;
+        let (is_list, count) = match marker {
+            0x00..=0x7F => return Ok(Bencoding::Int(i64::from(marker))),
+            0xE0..=0xFF => return Ok(Bencoding::Int(i64::from(marker as i8))),
+            0xCC..=0xCF => {
+                let int = reader.uint(1 << (marker - 0xCC))?;
+                return Ok(Bencoding::Int(reader.signed(int, start)?));
+            }
+            0xD0..=0xD3 => {
+                let size = 1 << (marker - 0xD0);
+                // Sign extend the integer, by shifting its sign bit into place and back
+                let shift = 64 - 8 * size;
+                let int = ((reader.uint(size)? << shift) as i64) >> shift;
+                return Ok(Bencoding::Int(int));
+            }
+            0xA0..=0xBF => {
+                return Ok(Bencoding::ByteString(
+                    reader.take(u64::from(marker & 0x1F))?.into(),
+                ))
+            }
+            0xC4 | 0xD9 => {
+                let len = reader.uint(1)?;
+                return Ok(Bencoding::ByteString(reader.take(len)?.into()));
+            }
+            0xC5 | 0xDA => {
+                let len = reader.uint(2)?;
+                return Ok(Bencoding::ByteString(reader.take(len)?.into()));
+            }
+            0xC6 | 0xDB => {
+                let len = reader.uint(4)?;
+                return Ok(Bencoding::ByteString(reader.take(len)?.into()));
+            }
+            0x90..=0x9F => (true, u64::from(marker & 0x0F)),
+            0x80..=0x8F => (false, u64::from(marker & 0x0F)),
+            0xDC => (true, reader.uint(2)?),
+            0xDD => (true, reader.uint(4)?),
+            0xDE => (false, reader.uint(2)?),
+            0xDF => (false, reader.uint(4)?),
+            // Nil, booleans, floats, and extensions
+            _ => return Err(BinaryError::Unsupported { offset: start }),
+        };
+        if depth >= MAX_DEPTH {
+            return Err(BinaryError::TooDeep { offset: start });
+        }
+        let count = reader.length(count)?;
+        if is_list {
+            let mut items = Vec::with_capacity(count);
+            for _ in 0..count {
+                items.push(read_element(reader, depth + 1)?);
+            }
+            return Ok(Bencoding::List(items.into_boxed_slice()));
+        }
+        let mut entries = Vec::with_capacity(count);
+        for _ in 0..count {
+            let offset = reader.pos;
+            let key = read_element(reader, depth + 1)?;
+            let value = read_element(reader, depth + 1)?;
+            entries.push(dict_entry(key, value, offset)?);
+        }
+        Ok(Bencoding::Dict(entries.into_iter().collect::<Dict>()))
+    }
+}
+
+impl Bencoding {
+    /// Convert this data into CBOR, following the conventions of this module.
+    #[cfg(feature = "cbor")]
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        cbor::write_element(self, &mut out);
+        out
+    }
+
+    /// Convert CBOR back into bencoding, following the conventions of this module.
+    #[cfg(feature = "cbor")]
+    pub fn from_cbor(input: &[u8]) -> Result<Bencoding, BinaryError> {
+        let mut reader = Reader { input, pos: 0 };
+        let bencoding = cbor::read_element(&mut reader, 0)?;
+        reader.finish()?;
+        Ok(bencoding)
+    }
+
+    /// Convert this data into MessagePack, following the conventions of this module.
+    ///
+    /// # Panics
+    ///
+    /// MessagePack can't represent strings, lists, or dictionaries with more than
+    /// `u32::MAX` elements, so this panics if we come across one.
+    #[cfg(feature = "msgpack")]
+    pub fn to_msgpack(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        msgpack::write_element(self, &mut out);
+        out
+    }
+
+    /// Convert MessagePack back into bencoding, following the conventions of this module.
+    #[cfg(feature = "msgpack")]
+    pub fn from_msgpack(input: &[u8]) -> Result<Bencoding, BinaryError> {
+        let mut reader = Reader { input, pos: 0 };
+        let bencoding = msgpack::read_element(&mut reader, 0)?;
+        reader.finish()?;
+        Ok(bencoding)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sample() -> Bencoding {
+        Bencoding::decode(
+            b"d1:zli0ei23ei24ei-1ei-24ei-25ei255ei256ei65536ei-9223372036854775807e\
+              i9223372036854775807ei-32ei-33ei-129ei-40000ee1:a2:\xff\x001:ad0:le1:bi1eee",
+        )
+        .unwrap()
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn cbor_conversion_is_lossless() {
+        let bencoding = sample();
+        let cbor = bencoding.to_cbor();
+        let back = Bencoding::from_cbor(&cbor).unwrap();
+        assert_eq!(bencoding.encode_in_order(), back.encode_in_order());
+        assert_eq!(
+            vec![0x82, 0x17, 0x38, 0x18],
+            Bencoding::from(vec![Bencoding::Int(23), Bencoding::Int(-25)]).to_cbor()
+        );
+        // A text string, and the half precision float 1.0
+        assert_eq!(
+            Ok(Bencoding::from(vec![Bencoding::from("a")])),
+            Bencoding::from_cbor(&[0x81, 0x61, b'a'])
+        );
+        assert_eq!(
+            Err(BinaryError::Unsupported { offset: 0 }),
+            Bencoding::from_cbor(&[0xF9, 0x3C, 0x00])
+        );
+        assert_eq!(
+            Err(BinaryError::IntegerOverflow { offset: 0 }),
+            Bencoding::from_cbor(&[0x1B, 0x80, 0, 0, 0, 0, 0, 0, 0])
+        );
+        assert_eq!(
+            Err(BinaryError::UnexpectedEof { offset: 5 }),
+            Bencoding::from_cbor(&[0x9A, 0xFF, 0xFF, 0xFF, 0xFF])
+        );
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn msgpack_conversion_is_lossless() {
+        let bencoding = sample();
+        let msgpack = bencoding.to_msgpack();
+        let back = Bencoding::from_msgpack(&msgpack).unwrap();
+        assert_eq!(bencoding.encode_in_order(), back.encode_in_order());
+        assert_eq!(
+            vec![0x93, 0x7F, 0xE0, 0xD0, 0xDF],
+            Bencoding::from(vec![
+                Bencoding::Int(127),
+                Bencoding::Int(-32),
+                Bencoding::Int(-33)
+            ])
+            .to_msgpack()
+        );
+        assert_eq!(
+            Ok(Bencoding::from(vec![Bencoding::from("a")])),
+            Bencoding::from_msgpack(&[0x91, 0xA1, b'a'])
+        );
+        assert_eq!(
+            Err(BinaryError::Unsupported { offset: 1 }),
+            Bencoding::from_msgpack(&[0x81, 0x01, 0x01])
+        );
+        assert_eq!(
+            Err(BinaryError::Unsupported { offset: 0 }),
+            Bencoding::from_msgpack(&[0xC0])
+        );
+        assert_eq!(
+            Err(BinaryError::TrailingData { offset: 1 }),
+            Bencoding::from_msgpack(&[0x01, 0x01])
+        );
+    }
+}
@@ -0,0 +1,156 @@
+//! This module makes it easy to construct bencoded data by hand.
+//!
+//! Most Rust values convert into `Bencoding` through `From`, and the builders
+//! in this module assemble those values into lists and dictionaries.
+//! Binary strings are passed in as a `Vec<u8>`, since a `&[u8]` converts by being decoded:
+//!
+//! ```
+//! use typhoon::bencoding::{Bencoding, DictBuilder, ListBuilder};
+//!
+//! let response = DictBuilder::new()
+//!     .insert("peers", b"\x7f\x00\x00\x01\x1a\xe1".to_vec())
+//!     .insert("interval", 1800)
+//!     .insert("tiers", ListBuilder::new().push("udp://a:1").push("udp://b:1"))
+//!     .build();
+//! assert_eq!(
+//!     b"d8:intervali1800e5:peers6:\x7f\x00\x00\x01\x1a\xe15:tiersl9:udp://a:19:udp://b:1ee".to_vec(),
+//!     response.encode()
+//! );
+//! ```
+use super::{Bencoding, Dict};
+
+impl From<i64> for Bencoding {
+    fn from(int: i64) -> Self {
+        Bencoding::Int(int)
+    }
+}
+
+impl From<Vec<u8>> for Bencoding {
+    fn from(bytes: Vec<u8>) -> Self {
+        Bencoding::ByteString(bytes.into_boxed_slice())
+    }
+}
+
+impl From<&str> for Bencoding {
+    fn from(string: &str) -> Self {
+        Bencoding::ByteString(string.as_bytes().into())
+    }
+}
+
+impl From<String> for Bencoding {
+    fn from(string: String) -> Self {
+        string.into_bytes().into()
+    }
+}
+
+impl From<Vec<Bencoding>> for Bencoding {
+    fn from(items: Vec<Bencoding>) -> Self {
+        Bencoding::List(items.into_boxed_slice())
+    }
+}
+
+impl From<Dict> for Bencoding {
+    fn from(dict: Dict) -> Self {
+        Bencoding::Dict(dict)
+    }
+}
+
+/// Builds up a bencoded list, one item at a time.
+#[derive(Clone, Debug, Default)]
+pub struct ListBuilder {
+    items: Vec<Bencoding>,
+}
+
+impl ListBuilder {
+    /// Create a builder for an empty list.
+    pub fn new() -> Self {
+        ListBuilder::default()
+    }
+
+    /// Add an item to the end of the list.
+    pub fn push(mut self, item: impl Into<Bencoding>) -> Self {
+        self.items.push(item.into());
+        self
+    }
+
+    /// Finish building the list.
+    pub fn build(self) -> Bencoding {
+        self.items.into()
+    }
+}
+
+impl From<ListBuilder> for Bencoding {
+    fn from(builder: ListBuilder) -> Self {
+        builder.build()
+    }
+}
+
+/// Builds up a bencoded dictionary, one entry at a time.
+///
+/// Entries can be inserted in any order, but the dictionary we build always has
+/// its keys sorted, as the specification requires. Inserting the same key twice
+/// keeps the last value.
+#[derive(Clone, Debug, Default)]
+pub struct DictBuilder {
+    entries: Vec<(Box<[u8]>, Bencoding)>,
+}
+
+impl DictBuilder {
+    /// Create a builder for an empty dictionary.
+    pub fn new() -> Self {
+        DictBuilder::default()
+    }
+
+    /// Add an entry to the dictionary.
+    pub fn insert(mut self, key: impl AsRef<[u8]>, value: impl Into<Bencoding>) -> Self {
+        self.entries.push((key.as_ref().into(), value.into()));
+        self
+    }
+
+    /// Finish building the dictionary.
+    pub fn build(mut self) -> Bencoding {
+        // The sort is stable, so the last value for each key comes last
+        self.entries.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut sorted: Vec<(Box<[u8]>, Bencoding)> = Vec::with_capacity(self.entries.len());
+        for (key, value) in self.entries {
+            match sorted.last_mut() {
+                Some(last) if last.0 == key => last.1 = value,
+                _ => sorted.push((key, value)),
+            }
+        }
+        Bencoding::Dict(sorted.into_iter().collect())
+    }
+}
+
+impl From<DictBuilder> for Bencoding {
+    fn from(builder: DictBuilder) -> Self {
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn building_dicts_sorts_their_keys() {
+        let bencoding = DictBuilder::new()
+            .insert("b", 1)
+            .insert(b"a", "x")
+            .insert("b", 2)
+            .build();
+        let keys: Vec<_> = bencoding.as_dict().unwrap().keys().collect();
+        assert_eq!(vec![&b"a"[..], b"b"], keys);
+        assert_eq!(b"d1:a1:x1:bi2ee".to_vec(), bencoding.encode());
+    }
+
+    #[test]
+    fn building_lists_works() {
+        let bencoding = ListBuilder::new()
+            .push(-1)
+            .push(vec![0u8, 255])
+            .push(DictBuilder::new())
+            .build();
+        assert_eq!(b"li-1e2:\x00\xffdee".to_vec(), bencoding.encode());
+    }
+}
@@ -0,0 +1,158 @@
+//! This module compares two pieces of bencoded data, element by element.
+//!
+//! Differences are reported with the same paths that `Bencoding::lookup` accepts,
+//! like `info.files[0].length`. Dictionaries are compared key by key, and lists
+//! index by index, so inserting an item at the start of a list shows up as a change
+//! to every item after it.
+use super::Bencoding;
+use std::fmt;
+
+/// Represents a single difference between two bencoded elements.
+///
+/// The path of the root element is empty.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiffEntry<'b> {
+    /// An element only present in the second piece of data.
+    Added { path: String, value: &'b Bencoding },
+    /// An element only present in the first piece of data.
+    Removed { path: String, value: &'b Bencoding },
+    /// An element present in both pieces of data, but with a different value.
+    ///
+    /// This is only reported for integers, byte strings, and elements whose type changed,
+    /// since we look inside of lists and dictionaries instead.
+    Changed {
+        path: String,
+        old: &'b Bencoding,
+        new: &'b Bencoding,
+    },
+}
+
+impl<'b> DiffEntry<'b> {
+    /// The path of the element that differs.
+    pub fn path(&self) -> &str {
+        match self {
+            DiffEntry::Added { path, .. }
+            | DiffEntry::Removed { path, .. }
+            | DiffEntry::Changed { path, .. } => path,
+        }
+    }
+}
+
+impl<'b> fmt::Display for DiffEntry<'b> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = match self.path() {
+            "" => "root",
+            path => path,
+        };
+        match self {
+            DiffEntry::Added { .. } => write!(f, "+ {}", path),
+            DiffEntry::Removed { .. } => write!(f, "- {}", path),
+            DiffEntry::Changed { .. } => write!(f, "~ {}", path),
+        }
+    }
+}
+
+fn key_path(parent: &str, key: &[u8]) -> String {
+    let key = String::from_utf8_lossy(key);
+    if parent.is_empty() {
+        key.into_owned()
+    } else {
+        format!("{}.{}", parent, key)
+    }
+}
+
+fn walk<'b>(path: String, a: &'b Bencoding, b: &'b Bencoding, out: &mut Vec<DiffEntry<'b>>) {
+    match (a, b) {
+        (Bencoding::List(xs), Bencoding::List(ys)) => {
+            for (i, (x, y)) in xs.iter().zip(ys.iter()).enumerate() {
+                walk(format!("{}[{}]", path, i), x, y, out);
+            }
+            for (i, x) in xs.iter().enumerate().skip(ys.len()) {
+                out.push(DiffEntry::Removed {
+                    path: format!("{}[{}]", path, i),
+                    value: x,
+                });
+            }
+            for (i, y) in ys.iter().enumerate().skip(xs.len()) {
+                out.push(DiffEntry::Added {
+                    path: format!("{}[{}]", path, i),
+                    value: y,
+                });
+            }
+        }
+        (Bencoding::Dict(xs), Bencoding::Dict(ys)) => {
+            let mut keys: Vec<&[u8]> = xs.keys().chain(ys.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = key_path(&path, key);
+                match (xs.get(key), ys.get(key)) {
+                    (Some(x), Some(y)) => walk(path, x, y, out),
+                    (Some(x), None) => out.push(DiffEntry::Removed { path, value: x }),
+                    (None, Some(y)) => out.push(DiffEntry::Added { path, value: y }),
+                    (None, None) => unreachable!(),
+                }
+            }
+        }
+        _ if a != b => out.push(DiffEntry::Changed {
+            path,
+            old: a,
+            new: b,
+        }),
+        _ => {}
+    }
+}
+
+/// Find the differences between two bencoded elements.
+///
+/// Differences are returned with dictionary keys in sorted order, and nothing is
+/// returned if both elements are equal.
+pub fn diff<'b>(a: &'b Bencoding, b: &'b Bencoding) -> Vec<DiffEntry<'b>> {
+    let mut out = Vec::new();
+    walk(String::new(), a, b, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn diffing_reports_paths() {
+        let a = Bencoding::decode(b"d4:infod5:filesli1ei2ee4:name1:ae1:xi0e1:zi0ee").unwrap();
+        let b = Bencoding::decode(b"d4:infod5:filesli1ei3ei4ee4:name2:abe1:yi0e1:zi0ee").unwrap();
+        let paths: Vec<_> = diff(&a, &b).iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            vec![
+                "~ info.files[1]",
+                "+ info.files[2]",
+                "~ info.name",
+                "- x",
+                "+ y"
+            ],
+            paths
+        );
+        assert_eq!(
+            DiffEntry::Changed {
+                path: "info.name".into(),
+                old: a.lookup("info.name").unwrap(),
+                new: b.lookup("info.name").unwrap(),
+            },
+            diff(&a, &b)[2]
+        );
+    }
+
+    #[test]
+    fn diffing_equal_data_finds_nothing() {
+        let a = Bencoding::decode(b"d1:ai1e1:bi2ee").unwrap();
+        let b = Bencoding::decode(b"d1:bi2e1:ai1ee").unwrap();
+        assert!(diff(&a, &b).is_empty());
+        assert_eq!(
+            vec!["~ root"],
+            diff(&a, &Bencoding::Int(1))
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! This module contains helpers for modifying bencoded data in place.
+//!
+//! Dictionaries are modified through `Dict`, which is reachable with `as_dict_mut`.
+//! Combined with `Bencoding::encode_in_order`, this lets us change part of a file,
+//! like the trackers of a torrent, while leaving everything else as it was.
+use super::{Bencoding, Dict};
+
+impl Bencoding {
+    /// Get the entries of this element for modification, if it's a dictionary.
+    pub fn as_dict_mut(&mut self) -> Option<&mut Dict> {
+        match self {
+            Bencoding::Dict(dict) => Some(dict),
+            _ => None,
+        }
+    }
+
+    /// Get the items of this element for modification, if it's a list.
+    ///
+    /// This allows replacing items, but not changing how many there are;
+    /// see `insert_item` and `remove_item` for that.
+    pub fn as_list_mut(&mut self) -> Option<&mut [Bencoding]> {
+        match self {
+            Bencoding::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Insert an item into this element at a given index, if it's a list.
+    ///
+    /// The item is given back if this isn't a list, or if the index is past the end.
+    pub fn insert_item(&mut self, index: usize, item: Bencoding) -> Result<(), Bencoding> {
+        match self {
+            Bencoding::List(items) if index <= items.len() => {
+                let mut vec = std::mem::take(items).into_vec();
+                vec.insert(index, item);
+                *items = vec.into_boxed_slice();
+                Ok(())
+            }
+            _ => Err(item),
+        }
+    }
+
+    /// Remove the item at a given index, if this element is a list containing it.
+    pub fn remove_item(&mut self, index: usize) -> Option<Bencoding> {
+        match self {
+            Bencoding::List(items) if index < items.len() => {
+                let mut vec = std::mem::take(items).into_vec();
+                let item = vec.remove(index);
+                *items = vec.into_boxed_slice();
+                Some(item)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn editing_keeps_the_rest_of_the_file_intact() {
+        // The info dictionary isn't sorted, and contains a duplicate key
+        let input = b"d8:announce5:old:14:infod4:name1:a6:lengthi1e4:name1:bee";
+        let mut bencoding = Bencoding::decode(input).unwrap();
+        let root = bencoding.as_dict_mut().unwrap();
+        root.insert(&b"announce"[..], "new:1".into());
+        let mut list = Bencoding::List(Box::new([]));
+        list.insert_item(0, "udp://a:1".into()).unwrap();
+        root.insert(&b"announce-list"[..], list);
+        assert_eq!(
+            b"d8:announce5:new:14:infod4:name1:a6:lengthi1e4:name1:be13:announce-listl9:udp://a:1ee"
+                .to_vec(),
+            bencoding.encode_in_order()
+        );
+        assert_eq!(
+            b"d8:announce5:new:113:announce-listl9:udp://a:1e4:infod6:lengthi1e4:name1:bee"
+                .to_vec(),
+            bencoding.encode()
+        );
+    }
+
+    #[test]
+    fn removing_entries_works() {
+        let mut bencoding = Bencoding::decode(b"d1:ci3e1:ai1e1:bli1ei2eee").unwrap();
+        let dict = bencoding.as_dict_mut().unwrap();
+        assert_eq!(Some(Bencoding::Int(1)), dict.remove(b"a"));
+        assert_eq!(None, dict.remove(b"a"));
+        let list = dict.get_mut(b"b").unwrap();
+        assert_eq!(Some(Bencoding::Int(1)), list.remove_item(0));
+        assert_eq!(None, list.remove_item(1));
+        assert_eq!(
+            Err(Bencoding::Int(0)),
+            list.insert_item(2, Bencoding::Int(0))
+        );
+        list.as_list_mut().unwrap()[0] = Bencoding::Int(5);
+        assert_eq!(b"d1:ci3e1:bli5eee".to_vec(), bencoding.encode_in_order());
+        assert_eq!(Some(&Bencoding::Int(3)), bencoding.lookup("c").ok());
+    }
+}
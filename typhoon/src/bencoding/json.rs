@@ -0,0 +1,421 @@
+//! This module converts bencoded data to and from JSON.
+//!
+//! Integers, lists, and dictionaries map directly onto JSON numbers, arrays, and objects.
+//! Byte strings need more care, since JSON strings have to be valid unicode:
+//!
+//! - Byte strings that are valid UTF-8 become JSON strings, as is.
+//! - Other byte strings become a JSON string containing `hex:`, followed by their bytes
+//!   in hexadecimal. For example, the bytes `FF 00` become `"hex:ff00"`.
+//! - UTF-8 strings that already start with `hex:` are also hex encoded, so that they
+//!   can't be confused with binary strings.
+//!
+//! The same rules apply to dictionary keys. With this convention, converting bencoding
+//! to JSON and back again always produces the data we started with.
+use super::Bencoding;
+use std::{error, fmt, str};
+
+// The prefix we use to mark hex encoded strings.
+const HEX_PREFIX: &str = "hex:";
+
+// How deeply arrays and objects can be nested before we give up.
+const MAX_DEPTH: usize = 512;
+
+/// Represents an error that occurs while converting JSON into bencoding.
+///
+/// Each variant contains the offset, in bytes, into the JSON where conversion failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JsonError {
+    /// The input isn't valid JSON.
+    Syntax { offset: usize },
+    /// The input contains a value with no equivalent in bencoding.
+    ///
+    /// This is the case for floating point numbers, booleans, and `null`.
+    Unsupported { offset: usize },
+    /// A number doesn't fit in 64 bits.
+    IntegerOverflow { offset: usize },
+    /// A string starting with `hex:` isn't followed by valid hexadecimal.
+    InvalidHex { offset: usize },
+    /// Arrays and objects are nested more deeply than we allow.
+    TooDeep { offset: usize },
+}
+
+impl JsonError {
+    /// The offset, in bytes, into the input where conversion failed.
+    pub fn offset(&self) -> usize {
+        use JsonError::*;
+        match *self {
+            Syntax { offset }
+            | Unsupported { offset }
+            | IntegerOverflow { offset }
+            | InvalidHex { offset }
+            | TooDeep { offset } => offset,
+        }
+    }
+}
+
+impl error::Error for JsonError {}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use JsonError::*;
+        write!(f, "at byte {}: ", self.offset())?;
+        match self {
+            Syntax { .. } => write!(f, "invalid JSON"),
+            Unsupported { .. } => write!(f, "value can't be represented in bencoding"),
+            IntegerOverflow { .. } => write!(f, "integer doesn't fit in 64 bits"),
+            InvalidHex { .. } => write!(f, "string marked as hex contains invalid hex"),
+            TooDeep { .. } => write!(f, "values are nested more than {} deep", MAX_DEPTH),
+        }
+    }
+}
+
+impl Bencoding {
+    /// Convert this data into JSON, following the conventions of this module.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        write_element(self, &mut out);
+        out
+    }
+
+    /// Convert JSON back into bencoding, following the conventions of this module.
+    pub fn from_json(input: &str) -> Result<Bencoding, JsonError> {
+        let mut parser = Parser { input, pos: 0 };
+        let bencoding = parser.element(0)?;
+        parser.whitespace();
+        if parser.pos < parser.input.len() {
+            return Err(JsonError::Syntax { offset: parser.pos });
+        }
+        Ok(bencoding)
+    }
+}
+
+fn write_string(string: &[u8], out: &mut String) {
+    out.push('"');
+    match str::from_utf8(string) {
+        Ok(s) if !s.starts_with(HEX_PREFIX) => {
+            for c in s.chars() {
+                match c {
+                    '"' => out.push_str("\\\""),
+                    '\\' => out.push_str("\\\\"),
+                    '\n' => out.push_str("\\n"),
+                    '\r' => out.push_str("\\r"),
+                    '\t' => out.push_str("\\t"),
+                    c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+                    c => out.push(c),
+                }
+            }
+        }
+        _ => {
+            out.push_str(HEX_PREFIX);
+            for b in string {
+                out.push_str(&format!("{:02x}", b));
+            }
+        }
+    }
+    out.push('"');
+}
+
+fn write_element(bencoding: &Bencoding, out: &mut String) {
+    match bencoding {
+        Bencoding::Int(i) => out.push_str(&i.to_string()),
+        Bencoding::ByteString(b) => write_string(b, out),
+        Bencoding::List(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_element(item, out);
+            }
+            out.push(']');
+        }
+        Bencoding::Dict(dict) => {
+            out.push('{');
+            for (i, (key, value)) in dict.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_string(key, out);
+                out.push(':');
+                write_element(value, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    // This always lies on a character boundary, since we only skip over whole characters.
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).copied()
+    }
+
+    fn whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, target: u8) -> Result<(), JsonError> {
+        self.whitespace();
+        if self.peek() == Some(target) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(JsonError::Syntax { offset: self.pos })
+        }
+    }
+
+    fn element(&mut self, depth: usize) -> Result<Bencoding, JsonError> {
+        self.whitespace();
+        let start = self.pos;
+        if depth > MAX_DEPTH {
+            return Err(JsonError::TooDeep { offset: start });
+        }
+        match self.peek() {
+            Some(b'"') => self.string().map(|s| Bencoding::ByteString(s.into())),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.whitespace();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                } else {
+                    loop {
+                        items.push(self.element(depth + 1)?);
+                        self.whitespace();
+                        match self.peek() {
+                            Some(b',') => self.pos += 1,
+                            Some(b']') => {
+                                self.pos += 1;
+                                break;
+                            }
+                            _ => return Err(JsonError::Syntax { offset: self.pos }),
+                        }
+                    }
+                }
+                Ok(Bencoding::List(items.into_boxed_slice()))
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                // Entries are kept in order, including duplicate keys, like decoding does
+                let mut entries = Vec::new();
+                self.whitespace();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                } else {
+                    loop {
+                        self.whitespace();
+                        let key = self.string()?;
+                        self.expect(b':')?;
+                        let value = self.element(depth + 1)?;
+                        entries.push((key, value));
+                        self.whitespace();
+                        match self.peek() {
+                            Some(b',') => self.pos += 1,
+                            Some(b'}') => {
+                                self.pos += 1;
+                                break;
+                            }
+                            _ => return Err(JsonError::Syntax { offset: self.pos }),
+                        }
+                    }
+                }
+                Ok(Bencoding::Dict(entries.into_iter().collect()))
+            }
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            Some(b't') | Some(b'f') | Some(b'n') => Err(JsonError::Unsupported { offset: start }),
+            _ => Err(JsonError::Syntax { offset: start }),
+        }
+    }
+
+    fn number(&mut self) -> Result<Bencoding, JsonError> {
+        let start = self.pos;
+        let negative = self.peek() == Some(b'-');
+        if negative {
+            self.pos += 1;
+        }
+        let digits_start = self.pos;
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        let digits = &self.input.as_bytes()[digits_start..self.pos];
+        if digits.is_empty() || (digits.len() > 1 && digits[0] == b'0') {
+            return Err(JsonError::Syntax { offset: start });
+        }
+        if let Some(b'.') | Some(b'e') | Some(b'E') = self.peek() {
+            return Err(JsonError::Unsupported { offset: start });
+        }
+        // Accumulating negatively lets us represent the smallest 64 bit integer
+        let mut acc: i64 = 0;
+        for &d in digits {
+            acc = acc
+                .checked_mul(10)
+                .and_then(|acc| acc.checked_sub((d - b'0') as i64))
+                .ok_or(JsonError::IntegerOverflow { offset: start })?;
+        }
+        if !negative {
+            acc = acc
+                .checked_neg()
+                .ok_or(JsonError::IntegerOverflow { offset: start })?;
+        }
+        Ok(Bencoding::Int(acc))
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let offset = self.pos;
+        let digits = self
+            .input
+            .get(self.pos..self.pos + 4)
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or(JsonError::Syntax { offset })?;
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    // Parse a string, decoding it into bytes following our hex convention.
+    fn string(&mut self) -> Result<Vec<u8>, JsonError> {
+        let start = self.pos;
+        if self.peek() != Some(b'"') {
+            return Err(JsonError::Syntax { offset: start });
+        }
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let offset = self.pos;
+            match self.peek() {
+                None => return Err(JsonError::Syntax { offset }),
+                Some(b'"') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let escaped = match self.peek() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            self.pos += 1;
+                            let mut code = self.hex4()?;
+                            // Characters outside the basic plane are written as surrogate pairs
+                            if (0xD800..0xDC00).contains(&code)
+                                && self.input[self.pos..].starts_with("\\u")
+                            {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                if !(0xDC00..0xE000).contains(&low) {
+                                    return Err(JsonError::Syntax { offset });
+                                }
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                            }
+                            out.push(char::from_u32(code).ok_or(JsonError::Syntax { offset })?);
+                            continue;
+                        }
+                        _ => return Err(JsonError::Syntax { offset }),
+                    };
+                    self.pos += 1;
+                    out.push(escaped);
+                }
+                Some(c) if c < 0x20 => return Err(JsonError::Syntax { offset }),
+                Some(_) => {
+                    let c = self.input[self.pos..]
+                        .chars()
+                        .next()
+                        .ok_or(JsonError::Syntax { offset })?;
+                    self.pos += c.len_utf8();
+                    out.push(c);
+                }
+            }
+        }
+        match out.strip_prefix(HEX_PREFIX) {
+            Some(hex) => decode_hex(hex).ok_or(JsonError::InvalidHex { offset: start }),
+            None => Ok(out.into_bytes()),
+        }
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn converting_to_json_works() {
+        let bencoding =
+            Bencoding::decode(b"d4:name3:a\"b6:pieces2:\xff\x004:listli-1ee3:tag6:hex:abe")
+                .unwrap();
+        assert_eq!(
+            r#"{"name":"a\"b","pieces":"hex:ff00","list":[-1],"tag":"hex:6865783a6162"}"#,
+            bencoding.to_json()
+        );
+        assert_eq!(
+            Ok(bencoding.clone()),
+            Bencoding::from_json(&bencoding.to_json())
+        );
+    }
+
+    #[test]
+    fn converting_from_json_works() {
+        let json = " { \"a\" : [ 1 , -5, \"\\u00e9\\ud83d\\ude00\" ], \"b\" : {} } ";
+        let expected = Bencoding::decode(b"d1:ali1ei-5e6:\xc3\xa9\xf0\x9f\x98\x80e1:bdee").unwrap();
+        assert_eq!(Ok(expected), Bencoding::from_json(json));
+    }
+
+    #[test]
+    fn converting_duplicate_keys_roundtrips() {
+        let input = b"d1:bi1e1:ai2e1:bi3ee";
+        let bencoding = Bencoding::decode(input).unwrap();
+        assert_eq!(r#"{"b":1,"a":2,"b":3}"#, bencoding.to_json());
+        let converted = Bencoding::from_json(&bencoding.to_json()).unwrap();
+        assert_eq!(bencoding, converted);
+        assert_eq!(input.to_vec(), converted.encode_in_order());
+    }
+
+    #[test]
+    fn converting_invalid_json_fails() {
+        assert_eq!(
+            Err(JsonError::Unsupported { offset: 1 }),
+            Bencoding::from_json("[1.5]")
+        );
+        assert_eq!(
+            Err(JsonError::Unsupported { offset: 0 }),
+            Bencoding::from_json("null")
+        );
+        assert_eq!(
+            Err(JsonError::IntegerOverflow { offset: 0 }),
+            Bencoding::from_json("9223372036854775808")
+        );
+        assert_eq!(
+            Err(JsonError::InvalidHex { offset: 0 }),
+            Bencoding::from_json("\"hex:zz\"")
+        );
+        assert_eq!(
+            Err(JsonError::Syntax { offset: 3 }),
+            Bencoding::from_json("[1,]")
+        );
+        assert_eq!(
+            Err(JsonError::Syntax { offset: 4 }),
+            Bencoding::from_json("[1] 2")
+        );
+    }
+}
@@ -0,0 +1,196 @@
+//! This module contains a best effort decoder for damaged bencoding.
+//!
+//! Instead of stopping at the first problem, we record it and try to carry on:
+//!
+//! - bytes that can't start an element are skipped,
+//! - malformed integers are skipped up to the next `e`,
+//! - strings running past the end of the input are truncated,
+//! - and lists or dictionaries left open at the end of the input are closed.
+//!
+//! This is useful to salvage what we can out of a corrupted or truncated torrent.
+use super::{bytestring, integer, Bencoding, BencodingError, Expected, Lexer, ParseOptions};
+
+// A list or dictionary we're in the middle of parsing.
+enum Frame<'a> {
+    List(Vec<Bencoding>),
+    Dict(Vec<(Box<[u8]>, Bencoding)>, Option<&'a [u8]>),
+}
+
+impl<'a> Frame<'a> {
+    fn close(self) -> Bencoding {
+        match self {
+            Frame::List(items) => Bencoding::List(items.into_boxed_slice()),
+            Frame::Dict(entries, _) => Bencoding::Dict(entries.into_iter().collect()),
+        }
+    }
+}
+
+// Parse a byte string, keeping whatever is left of the input if its length is too large.
+fn truncated_bytestring<'a>(
+    lexer: &mut Lexer<'a>,
+    errors: &mut Vec<BencodingError>,
+) -> Option<&'a [u8]> {
+    match bytestring(lexer) {
+        Ok(bytes) => Some(bytes),
+        Err(error) => {
+            let truncated = matches!(error, BencodingError::InvalidLength { .. })
+                && lexer.peek() == Some(&b':');
+            errors.push(error);
+            if !truncated {
+                return None;
+            }
+            lexer.next();
+            let rest = &lexer.input[lexer.pos..];
+            lexer.pos = lexer.input.len();
+            Some(rest)
+        }
+    }
+}
+
+impl Bencoding {
+    /// Decode as much as possible out of some malformed bytes.
+    ///
+    /// This returns the element we managed to recover, if any, along with every problem
+    /// we ran into, in the order we found them. Valid bencoding is decoded exactly as
+    /// `decode` would, with no problems reported.
+    pub fn parse_lenient(input: &[u8]) -> (Option<Bencoding>, Vec<BencodingError>) {
+        let max_depth = ParseOptions::default().max_depth;
+        let mut lexer = Lexer::new(input);
+        let mut stack: Vec<Frame> = Vec::new();
+        let mut errors = Vec::new();
+        let root = loop {
+            let node = match (lexer.peek(), stack.last_mut()) {
+                (None, None) => {
+                    errors.push(lexer.unexpected(Expected::Element));
+                    break None;
+                }
+                (None, Some(_)) => {
+                    errors.push(lexer.unexpected(Expected::Byte(b'e')));
+                    stack.pop().map(Frame::close).unwrap()
+                }
+                (Some(b'e'), Some(frame)) => {
+                    // A key without a value gets dropped
+                    if let Frame::Dict(_, Some(_)) = frame {
+                        errors.push(lexer.unexpected(Expected::Element));
+                    }
+                    lexer.next();
+                    stack.pop().map(Frame::close).unwrap()
+                }
+                (Some(&c), Some(Frame::Dict(_, key @ None))) => {
+                    if c.is_ascii_digit() {
+                        *key = truncated_bytestring(&mut lexer, &mut errors);
+                    } else {
+                        errors.push(lexer.unexpected(Expected::Digit));
+                        lexer.next();
+                    }
+                    continue;
+                }
+                (Some(b'i'), _) => {
+                    lexer.next();
+                    let start = lexer.pos;
+                    match integer(&mut lexer) {
+                        Ok(int) => Bencoding::Int(int),
+                        Err(error) => {
+                            errors.push(error);
+                            lexer.pos = match input[start..].iter().position(|&c| c == b'e') {
+                                Some(end) => start + end + 1,
+                                None => input.len(),
+                            };
+                            continue;
+                        }
+                    }
+                }
+                (Some(&c), _) if c == b'l' || c == b'd' => {
+                    if stack.len() >= max_depth {
+                        errors.push(BencodingError::TooDeep {
+                            offset: lexer.pos,
+                            limit: max_depth,
+                        });
+                    } else {
+                        stack.push(if c == b'l' {
+                            Frame::List(Vec::new())
+                        } else {
+                            Frame::Dict(Vec::new(), None)
+                        });
+                    }
+                    lexer.next();
+                    continue;
+                }
+                (Some(c), _) if c.is_ascii_digit() => {
+                    match truncated_bytestring(&mut lexer, &mut errors) {
+                        Some(bytes) => Bencoding::ByteString(bytes.into()),
+                        None => continue,
+                    }
+                }
+                (Some(_), _) => {
+                    errors.push(lexer.unexpected(Expected::Element));
+                    lexer.next();
+                    continue;
+                }
+            };
+            match stack.last_mut() {
+                None => break Some(node),
+                Some(Frame::List(items)) => items.push(node),
+                Some(Frame::Dict(entries, key)) => {
+                    if let Some(key) = key.take() {
+                        entries.push((key.into(), node));
+                    }
+                }
+            }
+        };
+        if root.is_some() && lexer.pos < input.len() {
+            errors.push(BencodingError::TrailingData { offset: lexer.pos });
+        }
+        (root, errors)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lenient_parsing_accepts_valid_data() {
+        let input = b"d1:ali1ei-2ee1:b3:xyze";
+        assert_eq!(
+            (Bencoding::decode(input).ok(), Vec::new()),
+            Bencoding::parse_lenient(input)
+        );
+    }
+
+    #[test]
+    fn lenient_parsing_recovers_from_errors() {
+        let (value, errors) = Bencoding::parse_lenient(b"d1:ali1ei1x2e?i3ee1:b5:xy");
+        assert_eq!(
+            Some(Bencoding::decode(b"d1:ali1ei3ee1:b2:xye").unwrap()),
+            value
+        );
+        assert_eq!(
+            vec![
+                BencodingError::UnexpectedByte {
+                    offset: 10,
+                    expected: Expected::Byte(b'e'),
+                    found: b'x'
+                },
+                BencodingError::UnexpectedByte {
+                    offset: 13,
+                    expected: Expected::Element,
+                    found: b'?'
+                },
+                BencodingError::InvalidLength {
+                    offset: 21,
+                    length: 5
+                },
+                BencodingError::UnexpectedEof {
+                    offset: 25,
+                    expected: Expected::Byte(b'e')
+                },
+            ],
+            errors
+        );
+        let (value, errors) = Bencoding::parse_lenient(b"i1ei2e");
+        assert_eq!(Some(Bencoding::Int(1)), value);
+        assert_eq!(vec![BencodingError::TrailingData { offset: 3 }], errors);
+        assert_eq!(1, Bencoding::parse_lenient(b"").1.len());
+    }
+}
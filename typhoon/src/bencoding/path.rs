@@ -0,0 +1,281 @@
+//! This module lets us navigate through nested bencoding with paths.
+//!
+//! A path is a sequence of dictionary keys separated by `.`, along with list
+//! indices in brackets. For example, `info.files[0].length` looks up the length
+//! of the first file in a torrent. Keys can't contain `.` or `[`, but can otherwise
+//! be any UTF-8 string.
+use super::{Bencoding, Dict};
+use std::{error, fmt};
+
+/// Represents an error that occurs while looking up a path.
+///
+/// Each variant contains the part of the path that was being looked up when
+/// we failed, which makes it easy to tell which segment was at fault.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PathError {
+    /// The path itself is malformed, at the given character offset.
+    InvalidPath { offset: usize },
+    /// A dictionary doesn't contain a key.
+    MissingKey { path: String },
+    /// An index is past the end of a list.
+    OutOfBounds { path: String, len: usize },
+    /// We tried to look up a key in something that isn't a dictionary.
+    ExpectedDict { path: String },
+    /// We tried to index into something that isn't a list.
+    ExpectedList { path: String },
+}
+
+impl error::Error for PathError {}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use PathError::*;
+        match self {
+            InvalidPath { offset } => write!(f, "invalid path at character {}", offset),
+            MissingKey { path } => write!(f, "{} doesn't exist", path),
+            OutOfBounds { path, len } => {
+                write!(f, "{} is out of bounds for a list of length {}", path, len)
+            }
+            ExpectedDict { path } if path.is_empty() => write!(f, "root is not a dictionary"),
+            ExpectedDict { path } => write!(f, "{} is not a dictionary", path),
+            ExpectedList { path } if path.is_empty() => write!(f, "root is not a list"),
+            ExpectedList { path } => write!(f, "{} is not a list", path),
+        }
+    }
+}
+
+// A single step in a path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Segment<'p> {
+    Key(&'p str),
+    Index(usize),
+}
+
+// Split a path into its segments, along with the offset where each segment ends.
+fn segments(path: &str) -> Result<Vec<(Segment<'_>, usize)>, PathError> {
+    let bytes = path.as_bytes();
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        if bytes[pos] == b'[' {
+            let start = pos + 1;
+            let end = start
+                + bytes[start..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_digit())
+                    .count();
+            if end == start || bytes.get(end) != Some(&b']') {
+                return Err(PathError::InvalidPath { offset: end });
+            }
+            let index = path[start..end]
+                .parse()
+                .map_err(|_| PathError::InvalidPath { offset: start })?;
+            pos = end + 1;
+            out.push((Segment::Index(index), pos));
+        } else {
+            // Keys after the first segment are introduced by a `.`
+            if !out.is_empty() {
+                if bytes[pos] != b'.' {
+                    return Err(PathError::InvalidPath { offset: pos });
+                }
+                pos += 1;
+            }
+            let start = pos;
+            while pos < bytes.len() && bytes[pos] != b'.' && bytes[pos] != b'[' {
+                pos += 1;
+            }
+            if pos == start {
+                return Err(PathError::InvalidPath { offset: pos });
+            }
+            out.push((Segment::Key(&path[start..pos]), pos));
+        }
+    }
+    Ok(out)
+}
+
+impl Bencoding {
+    /// Look up the element at a given path, like `info.files[0].length`.
+    ///
+    /// The empty path refers to this element itself.
+    pub fn lookup(&self, path: &str) -> Result<&Bencoding, PathError> {
+        let mut current = self;
+        let mut consumed = 0;
+        for (segment, end) in segments(path)? {
+            let parent = &path[..consumed];
+            let here = || path[..end].to_owned();
+            current = match (segment, current) {
+                (Segment::Key(key), Bencoding::Dict(dict)) => dict
+                    .get(key.as_bytes())
+                    .ok_or_else(|| PathError::MissingKey { path: here() })?,
+                (Segment::Index(index), Bencoding::List(items)) => {
+                    items.get(index).ok_or_else(|| PathError::OutOfBounds {
+                        path: here(),
+                        len: items.len(),
+                    })?
+                }
+                (Segment::Key(_), _) => {
+                    return Err(PathError::ExpectedDict {
+                        path: parent.to_owned(),
+                    })
+                }
+                (Segment::Index(_), _) => {
+                    return Err(PathError::ExpectedList {
+                        path: parent.to_owned(),
+                    })
+                }
+            };
+            consumed = end;
+        }
+        Ok(current)
+    }
+
+    /// Find the path leading to an element inside of this one, like `info.files[0].length`.
+    ///
+    /// The element is found by identity, rather than by value, which makes this useful to locate
+    /// references into this bencoding, like the ones that errors hold on to.
+    pub fn path_to(&self, target: &Bencoding) -> Option<String> {
+        // Paths to children are joined with a `.`, unless they start with an index
+        fn join(head: String, rest: String) -> String {
+            if rest.is_empty() || rest.starts_with('[') {
+                head + &rest
+            } else {
+                head + "." + &rest
+            }
+        }
+
+        if std::ptr::eq(self, target) {
+            return Some(String::new());
+        }
+        match self {
+            Bencoding::List(items) => items.iter().enumerate().find_map(|(index, item)| {
+                let rest = item.path_to(target)?;
+                Some(join(format!("[{}]", index), rest))
+            }),
+            Bencoding::Dict(dict) => dict.iter().find_map(|(key, value)| {
+                let rest = value.path_to(target)?;
+                Some(join(String::from_utf8_lossy(key).into_owned(), rest))
+            }),
+            _ => None,
+        }
+    }
+
+    /// Get the value of this element, if it's an integer.
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Bencoding::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Get the bytes of this element, if it's a byte string.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Bencoding::ByteString(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// Get this element as a string, if it's a byte string containing valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        self.as_bytes().and_then(|b| std::str::from_utf8(b).ok())
+    }
+
+    /// Get the items of this element, if it's a list.
+    pub fn as_list(&self) -> Option<&[Bencoding]> {
+        match self {
+            Bencoding::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Get the entries of this element, if it's a dictionary.
+    pub fn as_dict(&self) -> Option<&Dict> {
+        match self {
+            Bencoding::Dict(dict) => Some(dict),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn torrent() -> Bencoding {
+        Bencoding::decode(b"d4:infod5:filesld6:lengthi5e4:pathl1:aeee4:name3:dire3:x.yi1ee")
+            .unwrap()
+    }
+
+    #[test]
+    fn looking_up_paths_works() {
+        let torrent = torrent();
+        assert_eq!(
+            Some(5),
+            torrent.lookup("info.files[0].length").unwrap().as_int()
+        );
+        assert_eq!(
+            Some("a"),
+            torrent.lookup("info.files[0].path[0]").unwrap().as_str()
+        );
+        assert_eq!(Some("dir"), torrent.lookup("info.name").unwrap().as_str());
+        assert_eq!(Ok(&torrent), torrent.lookup(""));
+    }
+
+    #[test]
+    fn finding_paths_to_elements_works() {
+        let torrent = torrent();
+        for path in &[
+            "info.files[0].length",
+            "info.files[0].path[0]",
+            "info.name",
+            "",
+        ] {
+            let element = torrent.lookup(path).unwrap();
+            assert_eq!(Some(path.to_string()), torrent.path_to(element));
+        }
+        // An equal element elsewhere doesn't count
+        assert_eq!(None, torrent.path_to(&Bencoding::Int(5)));
+    }
+
+    #[test]
+    fn looking_up_paths_reports_the_failing_segment() {
+        let torrent = torrent();
+        assert_eq!(
+            Err(PathError::MissingKey {
+                path: "info.files[0].size".into()
+            }),
+            torrent.lookup("info.files[0].size")
+        );
+        assert_eq!(
+            Err(PathError::OutOfBounds {
+                path: "info.files[3]".into(),
+                len: 1
+            }),
+            torrent.lookup("info.files[3]")
+        );
+        assert_eq!(
+            Err(PathError::ExpectedList {
+                path: "info.name".into()
+            }),
+            torrent.lookup("info.name[0]")
+        );
+        assert_eq!(
+            Err(PathError::ExpectedDict {
+                path: "info.files[0].path".into()
+            }),
+            torrent.lookup("info.files[0].path.x")
+        );
+        assert_eq!(
+            Err(PathError::ExpectedDict { path: "".into() }),
+            Bencoding::Int(0).lookup("a")
+        );
+        assert_eq!(
+            Err(PathError::InvalidPath { offset: 5 }),
+            torrent.lookup("info..name")
+        );
+        assert_eq!(
+            Err(PathError::InvalidPath { offset: 6 }),
+            torrent.lookup("files[x]")
+        );
+    }
+}
@@ -0,0 +1,152 @@
+//! This module contains a human friendly printer for bencoded data.
+//!
+//! Unlike the `Display` implementation of `Bencoding`, this spreads nested
+//! elements over multiple lines, escapes control characters in strings, and
+//! renders binary strings as hexadecimal. Long strings, like the `pieces` field
+//! of a torrent, can be truncated to keep the output readable.
+use super::Bencoding;
+use std::{fmt, str};
+
+/// Controls how bencoded data gets pretty printed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrettyOptions {
+    /// The number of spaces to indent each level of nesting by.
+    pub indent: usize,
+    /// Only show the start of byte strings longer than this many bytes.
+    ///
+    /// Truncated strings are followed by their full length.
+    pub truncate: Option<usize>,
+}
+
+impl Default for PrettyOptions {
+    fn default() -> Self {
+        PrettyOptions {
+            indent: 2,
+            truncate: None,
+        }
+    }
+}
+
+/// Pretty prints bencoded data when displayed.
+///
+/// This is created with `Bencoding::pretty`.
+#[derive(Clone, Copy, Debug)]
+pub struct Pretty<'b> {
+    bencoding: &'b Bencoding,
+    options: &'b PrettyOptions,
+}
+
+impl Bencoding {
+    /// Display this data in a human friendly way.
+    pub fn pretty<'b>(&'b self, options: &'b PrettyOptions) -> Pretty<'b> {
+        Pretty {
+            bencoding: self,
+            options,
+        }
+    }
+}
+
+impl<'b> Pretty<'b> {
+    fn bytestring(&self, string: &[u8], f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shown = match self.options.truncate {
+            Some(limit) if string.len() > limit => &string[..limit],
+            _ => string,
+        };
+        // Cutting a UTF-8 string short might split a character, so we check the full string
+        match str::from_utf8(string) {
+            Ok(_) => {
+                let text = str::from_utf8(shown).unwrap_or_else(|e| {
+                    str::from_utf8(&shown[..e.valid_up_to()]).unwrap_or_default()
+                });
+                write!(f, "\"{}\"", text.escape_debug())?
+            }
+            Err(_) => {
+                write!(f, "<")?;
+                for b in shown {
+                    write!(f, "{:02x}", b)?;
+                }
+                write!(f, ">")?;
+            }
+        }
+        if shown.len() < string.len() {
+            write!(f, "... ({} bytes)", string.len())?;
+        }
+        Ok(())
+    }
+
+    fn newline(&self, level: usize, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f)?;
+        write!(f, "{:width$}", "", width = level * self.options.indent)
+    }
+
+    fn element(
+        &self,
+        bencoding: &Bencoding,
+        level: usize,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match bencoding {
+            Bencoding::Int(i) => write!(f, "{}", i),
+            Bencoding::ByteString(b) => self.bytestring(b, f),
+            Bencoding::List(items) if items.is_empty() => write!(f, "[]"),
+            Bencoding::List(items) => {
+                write!(f, "[")?;
+                for item in items.iter() {
+                    self.newline(level + 1, f)?;
+                    self.element(item, level + 1, f)?;
+                    write!(f, ",")?;
+                }
+                self.newline(level, f)?;
+                write!(f, "]")
+            }
+            Bencoding::Dict(dict) if dict.is_empty() => write!(f, "{{}}"),
+            Bencoding::Dict(dict) => {
+                write!(f, "{{")?;
+                for (key, value) in dict.iter() {
+                    self.newline(level + 1, f)?;
+                    self.bytestring(key, f)?;
+                    write!(f, ": ")?;
+                    self.element(value, level + 1, f)?;
+                    write!(f, ",")?;
+                }
+                self.newline(level, f)?;
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl<'b> fmt::Display for Pretty<'b> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.element(self.bencoding, 0, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pretty_printing_works() {
+        let bencoding = Bencoding::decode(
+            b"d4:name5:a\"b\nc6:pieces4:\xff\x00\x01\x025:emptyle4:listli1ei2eee",
+        )
+        .unwrap();
+        let expected = "{\n  \"name\": \"a\\\"b\\nc\",\n  \"pieces\": <ff000102>,\n  \"empty\": [],\n  \"list\": [\n    1,\n    2,\n  ],\n}";
+        assert_eq!(
+            expected,
+            bencoding.pretty(&PrettyOptions::default()).to_string()
+        );
+    }
+
+    #[test]
+    fn pretty_printing_truncates_long_strings() {
+        let options = PrettyOptions {
+            indent: 4,
+            truncate: Some(2),
+        };
+        let bencoding = Bencoding::decode(b"l4:\xff\x00\x01\x024:abcd2:\xc3\xa9e").unwrap();
+        let expected = "[\n    <ff00>... (4 bytes),\n    \"ab\"... (4 bytes),\n    \"é\",\n]";
+        assert_eq!(expected, bencoding.pretty(&options).to_string());
+    }
+}
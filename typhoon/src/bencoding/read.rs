@@ -0,0 +1,323 @@
+//! This module decodes bencoding directly from an `io::Read` source.
+//!
+//! This is useful when the data comes from a file or a socket, and we don't
+//! know how large it is ahead of time. We read exactly the bytes making up a
+//! single element, and nothing more, so the reader can keep being used afterwards.
+//! Reads are done a few bytes at a time, so unbuffered readers, like sockets,
+//! should be wrapped in an `io::BufReader` first.
+use super::{parse_from, Bencoding, BencodingError, Expected, ParseOptions, Source, Tree};
+use std::{convert::TryFrom, error, fmt, io};
+
+/// Represents an error that occurs while decoding bencoding from a reader.
+#[derive(Debug)]
+pub enum ReadError {
+    /// Reading from the underlying source failed.
+    Io(io::Error),
+    /// The data we read wasn't valid bencoding.
+    ///
+    /// The offset in the error counts the bytes read since we started decoding.
+    Bencoding(BencodingError),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::Io(e) => write!(f, "failed to read bencoding: {}", e),
+            ReadError::Bencoding(e) => e.fmt(f),
+        }
+    }
+}
+
+impl error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ReadError::Io(e) => Some(e),
+            ReadError::Bencoding(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for ReadError {
+    fn from(error: io::Error) -> Self {
+        ReadError::Io(error)
+    }
+}
+
+impl From<BencodingError> for ReadError {
+    fn from(error: BencodingError) -> Self {
+        ReadError::Bencoding(error)
+    }
+}
+
+// How many bytes of a string we read at once.
+//
+// Strings are read in chunks, so that a bogus length prefix can't make us
+// allocate more memory than the data we actually receive.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// Reads single bytes from a source, keeping track of how far into it we are.
+struct StreamLexer<R> {
+    reader: R,
+    pos: usize,
+    peeked: Option<u8>,
+    strict: bool,
+}
+
+impl<R: io::Read> StreamLexer<R> {
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        if self.peeked.is_none() {
+            let mut byte = [0];
+            loop {
+                match self.reader.read(&mut byte) {
+                    Ok(0) => return Ok(None),
+                    Ok(_) => break,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+            self.peeked = Some(byte[0]);
+        }
+        Ok(self.peeked)
+    }
+
+    fn next(&mut self) -> io::Result<Option<u8>> {
+        let byte = self.peek()?;
+        if byte.is_some() {
+            self.peeked = None;
+            self.pos += 1;
+        }
+        Ok(byte)
+    }
+
+    fn unexpected(&mut self, expected: Expected) -> ReadError {
+        let offset = self.pos;
+        match self.peek() {
+            Ok(None) => BencodingError::UnexpectedEof { offset, expected }.into(),
+            Ok(Some(found)) => BencodingError::UnexpectedByte {
+                offset,
+                expected,
+                found,
+            }
+            .into(),
+            Err(e) => e.into(),
+        }
+    }
+
+    fn expect(&mut self, target: u8) -> Result<(), ReadError> {
+        if self.peek()? == Some(target) {
+            self.next()?;
+            Ok(())
+        } else {
+            Err(self.unexpected(Expected::Byte(target)))
+        }
+    }
+
+    // Like `Lexer::digits`, negative values are accumulated as such, to reach `i64::MIN`.
+    fn digits(&mut self, negative: bool) -> Result<i64, ReadError> {
+        let start = self.pos;
+        let mut acc: i64 = 0;
+        let mut count = 0;
+        let mut leading_zero = false;
+        while let Some(c) = self.peek()?.filter(u8::is_ascii_digit) {
+            self.next()?;
+            if count == 0 {
+                leading_zero = c == b'0';
+            } else if leading_zero && self.strict {
+                return Err(BencodingError::LeadingZero { offset: start }.into());
+            }
+            count += 1;
+            let digit = (c - b'0') as i64;
+            acc = acc
+                .checked_mul(10)
+                .and_then(|acc| {
+                    if negative {
+                        acc.checked_sub(digit)
+                    } else {
+                        acc.checked_add(digit)
+                    }
+                })
+                .ok_or(BencodingError::IntegerOverflow { offset: start })?;
+        }
+        if count == 0 {
+            return Err(self.unexpected(Expected::Digit));
+        }
+        Ok(acc)
+    }
+
+    fn integer(&mut self) -> Result<i64, ReadError> {
+        let start = self.pos;
+        let negative = self.peek()? == Some(b'-');
+        if negative {
+            self.next()?;
+        }
+        let int = self.digits(negative)?;
+        if self.strict && negative && int == 0 {
+            return Err(BencodingError::NegativeZero { offset: start }.into());
+        }
+        self.expect(b'e')?;
+        Ok(int)
+    }
+
+    fn bytestring(&mut self, limit: usize) -> Result<Box<[u8]>, ReadError> {
+        let start = self.pos;
+        let length = usize::try_from(self.digits(false)?).unwrap_or(usize::MAX);
+        if length > limit {
+            return Err(BencodingError::StringTooLong {
+                offset: start,
+                length,
+                limit,
+            }
+            .into());
+        }
+        self.expect(b':')?;
+        let mut out = Vec::new();
+        while out.len() < length {
+            let chunk = (length - out.len()).min(CHUNK_SIZE);
+            let filled = out.len();
+            out.resize(filled + chunk, 0);
+            let mut read = 0;
+            while read < chunk {
+                match self.reader.read(&mut out[filled + read..]) {
+                    Ok(0) => {
+                        return Err(BencodingError::InvalidLength {
+                            offset: start,
+                            length,
+                        }
+                        .into())
+                    }
+                    Ok(n) => read += n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            self.pos += chunk;
+        }
+        Ok(out.into_boxed_slice())
+    }
+}
+
+impl<R: io::Read> Source for StreamLexer<R> {
+    type Bytes = Box<[u8]>;
+    type Error = ReadError;
+
+    fn offset(&self) -> usize {
+        self.pos
+    }
+
+    fn peek_byte(&mut self) -> Result<Option<u8>, ReadError> {
+        Ok(self.peek()?)
+    }
+
+    fn skip_byte(&mut self) -> Result<(), ReadError> {
+        self.next()?;
+        Ok(())
+    }
+
+    fn read_integer(&mut self) -> Result<i64, ReadError> {
+        self.integer()
+    }
+
+    fn read_bytestring(&mut self, limit: usize) -> Result<Box<[u8]>, ReadError> {
+        self.bytestring(limit)
+    }
+
+    fn unexpected_element(&mut self) -> ReadError {
+        self.unexpected(Expected::Element)
+    }
+
+    fn non_canonical(&mut self, error: BencodingError) -> Result<(), ReadError> {
+        Err(error.into())
+    }
+}
+
+impl Bencoding {
+    /// Decode a single bencoded element from a reader.
+    ///
+    /// This reads exactly as many bytes as the element takes up.
+    pub fn from_reader<R: io::Read>(reader: R) -> Result<Bencoding, ReadError> {
+        Bencoding::from_reader_with(reader, &ParseOptions::default())
+    }
+
+    /// Decode a single bencoded element from a reader, with custom limits on what we accept.
+    pub fn from_reader_with<R: io::Read>(
+        reader: R,
+        options: &ParseOptions,
+    ) -> Result<Bencoding, ReadError> {
+        let mut lexer = StreamLexer {
+            reader,
+            pos: 0,
+            peeked: None,
+            strict: options.strict,
+        };
+        parse_from(&mut lexer, &mut Tree::new(), options, &mut Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+
+    // Hands out data one byte at a time, like a slow socket would.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match (self.0.split_first(), buf.first_mut()) {
+                (Some((&b, rest)), Some(out)) => {
+                    *out = b;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn reading_from_a_stream_works() {
+        let input = b"d1:bli1ei-2ee1:a3:xyzei3e";
+        let mut reader = Trickle(input);
+        let bencoding = Bencoding::from_reader(&mut reader).unwrap();
+        assert_eq!(Bencoding::decode(&input[..22]), Ok(bencoding));
+        // We shouldn't have read past the end of the first element
+        assert_eq!(
+            Ok(Bencoding::Int(3)),
+            Bencoding::from_reader(&mut reader).map_err(|e| e.to_string())
+        );
+    }
+
+    #[test]
+    fn reading_extreme_integers_works() {
+        for &int in &[i64::MIN, i64::MAX] {
+            let encoded = Bencoding::Int(int).encode();
+            assert_eq!(
+                Ok(Bencoding::Int(int)),
+                Bencoding::from_reader(&encoded[..]).map_err(|e| e.to_string())
+            );
+        }
+        match Bencoding::from_reader(&b"i-9223372036854775809e"[..]) {
+            Err(ReadError::Bencoding(BencodingError::IntegerOverflow { offset: 2 })) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reading_invalid_streams_fails() {
+        match Bencoding::from_reader(&b"l4:abc"[..]) {
+            Err(ReadError::Bencoding(BencodingError::InvalidLength {
+                offset: 1,
+                length: 4,
+            })) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        let options = ParseOptions {
+            strict: true,
+            ..ParseOptions::default()
+        };
+        match Bencoding::from_reader_with(&b"d1:bi1e1:ai2ee"[..], &options) {
+            Err(ReadError::Bencoding(BencodingError::UnsortedKey { offset: 7 })) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
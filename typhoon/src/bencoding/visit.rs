@@ -0,0 +1,121 @@
+//! This module lets us go through every element of some bencoded data.
+//!
+//! Instead of matching on each kind of element, and recursing into lists and
+//! dictionaries, we implement the methods of `Visitor` for the elements we care
+//! about, and let `walk` drive the traversal.
+use super::{Bencoding, Dict};
+
+/// Gets notified about each element while walking through bencoded data.
+///
+/// Every method does nothing by default. Containers are visited before their
+/// contents, and left once all of them have been visited.
+#[allow(unused_variables)]
+pub trait Visitor<'b> {
+    /// Called for each integer.
+    fn visit_int(&mut self, int: i64) {}
+
+    /// Called for each byte string, except dictionary keys.
+    fn visit_bytes(&mut self, bytes: &'b [u8]) {}
+
+    /// Called when we start visiting a list.
+    fn visit_list(&mut self, items: &'b [Bencoding]) {}
+
+    /// Called before visiting each item of a list.
+    fn visit_list_item(&mut self, index: usize, item: &'b Bencoding) {}
+
+    /// Called once all of the items in a list have been visited.
+    fn leave_list(&mut self, items: &'b [Bencoding]) {}
+
+    /// Called when we start visiting a dictionary.
+    fn visit_dict(&mut self, dict: &'b Dict) {}
+
+    /// Called before visiting the value of each entry in a dictionary.
+    ///
+    /// Entries are visited in their original order.
+    fn visit_dict_entry(&mut self, key: &'b [u8], value: &'b Bencoding) {}
+
+    /// Called once all of the entries in a dictionary have been visited.
+    fn leave_dict(&mut self, dict: &'b Dict) {}
+}
+
+/// Walk through an element and everything it contains, notifying a visitor.
+pub fn walk<'b, V: Visitor<'b> + ?Sized>(bencoding: &'b Bencoding, visitor: &mut V) {
+    match bencoding {
+        Bencoding::Int(int) => visitor.visit_int(*int),
+        Bencoding::ByteString(bytes) => visitor.visit_bytes(bytes),
+        Bencoding::List(items) => {
+            visitor.visit_list(items);
+            for (index, item) in items.iter().enumerate() {
+                visitor.visit_list_item(index, item);
+                walk(item, visitor);
+            }
+            visitor.leave_list(items);
+        }
+        Bencoding::Dict(dict) => {
+            visitor.visit_dict(dict);
+            for (key, value) in dict.iter() {
+                visitor.visit_dict_entry(key, value);
+                walk(value, visitor);
+            }
+            visitor.leave_dict(dict);
+        }
+    }
+}
+
+impl Bencoding {
+    /// Walk through this element and everything it contains, notifying a visitor.
+    pub fn walk<'b, V: Visitor<'b> + ?Sized>(&'b self, visitor: &mut V) {
+        walk(self, visitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Default)]
+    struct Stats<'b> {
+        ints: i64,
+        keys: Vec<&'b [u8]>,
+        large: Vec<&'b [u8]>,
+        depth: usize,
+        max_depth: usize,
+    }
+
+    impl<'b> Visitor<'b> for Stats<'b> {
+        fn visit_int(&mut self, int: i64) {
+            self.ints += int;
+        }
+
+        fn visit_bytes(&mut self, bytes: &'b [u8]) {
+            if bytes.len() > 3 {
+                self.large.push(bytes);
+            }
+        }
+
+        fn visit_list(&mut self, _: &'b [Bencoding]) {
+            self.depth += 1;
+            self.max_depth = self.max_depth.max(self.depth);
+        }
+
+        fn leave_list(&mut self, _: &'b [Bencoding]) {
+            self.depth -= 1;
+        }
+
+        fn visit_dict_entry(&mut self, key: &'b [u8], _: &'b Bencoding) {
+            self.keys.push(key);
+        }
+    }
+
+    #[test]
+    fn walking_visits_every_element() {
+        let bencoding = Bencoding::decode(b"d1:bli1el4:abcdi2eee1:ad1:c2:xye5:piecei-4ee").unwrap();
+        let mut stats = Stats::default();
+        bencoding.walk(&mut stats);
+        assert_eq!(-1, stats.ints);
+        assert_eq!(vec![&b"b"[..], b"a", b"c", b"piece"], stats.keys);
+        assert_eq!(vec![&b"abcd"[..]], stats.large);
+        assert_eq!(2, stats.max_depth);
+        assert_eq!(0, stats.depth);
+    }
+}
@@ -2,11 +2,58 @@
 //!
 //! This includes definitions of things like piece hashes, peers, as well
 //! as what's included in a `.torrent` file, for example.
-use crate::bencoding::Bencoding;
-use std::{convert::TryFrom, error, fmt, path::PathBuf, str, time};
+#[cfg(feature = "fs")]
+mod builder;
+mod compare;
+mod features;
+mod magnet;
+mod merkle;
+mod pieces;
+mod selective;
+mod signature;
+mod stats;
+mod summary;
+mod url;
+mod validate;
+#[cfg(feature = "fs")]
+pub use builder::{PieceLengthStrategy, TorrentBuilder};
+pub use compare::{compare, ContentMatch};
+pub use features::Feature;
+pub use magnet::{MagnetLink, ParseMagnetError};
+pub use merkle::{
+    merkle_proof, merkle_proof_with, merkle_root, merkle_root_with, verify_merkle_proof,
+    verify_merkle_proof_with,
+};
+pub use pieces::PieceHashesRef;
+pub use selective::{ParseBytesError, TorrentSummary};
+pub use signature::{Signature, SignatureVerifier};
+pub use stats::{ExtensionStats, TorrentStats, TrackerCounts};
+pub use summary::{format_size, format_time};
+pub use url::{Host, ParseTrackerUrlError, TrackerUrl};
+pub use validate::{Severity, ValidationIssue};
+
+#[cfg(feature = "fs")]
+use crate::hash::Md5;
+use crate::{
+    bencoding::{Bencoding, Dict, DictBuilder, ListBuilder},
+    hash::{Hasher, Sha1, Sha256},
+};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    error, fmt,
+    path::{Component, Path, PathBuf},
+    str, time,
+};
+#[cfg(feature = "fs")]
+use std::{
+    fs,
+    io::{self, Read},
+};
 
 /// An error occurring when extracting a value from bencoding.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum TryFromBencodingError<'b> {
     /// We tried to get an int, but the bencoding wasn't an integer.
     ///
@@ -28,6 +75,10 @@ pub enum TryFromBencodingError<'b> {
     ///
     /// This branch contains the integer that was too large.
     ExceedsSystemTime(i64),
+    /// We tried to interpret an integer as a size, but it was negative, or too large.
+    ///
+    /// This branch contains the integer that wasn't a valid size.
+    InvalidSize(i64),
     /// We tried to parse a byte string as a UTF8 string, but the bytes weren't valid.
     NotUTF8 {
         /// The bencoding byte string that wasn't valid UTF8
@@ -51,6 +102,24 @@ impl<'b> TryFromBencodingError<'b> {
     fn from_utf8_error(bencoding: &'b Bencoding, error: str::Utf8Error) -> Self {
         TryFromBencodingError::NotUTF8 { bencoding, error }
     }
+
+    /// The bencoding this error is about, if it points at some.
+    pub fn bencoding(&self) -> Option<&'b Bencoding> {
+        use TryFromBencodingError::*;
+        match *self {
+            ExpectedInt(b) | ExpectedByteString(b) | ExpectedList(b) | ExpectedDict(b) => Some(b),
+            NotUTF8 { bencoding, .. } | MissingKey { bencoding, .. } => Some(bencoding),
+            ExceedsSystemTime(_) | InvalidSize(_) => None,
+        }
+    }
+
+    /// Convert this error into one which doesn't borrow the bencoding.
+    ///
+    /// This needs the bencoding that was being parsed, in order to find the path to
+    /// the part of it this error is about.
+    pub fn into_owned(self, root: &Bencoding) -> OwnedParseError {
+        OwnedParseError::new(self.to_string(), self.bencoding(), root)
+    }
 }
 
 impl<'b> fmt::Display for TryFromBencodingError<'b> {
@@ -62,6 +131,7 @@ impl<'b> fmt::Display for TryFromBencodingError<'b> {
             ExpectedList(incorrect) => write!(f, "bencoding {} is not a list", incorrect),
             ExpectedDict(incorrect) => write!(f, "bencoding {} is not a dictionary", incorrect),
             ExceedsSystemTime(big) => write!(f, "integer {} exceeds UNIX time bounds", big),
+            InvalidSize(bad) => write!(f, "integer {} is not a valid size", bad),
             NotUTF8 { bencoding, error } => write!(
                 f,
                 "bencoding {} is not valid UTF8 because: {}",
@@ -87,7 +157,7 @@ impl<'b> error::Error for TryFromBencodingError<'b> {
 }
 
 #[inline]
-fn extract_int<'b>(bencoding: &'b Bencoding) -> Result<i64, TryFromBencodingError<'b>> {
+pub(crate) fn extract_int<'b>(bencoding: &'b Bencoding) -> Result<i64, TryFromBencodingError<'b>> {
     match bencoding {
         &Bencoding::Int(i) => Ok(i),
         _ => Err(TryFromBencodingError::ExpectedInt(bencoding)),
@@ -95,7 +165,17 @@ fn extract_int<'b>(bencoding: &'b Bencoding) -> Result<i64, TryFromBencodingErro
 }
 
 #[inline]
-fn extract_bytes<'b>(bencoding: &'b Bencoding) -> Result<&'b [u8], TryFromBencodingError<'b>> {
+pub(crate) fn extract_usize<'b>(
+    bencoding: &'b Bencoding,
+) -> Result<usize, TryFromBencodingError<'b>> {
+    let int = extract_int(bencoding)?;
+    usize::try_from(int).map_err(|_| TryFromBencodingError::InvalidSize(int))
+}
+
+#[inline]
+pub(crate) fn extract_bytes<'b>(
+    bencoding: &'b Bencoding,
+) -> Result<&'b [u8], TryFromBencodingError<'b>> {
     match bencoding {
         Bencoding::ByteString(bx) => Ok(bx),
         _ => Err(TryFromBencodingError::ExpectedByteString(bencoding)),
@@ -103,13 +183,28 @@ fn extract_bytes<'b>(bencoding: &'b Bencoding) -> Result<&'b [u8], TryFromBencod
 }
 
 #[inline]
-fn extract_string<'b>(bencoding: &'b Bencoding) -> Result<&'b str, TryFromBencodingError<'b>> {
+pub(crate) fn extract_string<'b>(
+    bencoding: &'b Bencoding,
+) -> Result<&'b str, TryFromBencodingError<'b>> {
     let bytes = extract_bytes(bencoding)?;
     str::from_utf8(bytes).map_err(|e| TryFromBencodingError::from_utf8_error(bencoding, e))
 }
 
 #[inline]
-fn extract_key<'b>(
+// Extract a string meant for people, rather than programs, which we can decode lossily.
+fn extract_text<'b>(
+    bencoding: &'b Bencoding,
+    lossy: bool,
+) -> Result<Cow<'b, str>, TryFromBencodingError<'b>> {
+    if lossy {
+        Ok(String::from_utf8_lossy(extract_bytes(bencoding)?))
+    } else {
+        extract_string(bencoding).map(Cow::Borrowed)
+    }
+}
+
+#[inline]
+pub(crate) fn extract_key<'b>(
     bencoding: &'b Bencoding,
     key: &'static str,
 ) -> Result<&'b Bencoding, TryFromBencodingError<'b>> {
@@ -121,8 +216,20 @@ fn extract_key<'b>(
     }
 }
 
+// Set the source tag of an info dictionary, or remove it.
+fn set_source(info: &mut Dict, source: Option<&str>) {
+    match source {
+        Some(source) => {
+            info.insert(&b"source"[..], Bencoding::from(source));
+        }
+        None => {
+            info.remove(b"source");
+        }
+    }
+}
+
 #[inline]
-fn extract_list<'b>(
+pub(crate) fn extract_list<'b>(
     bencoding: &'b Bencoding,
 ) -> Result<&'b [Bencoding], TryFromBencodingError<'b>> {
     match bencoding {
@@ -136,10 +243,25 @@ fn extract_system_time<'b>(
     bencoding: &'b Bencoding,
 ) -> Result<time::SystemTime, TryFromBencodingError<'b>> {
     let seconds = extract_int(bencoding)?;
-    let from_beginning = time::Duration::from_secs(seconds as u64);
-    time::UNIX_EPOCH
-        .checked_add(from_beginning)
-        .ok_or(TryFromBencodingError::ExceedsSystemTime(seconds))
+    time_from_unix(seconds).ok_or(TryFromBencodingError::ExceedsSystemTime(seconds))
+}
+
+// Convert a UNIX timestamp into a point in time, which can be before 1970.
+fn time_from_unix(seconds: i64) -> Option<time::SystemTime> {
+    let from_epoch = time::Duration::from_secs(seconds.unsigned_abs());
+    if seconds < 0 {
+        time::UNIX_EPOCH.checked_sub(from_epoch)
+    } else {
+        time::UNIX_EPOCH.checked_add(from_epoch)
+    }
+}
+
+// Convert a point in time into a UNIX timestamp, which is negative before 1970.
+fn unix_from_time(time: time::SystemTime) -> i64 {
+    match time.duration_since(time::UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    }
 }
 
 /// Represents the location of some tracker.
@@ -150,7 +272,7 @@ fn extract_system_time<'b>(
 ///
 /// Addresses are kept as strings, because they often require some kind of DNS
 /// resolution, e.g. "tracker.leechers-paradise.org:6969".
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum TrackerAddr {
     /// An address of a tracker that speaks the UDP protocol.
     ///
@@ -171,7 +293,7 @@ pub enum TrackerAddr {
 
 impl From<&str> for TrackerAddr {
     fn from(string: &str) -> Self {
-        let maybe_udp = string.splitn(2, "udp://").skip(1).next();
+        let maybe_udp = string.split_once("udp://").map(|(_, udp)| udp);
         if let Some(udp) = maybe_udp {
             return TrackerAddr::UDP(udp.to_owned());
         }
@@ -179,7 +301,16 @@ impl From<&str> for TrackerAddr {
             // We include the entire string, because http clients like having the URL
             return TrackerAddr::HTTP(string.to_owned());
         }
-        return TrackerAddr::Unknown(string.to_owned());
+        TrackerAddr::Unknown(string.to_owned())
+    }
+}
+
+impl fmt::Display for TrackerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrackerAddr::UDP(addr) => write!(f, "udp://{}", addr),
+            TrackerAddr::HTTP(url) | TrackerAddr::Unknown(url) => write!(f, "{}", url),
+        }
     }
 }
 
@@ -191,6 +322,31 @@ impl<'b> TryFrom<&'b Bencoding> for TrackerAddr {
     }
 }
 
+/// Represents an HTTP server which hosts the content of a torrent.
+///
+/// Besides other peers, pieces can be downloaded from plain HTTP servers. There are two
+/// protocols for doing this, which need to be told apart, since they request data differently.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum HttpSource {
+    /// A server hosting the files of a torrent directly, as in BEP 19.
+    ///
+    /// These come from the `url-list` key, and are requested with ranges of the files themselves.
+    WebSeed(String),
+    /// A server implementing a script that serves pieces, as in BEP 17.
+    ///
+    /// These come from the older `httpseeds` key, and are requested by piece index.
+    HttpSeed(String),
+}
+
+impl HttpSource {
+    /// The URL of this server.
+    pub fn url(&self) -> &str {
+        match self {
+            HttpSource::WebSeed(url) | HttpSource::HttpSeed(url) => url,
+        }
+    }
+}
+
 const PIECE_HASH_SIZE: usize = 20;
 
 /// Represents the SHA1 hash of a given piece.
@@ -198,9 +354,323 @@ const PIECE_HASH_SIZE: usize = 20;
 /// This is how we verify the integrity of the data we receive from a torrent.
 /// For each piece, we can calculate the SHA1 hash of that piece, and compare that
 /// to the information we know about that torrent.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct PieceHash([u8; PIECE_HASH_SIZE]);
 
+// Hashes are much easier to read, and compare with other tools, in hex.
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    for b in bytes {
+        write!(f, "{:02x}", b)?;
+    }
+    Ok(())
+}
+
+// Decode a hex string into a buffer, which it needs to fill exactly.
+fn decode_hex(hex: &str, out: &mut [u8]) -> Option<()> {
+    if hex.len() != 2 * out.len() || !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(())
+}
+
+// Decode the unpadded, RFC 4648 base32 that older magnet links use for v1 info hashes.
+fn decode_base32(base32: &str, out: &mut [u8]) -> Option<()> {
+    if base32.len() * 5 != out.len() * 8 {
+        return None;
+    }
+    let mut acc: u64 = 0;
+    let mut bits = 0;
+    let mut i = 0;
+    for c in base32.bytes() {
+        let value = match c.to_ascii_uppercase() {
+            c @ b'A'..=b'Z' => c - b'A',
+            c @ b'2'..=b'7' => c - b'2' + 26,
+            _ => return None,
+        };
+        acc = (acc << 5) | u64::from(value);
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out[i] = (acc >> bits) as u8;
+            i += 1;
+        }
+    }
+    Some(())
+}
+
+// Compare two hashes in time depending only on their length, to avoid leaking where they differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// An error that can occur when reading a hash out of bytes, or a string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseHashError {
+    /// The input had the wrong length for this kind of hash.
+    ///
+    /// This contains the length of the input.
+    InvalidLength(usize),
+    /// The input had the right length, but contained something other than hex,
+    /// or base32, digits.
+    InvalidDigit,
+}
+
+impl fmt::Display for ParseHashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseHashError::InvalidLength(len) => write!(f, "hash has invalid length {}", len),
+            ParseHashError::InvalidDigit => write!(f, "hash contains invalid digits"),
+        }
+    }
+}
+
+impl error::Error for ParseHashError {}
+
+impl PieceHash {
+    /// Create a piece hash from its raw bytes.
+    pub fn new(bytes: [u8; PIECE_HASH_SIZE]) -> Self {
+        PieceHash(bytes)
+    }
+
+    /// Get the raw bytes of this hash.
+    pub fn as_bytes(&self) -> &[u8; PIECE_HASH_SIZE] {
+        &self.0
+    }
+}
+
+impl fmt::Display for PieceHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_hex(f, &self.0)
+    }
+}
+
+impl str::FromStr for PieceHash {
+    type Err = ParseHashError;
+
+    /// Parse a piece hash from 40 hex digits, in either case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 2 * PIECE_HASH_SIZE {
+            return Err(ParseHashError::InvalidLength(s.len()));
+        }
+        let mut bytes = [0; PIECE_HASH_SIZE];
+        decode_hex(s, &mut bytes).ok_or(ParseHashError::InvalidDigit)?;
+        Ok(PieceHash(bytes))
+    }
+}
+
+impl TryFrom<&[u8]> for PieceHash {
+    type Error = ParseHashError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let mut arr = [0; PIECE_HASH_SIZE];
+        if bytes.len() != PIECE_HASH_SIZE {
+            return Err(ParseHashError::InvalidLength(bytes.len()));
+        }
+        arr.copy_from_slice(bytes);
+        Ok(PieceHash(arr))
+    }
+}
+
+impl fmt::Debug for PieceHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PieceHash(")?;
+        write_hex(f, &self.0)?;
+        write!(f, ")")
+    }
+}
+
+const INFO_HASH_SIZE: usize = 20;
+
+/// Represents the SHA1 hash of the info dictionary of a torrent.
+///
+/// This is how a torrent gets identified when talking to trackers and peers.
+/// Two torrents with the same info hash describe the same content, and belong to the
+/// same swarm, even if the rest of their metadata differs.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InfoHash([u8; INFO_HASH_SIZE]);
+
+impl fmt::Debug for InfoHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "InfoHash(")?;
+        write_hex(f, &self.0)?;
+        write!(f, ")")
+    }
+}
+
+impl InfoHash {
+    /// Create an info hash from its raw bytes.
+    pub fn new(bytes: [u8; INFO_HASH_SIZE]) -> Self {
+        InfoHash(bytes)
+    }
+
+    /// Calculate the info hash of the bencoding of an info dictionary.
+    pub fn of(info: &[u8]) -> Self {
+        InfoHash::of_with::<Sha1>(info)
+    }
+
+    /// Calculate the info hash of an info dictionary, using a different SHA-1 implementation.
+    pub fn of_with<H: Hasher<Output = [u8; INFO_HASH_SIZE]>>(info: &[u8]) -> Self {
+        InfoHash(H::digest(info))
+    }
+
+    /// Get the raw bytes of this hash.
+    pub fn as_bytes(&self) -> &[u8; INFO_HASH_SIZE] {
+        &self.0
+    }
+
+    /// Compare this hash with another, in a way that doesn't leak where they differ.
+    ///
+    /// This matters for servers comparing a hash sent by a client with a secret one.
+    pub fn ct_eq(&self, other: &InfoHash) -> bool {
+        constant_time_eq(&self.0, &other.0)
+    }
+
+    /// Percent-encode the raw bytes of this hash, for the query string of an announce URL.
+    ///
+    /// ```
+    /// use typhoon::core::InfoHash;
+    ///
+    /// let mut bytes = *b"abcdefghij0123456789";
+    /// bytes[0] = 0xFF;
+    /// bytes[1] = b' ';
+    /// assert_eq!("%FF%20cdefghij0123456789", InfoHash::new(bytes).url_encode());
+    /// ```
+    pub fn url_encode(&self) -> String {
+        url_encode(&self.0)
+    }
+}
+
+// Undo percent-encoding, returning `None` if an escape is invalid.
+pub(crate) fn url_decode(value: &str, plus_as_space: bool) -> Option<Vec<u8>> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = bytes.get(i + 1..i + 3)?;
+                out.push(u8::from_str_radix(str::from_utf8(hex).ok()?, 16).ok()?);
+                i += 3;
+                continue;
+            }
+            b'+' if plus_as_space => out.push(b' '),
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    Some(out)
+}
+
+// Percent-encode bytes, leaving alone the characters that URLs never need escaped.
+pub(crate) fn url_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(3 * bytes.len());
+    for &b in bytes {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+impl fmt::Display for InfoHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_hex(f, &self.0)
+    }
+}
+
+impl str::FromStr for InfoHash {
+    type Err = ParseHashError;
+
+    /// Parse an info hash from 40 hex digits, or 32 base32 digits, in either case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bytes = [0; INFO_HASH_SIZE];
+        let decoded = match s.len() {
+            40 => decode_hex(s, &mut bytes),
+            32 => decode_base32(s, &mut bytes),
+            len => return Err(ParseHashError::InvalidLength(len)),
+        };
+        decoded.ok_or(ParseHashError::InvalidDigit)?;
+        Ok(InfoHash(bytes))
+    }
+}
+
+const INFO_HASH_V2_SIZE: usize = 32;
+
+/// Represents the SHA-256 hash of the info dictionary of a v2 torrent.
+///
+/// Trackers, and the DHT, only have room for 20 byte hashes, so the v2 hash gets
+/// truncated when talking to them. Peers use the full hash to verify the metadata they receive.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InfoHashV2([u8; INFO_HASH_V2_SIZE]);
+
+impl fmt::Debug for InfoHashV2 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "InfoHashV2(")?;
+        write_hex(f, &self.0)?;
+        write!(f, ")")
+    }
+}
+
+impl InfoHashV2 {
+    /// Create an info hash from its raw bytes.
+    pub fn new(bytes: [u8; INFO_HASH_V2_SIZE]) -> Self {
+        InfoHashV2(bytes)
+    }
+
+    /// Calculate the info hash of the bencoding of an info dictionary.
+    pub fn of(info: &[u8]) -> Self {
+        InfoHashV2::of_with::<Sha256>(info)
+    }
+
+    /// Calculate the info hash of an info dictionary, using a different SHA-256 implementation.
+    pub fn of_with<H: Hasher<Output = [u8; INFO_HASH_V2_SIZE]>>(info: &[u8]) -> Self {
+        InfoHashV2(H::digest(info))
+    }
+
+    /// Get the raw bytes of this hash.
+    pub fn as_bytes(&self) -> &[u8; INFO_HASH_V2_SIZE] {
+        &self.0
+    }
+
+    /// Truncate this hash to 20 bytes, which is how it's used with trackers and the DHT.
+    pub fn truncated(&self) -> InfoHash {
+        let mut bytes = [0; INFO_HASH_SIZE];
+        bytes.copy_from_slice(&self.0[..INFO_HASH_SIZE]);
+        InfoHash(bytes)
+    }
+
+    /// Compare this hash with another, in a way that doesn't leak where they differ.
+    pub fn ct_eq(&self, other: &InfoHashV2) -> bool {
+        constant_time_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Display for InfoHashV2 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_hex(f, &self.0)
+    }
+}
+
+impl str::FromStr for InfoHashV2 {
+    type Err = ParseHashError;
+
+    /// Parse a v2 info hash from 64 hex digits, in either case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 2 * INFO_HASH_V2_SIZE {
+            return Err(ParseHashError::InvalidLength(s.len()));
+        }
+        let mut bytes = [0; INFO_HASH_V2_SIZE];
+        decode_hex(s, &mut bytes).ok_or(ParseHashError::InvalidDigit)?;
+        Ok(InfoHashV2(bytes))
+    }
+}
+
 /// This contains the info about a specific file in this torrent.
 ///
 /// Torrents include multiple files, each of which has a full path, and a given length.
@@ -208,12 +678,182 @@ pub struct PieceHash([u8; PIECE_HASH_SIZE]);
 /// For example, a movie might have a main file `movie.mp4` as well as subtitles
 /// `subtitles/it.srt`, `subtitles/en.srt`. The video file will be quite a bit larger than
 /// the subtitles, of course.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct FileInfo {
     /// This holds the path of the file.
     pub name: PathBuf,
     /// How many bytes does this file contain.
     pub length: usize,
+    /// Extra information about how this file should be stored.
+    pub attributes: FileAttributes,
+    /// For symlinks, the path this file links to.
+    ///
+    /// Like `name`, this starts with the directory of the torrent, if it has one.
+    pub symlink_path: Option<PathBuf>,
+    /// If present, the SHA1 hash of the entire contents of this file.
+    pub sha1: Option<[u8; PIECE_HASH_SIZE]>,
+    /// If present, the MD5 hash of the entire contents of this file.
+    pub md5sum: Option<[u8; MD5_SIZE]>,
+}
+
+impl FileInfo {
+    /// Create the info for a plain file, without any attributes.
+    pub fn new(name: impl Into<PathBuf>, length: usize) -> Self {
+        FileInfo {
+            name: name.into(),
+            length,
+            attributes: FileAttributes::default(),
+            symlink_path: None,
+            sha1: None,
+            md5sum: None,
+        }
+    }
+
+    /// Check a file on disk against the MD5 checksum of this file.
+    ///
+    /// This returns `None` if we have no checksum to check against. Since pieces
+    /// already get verified, this is mainly useful as a second check after downloading.
+    #[cfg(feature = "fs")]
+    pub fn verify_md5(&self, path: impl AsRef<Path>) -> io::Result<Option<bool>> {
+        self.verify_md5_with::<Md5>(path)
+    }
+
+    /// Check a file on disk against its MD5 checksum, using a different MD5 implementation.
+    #[cfg(feature = "fs")]
+    pub fn verify_md5_with<H: Hasher<Output = [u8; MD5_SIZE]>>(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> io::Result<Option<bool>> {
+        let expected = match self.md5sum {
+            None => return Ok(None),
+            Some(expected) => expected,
+        };
+        let mut file = fs::File::open(path)?;
+        let mut hasher = H::default();
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let read = file.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buf[..read]);
+        }
+        Ok(Some(hasher.finish() == expected))
+    }
+}
+
+/// The attributes of a file in a torrent, as described in BEP 47.
+///
+/// These come from the `attr` string of each file, with one character per attribute.
+/// Characters we don't recognize are ignored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FileAttributes {
+    /// The file only exists to align the next file with a piece boundary, and is full of zeros.
+    ///
+    /// Padding files shouldn't be written to disk.
+    pub padding: bool,
+    /// The file should be marked as executable.
+    pub executable: bool,
+    /// The file should be hidden.
+    pub hidden: bool,
+    /// The file is a symbolic link, to its `symlink_path`.
+    pub symlink: bool,
+}
+
+impl FileAttributes {
+    /// Parse attributes from an `attr` string.
+    pub fn parse(attr: &[u8]) -> Self {
+        FileAttributes {
+            padding: attr.contains(&b'p'),
+            executable: attr.contains(&b'x'),
+            hidden: attr.contains(&b'h'),
+            symlink: attr.contains(&b'l'),
+        }
+    }
+
+    /// Convert these attributes back into an `attr` string.
+    pub fn to_attr(&self) -> String {
+        let flags = [
+            (self.padding, 'p'),
+            (self.executable, 'x'),
+            (self.hidden, 'h'),
+            (self.symlink, 'l'),
+        ];
+        flags
+            .iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, c)| c)
+            .collect()
+    }
+}
+
+const MD5_SIZE: usize = 16;
+
+const MERKLE_HASH_SIZE: usize = 32;
+
+/// Represents a SHA-256 hash in the merkle tree of a file in a v2 torrent.
+///
+/// In version 2 of the protocol, each file gets hashed separately, in blocks of 16KiB.
+/// These hashes form the leaves of a merkle tree, whose root identifies the file.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MerkleHash([u8; MERKLE_HASH_SIZE]);
+
+impl fmt::Debug for MerkleHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MerkleHash(")?;
+        write_hex(f, &self.0)?;
+        write!(f, ")")
+    }
+}
+
+impl MerkleHash {
+    /// Get the raw bytes of this hash.
+    pub fn as_bytes(&self) -> &[u8; MERKLE_HASH_SIZE] {
+        &self.0
+    }
+}
+
+/// Describes which versions of the protocol a torrent can be used with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TorrentVersion {
+    /// The original version of the protocol, hashing pieces with SHA-1.
+    V1,
+    /// Version 2 of the protocol, from BEP 52, hashing each file into a merkle tree with SHA-256.
+    V2,
+    /// A torrent containing the metadata for both versions, so that both kinds of clients
+    /// can join the same swarm.
+    Hybrid,
+    /// A version 1 torrent from BEP 30, which only contains the root of a merkle tree
+    /// over its piece hashes, instead of the hashes themselves.
+    ///
+    /// Peers send the hashes needed to verify each piece against this root along with it.
+    Merkle(PieceHash),
+}
+
+/// This contains the info about a file in a v2 torrent.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct V2FileInfo {
+    /// This holds the path of the file, in the same way as `FileInfo`.
+    pub name: PathBuf,
+    /// How many bytes does this file contain.
+    pub length: usize,
+    /// The root of the merkle tree of this file.
+    ///
+    /// Empty files don't have a root.
+    pub pieces_root: Option<MerkleHash>,
+}
+
+/// Represents the metadata in a torrent file used by version 2 of the protocol.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct V2Metadata {
+    /// The files in this torrent, as described by its `file tree`.
+    pub files: Box<[V2FileInfo]>,
+    /// The hashes of the pieces in each file, keyed by the root of the merkle tree of that file.
+    ///
+    /// Each piece is hashed into a layer of the merkle tree of its file, which is what
+    /// we store here. Files no larger than a single piece don't have an entry, since
+    /// their root is the hash of their only piece.
+    pub piece_layers: HashMap<MerkleHash, Box<[MerkleHash]>>,
 }
 
 /// Represents the information contained in a .torrent file.
@@ -221,7 +861,7 @@ pub struct FileInfo {
 /// This includes information about the files contained in a torrent, including
 /// how they're divided up into pieces, as well as how to connect to an existing
 /// swarm for this torrent.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Torrent {
     /// A list of trackers we can connect to, with different priorities.
     ///
@@ -230,8 +870,17 @@ pub struct Torrent {
     /// one by one, only moving on to the next if we fail to get a response. We can try
     /// trackers of the same priority in any order, but lower values should be tried before
     /// higher values.
+    ///
+    /// The `announce` tracker, and the trackers in the `announce-list`, get merged together,
+    /// with the `announce` tracker first. Each tracker only appears once, in the first tier
+    /// listing it, and tiers left empty are dropped, so tiers are numbered without gaps.
+    ///
+    /// This is empty for trackerless torrents, which rely on the DHT to find peers.
     pub trackers: Box<[(u8, TrackerAddr)]>,
     /// If present, this contains the time of creation of this torrent.
+    ///
+    /// Some torrents in the wild claim to have been created before 1970, which is
+    /// why this can be before the UNIX epoch. See also `creation_timestamp`.
     pub creation: Option<time::SystemTime>,
     /// If present, this contains a message about this torrent.
     pub comment: Option<String>,
@@ -246,7 +895,17 @@ pub struct Torrent {
     ///
     /// For private torrents, we are not allowed to find or broadcast to new peers besides communicating
     /// with the trackers listed in this torrent file.
+    ///
+    /// This flag lives in the info dictionary, so it's part of the info hash: making a public
+    /// torrent private, or the other way around, creates a different swarm.
     pub private: bool,
+    /// If present, a tag naming the tracker this torrent was made for.
+    ///
+    /// Private trackers ask for this so that the same content uploaded to two trackers ends up
+    /// with different info hashes, and thus different swarms. This is what lets users cross-seed
+    /// content, by re-tagging a torrent from one tracker with the source of another.
+    /// See [`Torrent::retag`].
+    pub source: Option<String>,
     /// How many bytes are in each piece (except for the last one).
     pub piece_length: usize,
     /// A sequence of hashes, for each piece in the torrent.
@@ -254,6 +913,9 @@ pub struct Torrent {
     /// This is what allows us to verify the integrity of the torrent as a whole.
     /// Whenever we download a new piece, we can hash its contents, and compare it to the
     /// corresponding hash contained here.
+    ///
+    /// These hashes are copied out of the bencoding into a single allocation.
+    /// `PieceHashesRef` can look them up in the bencoding directly instead.
     pub piece_hashes: Box<[PieceHash]>,
     /// This contained a sequence of information about the files in this torrent.
     ///
@@ -263,132 +925,761 @@ pub struct Torrent {
     /// This means that a piece can overlap an arbitrary number of files, and that the final
     /// piece may be a different length than the others.
     pub files: Box<[FileInfo]>,
-}
-
-/// An error that can occurr when parsing a torrent file.
-///
-/// One big source of these is the bencoding not matching up with our expectations.
-/// For example, we expect an initial dictionary with quite a few keys. If any of those
-/// keys are missing, or the bencoding isn't a dictionary, we have to generate one of
-/// these errors.
-#[derive(Clone, Debug, PartialEq)]
-pub enum ParseTorrentError<'b> {
-    /// The bencoding didn't match the shape of a torrent file.
-    Bencoding(TryFromBencodingError<'b>),
-    /// The length of the concatenated piece hashes was not a multiple of 20.
+    /// The SHA1 hash of the info dictionary of this torrent.
     ///
-    /// A torrent file contains a big byte string, with the hash of each piece one
-    /// after the other. Each hash is the SHA1 hash of the nth piece. SHA1 hashes are 20 bytes long.
-    /// If this byte string is not a multiple of 20, then it can't be a concatenation of N hashes.
-    BadHashLength(usize),
+    /// Torrents which only support v2 still have this hash, but it's only used to tell
+    /// torrents apart, since v2 swarms are identified by `info_hash_v2` instead.
+    ///
+    /// This is calculated from the info dictionary as it appeared in the original bencoding,
+    /// with its keys in their original order. This matches the hash of the original bytes
+    /// as long as the integers inside the info dictionary were encoded without leading zeros.
+    /// Parsing with `Torrent::from_bytes` always uses the original bytes.
+    pub info_hash: InfoHash,
+    /// Which versions of the protocol this torrent can be used with.
+    pub version: TorrentVersion,
+    /// The metadata for version 2 of the protocol, if this torrent has it.
+    ///
+    /// For torrents which only support version 2, the `files` of this torrent are filled in
+    /// from this metadata, but there are no `piece_hashes`.
+    pub v2: Option<V2Metadata>,
+    /// The SHA-256 hash of the info dictionary of this torrent, if it supports v2.
+    pub info_hash_v2: Option<InfoHashV2>,
+    /// HTTP servers we can download pieces from, in addition to peers.
+    ///
+    /// Web seeds come first, in the order of the `url-list`, followed by the `httpseeds`.
+    pub http_sources: Box<[HttpSource]>,
+    /// DHT nodes we can bootstrap from, as pairs of a host and a port.
+    ///
+    /// Torrents without trackers include these, so that we can find peers without
+    /// having joined the DHT beforehand.
+    pub dht_nodes: Box<[(String, u16)]>,
+    /// If present, the encoding the strings of this torrent were written in.
+    ///
+    /// We always decode strings as UTF8, but this can explain why that failed.
+    pub encoding: Option<String>,
+    /// The signatures over the info dictionary of this torrent, sorted by signer.
+    pub signatures: Box<[Signature]>,
+    /// The info hashes of other torrents sharing files with this one, from BEP 38.
+    ///
+    /// Clients can use these to reuse files they've already downloaded for another torrent,
+    /// like the files of an earlier release in a re-pack.
+    pub similar: Box<[InfoHash]>,
+    /// The names of the collections this torrent belongs to, from BEP 38.
+    ///
+    /// Torrents in the same collection are likely to share files, like different
+    /// releases of the same content.
+    pub collections: Box<[String]>,
+    /// The exact bytes of the info dictionary, as they appeared in the original file.
+    ///
+    /// Parsing from bencoding can't see the original bytes, so these are only kept when
+    /// parsing with `Torrent::from_bytes`. See `info_bytes`, which works either way.
+    pub raw_info: Option<Box<[u8]>>,
 }
 
-impl<'b> From<TryFromBencodingError<'b>> for ParseTorrentError<'b> {
-    fn from(error: TryFromBencodingError<'b>) -> Self {
-        ParseTorrentError::Bencoding(error)
+impl Torrent {
+    /// The hash identifying this torrent to trackers and peers.
+    pub fn info_hash(&self) -> InfoHash {
+        self.info_hash
     }
-}
 
-impl<'b> fmt::Display for ParseTorrentError<'b> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        use ParseTorrentError::*;
-        match self {
-            Bencoding(err) => write!(f, "{}", err),
-            BadHashLength(size) => write!(f, "hash length {} is not a multiple of 20", size),
+    /// The hash identifying this torrent to v2 peers, if it supports v2.
+    pub fn info_hash_v2(&self) -> Option<InfoHashV2> {
+        self.info_hash_v2
+    }
+
+    /// The name of this torrent, as suggested by its creator.
+    ///
+    /// This is the directory holding all of the files, or the name of the file, for torrents
+    /// with a single file.
+    pub fn name(&self) -> Option<&str> {
+        match self.files.first()?.name.components().next()? {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
         }
     }
-}
 
-impl<'b> error::Error for ParseTorrentError<'b> {}
+    /// The creation date of this torrent, as a UNIX timestamp, if it has one.
+    ///
+    /// This is negative for torrents claiming to have been created before 1970.
+    pub fn creation_timestamp(&self) -> Option<i64> {
+        self.creation.map(unix_from_time)
+    }
+
+    /// Check whether this torrent has no trackers at all.
+    ///
+    /// Peers for these torrents can only be found through the DHT, or through the
+    /// nodes listed in the torrent.
+    pub fn is_trackerless(&self) -> bool {
+        self.trackers.is_empty()
+    }
+
+    /// The total number of bytes in this torrent, including padding files.
+    pub fn total_size(&self) -> usize {
+        self.files
+            .iter()
+            .fold(0usize, |acc, file| acc.saturating_add(file.length))
+    }
+
+    /// The number of pieces the files of this torrent are split into.
+    ///
+    /// This is derived from the size of the files, and not from the number of piece hashes,
+    /// which might not agree, see `has_consistent_pieces`.
+    pub fn num_pieces(&self) -> usize {
+        if self.piece_length == 0 {
+            return 0;
+        }
+        self.total_size().div_ceil(self.piece_length)
+    }
+
+    /// The number of bytes in the last piece, which can be shorter than the others.
+    ///
+    /// This is 0 if the torrent has no pieces at all.
+    pub fn last_piece_length(&self) -> usize {
+        match self.num_pieces() {
+            0 => 0,
+            n => self.total_size() - (n - 1) * self.piece_length,
+        }
+    }
+
+    /// The number of bytes in a given piece, if that piece exists.
+    pub fn piece_len(&self, index: usize) -> Option<usize> {
+        let num_pieces = self.num_pieces();
+        if index + 1 < num_pieces {
+            Some(self.piece_length)
+        } else if index + 1 == num_pieces {
+            Some(self.last_piece_length())
+        } else {
+            None
+        }
+    }
+
+    /// Check that this torrent has a hash for each of its pieces, and no more.
+    ///
+    /// Torrents which only support v2, and merkle torrents, don't have piece hashes,
+    /// and are always consistent.
+    pub fn has_consistent_pieces(&self) -> bool {
+        match self.version {
+            TorrentVersion::V2 | TorrentVersion::Merkle(_) => true,
+            _ => self.piece_hashes.len() == self.num_pieces(),
+        }
+    }
+
+    /// The files making up the content of this torrent, whatever its version.
+    ///
+    /// Unlike `files`, this never includes padding files, like the ones hybrid torrents
+    /// need to align each file in v1 pieces.
+    pub fn content_files(&self) -> Vec<FileInfo> {
+        self.files
+            .iter()
+            .filter(|file| !file.attributes.padding)
+            .cloned()
+            .collect()
+    }
+
+    /// Create a copy of this torrent with a different source tag, or without one.
+    ///
+    /// Since the source is part of the info dictionary, this gives the torrent a new info hash.
+    /// We compute it by changing the source key in `info_bytes`, and leaving every other entry
+    /// as it was, including keys we don't otherwise keep track of. The new bytes are kept
+    /// in `raw_info`, so that they can be sent to peers.
+    ///
+    /// If this torrent was parsed from bencoding, rather than bytes, `info_bytes` only contains
+    /// the keys we know about; use `retag_bencoding` on the original bencoding instead.
+    ///
+    /// ```
+    /// use typhoon::core::Torrent;
+    ///
+    /// let input = b"d4:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces0:6:source1:Aee";
+    /// let torrent = Torrent::from_bytes(input).unwrap();
+    /// let retagged = torrent.retag(Some("B"));
+    /// assert_eq!(Some("B"), retagged.source.as_deref());
+    /// assert_ne!(torrent.info_hash(), retagged.info_hash());
+    /// ```
+    pub fn retag(&self, source: Option<&str>) -> Torrent {
+        let mut info =
+            Bencoding::decode(&self.info_bytes()).expect("info dictionaries are valid bencoding");
+        if let Some(info) = info.as_dict_mut() {
+            set_source(info, source);
+        }
+        let info_bytes = info.encode_in_order();
+        Torrent {
+            source: source.map(String::from),
+            info_hash: InfoHash::of(&info_bytes),
+            info_hash_v2: self.info_hash_v2.map(|_| InfoHashV2::of(&info_bytes)),
+            raw_info: Some(info_bytes.into_boxed_slice()),
+            ..self.clone()
+        }
+    }
+
+    /// Change the source tag of the torrent in some bencoding, or remove it.
+    ///
+    /// Like `retag`, but working on the bencoding of a whole .torrent file, which
+    /// can then be encoded with `encode_in_order`. Nothing else in the file changes.
+    /// This returns false, and does nothing, if the bencoding has no info dictionary.
+    ///
+    /// ```
+    /// use std::convert::TryFrom;
+    /// use typhoon::{bencoding::Bencoding, core::Torrent};
+    ///
+    /// let input = b"d4:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces0:6:source1:Aee";
+    /// let mut bencoding = Bencoding::decode(input).unwrap();
+    /// assert!(Torrent::retag_bencoding(&mut bencoding, Some("B")));
+    /// let torrent = Torrent::try_from(&bencoding).unwrap();
+    /// assert_eq!(Some("B"), torrent.source.as_deref());
+    /// ```
+    pub fn retag_bencoding(bencoding: &mut Bencoding, source: Option<&str>) -> bool {
+        let info = bencoding
+            .as_dict_mut()
+            .and_then(|root| root.get_mut(b"info"))
+            .and_then(Bencoding::as_dict_mut);
+        match info {
+            Some(info) => {
+                set_source(info, source);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The bytes of the info dictionary of this torrent, which its info hash is the hash of.
+    ///
+    /// These are the original bytes if we kept them, and the canonical encoding of the
+    /// info dictionary otherwise. Only the original bytes are guaranteed to match the
+    /// info hash, which matters when sending the metadata of a torrent to other peers.
+    pub fn info_bytes(&self) -> Cow<'_, [u8]> {
+        match &self.raw_info {
+            Some(raw) => Cow::Borrowed(raw),
+            None => Cow::Owned(
+                extract_key(&self.to_bencoding(), "info")
+                    .expect("serialized torrents have an info dictionary")
+                    .encode(),
+            ),
+        }
+    }
+
+    /// Convert this torrent back into bencoding.
+    ///
+    /// The keys of each dictionary end up sorted, so encoding the result gives canonical
+    /// bytes, and parsing those bytes again gives back the same torrent. The info hash of
+    /// the result only matches `info_hash` if the original info dictionary was canonical,
+    /// and didn't contain any keys we don't keep track of.
+    pub fn to_bencoding(&self) -> Bencoding {
+        Bencoding::from(self)
+    }
+
+    /// Convert this torrent into the canonical bytes of a .torrent file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_bencoding().encode()
+    }
+}
+
+/// An error that can occurr when parsing a torrent file.
+///
+/// One big source of these is the bencoding not matching up with our expectations.
+/// For example, we expect an initial dictionary with quite a few keys. If any of those
+/// keys are missing, or the bencoding isn't a dictionary, we have to generate one of
+/// these errors.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseTorrentError<'b> {
+    /// The bencoding didn't match the shape of a torrent file.
+    Bencoding(TryFromBencodingError<'b>),
+    /// The length of the concatenated piece hashes was not a multiple of 20.
+    ///
+    /// A torrent file contains a big byte string, with the hash of each piece one
+    /// after the other. Each hash is the SHA1 hash of the nth piece. SHA1 hashes are 20 bytes long.
+    /// If this byte string is not a multiple of 20, then it can't be a concatenation of N hashes.
+    BadHashLength(usize),
+    /// A pieces root, or a piece layer, of a v2 torrent wasn't made of SHA-256 hashes.
+    ///
+    /// This contains the length of the byte string, which should have been a multiple of 32.
+    BadMerkleHashLength(usize),
+    /// An info hash in the `similar` list of a torrent wasn't 20 bytes long.
+    ///
+    /// This contains the length of the byte string we found instead.
+    BadSimilarHashLength(usize),
+    /// The root hash of a merkle torrent wasn't 20 bytes long.
+    ///
+    /// This contains the length of the byte string we found instead.
+    BadRootHashLength(usize),
+    /// The v1 and v2 metadata of a hybrid torrent describe different files.
+    ///
+    /// Besides padding files, the v1 file list of a hybrid torrent needs to match the
+    /// files in its v2 file tree, in the same order. Otherwise, v1 and v2 peers would
+    /// end up downloading different content.
+    HybridMismatch,
+    /// The SHA1 hash of a file wasn't 20 bytes long.
+    ///
+    /// This contains the length of the byte string we found instead.
+    BadFileHashLength(usize),
+    /// The MD5 checksum of a file wasn't 16 bytes, or 32 hexadecimal digits.
+    ///
+    /// This contains the offending checksum.
+    BadMd5Sum(&'b Bencoding),
+    /// An entry in the DHT nodes of a torrent wasn't a pair of a host and a port.
+    ///
+    /// This contains the offending entry.
+    BadNode(&'b Bencoding),
+}
+
+impl<'b> From<TryFromBencodingError<'b>> for ParseTorrentError<'b> {
+    fn from(error: TryFromBencodingError<'b>) -> Self {
+        ParseTorrentError::Bencoding(error)
+    }
+}
+
+impl<'b> fmt::Display for ParseTorrentError<'b> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ParseTorrentError::*;
+        match self {
+            Bencoding(err) => write!(f, "{}", err),
+            BadHashLength(size) => write!(f, "hash length {} is not a multiple of 20", size),
+            BadMerkleHashLength(size) => {
+                write!(f, "merkle hash length {} is not a multiple of 32", size)
+            }
+            BadSimilarHashLength(size) => write!(f, "similar info hash length {} is not 20", size),
+            BadRootHashLength(size) => write!(f, "root hash length {} is not 20", size),
+            HybridMismatch => write!(f, "v1 and v2 metadata describe different files"),
+            BadFileHashLength(size) => write!(f, "file hash length {} is not 20", size),
+            BadMd5Sum(sum) => write!(f, "bencoding {} is not an MD5 checksum", sum),
+            BadNode(node) => write!(f, "bencoding {} is not a host and a port", node),
+        }
+    }
+}
+
+impl<'b> error::Error for ParseTorrentError<'b> {}
+
+impl<'b> ParseTorrentError<'b> {
+    /// The bencoding this error is about, if it points at some.
+    pub fn bencoding(&self) -> Option<&'b Bencoding> {
+        match self {
+            ParseTorrentError::Bencoding(error) => error.bencoding(),
+            ParseTorrentError::BadNode(node) | ParseTorrentError::BadMd5Sum(node) => Some(node),
+            _ => None,
+        }
+    }
+
+    /// Convert this error into one which doesn't borrow the bencoding.
+    ///
+    /// This needs the bencoding that was being parsed, in order to find the path to
+    /// the part of it this error is about.
+    pub fn into_owned(self, root: &Bencoding) -> OwnedParseError {
+        OwnedParseError::new(self.to_string(), self.bencoding(), root)
+    }
+}
+
+/// A snapshot of an error that occurred while parsing a torrent, owning all of its data.
+///
+/// The errors we produce while parsing borrow the offending bencoding, which is useful
+/// for inspecting it, but prevents them from outliving that bencoding. This error can be
+/// returned along with the bencoding, or sent to another thread.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OwnedParseError {
+    /// A description of the error, as given by the original error.
+    pub message: String,
+    /// A rendering of the bencoding the error was about, if any.
+    pub value: Option<String>,
+    /// The path to the bencoding the error was about, like `info.files[0].length`.
+    pub path: Option<String>,
+}
+
+impl OwnedParseError {
+    fn new(message: String, bencoding: Option<&Bencoding>, root: &Bencoding) -> Self {
+        OwnedParseError {
+            message,
+            value: bencoding.map(|b| b.to_string()),
+            path: bencoding.and_then(|b| root.path_to(b)),
+        }
+    }
+}
+
+impl fmt::Display for OwnedParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) if path.is_empty() => write!(f, "at root: {}", self.message),
+            Some(path) => write!(f, "at {}: {}", path, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl error::Error for OwnedParseError {}
+
+/// Options controlling how lenient we are when parsing a torrent.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TorrentOptions {
+    /// Replace invalid UTF8 in names, paths, and comments, instead of failing.
+    ///
+    /// Torrents created on systems using other encodings often contain such strings.
+    /// When a torrent has `name.utf-8` or `path.utf-8` keys, those get used instead,
+    /// whether or not this is set.
+    pub lossy_strings: bool,
+}
 
 impl<'b> TryFrom<&'b Bencoding> for Torrent {
     type Error = ParseTorrentError<'b>;
 
     fn try_from(bencoding: &'b Bencoding) -> Result<Self, Self::Error> {
+        Torrent::parse_with(bencoding, &TorrentOptions::default())
+    }
+}
+
+impl Torrent {
+    /// Parse a torrent out of bencoding, with some options.
+    ///
+    /// Using `TryFrom` is equivalent to using the default options.
+    pub fn parse_with<'b>(
+        bencoding: &'b Bencoding,
+        options: &TorrentOptions,
+    ) -> Result<Torrent, ParseTorrentError<'b>> {
+        Torrent::parse_raw(bencoding, options, None)
+    }
+
+    // Parse a torrent, using the original bytes of its info dictionary, if we have them.
+    fn parse_raw<'b>(
+        bencoding: &'b Bencoding,
+        options: &TorrentOptions,
+        raw_info: Option<&[u8]>,
+    ) -> Result<Torrent, ParseTorrentError<'b>> {
+        // Get a key, preferring the variant with a `.utf-8` suffix, which some programs add
+        // next to the original, when the original isn't encoded in UTF8.
+        fn extract_utf8_key<'b>(
+            bencoding: &'b Bencoding,
+            key: &'static str,
+            utf8_key: &'static str,
+        ) -> Result<&'b Bencoding, ParseTorrentError<'b>> {
+            Ok(extract_key(bencoding, utf8_key).or_else(|_| extract_key(bencoding, key))?)
+        }
+
         fn extract_trackers(
             bencoding: &Bencoding,
         ) -> Result<Box<[(u8, TrackerAddr)]>, ParseTorrentError<'_>> {
-            match extract_key(bencoding, "announce-list") {
-                Err(_) => {
-                    let tracker = TrackerAddr::try_from(bencoding)?;
-                    Ok(vec![(0, tracker)].into_boxed_slice())
-                }
-                Ok(inner) => {
-                    let tiers = extract_list(inner)?;
-                    let mut trackers = Vec::with_capacity(tiers.len());
-                    for (index, tier) in tiers.iter().enumerate() {
-                        let tier_list = extract_list(tier)?;
-                        for tracker in tier_list {
-                            trackers.push((index as u8, TrackerAddr::try_from(tracker)?))
+            let mut trackers: Vec<(u8, TrackerAddr)> = Vec::new();
+            if let Ok(inner) = extract_key(bencoding, "announce-list") {
+                let mut tier: u8 = 0;
+                for tier_list in extract_list(inner)? {
+                    let mut added = false;
+                    for tracker in extract_list(tier_list)? {
+                        let tracker = TrackerAddr::try_from(tracker)?;
+                        // Listing a tracker again would only make us contact it twice
+                        if !trackers.iter().any(|(_, t)| *t == tracker) {
+                            trackers.push((tier, tracker));
+                            added = true;
                         }
                     }
-                    Ok(trackers.into_boxed_slice())
+                    if added {
+                        tier = tier.saturating_add(1);
+                    }
+                }
+            }
+            // The announce tracker comes first, unless the announce-list already has it
+            if let Ok(announce) = extract_key(bencoding, "announce") {
+                let tracker = TrackerAddr::try_from(announce)?;
+                if !trackers.iter().any(|(_, t)| *t == tracker) {
+                    trackers.insert(0, (0, tracker));
                 }
             }
+            Ok(trackers.into_boxed_slice())
         }
 
         fn extract_piece_hashes(
             info: &Bencoding,
         ) -> Result<Box<[PieceHash]>, ParseTorrentError<'_>> {
             let piece_bytes = extract_bytes(extract_key(info, "pieces")?)?;
-            let piece_bytes_len = piece_bytes.len();
-            if piece_bytes_len % PIECE_HASH_SIZE != 0 {
-                return Err(ParseTorrentError::BadHashLength(piece_bytes_len));
-            }
-            let mut piece_hashes = Vec::with_capacity(piece_bytes_len / PIECE_HASH_SIZE);
-            for chunk in piece_bytes.chunks_exact(PIECE_HASH_SIZE) {
-                let mut arr: [u8; PIECE_HASH_SIZE] = Default::default();
-                arr.copy_from_slice(chunk);
-                piece_hashes.push(PieceHash(arr));
-            }
-            Ok(piece_hashes.into_boxed_slice())
+            let piece_hashes = PieceHashesRef::new(piece_bytes)
+                .map_err(|_| ParseTorrentError::BadHashLength(piece_bytes.len()))?;
+            Ok(piece_hashes.to_piece_hashes())
         }
 
         fn extract_path_from_list<'b>(
             bencoding: &'b Bencoding,
             path: &mut PathBuf,
+            lossy: bool,
         ) -> Result<(), ParseTorrentError<'b>> {
             let list = extract_list(bencoding)?;
             for el in list {
-                let path_part: PathBuf = extract_string(el)?.into();
-                path.push(path_part);
+                path.push(&*extract_text(el, lossy)?);
+            }
+            Ok(())
+        }
+
+        // Fill in the optional fields of a file, from BEP 47, with paths relative to a directory.
+        fn extract_file_extras<'b>(
+            file: &'b Bencoding,
+            dir: &Path,
+            info: &mut FileInfo,
+            lossy: bool,
+        ) -> Result<(), ParseTorrentError<'b>> {
+            if let Ok(attr) = extract_key(file, "attr") {
+                info.attributes = FileAttributes::parse(extract_bytes(attr)?);
+            }
+            if let Ok(symlink) = extract_key(file, "symlink path") {
+                let mut path = dir.to_path_buf();
+                extract_path_from_list(symlink, &mut path, lossy)?;
+                info.symlink_path = Some(path);
+            }
+            if let Ok(sha1) = extract_key(file, "sha1") {
+                let bytes = extract_bytes(sha1)?;
+                let mut arr = [0; PIECE_HASH_SIZE];
+                if bytes.len() != PIECE_HASH_SIZE {
+                    return Err(ParseTorrentError::BadFileHashLength(bytes.len()));
+                }
+                arr.copy_from_slice(bytes);
+                info.sha1 = Some(arr);
+            }
+            // This is usually written out in hex, but some programs put in the raw bytes instead
+            if let Ok(md5sum) = extract_key(file, "md5sum") {
+                let bytes = extract_bytes(md5sum)?;
+                let mut arr = [0; MD5_SIZE];
+                let valid = if bytes.len() == MD5_SIZE {
+                    arr.copy_from_slice(bytes);
+                    true
+                } else {
+                    let hex = str::from_utf8(bytes).ok();
+                    hex.and_then(|hex| decode_hex(hex, &mut arr)).is_some()
+                };
+                if !valid {
+                    return Err(ParseTorrentError::BadMd5Sum(md5sum));
+                }
+                info.md5sum = Some(arr);
             }
             Ok(())
         }
 
-        fn extract_files(info: &Bencoding) -> Result<Box<[FileInfo]>, ParseTorrentError<'_>> {
+        fn extract_files(
+            info: &Bencoding,
+            lossy: bool,
+        ) -> Result<Box<[FileInfo]>, ParseTorrentError<'_>> {
+            let name = extract_utf8_key(info, "name", "name.utf-8")?;
+            let name = PathBuf::from(&*extract_text(name, lossy)?);
             match extract_key(info, "files") {
                 Err(_) => {
-                    let name: PathBuf = extract_string(extract_key(info, "name")?)?.into();
-                    let length = extract_int(extract_key(info, "length")?)? as usize;
-                    Ok(vec![FileInfo { name, length }].into_boxed_slice())
+                    let length = extract_usize(extract_key(info, "length")?)?;
+                    let mut file_info = FileInfo::new(name, length);
+                    extract_file_extras(info, Path::new(""), &mut file_info, lossy)?;
+                    Ok(vec![file_info].into_boxed_slice())
                 }
                 Ok(inner) => {
-                    let dir: PathBuf = extract_string(extract_key(info, "name")?)?.into();
+                    let dir = name;
                     let files = extract_list(inner)?;
                     let mut file_infos = Vec::with_capacity(files.len());
                     for file in files {
                         let mut name = dir.clone();
-                        let length = extract_int(extract_key(file, "length")?)? as usize;
-                        let path_list = extract_key(file, "path")?;
-                        extract_path_from_list(path_list, &mut name)?;
-                        file_infos.push(FileInfo { name, length });
+                        let length = extract_usize(extract_key(file, "length")?)?;
+                        let path_list = extract_utf8_key(file, "path", "path.utf-8")?;
+                        extract_path_from_list(path_list, &mut name, lossy)?;
+                        let mut file_info = FileInfo::new(name, length);
+                        extract_file_extras(file, &dir, &mut file_info, lossy)?;
+                        file_infos.push(file_info);
                     }
                     Ok(file_infos.into_boxed_slice())
                 }
             }
         }
 
+        fn extract_merkle_hashes(
+            bencoding: &Bencoding,
+        ) -> Result<Box<[MerkleHash]>, ParseTorrentError<'_>> {
+            let bytes = extract_bytes(bencoding)?;
+            if bytes.len() % MERKLE_HASH_SIZE != 0 {
+                return Err(ParseTorrentError::BadMerkleHashLength(bytes.len()));
+            }
+            let mut hashes = Vec::with_capacity(bytes.len() / MERKLE_HASH_SIZE);
+            for chunk in bytes.chunks_exact(MERKLE_HASH_SIZE) {
+                let mut arr = [0; MERKLE_HASH_SIZE];
+                arr.copy_from_slice(chunk);
+                hashes.push(MerkleHash(arr));
+            }
+            Ok(hashes.into_boxed_slice())
+        }
+
+        fn extract_merkle_hash(bencoding: &Bencoding) -> Result<MerkleHash, ParseTorrentError<'_>> {
+            match *extract_merkle_hashes(bencoding)? {
+                [hash] => Ok(hash),
+                ref other => Err(ParseTorrentError::BadMerkleHashLength(
+                    other.len() * MERKLE_HASH_SIZE,
+                )),
+            }
+        }
+
+        // Walk through a file tree, where files are marked by an entry with an empty key.
+        fn extract_file_tree<'b>(
+            tree: &'b Bencoding,
+            path: &mut PathBuf,
+            files: &mut Vec<V2FileInfo>,
+        ) -> Result<(), ParseTorrentError<'b>> {
+            let dict = match tree {
+                Bencoding::Dict(dict) => dict,
+                _ => return Err(TryFromBencodingError::ExpectedDict(tree).into()),
+            };
+            for (key, value) in dict.iter() {
+                if key.is_empty() {
+                    let length = extract_usize(extract_key(value, "length")?)?;
+                    let pieces_root = extract_key(value, "pieces root")
+                        .ok()
+                        .map(extract_merkle_hash)
+                        .transpose()?;
+                    files.push(V2FileInfo {
+                        name: path.clone(),
+                        length,
+                        pieces_root,
+                    });
+                    continue;
+                }
+                // Path elements are keys rather than values, so we point at the directory instead
+                let part = str::from_utf8(key)
+                    .map_err(|e| TryFromBencodingError::from_utf8_error(tree, e))?;
+                path.push(part);
+                extract_file_tree(value, path, files)?;
+                path.pop();
+            }
+            Ok(())
+        }
+
+        fn extract_v2<'b>(
+            bencoding: &'b Bencoding,
+            info: &'b Bencoding,
+            lossy: bool,
+        ) -> Result<Option<V2Metadata>, ParseTorrentError<'b>> {
+            let meta_version = extract_key(info, "meta version")
+                .ok()
+                .map(extract_int)
+                .transpose()?;
+            if meta_version != Some(2) {
+                return Ok(None);
+            }
+            let mut files = Vec::new();
+            extract_file_tree(
+                extract_key(info, "file tree")?,
+                &mut PathBuf::new(),
+                &mut files,
+            )?;
+            // Like in v1 torrents, the name is the directory holding the files, unless
+            // there's only a single file, in which case it's the name of that file.
+            let single_file = files.len() == 1 && files[0].name.components().count() == 1;
+            if !single_file {
+                let name = extract_utf8_key(info, "name", "name.utf-8")?;
+                let dir = PathBuf::from(&*extract_text(name, lossy)?);
+                for file in &mut files {
+                    file.name = dir.join(&file.name);
+                }
+            }
+            let mut piece_layers = HashMap::new();
+            if let Ok(layers) = extract_key(bencoding, "piece layers") {
+                let layers = match layers {
+                    Bencoding::Dict(dict) => dict,
+                    _ => return Err(TryFromBencodingError::ExpectedDict(layers).into()),
+                };
+                for (root, layer) in layers.iter() {
+                    if root.len() != MERKLE_HASH_SIZE {
+                        return Err(ParseTorrentError::BadMerkleHashLength(root.len()));
+                    }
+                    let mut arr = [0; MERKLE_HASH_SIZE];
+                    arr.copy_from_slice(root);
+                    piece_layers.insert(MerkleHash(arr), extract_merkle_hashes(layer)?);
+                }
+            }
+            Ok(Some(V2Metadata {
+                files: files.into_boxed_slice(),
+                piece_layers,
+            }))
+        }
+
+        fn extract_http_sources(
+            bencoding: &Bencoding,
+        ) -> Result<Box<[HttpSource]>, ParseTorrentError<'_>> {
+            let mut sources = Vec::new();
+            // A single web seed can be a string on its own, rather than a list
+            match extract_key(bencoding, "url-list") {
+                Err(_) => {}
+                Ok(Bencoding::List(urls)) => {
+                    for url in urls.iter() {
+                        sources.push(HttpSource::WebSeed(extract_string(url)?.to_owned()));
+                    }
+                }
+                Ok(url) => sources.push(HttpSource::WebSeed(extract_string(url)?.to_owned())),
+            }
+            if let Ok(urls) = extract_key(bencoding, "httpseeds") {
+                for url in extract_list(urls)? {
+                    sources.push(HttpSource::HttpSeed(extract_string(url)?.to_owned()));
+                }
+            }
+            // Some torrents contain an empty string when they don't have any seeds
+            sources.retain(|source| !source.url().is_empty());
+            Ok(sources.into_boxed_slice())
+        }
+
+        fn extract_dht_nodes(
+            bencoding: &Bencoding,
+        ) -> Result<Box<[(String, u16)]>, ParseTorrentError<'_>> {
+            let nodes = match extract_key(bencoding, "nodes") {
+                Err(_) => return Ok(Box::new([])),
+                Ok(nodes) => extract_list(nodes)?,
+            };
+            let mut dht_nodes = Vec::with_capacity(nodes.len());
+            for node in nodes {
+                match extract_list(node)? {
+                    [host, port] => {
+                        let host = extract_string(host)?.to_owned();
+                        let port = u16::try_from(extract_int(port)?)
+                            .map_err(|_| ParseTorrentError::BadNode(node))?;
+                        dht_nodes.push((host, port));
+                    }
+                    _ => return Err(ParseTorrentError::BadNode(node)),
+                }
+            }
+            Ok(dht_nodes.into_boxed_slice())
+        }
+
+        fn extract_similar(info: &Bencoding) -> Result<Box<[InfoHash]>, ParseTorrentError<'_>> {
+            let similar = match extract_key(info, "similar") {
+                Err(_) => return Ok(Box::new([])),
+                Ok(similar) => extract_list(similar)?,
+            };
+            let mut hashes = Vec::with_capacity(similar.len());
+            for hash in similar {
+                let bytes = extract_bytes(hash)?;
+                if bytes.len() != INFO_HASH_SIZE {
+                    return Err(ParseTorrentError::BadSimilarHashLength(bytes.len()));
+                }
+                let mut arr = [0; INFO_HASH_SIZE];
+                arr.copy_from_slice(bytes);
+                hashes.push(InfoHash(arr));
+            }
+            Ok(hashes.into_boxed_slice())
+        }
+
+        fn extract_collections(
+            info: &Bencoding,
+            lossy: bool,
+        ) -> Result<Box<[String]>, ParseTorrentError<'_>> {
+            let collections = match extract_key(info, "collections") {
+                Err(_) => return Ok(Box::new([])),
+                Ok(collections) => extract_list(collections)?,
+            };
+            let mut names = Vec::with_capacity(collections.len());
+            for name in collections {
+                names.push(extract_text(name, lossy)?.into_owned());
+            }
+            Ok(names.into_boxed_slice())
+        }
+
+        let lossy = options.lossy_strings;
         let trackers = extract_trackers(bencoding)?;
+        let dht_nodes = extract_dht_nodes(bencoding)?;
+        let http_sources = extract_http_sources(bencoding)?;
         let creation = extract_key(bencoding, "creation date")
             .ok()
             .map(extract_system_time)
             .transpose()?;
         let comment = extract_key(bencoding, "comment")
             .ok()
-            .map(|inner| extract_string(inner).map(String::from))
+            .map(|inner| extract_text(inner, lossy).map(String::from))
             .transpose()?;
         let created_by = extract_key(bencoding, "created by")
+            .ok()
+            .map(|inner| extract_text(inner, lossy).map(String::from))
+            .transpose()?;
+        let encoding = extract_key(bencoding, "encoding")
             .ok()
             .map(|inner| extract_string(inner).map(String::from))
             .transpose()?;
@@ -398,25 +1689,318 @@ impl<'b> TryFrom<&'b Bencoding> for Torrent {
             .map(extract_int)
             .transpose()?;
         let private = private_option.map(|x| x == 1).unwrap_or(false);
-        let piece_length = extract_int(extract_key(info, "piece length")?)? as usize;
-        let piece_hashes = extract_piece_hashes(info)?;
-        let files = extract_files(info)?;
+        let source = extract_key(info, "source")
+            .ok()
+            .map(|inner| extract_text(inner, lossy).map(String::from))
+            .transpose()?;
+        let piece_length = extract_usize(extract_key(info, "piece length")?)?;
+        let similar = extract_similar(info)?;
+        let collections = extract_collections(info, lossy)?;
+        let v2 = extract_v2(bencoding, info, lossy)?;
+        // Torrents which only support v2 have no v1 metadata to speak of
+        let (version, piece_hashes, files) = match &v2 {
+            Some(v2) if extract_key(info, "pieces").is_err() => {
+                let files = v2
+                    .files
+                    .iter()
+                    .map(|file| FileInfo::new(file.name.clone(), file.length))
+                    .collect();
+                (TorrentVersion::V2, Box::new([]) as Box<[PieceHash]>, files)
+            }
+            Some(v2) => {
+                let files = extract_files(info, lossy)?;
+                let content = files
+                    .iter()
+                    .filter(|file| !file.attributes.padding)
+                    .map(|file| (&file.name, file.length));
+                if !content.eq(v2.files.iter().map(|file| (&file.name, file.length))) {
+                    return Err(ParseTorrentError::HybridMismatch);
+                }
+                (TorrentVersion::Hybrid, extract_piece_hashes(info)?, files)
+            }
+            // Merkle torrents replace their piece hashes with the root of a tree over them
+            None if extract_key(info, "pieces").is_err() => {
+                let root = extract_bytes(extract_key(info, "root hash")?)?;
+                let root = PieceHash::try_from(root)
+                    .map_err(|_| ParseTorrentError::BadRootHashLength(root.len()))?;
+                let files = extract_files(info, lossy)?;
+                (
+                    TorrentVersion::Merkle(root),
+                    Box::new([]) as Box<[PieceHash]>,
+                    files,
+                )
+            }
+            None => (
+                TorrentVersion::V1,
+                extract_piece_hashes(info)?,
+                extract_files(info, lossy)?,
+            ),
+        };
+        let info_bytes = match raw_info {
+            Some(raw) => Cow::Borrowed(raw),
+            None => Cow::Owned(info.encode_in_order()),
+        };
+        let info_hash = InfoHash::of(&info_bytes);
+        let info_hash_v2 = v2.as_ref().map(|_| InfoHashV2::of(&info_bytes));
+        let signatures = signature::extract_signatures(bencoding)?;
         Ok(Torrent {
             trackers,
             creation,
             comment,
             created_by,
             private,
+            source,
             piece_length,
             piece_hashes,
             files,
+            info_hash,
+            version,
+            v2,
+            info_hash_v2,
+            http_sources,
+            dht_nodes,
+            encoding,
+            signatures,
+            similar,
+            collections,
+            raw_info: raw_info.map(Box::from),
         })
     }
 }
 
+// A directory in the file tree of a v2 torrent, which we build up before converting to bencoding.
+#[derive(Default)]
+struct FileTree(BTreeMap<String, FileTreeNode>);
+
+enum FileTreeNode {
+    File(Bencoding),
+    Dir(FileTree),
+}
+
+impl FileTree {
+    fn insert(&mut self, parts: &[String], file: Bencoding) {
+        match parts {
+            [] => {}
+            [name] => {
+                self.0.insert(name.clone(), FileTreeNode::File(file));
+            }
+            [dir, rest @ ..] => {
+                let node = self
+                    .0
+                    .entry(dir.clone())
+                    .or_insert_with(|| FileTreeNode::Dir(FileTree::default()));
+                if let FileTreeNode::File(_) = node {
+                    *node = FileTreeNode::Dir(FileTree::default());
+                }
+                if let FileTreeNode::Dir(tree) = node {
+                    tree.insert(rest, file);
+                }
+            }
+        }
+    }
+}
+
+impl From<FileTree> for Bencoding {
+    fn from(tree: FileTree) -> Self {
+        let mut dict = DictBuilder::new();
+        for (name, node) in tree.0 {
+            dict = match node {
+                FileTreeNode::File(file) => dict.insert(name, DictBuilder::new().insert("", file)),
+                FileTreeNode::Dir(tree) => dict.insert(name, tree),
+            };
+        }
+        dict.build()
+    }
+}
+
+// Split a path into its components, as they appear in a torrent file.
+fn path_parts(path: &Path) -> Vec<String> {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+impl From<&Torrent> for Bencoding {
+    fn from(torrent: &Torrent) -> Self {
+        // Paths in a torrent are relative to its name, unless there's a single file at the top
+        let first = torrent
+            .files
+            .first()
+            .map(|file| path_parts(&file.name))
+            .unwrap_or_default();
+        let name = first.first().cloned().unwrap_or_default();
+        let single = torrent.files.len() == 1 && first.len() == 1;
+        let relative = |path: &Path| -> Vec<String> {
+            let parts = path_parts(path);
+            if single {
+                parts
+            } else {
+                parts.into_iter().skip(1).collect()
+            }
+        };
+
+        let mut info = DictBuilder::new()
+            .insert("name", name.as_str())
+            .insert("piece length", torrent.piece_length as i64);
+        if torrent.private {
+            info = info.insert("private", 1);
+        }
+        if let Some(source) = &torrent.source {
+            info = info.insert("source", source.as_str());
+        }
+        if !torrent.similar.is_empty() {
+            let similar: Vec<Bencoding> = torrent
+                .similar
+                .iter()
+                .map(|hash| hash.0.to_vec().into())
+                .collect();
+            info = info.insert("similar", similar);
+        }
+        if !torrent.collections.is_empty() {
+            let collections: Vec<Bencoding> = torrent
+                .collections
+                .iter()
+                .map(|name| name.as_str().into())
+                .collect();
+            info = info.insert("collections", collections);
+        }
+        if let TorrentVersion::Merkle(root) = torrent.version {
+            info = info.insert("root hash", root.0.to_vec());
+        } else if torrent.version != TorrentVersion::V2 {
+            let pieces: Vec<u8> = torrent
+                .piece_hashes
+                .iter()
+                .flat_map(|hash| hash.0.iter().copied())
+                .collect();
+            info = info.insert("pieces", pieces);
+        }
+        if torrent.version != TorrentVersion::V2 {
+            let path_list = |path: &Path| -> Bencoding {
+                let parts: Vec<Bencoding> =
+                    relative(path).into_iter().map(Bencoding::from).collect();
+                parts.into()
+            };
+            let with_extras = |mut entry: DictBuilder, file: &FileInfo| {
+                let attr = file.attributes.to_attr();
+                if !attr.is_empty() {
+                    entry = entry.insert("attr", attr);
+                }
+                if let Some(symlink) = &file.symlink_path {
+                    entry = entry.insert("symlink path", path_list(symlink));
+                }
+                if let Some(sha1) = file.sha1 {
+                    entry = entry.insert("sha1", sha1.to_vec());
+                }
+                if let Some(md5sum) = file.md5sum {
+                    let hex: String = md5sum.iter().map(|b| format!("{:02x}", b)).collect();
+                    entry = entry.insert("md5sum", hex);
+                }
+                entry
+            };
+            if single {
+                let file = &torrent.files[0];
+                info = with_extras(info.insert("length", file.length as i64), file);
+            } else {
+                let mut files = ListBuilder::new();
+                for file in torrent.files.iter() {
+                    let entry = DictBuilder::new()
+                        .insert("length", file.length as i64)
+                        .insert("path", path_list(&file.name));
+                    files = files.push(with_extras(entry, file));
+                }
+                info = info.insert("files", files);
+            }
+        }
+
+        let mut torrent_dict = DictBuilder::new();
+        if let Some(v2) = &torrent.v2 {
+            let mut tree = FileTree::default();
+            for file in v2.files.iter() {
+                let mut entry = DictBuilder::new().insert("length", file.length as i64);
+                if let Some(root) = file.pieces_root {
+                    entry = entry.insert("pieces root", root.0.to_vec());
+                }
+                tree.insert(&relative(&file.name), entry.build());
+            }
+            info = info.insert("meta version", 2).insert("file tree", tree);
+            if !v2.piece_layers.is_empty() {
+                let mut layers = DictBuilder::new();
+                for (root, layer) in &v2.piece_layers {
+                    let hashes: Vec<u8> = layer
+                        .iter()
+                        .flat_map(|hash| hash.0.iter().copied())
+                        .collect();
+                    layers = layers.insert(root.0, hashes);
+                }
+                torrent_dict = torrent_dict.insert("piece layers", layers);
+            }
+        }
+        torrent_dict = torrent_dict.insert("info", info);
+
+        if let Some((_, first)) = torrent.trackers.first() {
+            torrent_dict = torrent_dict.insert("announce", first.to_string());
+            let mut tiers: BTreeMap<u8, Vec<Bencoding>> = BTreeMap::new();
+            for (tier, tracker) in torrent.trackers.iter() {
+                tiers
+                    .entry(*tier)
+                    .or_default()
+                    .push(tracker.to_string().into());
+            }
+            let tiers: Vec<Bencoding> = tiers.into_values().map(Bencoding::from).collect();
+            torrent_dict = torrent_dict.insert("announce-list", tiers);
+        }
+        let mut web_seeds: Vec<Bencoding> = Vec::new();
+        let mut http_seeds: Vec<Bencoding> = Vec::new();
+        for source in torrent.http_sources.iter() {
+            match source {
+                HttpSource::WebSeed(url) => web_seeds.push(url.as_str().into()),
+                HttpSource::HttpSeed(url) => http_seeds.push(url.as_str().into()),
+            }
+        }
+        if !torrent.dht_nodes.is_empty() {
+            let nodes: Vec<Bencoding> = torrent
+                .dht_nodes
+                .iter()
+                .map(|(host, port)| {
+                    vec![host.as_str().into(), Bencoding::from(*port as i64)].into()
+                })
+                .collect();
+            torrent_dict = torrent_dict.insert("nodes", nodes);
+        }
+        if !web_seeds.is_empty() {
+            torrent_dict = torrent_dict.insert("url-list", web_seeds);
+        }
+        if !http_seeds.is_empty() {
+            torrent_dict = torrent_dict.insert("httpseeds", http_seeds);
+        }
+        if let Some(seconds) = torrent.creation_timestamp() {
+            torrent_dict = torrent_dict.insert("creation date", seconds);
+        }
+        if let Some(comment) = &torrent.comment {
+            torrent_dict = torrent_dict.insert("comment", comment.as_str());
+        }
+        if let Some(created_by) = &torrent.created_by {
+            torrent_dict = torrent_dict.insert("created by", created_by.as_str());
+        }
+        if let Some(encoding) = &torrent.encoding {
+            torrent_dict = torrent_dict.insert("encoding", encoding.as_str());
+        }
+        if !torrent.signatures.is_empty() {
+            let signatures = signature::signatures_to_bencoding(&torrent.signatures);
+            torrent_dict = torrent_dict.insert("signatures", signatures);
+        }
+        torrent_dict.build()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::hash::Md5;
+    use std::collections::HashSet;
 
     #[test]
     fn parsing_udp_tracker_addrs() {
@@ -431,4 +2015,541 @@ mod test {
         let expected = TrackerAddr::HTTP("http://tracker.leechers-paradise.org:6969".to_owned());
         assert_eq!(expected, TrackerAddr::from(tracker_string));
     }
+
+    #[test]
+    fn parsing_computes_the_info_hash() {
+        // The info dictionary isn't sorted, so re-encoding it canonically would change its hash
+        let info = b"d4:name1:f6:lengthi5e12:piece lengthi16e6:pieces0:e";
+        let mut input = b"d13:announce-listll9:udp://a:1ee4:info".to_vec();
+        input.extend_from_slice(info);
+        input.push(b'e');
+        let bencoding = Bencoding::decode(&input).unwrap();
+        let torrent = Torrent::try_from(&bencoding).unwrap();
+        assert_eq!(InfoHash::of(info), torrent.info_hash());
+        assert_eq!(Sha1::digest(info), *torrent.info_hash().as_bytes());
+    }
+
+    #[test]
+    fn parsing_v2_torrents_works() {
+        let root = [7u8; 32];
+        let mut input = b"d13:announce-listll9:udp://a:1ee4:infod9:file treed".to_vec();
+        input.extend_from_slice(b"1:ad0:d6:lengthi0eee1:bd1:cd0:d6:lengthi40000e11:pieces root32:");
+        input.extend_from_slice(&root);
+        input.extend_from_slice(b"eeee12:meta versioni2e4:name3:dir12:piece lengthi16384ee");
+        input.extend_from_slice(b"12:piece layersd32:");
+        input.extend_from_slice(&root);
+        input.extend_from_slice(b"96:");
+        input.extend_from_slice(&[1; 96]);
+        input.extend_from_slice(b"ee");
+        let bencoding = Bencoding::decode(&input).unwrap();
+        let torrent = Torrent::try_from(&bencoding).unwrap();
+        assert_eq!(TorrentVersion::V2, torrent.version);
+        assert!(torrent.piece_hashes.is_empty());
+        let v2 = torrent.v2.unwrap();
+        assert_eq!(
+            vec![
+                V2FileInfo {
+                    name: PathBuf::from("dir/a"),
+                    length: 0,
+                    pieces_root: None
+                },
+                V2FileInfo {
+                    name: PathBuf::from("dir/b/c"),
+                    length: 40000,
+                    pieces_root: Some(MerkleHash(root))
+                },
+            ],
+            v2.files.to_vec()
+        );
+        assert_eq!(3, v2.piece_layers[&MerkleHash(root)].len());
+        let names: Vec<_> = torrent.files.iter().map(|f| f.name.clone()).collect();
+        assert_eq!(
+            vec![PathBuf::from("dir/a"), PathBuf::from("dir/b/c")],
+            names
+        );
+    }
+
+    fn hybrid(padding_attr: &str) -> Vec<u8> {
+        let mut input = b"d13:announce-listll9:udp://a:1ee4:infod9:file treed".to_vec();
+        input.extend_from_slice(b"1:ad0:d6:lengthi5e11:pieces root32:");
+        input.extend_from_slice(&[1; 32]);
+        input.extend_from_slice(b"ee1:bd0:d6:lengthi3e11:pieces root32:");
+        input.extend_from_slice(&[2; 32]);
+        input.extend_from_slice(b"eee5:filesld6:lengthi5e4:pathl1:aeed4:attr");
+        input.extend_from_slice(format!("{}:{}", padding_attr.len(), padding_attr).as_bytes());
+        input.extend_from_slice(b"6:lengthi11e4:pathl4:.pad2:11eed6:lengthi3e4:pathl1:bee");
+        input.extend_from_slice(b"e12:meta versioni2e4:name3:dir12:piece lengthi16e6:pieces20:");
+        input.extend_from_slice(&[0; 20]);
+        input.extend_from_slice(b"ee");
+        input
+    }
+
+    #[test]
+    fn parsing_hybrid_torrents_works() {
+        let bencoding = Bencoding::decode(&hybrid("p")).unwrap();
+        let torrent = Torrent::try_from(&bencoding).unwrap();
+        assert_eq!(TorrentVersion::Hybrid, torrent.version);
+        assert_eq!(3, torrent.files.len());
+        let names: Vec<_> = torrent
+            .content_files()
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        assert_eq!(vec![PathBuf::from("dir/a"), PathBuf::from("dir/b")], names);
+        let info = bencoding.lookup("info").unwrap().encode_in_order();
+        let v2 = torrent.info_hash_v2().unwrap();
+        assert_eq!(Sha256::digest(&info), *v2.as_bytes());
+        assert_eq!(v2.as_bytes()[..20], v2.truncated().as_bytes()[..]);
+        assert_ne!(torrent.info_hash(), v2.truncated());
+        // Without the padding attribute, the padding file counts as content
+        let bencoding = Bencoding::decode(&hybrid("x")).unwrap();
+        assert_eq!(
+            Err(ParseTorrentError::HybridMismatch),
+            Torrent::try_from(&bencoding)
+        );
+    }
+
+    #[test]
+    fn torrents_convert_to_magnet_links() {
+        let bencoding = Bencoding::decode(&hybrid("p")).unwrap();
+        let torrent = Torrent::try_from(&bencoding).unwrap();
+        let magnet = torrent.to_magnet();
+        assert_eq!(Some(torrent.info_hash()), magnet.info_hash);
+        assert_eq!(torrent.info_hash_v2(), magnet.info_hash_v2);
+        assert_eq!(Some("dir".to_owned()), magnet.name);
+        assert_eq!(vec![TrackerAddr::UDP("a:1".to_owned())], magnet.trackers);
+        assert_eq!(Ok(magnet.clone()), magnet.to_string().parse());
+    }
+
+    #[test]
+    fn serializing_torrents_round_trips() {
+        let inputs = vec![
+            b"d13:announce-listll9:udp://a:1el9:udp://b:1ee7:comment2:hi4:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces20:aaaaaaaaaaaaaaaaaaaa7:privatei1eee".to_vec(),
+            b"d13:announce-listll9:udp://a:1ee4:infod5:filesld6:lengthi3e4:pathl1:aeed6:lengthi4e4:pathl1:b1:ceee4:name3:dir12:piece lengthi16e6:pieces0:ee".to_vec(),
+            hybrid("p"),
+        ];
+        for input in inputs {
+            let torrent = Torrent::try_from(&Bencoding::decode(&input).unwrap()).unwrap();
+            let bytes = torrent.to_bytes();
+            assert_eq!(Ok(()), Bencoding::is_canonical(&bytes));
+            let reparsed = Torrent::try_from(&Bencoding::decode(&bytes).unwrap()).unwrap();
+            assert_eq!(torrent, reparsed);
+            let info = Bencoding::decode(&input)
+                .unwrap()
+                .lookup("info")
+                .unwrap()
+                .encode();
+            assert_eq!(
+                Bencoding::decode(&bytes)
+                    .unwrap()
+                    .lookup("info")
+                    .unwrap()
+                    .encode(),
+                info
+            );
+        }
+    }
+
+    #[test]
+    fn parsing_http_sources_works() {
+        let input = b"d13:announce-listll9:udp://a:1ee9:httpseedsl8:http://he4:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces0:e8:url-list8:http://we";
+        let torrent = Torrent::try_from(&Bencoding::decode(input).unwrap()).unwrap();
+        let expected = vec![
+            HttpSource::WebSeed("http://w".to_owned()),
+            HttpSource::HttpSeed("http://h".to_owned()),
+        ];
+        assert_eq!(expected, torrent.http_sources.to_vec());
+        let bencoding = Bencoding::decode(&torrent.to_bytes()).unwrap();
+        assert_eq!(Ok(torrent), Torrent::try_from(&bencoding));
+        let input = b"d13:announce-listll9:udp://a:1ee4:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces0:e8:url-listl0:8:http://wee";
+        let torrent = Torrent::try_from(&Bencoding::decode(input).unwrap()).unwrap();
+        assert_eq!(
+            vec![HttpSource::WebSeed("http://w".to_owned())],
+            torrent.http_sources.to_vec()
+        );
+    }
+
+    #[test]
+    fn parsing_dht_nodes_works() {
+        let input = b"d13:announce-listll9:udp://a:1ee4:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces0:e5:nodesll9:127.0.0.1i6881eel4:nodei1eeee";
+        let torrent = Torrent::try_from(&Bencoding::decode(input).unwrap()).unwrap();
+        let expected = vec![("127.0.0.1".to_owned(), 6881), ("node".to_owned(), 1)];
+        assert_eq!(expected, torrent.dht_nodes.to_vec());
+        let bencoding = Bencoding::decode(&torrent.to_bytes()).unwrap();
+        assert_eq!(Ok(torrent), Torrent::try_from(&bencoding));
+        let input = b"d13:announce-listll9:udp://a:1ee4:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces0:e5:nodesll4:nodei70000eeee";
+        let bencoding = Bencoding::decode(input).unwrap();
+        let node = bencoding.lookup("nodes[0]").unwrap();
+        assert_eq!(
+            Err(ParseTorrentError::BadNode(node)),
+            Torrent::try_from(&bencoding)
+        );
+    }
+
+    #[test]
+    fn parsing_trackers_merges_and_deduplicates_them() {
+        let input = b"d8:announce9:udp://x:113:announce-listll9:udp://a:19:udp://a:1el9:udp://a:1el9:udp://b:19:udp://c:1ee4:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces0:ee";
+        let torrent = Torrent::try_from(&Bencoding::decode(input).unwrap()).unwrap();
+        let expected = vec![
+            (0, TrackerAddr::from("udp://x:1")),
+            (0, TrackerAddr::from("udp://a:1")),
+            (1, TrackerAddr::from("udp://b:1")),
+            (1, TrackerAddr::from("udp://c:1")),
+        ];
+        assert_eq!(expected, torrent.trackers.to_vec());
+        let bencoding = Bencoding::decode(&torrent.to_bytes()).unwrap();
+        assert_eq!(Ok(torrent), Torrent::try_from(&bencoding));
+    }
+
+    #[test]
+    fn parsing_trackerless_torrents_works() {
+        let input =
+            b"d4:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces0:e5:nodesll4:nodei1eeee";
+        let torrent = Torrent::try_from(&Bencoding::decode(input).unwrap()).unwrap();
+        assert!(torrent.is_trackerless());
+        assert_eq!(&input[..], &torrent.to_bytes()[..]);
+        let input =
+            b"d8:announce9:udp://a:14:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces0:ee";
+        let torrent = Torrent::try_from(&Bencoding::decode(input).unwrap()).unwrap();
+        assert!(!torrent.is_trackerless());
+        assert_eq!(
+            vec![(0, TrackerAddr::UDP("a:1".to_owned()))],
+            torrent.trackers.to_vec()
+        );
+    }
+
+    #[test]
+    fn retagging_torrents_changes_the_info_hash() {
+        let input =
+            b"d4:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces0:7:privatei1e6:source1:Aee";
+        let torrent = Torrent::from_bytes(input).unwrap();
+        assert_eq!(Some("A"), torrent.source.as_deref());
+        assert_eq!(torrent, torrent.retag(Some("A")));
+        let untagged = torrent.retag(None);
+        assert_ne!(torrent.info_hash(), untagged.info_hash());
+        let bencoding = Bencoding::decode(&untagged.to_bytes()).unwrap();
+        let reparsed = Torrent::try_from(&bencoding).unwrap();
+        assert_eq!(None, reparsed.source);
+        assert_eq!(untagged.info_hash(), reparsed.info_hash());
+    }
+
+    #[test]
+    fn retagging_torrents_keeps_unknown_keys() {
+        // The keys aren't sorted, and some of them aren't ones we keep track of
+        let input = b"d4:infod4:name1:f10:name.utf-81:g6:lengthi5e12:piece lengthi16e6:pieces0:6:source1:A6:vendori1eee";
+        let torrent = Torrent::from_bytes(input).unwrap();
+        let retagged = torrent.retag(Some("B"));
+        let expected = b"d4:name1:f10:name.utf-81:g6:lengthi5e12:piece lengthi16e6:pieces0:6:source1:B6:vendori1ee";
+        assert_eq!(&expected[..], &*retagged.info_bytes());
+        assert_eq!(InfoHash::of(expected), retagged.info_hash());
+
+        let mut bencoding = Bencoding::decode(input).unwrap();
+        assert!(Torrent::retag_bencoding(&mut bencoding, Some("B")));
+        let reparsed = Torrent::from_bytes(&bencoding.encode_in_order()).unwrap();
+        assert_eq!(retagged.info_hash(), reparsed.info_hash());
+        assert!(!Torrent::retag_bencoding(&mut Bencoding::Int(0), None));
+    }
+
+    #[test]
+    fn parsing_similar_torrents_and_collections_works() {
+        let mut input = b"d4:infod11:collectionsl6:seriese6:lengthi5e4:name1:f12:piece lengthi16e6:pieces0:7:similarl20:".to_vec();
+        input.extend_from_slice(&[1; 20]);
+        input.extend_from_slice(b"eee");
+        let torrent = Torrent::try_from(&Bencoding::decode(&input).unwrap()).unwrap();
+        assert_eq!(vec![InfoHash([1; 20])], torrent.similar.to_vec());
+        assert_eq!(vec!["series".to_owned()], torrent.collections.to_vec());
+        assert_eq!(input, torrent.to_bytes());
+        let input = b"d4:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces0:7:similarl1:aeee";
+        assert_eq!(
+            Err(ParseTorrentError::BadSimilarHashLength(1)),
+            Torrent::try_from(&Bencoding::decode(input).unwrap())
+        );
+    }
+
+    #[test]
+    fn errors_convert_to_owned_errors() {
+        let input = b"d13:announce-listll9:udp://a:1ee4:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces0:e5:nodesll4:nodei70000eeee";
+        let owned = {
+            let bencoding = Bencoding::decode(input).unwrap();
+            Torrent::try_from(&bencoding)
+                .unwrap_err()
+                .into_owned(&bencoding)
+        };
+        assert_eq!(Some("nodes[0]".to_owned()), owned.path);
+        assert_eq!(Some("[\"node\", 70000, ]".to_owned()), owned.value);
+        assert!(owned.to_string().starts_with("at nodes[0]: "));
+        let bencoding = Bencoding::decode(b"d13:announce-listll9:udp://a:1ee4:infoi3ee").unwrap();
+        let owned = Torrent::try_from(&bencoding)
+            .unwrap_err()
+            .into_owned(&bencoding);
+        assert_eq!(Some("info".to_owned()), owned.path);
+    }
+
+    #[test]
+    fn parsing_file_attributes_works() {
+        let mut input = b"d13:announce-listll9:udp://a:1ee4:infod5:filesl".to_vec();
+        input.extend_from_slice(b"d4:attr2:xh6:lengthi3e4:pathl1:ae4:sha120:");
+        input.extend_from_slice(&[9; 20]);
+        input.extend_from_slice(b"ed4:attr1:p6:lengthi13e4:pathl4:.pad2:13ee");
+        input.extend_from_slice(b"d4:attr1:l6:lengthi0e4:pathl1:be12:symlink pathl1:aee");
+        input.extend_from_slice(b"e4:name3:dir12:piece lengthi16e6:pieces20:");
+        input.extend_from_slice(&[0; 20]);
+        input.extend_from_slice(b"ee");
+        let torrent = Torrent::try_from(&Bencoding::decode(&input).unwrap()).unwrap();
+        let first = &torrent.files[0];
+        assert!(first.attributes.executable && first.attributes.hidden);
+        assert_eq!(Some([9; 20]), first.sha1);
+        assert!(torrent.files[1].attributes.padding);
+        assert!(torrent.files[2].attributes.symlink);
+        assert_eq!(Some(PathBuf::from("dir/a")), torrent.files[2].symlink_path);
+        let names: Vec<_> = torrent
+            .content_files()
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        assert_eq!(vec![PathBuf::from("dir/a"), PathBuf::from("dir/b")], names);
+        let info = |bytes: &[u8]| {
+            Bencoding::decode(bytes)
+                .unwrap()
+                .lookup("info")
+                .unwrap()
+                .encode()
+        };
+        assert_eq!(info(&input), info(&torrent.to_bytes()));
+        assert_eq!("pxhl", FileAttributes::parse(b"lhxp?").to_attr());
+    }
+
+    // A stand-in for a real hash function, whose digest counts the bytes fed into it.
+    #[derive(Default)]
+    struct Counting<const N: usize>(usize);
+
+    impl<const N: usize> Hasher for Counting<N> {
+        type Output = [u8; N];
+
+        fn update(&mut self, data: &[u8]) {
+            self.0 += data.len();
+        }
+
+        fn finish(self) -> [u8; N] {
+            [self.0 as u8; N]
+        }
+    }
+
+    #[test]
+    fn hashing_with_other_hashers_works() {
+        assert_eq!(
+            InfoHash::new([3; 20]),
+            InfoHash::of_with::<Counting<20>>(b"abc")
+        );
+        assert_eq!(
+            InfoHashV2::new([3; 32]),
+            InfoHashV2::of_with::<Counting<32>>(b"abc")
+        );
+        let leaves = [PieceHash::new([0; 20]); 2];
+        let root = merkle_root_with::<Counting<20>>(&leaves);
+        assert_eq!(PieceHash::new([40; 20]), root);
+        let proof = merkle_proof_with::<Counting<20>>(&leaves, 1).unwrap();
+        assert!(verify_merkle_proof_with::<Counting<20>>(
+            &root, 1, &leaves[1], &proof
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn building_and_verifying_with_other_hashers_works() {
+        let dir = std::env::temp_dir().join(format!("typhoon-hashers-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("f");
+        fs::write(&path, [1; 300]).unwrap();
+        let mut file = FileInfo::new("f", 300);
+        file.md5sum = Some([44; 16]);
+        assert_eq!(
+            Some(true),
+            file.verify_md5_with::<Counting<16>>(&path).unwrap()
+        );
+        let built = TorrentBuilder::new(&path)
+            .piece_length(256)
+            .build_with::<Counting<20>>()
+            .unwrap();
+        let pieces = [[0; 20], [44; 20]].concat();
+        assert_eq!(Ok(&Bencoding::from(pieces)), built.lookup("info.pieces"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parsing_md5sums_works() {
+        let input = b"d13:announce-listll9:udp://a:1ee4:infod6:lengthi3e6:md5sum32:900150983cd24fb0d6963f7d28e17f724:name1:f12:piece lengthi16e6:pieces0:ee";
+        let torrent = Torrent::try_from(&Bencoding::decode(input).unwrap()).unwrap();
+        let file = &torrent.files[0];
+        assert_eq!(Some(Md5::digest(b"abc")), file.md5sum);
+        let bencoding = Bencoding::decode(&torrent.to_bytes()).unwrap();
+        assert_eq!(Ok(torrent.clone()), Torrent::try_from(&bencoding));
+
+        let input = b"d13:announce-listll9:udp://a:1ee4:infod6:lengthi3e6:md5sum3:abc4:name1:f12:piece lengthi16e6:pieces0:ee";
+        let bencoding = Bencoding::decode(input).unwrap();
+        let sum = bencoding.lookup("info.md5sum").unwrap();
+        assert_eq!(
+            Err(ParseTorrentError::BadMd5Sum(sum)),
+            Torrent::try_from(&bencoding)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn verifying_md5sums_works() {
+        let mut file = FileInfo::new("f", 3);
+        file.md5sum = Some(Md5::digest(b"abc"));
+        let path = std::env::temp_dir().join(format!("typhoon-md5-{}", std::process::id()));
+        fs::write(&path, b"abc").unwrap();
+        assert_eq!(Some(true), file.verify_md5(&path).unwrap());
+        fs::write(&path, b"abd").unwrap();
+        assert_eq!(Some(false), file.verify_md5(&path).unwrap());
+        assert_eq!(None, FileInfo::new("f", 3).verify_md5(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn parsing_non_utf8_torrents_works() {
+        let mut input =
+            b"d13:announce-listll9:udp://a:1ee7:comment2:\xff!8:encoding3:GBK4:infod5:filesl"
+                .to_vec();
+        input.extend_from_slice(b"d6:lengthi3e4:pathl2:\xfe\xfde10:path.utf-8l2:okeee");
+        input.extend_from_slice(b"4:name3:\xe4\xb8\x0012:piece lengthi16e6:pieces0:ee");
+        let bencoding = Bencoding::decode(&input).unwrap();
+        let comment = bencoding.lookup("comment").unwrap();
+        assert!(matches!(
+            Torrent::try_from(&bencoding),
+            Err(ParseTorrentError::Bencoding(TryFromBencodingError::NotUTF8 { bencoding, .. }))
+                if bencoding == comment
+        ));
+        let options = TorrentOptions {
+            lossy_strings: true,
+        };
+        let torrent = Torrent::parse_with(&bencoding, &options).unwrap();
+        assert_eq!(Some("GBK".to_owned()), torrent.encoding);
+        assert_eq!(Some("\u{FFFD}!".to_owned()), torrent.comment);
+        assert_eq!(PathBuf::from("\u{FFFD}\u{0}/ok"), torrent.files[0].name);
+        // With UTF8 variants of each name, we don't need to be lossy at all
+        let mut input = b"d13:announce-listll9:udp://a:1ee4:infod5:filesl".to_vec();
+        input.extend_from_slice(b"d6:lengthi3e4:pathl2:\xfe\xfde10:path.utf-8l2:okeee");
+        input.extend_from_slice(
+            b"4:name3:\xe4\xb8\x0010:name.utf-83:dir12:piece lengthi16e6:pieces0:ee",
+        );
+        let torrent = Torrent::try_from(&Bencoding::decode(&input).unwrap()).unwrap();
+        assert_eq!(PathBuf::from("dir/ok"), torrent.files[0].name);
+    }
+
+    #[test]
+    fn piece_arithmetic_works() {
+        let mut input = b"d13:announce-listll9:udp://a:1ee4:infod5:filesl".to_vec();
+        input.extend_from_slice(b"d6:lengthi20e4:pathl1:aeed6:lengthi5e4:pathl1:beee");
+        input.extend_from_slice(b"4:name3:dir12:piece lengthi16e6:pieces40:");
+        input.extend_from_slice(&[0; 40]);
+        input.extend_from_slice(b"ee");
+        let mut torrent = Torrent::try_from(&Bencoding::decode(&input).unwrap()).unwrap();
+        assert_eq!(25, torrent.total_size());
+        assert_eq!(2, torrent.num_pieces());
+        assert_eq!(9, torrent.last_piece_length());
+        assert_eq!(Some(16), torrent.piece_len(0));
+        assert_eq!(Some(9), torrent.piece_len(1));
+        assert_eq!(None, torrent.piece_len(2));
+        assert!(torrent.has_consistent_pieces());
+        torrent.files[1].length = 12;
+        assert_eq!(16, torrent.last_piece_length());
+        torrent.files[1].length = 13;
+        assert!(!torrent.has_consistent_pieces());
+        torrent.files = Box::new([]);
+        assert_eq!(
+            (0, 0, None),
+            (
+                torrent.num_pieces(),
+                torrent.last_piece_length(),
+                torrent.piece_len(0)
+            )
+        );
+    }
+
+    #[test]
+    fn info_hashes_convert_to_and_from_strings() {
+        let hash = InfoHash::new([0xC1; 20]);
+        let hex = hash.to_string();
+        assert_eq!("c1".repeat(20), hex);
+        assert_eq!(Ok(hash), hex.to_uppercase().parse());
+        assert_eq!(Ok(hash), "YHA4DQOBYHA4DQOBYHA4DQOBYHA4DQOB".parse());
+        assert_eq!(Ok(hash), "yha4dqobyha4dqobyha4dqobyha4dqob".parse());
+        assert_eq!(
+            Err(ParseHashError::InvalidLength(3)),
+            "abc".parse::<InfoHash>()
+        );
+        assert_eq!(
+            Err(ParseHashError::InvalidDigit),
+            "1".repeat(32).parse::<InfoHash>()
+        );
+        let v2 = InfoHashV2::new([0xAB; 32]);
+        assert_eq!(Ok(v2), v2.to_string().parse());
+        assert_eq!(
+            Err(ParseHashError::InvalidLength(40)),
+            hex.parse::<InfoHashV2>()
+        );
+        assert!(hash.ct_eq(&InfoHash::new([0xC1; 20])));
+        assert!(!hash.ct_eq(&InfoHash::default()));
+        assert!(v2.ct_eq(&v2) && !v2.ct_eq(&InfoHashV2::default()));
+        assert_eq!("%C1".repeat(20), hash.url_encode());
+    }
+
+    #[test]
+    fn piece_hashes_convert_to_and_from_hex() {
+        let hex = "a9993e364706816aba3e25717850c26c9cd0d89d";
+        let hash: PieceHash = hex.parse().unwrap();
+        assert_eq!(Sha1::digest(b"abc"), *hash.as_bytes());
+        assert_eq!(hex, hash.to_string());
+        assert_eq!(Ok(hash), hex.to_uppercase().parse());
+        assert_eq!(Ok(hash), PieceHash::try_from(&hash.as_bytes()[..]));
+        assert_eq!(
+            Err(ParseHashError::InvalidLength(3)),
+            "abc".parse::<PieceHash>()
+        );
+        assert_eq!(
+            Err(ParseHashError::InvalidDigit),
+            "+9993e364706816aba3e25717850c26c9cd0d89d".parse::<PieceHash>()
+        );
+        assert_eq!(
+            Err(ParseHashError::InvalidLength(2)),
+            PieceHash::try_from(&[0u8, 1][..])
+        );
+    }
+
+    #[test]
+    fn torrents_can_be_compared_and_stored() {
+        let input = b"d13:announce-listll9:udp://a:1el9:udp://a:1ee4:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces0:ee";
+        let torrent = Torrent::try_from(&Bencoding::decode(input).unwrap()).unwrap();
+        let trackers: HashSet<_> = torrent.trackers.iter().map(|(_, t)| t.clone()).collect();
+        assert_eq!(1, trackers.len());
+        let mut by_hash = HashMap::new();
+        by_hash.insert(torrent.info_hash(), torrent.clone());
+        assert_eq!(Some(&torrent), by_hash.get(&torrent.info_hash()));
+        let files: HashSet<_> = torrent.files.iter().cloned().collect();
+        assert!(files.contains(&FileInfo::new("f", 5)));
+    }
+
+    #[test]
+    fn parsing_creation_dates_before_1970_works() {
+        let input =
+            b"d13:creation datei-86400e4:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces0:ee";
+        let torrent = Torrent::try_from(&Bencoding::decode(input).unwrap()).unwrap();
+        assert_eq!(Some(-86400), torrent.creation_timestamp());
+        assert_eq!(
+            Some(time::UNIX_EPOCH - time::Duration::from_secs(86400)),
+            torrent.creation
+        );
+        assert_eq!(&input[..], &torrent.to_bytes()[..]);
+    }
+
+    #[test]
+    fn parsing_negative_lengths_fails() {
+        let input = b"d13:announce-listll9:udp://a:1ee4:infod6:lengthi-5e4:name1:f12:piece lengthi16e6:pieces0:ee";
+        let bencoding = Bencoding::decode(input).unwrap();
+        let expected = ParseTorrentError::Bencoding(TryFromBencodingError::InvalidSize(-5));
+        assert_eq!(Err(expected), Torrent::try_from(&bencoding));
+    }
 }
@@ -0,0 +1,491 @@
+//! This module creates new torrents out of files on disk.
+//!
+//! The files get concatenated, in order, and cut into pieces of a fixed size.
+//! Each piece is then hashed, which is the slow part of creating a torrent, since
+//! it means reading all of the content. This work can be spread over multiple threads.
+use crate::{
+    bencoding::{Bencoding, DictBuilder, ListBuilder},
+    hash::{Hasher, Sha1},
+};
+use std::{
+    cmp,
+    convert::TryFrom,
+    fs,
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+    path::{Path, PathBuf},
+    thread, time,
+};
+
+/// How to choose the number of bytes in each piece of a new torrent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PieceLengthStrategy {
+    /// Use the same piece length no matter how large the content is.
+    Fixed(usize),
+    /// Pick the smallest power of two splitting the content into at most `target_pieces`,
+    /// but no smaller than `min`, and no larger than `max`.
+    ///
+    /// This is what most torrent creators do: small pieces make for large torrent files,
+    /// while large pieces take longer to download before they can be verified and shared.
+    Auto {
+        /// How many pieces the content should be split into, at most.
+        target_pieces: usize,
+        /// The smallest piece length to pick, which should be a power of two.
+        min: usize,
+        /// The largest piece length to pick, which should be a power of two.
+        max: usize,
+    },
+}
+
+impl Default for PieceLengthStrategy {
+    /// Aim for around 1500 pieces, of between 16KiB and 16MiB.
+    fn default() -> Self {
+        PieceLengthStrategy::Auto {
+            target_pieces: 1500,
+            min: 16 * 1024,
+            max: 16 * 1024 * 1024,
+        }
+    }
+}
+
+impl PieceLengthStrategy {
+    /// The piece length to use for content with a given number of bytes.
+    ///
+    /// ```
+    /// use typhoon::core::PieceLengthStrategy;
+    ///
+    /// let strategy = PieceLengthStrategy::default();
+    /// assert_eq!(1 << 20, strategy.piece_length(1_000_000_000));
+    /// assert_eq!(16 * 1024, strategy.piece_length(1000));
+    /// ```
+    pub fn piece_length(&self, total_size: u64) -> usize {
+        match *self {
+            PieceLengthStrategy::Fixed(piece_length) => piece_length,
+            PieceLengthStrategy::Auto {
+                target_pieces,
+                min,
+                max,
+            } => {
+                let ideal = total_size.div_ceil(target_pieces.max(1) as u64);
+                let ideal = usize::try_from(ideal.next_power_of_two()).unwrap_or(usize::MAX);
+                ideal.min(max).max(min).max(1)
+            }
+        }
+    }
+}
+
+/// Creates a new torrent out of a file, or a directory.
+///
+/// ```no_run
+/// use typhoon::core::TorrentBuilder;
+///
+/// let torrent = TorrentBuilder::new("show")
+///     .tracker("udp://tracker.example.com:1337")
+///     .comment("all the episodes")
+///     .threads(4)
+///     .build()
+///     .unwrap();
+/// std::fs::write("show.torrent", torrent.encode()).unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TorrentBuilder {
+    root: PathBuf,
+    piece_length: PieceLengthStrategy,
+    tiers: Vec<Vec<String>>,
+    comment: Option<String>,
+    created_by: Option<String>,
+    creation: Option<time::SystemTime>,
+    private: bool,
+    source: Option<String>,
+    threads: usize,
+}
+
+impl TorrentBuilder {
+    /// Start building a torrent for the content at a path.
+    ///
+    /// If the path is a directory, every file inside of it gets included.
+    /// The piece length is picked with `PieceLengthStrategy::default`, unless set otherwise.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        TorrentBuilder {
+            root: root.into(),
+            piece_length: PieceLengthStrategy::default(),
+            tiers: Vec::new(),
+            comment: None,
+            created_by: None,
+            creation: None,
+            private: false,
+            source: None,
+            threads: 1,
+        }
+    }
+
+    /// Use the same number of bytes in each piece, no matter how large the content is.
+    pub fn piece_length(self, piece_length: usize) -> Self {
+        self.piece_length_strategy(PieceLengthStrategy::Fixed(piece_length))
+    }
+
+    /// Choose the number of bytes in each piece based on the size of the content.
+    pub fn piece_length_strategy(mut self, strategy: PieceLengthStrategy) -> Self {
+        self.piece_length = strategy;
+        self
+    }
+
+    /// Add a tracker, in a tier of its own, after the trackers added so far.
+    pub fn tracker(self, tracker: impl Into<String>) -> Self {
+        self.tier(vec![tracker.into()])
+    }
+
+    /// Add a tier of trackers, which clients can try in any order.
+    pub fn tier(mut self, trackers: Vec<String>) -> Self {
+        self.tiers.push(trackers);
+        self
+    }
+
+    /// Set a message to include with the torrent.
+    pub fn comment(mut self, comment: impl Into<String>) -> Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// Set a description of the program creating the torrent.
+    pub fn created_by(mut self, created_by: impl Into<String>) -> Self {
+        self.created_by = Some(created_by.into());
+        self
+    }
+
+    /// Set the time at which the torrent was created.
+    ///
+    /// Torrents don't contain a creation date by default, since that would make
+    /// building a torrent for the same content twice produce different files.
+    pub fn creation_date(mut self, creation: time::SystemTime) -> Self {
+        self.creation = Some(creation);
+        self
+    }
+
+    /// Mark the torrent as private, so that peers only get found through its trackers.
+    pub fn private(mut self, private: bool) -> Self {
+        self.private = private;
+        self
+    }
+
+    /// Tag the torrent with the tracker it's made for, which changes its info hash.
+    pub fn source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// Set how many threads to hash pieces with, 1 by default.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Read the content of the torrent, and build its bencoding.
+    ///
+    /// The result can be written to a .torrent file with `Bencoding::encode`.
+    pub fn build(&self) -> io::Result<Bencoding> {
+        self.build_with::<Sha1>()
+    }
+
+    /// Build the torrent, hashing its pieces with a different SHA-1 implementation.
+    pub fn build_with<H: Hasher<Output = [u8; 20]>>(&self) -> io::Result<Bencoding> {
+        let name = self
+            .root
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| invalid_input("content must have a UTF8 name"))?;
+        let single = fs::metadata(&self.root)?.is_file();
+        let mut files = Vec::new();
+        if single {
+            files.push((self.root.clone(), fs::metadata(&self.root)?.len()));
+        } else {
+            walk(&self.root, &mut files)?;
+            files.sort();
+        }
+        if files.is_empty() {
+            return Err(invalid_input("directory doesn't contain any files"));
+        }
+        let total: u64 = files.iter().map(|(_, length)| length).sum();
+        let piece_length = self.piece_length.piece_length(total);
+        if piece_length == 0 {
+            return Err(invalid_input("piece length must not be zero"));
+        }
+
+        let mut info = DictBuilder::new()
+            .insert("name", name)
+            .insert("piece length", piece_length as i64)
+            .insert(
+                "pieces",
+                self.hash_pieces::<H>(&files, piece_length as u64)?,
+            );
+        if single {
+            info = info.insert("length", files[0].1 as i64);
+        } else {
+            let mut list = ListBuilder::new();
+            for (path, length) in &files {
+                let mut parts = ListBuilder::new();
+                for part in path.strip_prefix(&self.root).unwrap_or(path) {
+                    let part = part
+                        .to_str()
+                        .ok_or_else(|| invalid_input("file names must be valid UTF8"))?;
+                    parts = parts.push(part);
+                }
+                list = list.push(
+                    DictBuilder::new()
+                        .insert("length", *length as i64)
+                        .insert("path", parts),
+                );
+            }
+            info = info.insert("files", list);
+        }
+        if self.private {
+            info = info.insert("private", 1);
+        }
+        if let Some(source) = &self.source {
+            info = info.insert("source", source.as_str());
+        }
+
+        let mut torrent = DictBuilder::new().insert("info", info);
+        if let Some(first) = self.tiers.iter().flatten().next() {
+            torrent = torrent.insert("announce", first.as_str());
+            let mut tiers = ListBuilder::new();
+            for tier in &self.tiers {
+                let trackers: Vec<Bencoding> = tier.iter().map(|t| t.as_str().into()).collect();
+                tiers = tiers.push(trackers);
+            }
+            torrent = torrent.insert("announce-list", tiers);
+        }
+        if let Some(comment) = &self.comment {
+            torrent = torrent.insert("comment", comment.as_str());
+        }
+        if let Some(created_by) = &self.created_by {
+            torrent = torrent.insert("created by", created_by.as_str());
+        }
+        if let Some(creation) = self.creation {
+            torrent = torrent.insert("creation date", super::unix_from_time(creation));
+        }
+        Ok(torrent.build())
+    }
+
+    // Hash every piece of the content, returning the concatenated hashes.
+    fn hash_pieces<H: Hasher<Output = [u8; 20]>>(
+        &self,
+        files: &[(PathBuf, u64)],
+        piece_length: u64,
+    ) -> io::Result<Vec<u8>> {
+        let total: u64 = files.iter().map(|(_, length)| length).sum();
+        let count = total.div_ceil(piece_length);
+        // Each thread hashes a contiguous range of pieces, so that it reads its part sequentially
+        let per_thread = count.div_ceil(self.threads as u64).max(1);
+        let ranges: Vec<Range<u64>> = (0..count)
+            .step_by(per_thread as usize)
+            .map(|start| start..cmp::min(start + per_thread, count))
+            .collect();
+        let results: Vec<io::Result<Vec<u8>>> = if ranges.len() <= 1 {
+            ranges
+                .into_iter()
+                .map(|range| hash_range::<H>(files, piece_length, total, range))
+                .collect()
+        } else {
+            thread::scope(|scope| {
+                let handles: Vec<_> = ranges
+                    .into_iter()
+                    .map(|range| {
+                        scope.spawn(move || hash_range::<H>(files, piece_length, total, range))
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().expect("hashing thread panicked"))
+                    .collect()
+            })
+        };
+        let mut pieces = Vec::with_capacity(count as usize * 20);
+        for result in results {
+            pieces.extend_from_slice(&result?);
+        }
+        Ok(pieces)
+    }
+}
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+// Find every file in a directory, along with its length.
+fn walk(dir: &Path, files: &mut Vec<(PathBuf, u64)>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            walk(&entry.path(), files)?;
+        } else if metadata.is_file() {
+            files.push((entry.path(), metadata.len()));
+        }
+    }
+    Ok(())
+}
+
+// Reads the concatenated content of some files, starting at some offset.
+struct Concat<'f> {
+    files: &'f [(PathBuf, u64)],
+    current: Option<fs::File>,
+    next: usize,
+}
+
+impl<'f> Concat<'f> {
+    fn new(files: &'f [(PathBuf, u64)], mut offset: u64) -> io::Result<Self> {
+        let mut next = 0;
+        while next < files.len() && offset >= files[next].1 {
+            offset -= files[next].1;
+            next += 1;
+        }
+        let mut current = None;
+        if next < files.len() {
+            let mut file = fs::File::open(&files[next].0)?;
+            file.seek(SeekFrom::Start(offset))?;
+            current = Some(file);
+            next += 1;
+        }
+        Ok(Concat {
+            files,
+            current,
+            next,
+        })
+    }
+}
+
+impl<'f> Read for Concat<'f> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = match &mut self.current {
+                None => return Ok(0),
+                Some(file) => file.read(buf)?,
+            };
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            self.current = match self.files.get(self.next) {
+                None => None,
+                Some((path, _)) => Some(fs::File::open(path)?),
+            };
+            self.next += 1;
+        }
+    }
+}
+
+fn hash_range<H: Hasher<Output = [u8; 20]>>(
+    files: &[(PathBuf, u64)],
+    piece_length: u64,
+    total: u64,
+    pieces: Range<u64>,
+) -> io::Result<Vec<u8>> {
+    let mut reader = Concat::new(files, pieces.start * piece_length)?;
+    let mut buf = vec![0; piece_length as usize];
+    let mut hashes = Vec::with_capacity((pieces.end - pieces.start) as usize * 20);
+    for piece in pieces {
+        let length = cmp::min(piece_length, total - piece * piece_length) as usize;
+        reader.read_exact(&mut buf[..length])?;
+        hashes.extend_from_slice(&H::digest(&buf[..length]));
+    }
+    Ok(hashes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::Torrent;
+    use std::{convert::TryFrom, env};
+
+    #[test]
+    fn building_torrents_works() {
+        let root = env::temp_dir().join(format!("typhoon-builder-{}", std::process::id()));
+        let dir = root.join("show");
+        fs::create_dir_all(dir.join("extras")).unwrap();
+        let a: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        let b: Vec<u8> = (0..300u32).map(|i| (i * 7) as u8).collect();
+        fs::write(dir.join("a.mkv"), &a).unwrap();
+        fs::write(dir.join("extras").join("b.srt"), &b).unwrap();
+        fs::write(dir.join("empty"), b"").unwrap();
+
+        let builder = TorrentBuilder::new(&dir)
+            .piece_length(256)
+            .tracker("udp://a:1")
+            .tier(vec!["udp://b:1".into(), "udp://c:1".into()])
+            .comment("hello")
+            .private(true)
+            .source("TPH");
+        let bencoding = builder.build().unwrap();
+        assert_eq!(bencoding, builder.clone().threads(3).build().unwrap());
+        let torrent = Torrent::try_from(&bencoding).unwrap();
+
+        let content = [&a[..], &b[..]].concat();
+        let expected: Vec<_> = content.chunks(256).map(Sha1::digest).collect();
+        let hashes: Vec<_> = bencoding
+            .lookup("info.pieces")
+            .unwrap()
+            .as_bytes()
+            .unwrap()
+            .chunks(20)
+            .map(|c| c.to_vec())
+            .collect();
+        assert_eq!(
+            expected.iter().map(|h| h.to_vec()).collect::<Vec<_>>(),
+            hashes
+        );
+        assert_eq!(6, torrent.piece_hashes.len());
+        let files: Vec<_> = torrent
+            .files
+            .iter()
+            .map(|f| (f.name.clone(), f.length))
+            .collect();
+        assert_eq!(
+            vec![
+                (PathBuf::from("show/a.mkv"), 1000),
+                (PathBuf::from("show/empty"), 0),
+                (PathBuf::from("show/extras/b.srt"), 300),
+            ],
+            files
+        );
+        assert!(torrent.private);
+        assert_eq!(Some("TPH"), torrent.source.as_deref());
+        assert_eq!(Some("hello".to_owned()), torrent.comment);
+        assert_eq!(3, torrent.trackers.len());
+        assert_eq!(Ok(()), Bencoding::is_canonical(&bencoding.encode()));
+
+        let single = TorrentBuilder::new(dir.join("a.mkv"))
+            .piece_length(512)
+            .build()
+            .unwrap();
+        assert_eq!(Some(1000), single.lookup("info.length").unwrap().as_int());
+        assert_eq!(Some("a.mkv"), single.lookup("info.name").unwrap().as_str());
+        let auto = TorrentBuilder::new(&dir)
+            .piece_length_strategy(PieceLengthStrategy::Auto {
+                target_pieces: 4,
+                min: 64,
+                max: 1024,
+            })
+            .build()
+            .unwrap();
+        assert_eq!(
+            Some(512),
+            auto.lookup("info.piece length").unwrap().as_int()
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn picking_piece_lengths_works() {
+        let auto = PieceLengthStrategy::Auto {
+            target_pieces: 4,
+            min: 64,
+            max: 256,
+        };
+        assert_eq!(64, auto.piece_length(0));
+        assert_eq!(128, auto.piece_length(400));
+        assert_eq!(256, auto.piece_length(1 << 40));
+        assert_eq!(1000, PieceLengthStrategy::Fixed(1000).piece_length(1 << 40));
+        let builder = TorrentBuilder::new("show");
+        assert_eq!(PieceLengthStrategy::default(), builder.piece_length);
+    }
+}
@@ -0,0 +1,170 @@
+//! This module compares the content of two torrents, to find cross-seeding opportunities.
+//!
+//! The same files often get uploaded to several trackers, each with its own torrent,
+//! and a different info hash. If two torrents describe the same content, the data
+//! downloaded for one can be seeded in the other, without downloading it again.
+use super::{merkle_root, FileInfo, MerkleHash, PieceHash, Torrent, TorrentVersion, V2Metadata};
+use std::path::Path;
+
+/// How the content of two torrents relates, as returned by `compare`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ContentMatch {
+    /// The torrents have the same files, and their hashes agree.
+    ///
+    /// The data of one torrent can be seeded in the other right away.
+    Identical,
+    /// The torrents have the same files, but their hashes can't be compared.
+    ///
+    /// This happens when they use different piece lengths, or lay out their files differently
+    /// around padding. The data of one torrent can probably be reused for the other,
+    /// but needs to be checked against its hashes first.
+    SameFiles,
+    /// The torrents have the same files, but some of their hashes disagree.
+    ///
+    /// The files have the same names and sizes, but different contents.
+    Conflicting,
+    /// The torrents don't have the same files.
+    Different,
+}
+
+impl ContentMatch {
+    /// Check whether the data of one torrent could be reused for the other.
+    ///
+    /// This is true for `Identical` and `SameFiles`, but the latter still needs verification.
+    pub fn is_cross_seedable(&self) -> bool {
+        matches!(self, ContentMatch::Identical | ContentMatch::SameFiles)
+    }
+}
+
+// The path and length of each content file, in a consistent order.
+fn file_keys(torrent: &Torrent) -> Vec<(&Path, usize)> {
+    let mut keys: Vec<_> = torrent
+        .files
+        .iter()
+        .filter(|file| !file.attributes.padding)
+        .map(|file| (file.name.as_path(), file.length))
+        .collect();
+    keys.sort_unstable();
+    keys
+}
+
+// Check that two torrents split the same bytes into the same v1 pieces, padding included.
+fn same_layout(a: &Torrent, b: &Torrent) -> bool {
+    let lengths = |files: &[FileInfo]| files.iter().map(|f| f.length).collect::<Vec<_>>();
+    a.piece_length == b.piece_length && lengths(&a.files) == lengths(&b.files)
+}
+
+// The root of each file of a v2 torrent, which doesn't depend on the piece length.
+fn file_roots(meta: &V2Metadata) -> Vec<(&Path, Option<MerkleHash>)> {
+    let mut roots: Vec<_> = meta
+        .files
+        .iter()
+        .map(|f| (f.name.as_path(), f.pieces_root))
+        .collect();
+    roots.sort_unstable_by(|x, y| x.0.cmp(y.0));
+    roots
+}
+
+// The v1 piece hashes of a torrent, if it lists them.
+fn v1_hashes(torrent: &Torrent) -> Option<&[PieceHash]> {
+    match torrent.version {
+        TorrentVersion::V1 | TorrentVersion::Hybrid => Some(&torrent.piece_hashes),
+        _ => None,
+    }
+}
+
+// Compare the v1 hashes of two torrents with the same layout, if they both have some.
+fn same_pieces(a: &Torrent, b: &Torrent) -> Option<bool> {
+    match (v1_hashes(a), v1_hashes(b), a.merkle_root(), b.merkle_root()) {
+        (Some(x), Some(y), _, _) => Some(x == y),
+        (_, _, Some(x), Some(y)) => Some(x == y),
+        // A merkle torrent only has a root, which we can compute for the other torrent
+        (Some(x), _, _, Some(y)) | (_, Some(x), Some(y), _) => Some(merkle_root(x) == y),
+        _ => None,
+    }
+}
+
+/// Compare the content of two torrents, to see if they can be cross-seeded.
+///
+/// Two torrents have the same files if every file, ignoring padding files, has the same
+/// path and length in both. Their hashes are then compared wherever possible: v1 piece
+/// hashes when the pieces line up, and v2 file roots whenever both torrents have them.
+///
+/// ```
+/// use std::convert::TryFrom;
+/// use typhoon::{bencoding::Bencoding, core::{compare, ContentMatch, Torrent}};
+///
+/// let parse = |input: &[u8]| Torrent::try_from(&Bencoding::decode(input).unwrap()).unwrap();
+/// let a = parse(b"d4:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces0:ee");
+/// let b = parse(b"d4:infod6:lengthi5e4:name1:f12:piece lengthi32e6:pieces0:ee");
+/// assert_eq!(ContentMatch::SameFiles, compare(&a, &b));
+/// ```
+pub fn compare(a: &Torrent, b: &Torrent) -> ContentMatch {
+    if file_keys(a) != file_keys(b) {
+        return ContentMatch::Different;
+    }
+    let mut checks = Vec::with_capacity(2);
+    if let (Some(x), Some(y)) = (&a.v2, &b.v2) {
+        checks.push(file_roots(x) == file_roots(y));
+    }
+    if same_layout(a, b) {
+        checks.extend(same_pieces(a, b));
+    }
+    if checks.contains(&false) {
+        ContentMatch::Conflicting
+    } else if checks.is_empty() {
+        ContentMatch::SameFiles
+    } else {
+        ContentMatch::Identical
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bencoding::Bencoding;
+    use std::convert::TryFrom;
+
+    fn torrent(piece_length: usize, hashes: &[u8], private: bool) -> Torrent {
+        let mut input =
+            b"d4:infod5:filesld6:lengthi20e4:pathl1:aeed6:lengthi12e4:pathl1:beee".to_vec();
+        input.extend_from_slice(b"4:name3:dir12:piece length");
+        input.extend_from_slice(format!("i{}e6:pieces{}:", piece_length, hashes.len()).as_bytes());
+        input.extend_from_slice(hashes);
+        input.extend_from_slice(if private { b"7:privatei1eee" } else { b"ee" });
+        Torrent::try_from(&Bencoding::decode(&input).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn comparing_torrents_works() {
+        let a = torrent(16, &[1; 40], false);
+        let b = torrent(16, &[1; 40], true);
+        assert_ne!(a.info_hash(), b.info_hash());
+        assert_eq!(ContentMatch::Identical, compare(&a, &b));
+        let mut hashes = [1; 40];
+        hashes[39] = 2;
+        assert_eq!(
+            ContentMatch::Conflicting,
+            compare(&a, &torrent(16, &hashes, false))
+        );
+        let c = torrent(32, &[1; 20], false);
+        assert_eq!(ContentMatch::SameFiles, compare(&a, &c));
+        assert!(compare(&a, &c).is_cross_seedable());
+        let mut d = a.clone();
+        d.files[1].length += 1;
+        assert_eq!(ContentMatch::Different, compare(&a, &d));
+        assert!(!compare(&a, &d).is_cross_seedable());
+    }
+
+    #[test]
+    fn comparing_merkle_torrents_works() {
+        let a = torrent(16, &[1; 40], false);
+        let mut merkle = a.clone();
+        merkle.piece_hashes = Box::new([]);
+        merkle.version = TorrentVersion::Merkle(merkle_root(&a.piece_hashes));
+        assert_eq!(ContentMatch::Identical, compare(&a, &merkle));
+        assert_eq!(ContentMatch::Identical, compare(&merkle, &a));
+        merkle.version = TorrentVersion::Merkle(PieceHash::new([0; 20]));
+        assert_eq!(ContentMatch::Conflicting, compare(&merkle, &a));
+    }
+}
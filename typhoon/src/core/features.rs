@@ -0,0 +1,154 @@
+//! This module reports which extensions of the protocol a torrent relies on.
+//!
+//! Many extensions change how a torrent needs to be downloaded, like padding files that
+//! shouldn't be written to disk, or v2 hashes. Clients which don't implement one of these
+//! yet can use this report to warn about a torrent, or refuse it outright.
+use super::{FileAttributes, HttpSource, Torrent, TorrentVersion};
+use std::fmt;
+
+/// An extension of the protocol which a torrent can make use of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Feature {
+    /// The torrent only supports version 2 of the protocol.
+    V2,
+    /// The torrent supports both versions of the protocol.
+    Hybrid,
+    /// The torrent only contains the root of a merkle tree over its pieces.
+    Merkle,
+    /// The torrent contains padding files, to align files with pieces.
+    PaddingFiles,
+    /// Some files are executable, hidden, or symbolic links.
+    FileAttributes,
+    /// The torrent is private, and peers can only be found through its trackers.
+    Private,
+    /// The torrent lists several trackers, possibly in different tiers.
+    MultiTracker,
+    /// The content can be downloaded from web servers hosting the files.
+    WebSeeds,
+    /// The content can be downloaded from HTTP servers serving pieces.
+    HttpSeeds,
+    /// The torrent lists DHT nodes to bootstrap from.
+    DhtNodes,
+    /// The info dictionary of the torrent is signed.
+    Signatures,
+    /// The torrent lists similar torrents, or collections it belongs to.
+    SimilarTorrents,
+}
+
+impl Feature {
+    /// The number of the BEP describing this feature.
+    pub fn bep(&self) -> u32 {
+        match self {
+            Feature::V2 | Feature::Hybrid => 52,
+            Feature::Merkle => 30,
+            Feature::PaddingFiles | Feature::FileAttributes => 47,
+            Feature::Private => 27,
+            Feature::MultiTracker => 12,
+            Feature::WebSeeds => 19,
+            Feature::HttpSeeds => 17,
+            Feature::DhtNodes => 5,
+            Feature::Signatures => 35,
+            Feature::SimilarTorrents => 38,
+        }
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Feature::V2 => "v2",
+            Feature::Hybrid => "hybrid",
+            Feature::Merkle => "merkle",
+            Feature::PaddingFiles => "padding files",
+            Feature::FileAttributes => "file attributes",
+            Feature::Private => "private",
+            Feature::MultiTracker => "multiple trackers",
+            Feature::WebSeeds => "web seeds",
+            Feature::HttpSeeds => "http seeds",
+            Feature::DhtNodes => "dht nodes",
+            Feature::Signatures => "signatures",
+            Feature::SimilarTorrents => "similar torrents",
+        };
+        write!(f, "{} (BEP {})", name, self.bep())
+    }
+}
+
+impl Torrent {
+    /// List the extensions of the protocol this torrent relies on, in a fixed order.
+    ///
+    /// ```
+    /// use std::convert::TryFrom;
+    /// use typhoon::{bencoding::Bencoding, core::{Feature, Torrent}};
+    ///
+    /// let input = b"d4:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces0:7:privatei1eee";
+    /// let torrent = Torrent::try_from(&Bencoding::decode(input).unwrap()).unwrap();
+    /// assert_eq!(vec![Feature::Private], torrent.features());
+    /// ```
+    pub fn features(&self) -> Vec<Feature> {
+        let attributes = |check: fn(&FileAttributes) -> bool| {
+            self.files.iter().any(|file| check(&file.attributes))
+        };
+        let sources = |check: fn(&HttpSource) -> bool| self.http_sources.iter().any(check);
+        let checks = [
+            (Feature::V2, self.version == TorrentVersion::V2),
+            (Feature::Hybrid, self.version == TorrentVersion::Hybrid),
+            (
+                Feature::Merkle,
+                matches!(self.version, TorrentVersion::Merkle(_)),
+            ),
+            (Feature::PaddingFiles, attributes(|a| a.padding)),
+            (
+                Feature::FileAttributes,
+                attributes(|a| a.executable || a.hidden || a.symlink),
+            ),
+            (Feature::Private, self.private),
+            (Feature::MultiTracker, self.trackers.len() > 1),
+            (
+                Feature::WebSeeds,
+                sources(|s| matches!(s, HttpSource::WebSeed(_))),
+            ),
+            (
+                Feature::HttpSeeds,
+                sources(|s| matches!(s, HttpSource::HttpSeed(_))),
+            ),
+            (Feature::DhtNodes, !self.dht_nodes.is_empty()),
+            (Feature::Signatures, !self.signatures.is_empty()),
+            (
+                Feature::SimilarTorrents,
+                !self.similar.is_empty() || !self.collections.is_empty(),
+            ),
+        ];
+        checks
+            .iter()
+            .filter(|(_, used)| *used)
+            .map(|(feature, _)| *feature)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bencoding::Bencoding;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn listing_features_works() {
+        let input = b"d13:announce-listll9:udp://a:1el9:udp://b:1ee\
+            4:infod5:filesld6:lengthi10e4:pathl1:aeed4:attr2:px6:lengthi6e4:pathl1:beee\
+            4:name3:dir12:piece lengthi16e6:pieces20:AAAAAAAAAAAAAAAAAAAAe\
+            5:nodesll1:ai1eee8:url-list1:ue";
+        let torrent = Torrent::try_from(&Bencoding::decode(input).unwrap()).unwrap();
+        assert_eq!(
+            vec![
+                Feature::PaddingFiles,
+                Feature::FileAttributes,
+                Feature::MultiTracker,
+                Feature::WebSeeds,
+                Feature::DhtNodes
+            ],
+            torrent.features()
+        );
+        assert_eq!("web seeds (BEP 19)", Feature::WebSeeds.to_string());
+    }
+}
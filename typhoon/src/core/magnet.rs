@@ -0,0 +1,308 @@
+//! This module contains magnet links, which identify torrents without a .torrent file.
+//!
+//! A magnet link looks like `magnet:?xt=urn:btih:<hash>&dn=<name>&tr=<tracker>`.
+//! It contains the info hash of a torrent, along with a few hints about where to
+//! find peers. The rest of the metadata then gets fetched from those peers.
+use super::{
+    url_decode, url_encode, write_hex, HttpSource, InfoHash, InfoHashV2, Torrent, TorrentVersion,
+    TrackerAddr,
+};
+use std::{error, fmt, ops::RangeInclusive, str};
+
+/// Represents a parsed magnet link.
+///
+/// A magnet link contains at least one of the two info hashes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MagnetLink {
+    /// The v1 info hash of the torrent, from an `urn:btih:` topic.
+    pub info_hash: Option<InfoHash>,
+    /// The v2 info hash of the torrent, from an `urn:btmh:` topic.
+    pub info_hash_v2: Option<InfoHashV2>,
+    /// A name to display for the torrent, before we get its metadata.
+    pub name: Option<String>,
+    /// Trackers we can ask for peers, in the order they appeared.
+    pub trackers: Vec<TrackerAddr>,
+    /// The URLs of web seeds serving the content of the torrent.
+    pub web_seeds: Vec<String>,
+    /// The addresses of peers we can connect to directly, like `10.0.0.1:6881`.
+    pub peers: Vec<String>,
+    /// The indices of the files to download, from BEP 53, or every file if this is empty.
+    ///
+    /// Indices refer to the files of the torrent, in order, including padding files.
+    /// Applying this selection once the metadata arrives is up to the session.
+    pub select_only: Vec<RangeInclusive<usize>>,
+}
+
+/// An error that can occur when parsing a magnet link.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseMagnetError {
+    /// The link doesn't start with `magnet:?`.
+    NotMagnet,
+    /// A parameter contains an invalid percent escape, or isn't valid UTF8 once unescaped.
+    ///
+    /// This contains the name of the parameter.
+    InvalidEncoding(String),
+    /// An exact topic contains an info hash we can't decode.
+    ///
+    /// This contains the topic, as it appeared in the link.
+    InvalidHash(String),
+    /// The link doesn't contain an info hash.
+    MissingHash,
+    /// The list of files to select isn't made of indices and ranges like `0,2-4`.
+    ///
+    /// This contains the list, as it appeared in the link.
+    InvalidSelection(String),
+}
+
+impl fmt::Display for ParseMagnetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ParseMagnetError::*;
+        match self {
+            NotMagnet => write!(f, "link doesn't start with magnet:?"),
+            InvalidEncoding(key) => write!(f, "parameter {} is incorrectly encoded", key),
+            InvalidHash(topic) => write!(f, "topic {} doesn't contain a valid info hash", topic),
+            MissingHash => write!(f, "link doesn't contain an info hash"),
+            InvalidSelection(list) => write!(f, "file selection {} is invalid", list),
+        }
+    }
+}
+
+impl error::Error for ParseMagnetError {}
+
+fn unescape(value: &str, plus_as_space: bool) -> Option<String> {
+    String::from_utf8(url_decode(value, plus_as_space)?).ok()
+}
+
+// Escape everything but the characters that URLs never need escaped.
+fn write_escaped(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    write!(f, "{}", url_encode(value.as_bytes()))
+}
+
+// Parse a list of file indices and inclusive ranges, like `0,2-4`.
+fn parse_selection(list: &str) -> Option<Vec<RangeInclusive<usize>>> {
+    list.split(',')
+        .map(|item| {
+            let (start, end) = item.split_once('-').unwrap_or((item, item));
+            let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+            if start > end {
+                return None;
+            }
+            Some(start..=end)
+        })
+        .collect()
+}
+
+// The prefix of a v2 info hash, as a multihash: the code for SHA-256, followed by its length.
+const SHA256_MULTIHASH: &str = "1220";
+
+impl str::FromStr for MagnetLink {
+    type Err = ParseMagnetError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let query = s
+            .strip_prefix("magnet:?")
+            .ok_or(ParseMagnetError::NotMagnet)?;
+        let mut link = MagnetLink::default();
+        for param in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            let value = unescape(value, key == "dn")
+                .ok_or_else(|| ParseMagnetError::InvalidEncoding(key.to_owned()))?;
+            // Multiple parameters of the same kind can be numbered, like `tr.1`
+            let kind = match key.split_once('.') {
+                Some((kind, n)) if n.bytes().all(|c| c.is_ascii_digit()) => kind,
+                _ => key,
+            };
+            match kind {
+                "xt" => {
+                    let invalid = || ParseMagnetError::InvalidHash(value.clone());
+                    if let Some(hash) = value.strip_prefix("urn:btih:") {
+                        link.info_hash = Some(hash.parse().map_err(|_| invalid())?);
+                    } else if let Some(hash) = value.strip_prefix("urn:btmh:") {
+                        let hash = hash.strip_prefix(SHA256_MULTIHASH).ok_or_else(invalid)?;
+                        link.info_hash_v2 = Some(hash.parse().map_err(|_| invalid())?);
+                    }
+                }
+                "dn" => link.name = Some(value),
+                "tr" => link.trackers.push(TrackerAddr::from(value.as_str())),
+                "ws" => link.web_seeds.push(value),
+                "x.pe" => link.peers.push(value),
+                "so" => {
+                    let selection = parse_selection(&value)
+                        .ok_or_else(|| ParseMagnetError::InvalidSelection(value.clone()))?;
+                    link.select_only.extend(selection);
+                }
+                _ => {}
+            }
+        }
+        if link.info_hash.is_none() && link.info_hash_v2.is_none() {
+            return Err(ParseMagnetError::MissingHash);
+        }
+        Ok(link)
+    }
+}
+
+impl fmt::Display for MagnetLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "magnet:?")?;
+        let mut separator = "";
+        if let Some(hash) = &self.info_hash {
+            write!(f, "xt=urn:btih:")?;
+            write_hex(f, hash.as_bytes())?;
+            separator = "&";
+        }
+        if let Some(hash) = &self.info_hash_v2 {
+            write!(f, "{}xt=urn:btmh:{}", separator, SHA256_MULTIHASH)?;
+            write_hex(f, hash.as_bytes())?;
+        }
+        if let Some(name) = &self.name {
+            write!(f, "&dn=")?;
+            write_escaped(f, name)?;
+        }
+        for tracker in &self.trackers {
+            write!(f, "&tr=")?;
+            write_escaped(f, &tracker.to_string())?;
+        }
+        for seed in &self.web_seeds {
+            write!(f, "&ws=")?;
+            write_escaped(f, seed)?;
+        }
+        for peer in &self.peers {
+            write!(f, "&x.pe=")?;
+            write_escaped(f, peer)?;
+        }
+        for (i, range) in self.select_only.iter().enumerate() {
+            write!(f, "{}", if i == 0 { "&so=" } else { "," })?;
+            if range.start() == range.end() {
+                write!(f, "{}", range.start())?;
+            } else {
+                write!(f, "{}-{}", range.start(), range.end())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Torrent {
+    /// Create a magnet link pointing to this torrent.
+    ///
+    /// This includes the info hashes for each version this torrent supports, its name,
+    /// its trackers, in order of priority, and its web seeds.
+    pub fn to_magnet(&self) -> MagnetLink {
+        let (info_hash, info_hash_v2) = match self.version {
+            TorrentVersion::V1 | TorrentVersion::Merkle(_) => (Some(self.info_hash), None),
+            TorrentVersion::V2 => (None, self.info_hash_v2),
+            TorrentVersion::Hybrid => (Some(self.info_hash), self.info_hash_v2),
+        };
+        let name = self.name().map(String::from);
+        MagnetLink {
+            info_hash,
+            info_hash_v2,
+            name,
+            trackers: self.trackers.iter().map(|(_, t)| t.clone()).collect(),
+            web_seeds: self
+                .http_sources
+                .iter()
+                .filter_map(|source| match source {
+                    HttpSource::WebSeed(url) => Some(url.clone()),
+                    HttpSource::HttpSeed(_) => None,
+                })
+                .collect(),
+            peers: Vec::new(),
+            select_only: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parsing_magnet_links_works() {
+        let link: MagnetLink = "magnet:?xt=urn:btih:C12FE1C06BBA254A9DC9F519B335AA7C1367A88A\
+            &dn=Some+Show%20S01&tr=udp%3A%2F%2Ftracker.example.com%3A1337\
+            &tr.1=https://t.example.org/announce&ws=https%3A%2F%2Fseed.example.com%2F\
+            &x.pe=10.0.0.1:6881"
+            .parse()
+            .unwrap();
+        assert_eq!(0xC1, link.info_hash.unwrap().as_bytes()[0]);
+        assert_eq!(0x8A, link.info_hash.unwrap().as_bytes()[19]);
+        assert_eq!(Some("Some Show S01".to_owned()), link.name);
+        assert_eq!(
+            vec![
+                TrackerAddr::UDP("tracker.example.com:1337".to_owned()),
+                TrackerAddr::HTTP("https://t.example.org/announce".to_owned())
+            ],
+            link.trackers
+        );
+        assert_eq!(vec!["https://seed.example.com/".to_owned()], link.web_seeds);
+        assert_eq!(vec!["10.0.0.1:6881".to_owned()], link.peers);
+    }
+
+    #[test]
+    fn displaying_magnet_links_round_trips() {
+        let link = MagnetLink {
+            info_hash: Some(InfoHash::new([0xC1; 20])),
+            info_hash_v2: Some(InfoHashV2::new([0x0F; 32])),
+            name: Some("a b&c/ü".to_owned()),
+            trackers: vec![
+                TrackerAddr::UDP("t.example.com:80".to_owned()),
+                TrackerAddr::HTTP("http://t.example.org/a?b=c&d".to_owned()),
+            ],
+            web_seeds: vec!["https://s.example.com/".to_owned()],
+            peers: vec!["[::1]:6881".to_owned()],
+            select_only: vec![0..=0, 2..=4],
+        };
+        let string = link.to_string();
+        assert!(string.starts_with(&format!(
+            "magnet:?xt=urn:btih:{}&xt=urn:btmh:1220{}&dn=a%20b%26c%2F%C3%BC&tr=udp%3A%2F%2F",
+            "c1".repeat(20),
+            "0f".repeat(32)
+        )));
+        assert!(string.ends_with("&so=0,2-4"));
+        assert_eq!(Ok(link), string.parse());
+    }
+
+    #[test]
+    fn parsing_file_selections_works() {
+        let link: MagnetLink = format!("magnet:?xt=urn:btih:{}&so=0,2,4,6-8", "ab".repeat(20))
+            .parse()
+            .unwrap();
+        assert_eq!(vec![0..=0, 2..=2, 4..=4, 6..=8], link.select_only);
+        for list in &["", "1,", "a", "3-1", "1-2-3"] {
+            assert_eq!(
+                Err(ParseMagnetError::InvalidSelection(list.to_string())),
+                format!("magnet:?xt=urn:btih:{}&so={}", "ab".repeat(20), list)
+                    .parse::<MagnetLink>()
+            );
+        }
+    }
+
+    #[test]
+    fn parsing_magnet_hashes_works() {
+        let hex: MagnetLink = "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a"
+            .parse()
+            .unwrap();
+        let base32: MagnetLink = "magnet:?xt=urn:btih:YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKEK"
+            .parse()
+            .unwrap();
+        assert_eq!(hex.info_hash, base32.info_hash);
+        let v2: MagnetLink = format!("magnet:?xt=urn:btmh:1220{}", "ab".repeat(32))
+            .parse()
+            .unwrap();
+        assert_eq!(None, v2.info_hash);
+        assert_eq!(Some(InfoHashV2::new([0xAB; 32])), v2.info_hash_v2);
+        assert_eq!(
+            Err(ParseMagnetError::MissingHash),
+            "magnet:?dn=x".parse::<MagnetLink>()
+        );
+        assert_eq!(
+            Err(ParseMagnetError::InvalidHash("urn:btih:123".to_owned())),
+            "magnet:?xt=urn:btih:123".parse::<MagnetLink>()
+        );
+        assert_eq!(
+            Err(ParseMagnetError::NotMagnet),
+            "http://example.com".parse::<MagnetLink>()
+        );
+    }
+}
@@ -0,0 +1,214 @@
+//! This module contains the hash trees used by merkle torrents, from BEP 30.
+//!
+//! Instead of listing the hash of every piece, a merkle torrent only contains the root of a
+//! binary tree built over those hashes. Peers send the hashes needed to walk from a piece
+//! up to that root alongside the piece itself, which lets us verify each piece on its own
+//! while keeping the torrent file tiny.
+//!
+//! The leaves of the tree are the SHA-1 hashes of each piece, padded with zeroed hashes
+//! up to a power of two. Each node is the SHA-1 hash of its two children, one after the other.
+//! Each function has a `_with` variant, taking the SHA-1 implementation to use.
+use super::{PieceHash, Torrent, TorrentVersion, PIECE_HASH_SIZE};
+use crate::hash::{Hasher, Sha1};
+
+// The hash of a parent in the tree, given its two children.
+fn hash_pair<H: Hasher<Output = [u8; PIECE_HASH_SIZE]>>(
+    left: &PieceHash,
+    right: &PieceHash,
+) -> PieceHash {
+    let mut hasher = H::default();
+    hasher.update(left.as_bytes());
+    hasher.update(right.as_bytes());
+    PieceHash::new(hasher.finish())
+}
+
+// Compute the layer above another, which needs to have an even number of hashes.
+fn parent_layer<H: Hasher<Output = [u8; PIECE_HASH_SIZE]>>(layer: &[PieceHash]) -> Vec<PieceHash> {
+    layer
+        .chunks_exact(2)
+        .map(|pair| hash_pair::<H>(&pair[0], &pair[1]))
+        .collect()
+}
+
+// Pad the hashes of each piece into the bottom layer of the tree.
+fn leaf_layer(piece_hashes: &[PieceHash]) -> Vec<PieceHash> {
+    let width = piece_hashes.len().max(1).next_power_of_two();
+    let mut layer = piece_hashes.to_vec();
+    layer.resize(width, PieceHash::new([0; 20]));
+    layer
+}
+
+/// Compute the root hash of the merkle tree over the hashes of each piece.
+pub fn merkle_root(piece_hashes: &[PieceHash]) -> PieceHash {
+    merkle_root_with::<Sha1>(piece_hashes)
+}
+
+/// Compute the root hash of a merkle tree, using a different SHA-1 implementation.
+pub fn merkle_root_with<H: Hasher<Output = [u8; PIECE_HASH_SIZE]>>(
+    piece_hashes: &[PieceHash],
+) -> PieceHash {
+    let mut layer = leaf_layer(piece_hashes);
+    while layer.len() > 1 {
+        layer = parent_layer::<H>(&layer);
+    }
+    layer[0]
+}
+
+/// Compute the hash chain proving that a piece belongs to a merkle tree.
+///
+/// This contains the sibling of each node on the path from the piece to the root,
+/// starting from the sibling of the piece itself, and stopping before the root.
+/// This returns `None` if the index is past the last piece.
+pub fn merkle_proof(piece_hashes: &[PieceHash], index: usize) -> Option<Vec<PieceHash>> {
+    merkle_proof_with::<Sha1>(piece_hashes, index)
+}
+
+/// Compute the hash chain for a piece, using a different SHA-1 implementation.
+pub fn merkle_proof_with<H: Hasher<Output = [u8; PIECE_HASH_SIZE]>>(
+    piece_hashes: &[PieceHash],
+    index: usize,
+) -> Option<Vec<PieceHash>> {
+    if index >= piece_hashes.len() {
+        return None;
+    }
+    let mut layer = leaf_layer(piece_hashes);
+    let mut index = index;
+    let mut proof = Vec::new();
+    while layer.len() > 1 {
+        proof.push(layer[index ^ 1]);
+        layer = parent_layer::<H>(&layer);
+        index /= 2;
+    }
+    Some(proof)
+}
+
+/// Check that a hash chain leads from the hash of a piece to the root of a merkle tree.
+///
+/// The proof has the same shape as the one returned by `merkle_proof`.
+pub fn verify_merkle_proof(
+    root: &PieceHash,
+    index: usize,
+    piece_hash: &PieceHash,
+    proof: &[PieceHash],
+) -> bool {
+    verify_merkle_proof_with::<Sha1>(root, index, piece_hash, proof)
+}
+
+/// Check a hash chain, using a different SHA-1 implementation.
+pub fn verify_merkle_proof_with<H: Hasher<Output = [u8; PIECE_HASH_SIZE]>>(
+    root: &PieceHash,
+    index: usize,
+    piece_hash: &PieceHash,
+    proof: &[PieceHash],
+) -> bool {
+    let mut index = index;
+    let mut current = *piece_hash;
+    for sibling in proof {
+        current = if index.is_multiple_of(2) {
+            hash_pair::<H>(&current, sibling)
+        } else {
+            hash_pair::<H>(sibling, &current)
+        };
+        index /= 2;
+    }
+    index == 0 && current == *root
+}
+
+impl Torrent {
+    /// The root hash of this torrent, if it's a merkle torrent.
+    pub fn merkle_root(&self) -> Option<PieceHash> {
+        match self.version {
+            TorrentVersion::Merkle(root) => Some(root),
+            _ => None,
+        }
+    }
+
+    /// Verify a piece of a merkle torrent, using the hash chain a peer sent along with it.
+    ///
+    /// The proof needs to contain exactly the hashes needed to walk from this piece up
+    /// to the root, as described in `merkle_proof`. This returns false for torrents
+    /// that aren't merkle torrents, or for pieces that aren't part of this torrent.
+    pub fn verify_merkle_piece(&self, index: usize, piece: &[u8], proof: &[PieceHash]) -> bool {
+        self.verify_merkle_piece_with::<Sha1>(index, piece, proof)
+    }
+
+    /// Verify a piece of a merkle torrent, using a different SHA-1 implementation.
+    pub fn verify_merkle_piece_with<H: Hasher<Output = [u8; PIECE_HASH_SIZE]>>(
+        &self,
+        index: usize,
+        piece: &[u8],
+        proof: &[PieceHash],
+    ) -> bool {
+        let root = match self.merkle_root() {
+            Some(root) => root,
+            None => return false,
+        };
+        let depth = self
+            .num_pieces()
+            .max(1)
+            .next_power_of_two()
+            .trailing_zeros();
+        if self.piece_len(index) != Some(piece.len()) || proof.len() != depth as usize {
+            return false;
+        }
+        let piece_hash = PieceHash::new(H::digest(piece));
+        verify_merkle_proof_with::<H>(&root, index, &piece_hash, proof)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bencoding::Bencoding;
+    use std::convert::TryFrom;
+
+    fn hashes(pieces: &[&[u8]]) -> Vec<PieceHash> {
+        pieces
+            .iter()
+            .map(|piece| PieceHash::new(Sha1::digest(piece)))
+            .collect()
+    }
+
+    #[test]
+    fn merkle_roots_pad_to_a_power_of_two() {
+        let leaves = hashes(&[b"a", b"b", b"c"]);
+        let zero = PieceHash::new([0; 20]);
+        let expected = hash_pair::<Sha1>(
+            &hash_pair::<Sha1>(&leaves[0], &leaves[1]),
+            &hash_pair::<Sha1>(&leaves[2], &zero),
+        );
+        assert_eq!(expected, merkle_root(&leaves));
+        assert_eq!(leaves[0], merkle_root(&leaves[..1]));
+    }
+
+    #[test]
+    fn merkle_proofs_verify() {
+        let leaves = hashes(&[b"a", b"b", b"c", b"d", b"e"]);
+        let root = merkle_root(&leaves);
+        for (index, leaf) in leaves.iter().enumerate() {
+            let proof = merkle_proof(&leaves, index).unwrap();
+            assert_eq!(3, proof.len());
+            assert!(verify_merkle_proof(&root, index, leaf, &proof));
+            assert!(!verify_merkle_proof(&root, index ^ 1, leaf, &proof));
+        }
+        assert_eq!(None, merkle_proof(&leaves, 5));
+    }
+
+    #[test]
+    fn verifying_merkle_pieces_works() {
+        let pieces: [&[u8]; 3] = [b"aaaa", b"bbbb", b"cc"];
+        let leaves = hashes(&pieces);
+        let root = merkle_root(&leaves);
+        let mut input = b"d4:infod6:lengthi10e4:name1:f12:piece lengthi4e9:root hash20:".to_vec();
+        input.extend_from_slice(root.as_bytes());
+        input.extend_from_slice(b"ee");
+        let torrent = Torrent::try_from(&Bencoding::decode(&input).unwrap()).unwrap();
+        assert_eq!(Some(root), torrent.merkle_root());
+        assert!(torrent.has_consistent_pieces());
+        let proof = merkle_proof(&leaves, 2).unwrap();
+        assert!(torrent.verify_merkle_piece(2, b"cc", &proof));
+        assert!(!torrent.verify_merkle_piece(2, b"cd", &proof));
+        assert!(!torrent.verify_merkle_piece(2, b"cc", &proof[..1]));
+        assert_eq!(input, torrent.to_bytes());
+    }
+}
@@ -0,0 +1,117 @@
+//! This module contains a borrowed view over the piece hashes of a torrent.
+//!
+//! Parsing a torrent copies its piece hashes out of the bencoding. For torrents with hundreds
+//! of thousands of pieces, that's a lot of memory to spend when the bencoding is still around,
+//! so this view lets us look up hashes straight from the `pieces` byte string instead.
+use super::{
+    extract_bytes, extract_key, ParseHashError, ParseTorrentError, PieceHash, Torrent,
+    PIECE_HASH_SIZE,
+};
+use crate::bencoding::Bencoding;
+use std::convert::TryFrom;
+
+/// The piece hashes of a torrent, borrowed from the concatenated hashes in its bencoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PieceHashesRef<'b>(&'b [u8]);
+
+impl<'b> PieceHashesRef<'b> {
+    /// Create a view over concatenated piece hashes, which need to be a multiple of 20 bytes.
+    pub fn new(bytes: &'b [u8]) -> Result<Self, ParseHashError> {
+        if !bytes.len().is_multiple_of(PIECE_HASH_SIZE) {
+            return Err(ParseHashError::InvalidLength(bytes.len()));
+        }
+        Ok(PieceHashesRef(bytes))
+    }
+
+    /// Find the piece hashes in the bencoding of a torrent, without copying them.
+    ///
+    /// ```
+    /// use typhoon::{bencoding::Bencoding, core::PieceHashesRef};
+    ///
+    /// let mut input = b"d4:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces20:".to_vec();
+    /// input.extend_from_slice(&[7; 20]);
+    /// input.extend_from_slice(b"ee");
+    /// let bencoding = Bencoding::decode(&input).unwrap();
+    /// let hashes = PieceHashesRef::of(&bencoding).unwrap();
+    /// assert_eq!(Some(&[7; 20]), hashes.get(0));
+    /// ```
+    pub fn of(bencoding: &'b Bencoding) -> Result<Self, ParseTorrentError<'b>> {
+        let bytes = extract_bytes(extract_key(extract_key(bencoding, "info")?, "pieces")?)?;
+        PieceHashesRef::new(bytes).map_err(|_| ParseTorrentError::BadHashLength(bytes.len()))
+    }
+
+    /// The number of piece hashes.
+    pub fn len(&self) -> usize {
+        self.0.len() / PIECE_HASH_SIZE
+    }
+
+    /// Check whether there are no piece hashes at all.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The hash of a given piece, if that piece exists.
+    pub fn get(&self, index: usize) -> Option<&'b [u8; PIECE_HASH_SIZE]> {
+        let start = index.checked_mul(PIECE_HASH_SIZE)?;
+        let chunk = self.0.get(start..start.checked_add(PIECE_HASH_SIZE)?)?;
+        <&[u8; PIECE_HASH_SIZE]>::try_from(chunk).ok()
+    }
+
+    /// Iterate over the hash of each piece, in order.
+    pub fn iter(&self) -> impl Iterator<Item = &'b [u8; PIECE_HASH_SIZE]> {
+        self.0
+            .chunks_exact(PIECE_HASH_SIZE)
+            .filter_map(|chunk| <&[u8; PIECE_HASH_SIZE]>::try_from(chunk).ok())
+    }
+
+    /// Copy these hashes into owned piece hashes, like the ones in a `Torrent`.
+    pub fn to_piece_hashes(&self) -> Box<[PieceHash]> {
+        self.iter().map(|hash| PieceHash(*hash)).collect()
+    }
+}
+
+impl Torrent {
+    /// The hash of a given piece, if that piece exists.
+    ///
+    /// Torrents which only support v2, and merkle torrents, don't have any piece hashes.
+    pub fn piece_hash(&self, index: usize) -> Option<&[u8; PIECE_HASH_SIZE]> {
+        self.piece_hashes.get(index).map(PieceHash::as_bytes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn piece_hash_views_work() {
+        let mut bytes = vec![1; 20];
+        bytes.extend_from_slice(&[2; 20]);
+        let hashes = PieceHashesRef::new(&bytes).unwrap();
+        assert_eq!(2, hashes.len());
+        assert_eq!(Some(&[2; 20]), hashes.get(1));
+        assert_eq!(None, hashes.get(2));
+        assert_eq!(None, hashes.get(usize::MAX));
+        assert_eq!(vec![&[1; 20], &[2; 20]], hashes.iter().collect::<Vec<_>>());
+        let owned = hashes.to_piece_hashes();
+        assert_eq!(PieceHash([2; 20]), owned[1]);
+        assert_eq!(
+            Err(ParseHashError::InvalidLength(39)),
+            PieceHashesRef::new(&bytes[1..])
+        );
+    }
+
+    #[test]
+    fn piece_hash_views_match_parsed_torrents() {
+        let mut input = b"d4:infod6:lengthi40e4:name1:f12:piece lengthi20e6:pieces40:".to_vec();
+        input.extend_from_slice(&[3; 20]);
+        input.extend_from_slice(&[4; 20]);
+        input.extend_from_slice(b"ee");
+        let bencoding = Bencoding::decode(&input).unwrap();
+        let torrent = Torrent::try_from(&bencoding).unwrap();
+        let hashes = PieceHashesRef::of(&bencoding).unwrap();
+        assert_eq!(torrent.piece_hashes, hashes.to_piece_hashes());
+        assert_eq!(torrent.piece_hash(1), hashes.get(1));
+        assert_eq!(None, torrent.piece_hash(2));
+    }
+}
@@ -0,0 +1,236 @@
+//! This module parses torrents straight out of their bytes, instead of out of bencoding.
+//!
+//! Working with the bytes lets us find the exact bytes of the info dictionary, which
+//! peers need when sending metadata to each other, and which the info hash is computed from.
+//!
+//! It also lets us parse just enough of a torrent to summarize it. Indexers usually want
+//! the name, size, files, and info hash of a torrent, but not its piece hashes, which make
+//! up most of the file for large torrents. Instead of decoding everything, we stream through
+//! the input, slicing out the info dictionary without decoding it, and then decode
+//! everything in it except for the pieces.
+use super::{
+    FileInfo, InfoHash, InfoHashV2, OwnedParseError, Torrent, TorrentOptions, TorrentVersion,
+};
+use crate::bencoding::{Bencoding, BencodingError, BencodingReader, Dict, Event};
+use std::{convert::TryFrom, error, fmt};
+
+/// The parts of a torrent an indexer cares about, as returned by `Torrent::parse_summary`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TorrentSummary {
+    /// The name of this torrent, as suggested by its creator.
+    pub name: Option<String>,
+    /// The SHA1 hash of the info dictionary, exactly as it appeared in the input.
+    pub info_hash: InfoHash,
+    /// The SHA-256 hash of the info dictionary, if this torrent supports v2.
+    pub info_hash_v2: Option<InfoHashV2>,
+    /// Which versions of the protocol this torrent can be used with.
+    pub version: TorrentVersion,
+    /// Whether or not this torrent is private.
+    pub private: bool,
+    /// How many bytes are in each piece (except for the last one).
+    pub piece_length: usize,
+    /// The total number of bytes in the files of this torrent, without padding.
+    pub total_size: usize,
+    /// The files making up the content of this torrent, without padding files.
+    pub files: Box<[FileInfo]>,
+}
+
+/// An error that can occur when parsing a torrent out of its bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseBytesError {
+    /// The input wasn't valid bencoding.
+    Bencoding(BencodingError),
+    /// The bencoding didn't describe a valid torrent.
+    Torrent(OwnedParseError),
+}
+
+impl fmt::Display for ParseBytesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseBytesError::Bencoding(error) => write!(f, "{}", error),
+            ParseBytesError::Torrent(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl error::Error for ParseBytesError {}
+
+impl From<BencodingError> for ParseBytesError {
+    fn from(error: BencodingError) -> Self {
+        ParseBytesError::Bencoding(error)
+    }
+}
+
+// Find the bytes of the info dictionary, without decoding anything else.
+fn find_info(input: &[u8]) -> Result<Option<&[u8]>, BencodingError> {
+    let mut reader = BencodingReader::new(input);
+    if reader.next().transpose()? != Some(Event::DictStart) {
+        return Ok(None);
+    }
+    while let Some(event) = reader.next().transpose()? {
+        match event {
+            Event::DictKey(b"info") => {
+                let start = reader.offset();
+                reader.skip_value()?;
+                return Ok(Some(&input[start..reader.offset()]));
+            }
+            Event::DictKey(_) => reader.skip_value()?,
+            _ => break,
+        }
+    }
+    Ok(None)
+}
+
+// Decode an info dictionary, leaving its piece hashes empty.
+fn decode_without_pieces(info: &[u8]) -> Result<Bencoding, BencodingError> {
+    let mut reader = BencodingReader::new(info);
+    if reader.next().transpose()? != Some(Event::DictStart) {
+        return Bencoding::decode(info);
+    }
+    let mut dict = Dict::new();
+    while let Some(Event::DictKey(key)) = reader.next().transpose()? {
+        let value = if key == b"pieces" {
+            Bencoding::ByteString(Box::new([]))
+        } else {
+            Bencoding::parse_prefix(&info[reader.offset()..])?.0
+        };
+        reader.skip_value()?;
+        dict.insert(key, value);
+    }
+    Ok(Bencoding::Dict(dict))
+}
+
+impl Torrent {
+    /// Parse a torrent out of the bytes of a .torrent file, keeping the bytes of its info dictionary.
+    ///
+    /// Unlike parsing from bencoding, this fills in `raw_info`, and computes the info hashes
+    /// from the exact bytes of the info dictionary, even if they weren't canonical.
+    ///
+    /// ```
+    /// use typhoon::core::Torrent;
+    ///
+    /// // The info dictionary isn't sorted, which doesn't change its info hash
+    /// let input = b"d4:infod4:name1:f6:lengthi5e12:piece lengthi16e6:pieces0:ee";
+    /// let torrent = Torrent::from_bytes(input).unwrap();
+    /// assert_eq!(&input[7..input.len() - 1], &*torrent.info_bytes());
+    /// ```
+    pub fn from_bytes(input: &[u8]) -> Result<Torrent, ParseBytesError> {
+        Torrent::from_bytes_with(input, &TorrentOptions::default())
+    }
+
+    /// Parse a torrent out of the bytes of a .torrent file, with some options.
+    pub fn from_bytes_with(
+        input: &[u8],
+        options: &TorrentOptions,
+    ) -> Result<Torrent, ParseBytesError> {
+        let root = Bencoding::decode(input)?;
+        let raw_info = find_info(input)?;
+        Torrent::parse_raw(&root, options, raw_info)
+            .map_err(|e| ParseBytesError::Torrent(e.into_owned(&root)))
+    }
+
+    /// Parse the summary of a torrent out of its bytes, without decoding its piece hashes.
+    ///
+    /// This is much faster, and uses much less memory, than parsing the entire torrent,
+    /// while giving the same results for everything that's included in the summary.
+    ///
+    /// ```
+    /// use typhoon::core::Torrent;
+    ///
+    /// let input = b"d4:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces20:AAAAAAAAAAAAAAAAAAAAee";
+    /// let summary = Torrent::parse_summary(input).unwrap();
+    /// assert_eq!(Some("f"), summary.name.as_deref());
+    /// assert_eq!(5, summary.total_size);
+    /// ```
+    pub fn parse_summary(input: &[u8]) -> Result<TorrentSummary, ParseBytesError> {
+        let info_bytes = find_info(input)?;
+        let mut root = Dict::new();
+        if let Some(info_bytes) = info_bytes {
+            root.insert(&b"info"[..], decode_without_pieces(info_bytes)?);
+        }
+        let root = Bencoding::Dict(root);
+        let torrent =
+            Torrent::try_from(&root).map_err(|e| ParseBytesError::Torrent(e.into_owned(&root)))?;
+        // We found an info dictionary, or parsing would have failed
+        let info_bytes = info_bytes.unwrap_or_default();
+        let files = torrent.content_files().into_boxed_slice();
+        Ok(TorrentSummary {
+            name: torrent.name().map(String::from),
+            info_hash: InfoHash::of(info_bytes),
+            info_hash_v2: torrent.v2.as_ref().map(|_| InfoHashV2::of(info_bytes)),
+            version: torrent.version,
+            private: torrent.private,
+            piece_length: torrent.piece_length,
+            total_size: files.iter().map(|file| file.length).sum(),
+            files,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parsing_from_bytes_keeps_the_info_dictionary() {
+        // The integer has a leading zero, so re-encoding the info dictionary changes it
+        let input = b"d4:infod6:lengthi05e4:name1:f12:piece lengthi16e6:pieces0:ee";
+        let decoded = Bencoding::decode(input).unwrap();
+        let torrent = Torrent::from_bytes(input).unwrap();
+        let info = &input[7..input.len() - 1];
+        assert_eq!(Some(info), torrent.raw_info.as_deref());
+        assert_eq!(InfoHash::of(info), torrent.info_hash());
+        assert_ne!(
+            Torrent::try_from(&decoded).unwrap().info_hash(),
+            torrent.info_hash()
+        );
+        // Retagging starts from the original bytes, but can't keep the leading zero
+        let retagged = torrent.retag(None);
+        let info = b"d6:lengthi5e4:name1:f12:piece lengthi16e6:pieces0:e";
+        assert_eq!(Some(&info[..]), retagged.raw_info.as_deref());
+        assert_eq!(InfoHash::of(info), retagged.info_hash());
+    }
+
+    #[test]
+    fn summaries_match_full_parses() {
+        let mut input = b"d8:announce9:udp://a:14:infod5:filesl".to_vec();
+        input.extend_from_slice(b"d6:lengthi20e4:pathl1:aeed6:lengthi5e4:pathl1:beee");
+        // The keys aren't sorted, which the info hash needs to take into account
+        input.extend_from_slice(b"12:piece lengthi16e4:name3:dir6:pieces40:");
+        input.extend_from_slice(&[0; 40]);
+        input.extend_from_slice(b"7:privatei1eee");
+        let torrent = Torrent::try_from(&Bencoding::decode(&input).unwrap()).unwrap();
+        let summary = Torrent::parse_summary(&input).unwrap();
+        assert_eq!(
+            TorrentSummary {
+                name: Some("dir".to_owned()),
+                info_hash: torrent.info_hash(),
+                info_hash_v2: None,
+                version: TorrentVersion::V1,
+                private: true,
+                piece_length: 16,
+                total_size: 25,
+                files: torrent.files.clone(),
+            },
+            summary
+        );
+    }
+
+    #[test]
+    fn summarizing_broken_torrents_fails() {
+        assert!(matches!(
+            Torrent::parse_summary(b"d4:infod4:name"),
+            Err(ParseBytesError::Bencoding(_))
+        ));
+        let error = Torrent::parse_summary(b"d7:comment2:hie").unwrap_err();
+        assert!(matches!(error, ParseBytesError::Torrent(_)));
+        // Errors in the info dictionary should be the same as with a full parse
+        let input = b"d4:infod6:lengthi-1e4:name1:f12:piece lengthi16e6:pieces0:ee";
+        let root = Bencoding::decode(input).unwrap();
+        let expected = Torrent::try_from(&root).unwrap_err().into_owned(&root);
+        assert_eq!(
+            Err(ParseBytesError::Torrent(expected)),
+            Torrent::parse_summary(input)
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! This module contains the signatures of signed torrents, from BEP 35.
+//!
+//! A publisher can sign the info dictionary of a torrent, letting anyone holding their
+//! public key check that a torrent really came from them. Signatures live outside of the
+//! info dictionary, so adding one doesn't change the info hash of a torrent.
+//!
+//! We don't implement any public key cryptography ourselves. Instead, verification goes
+//! through the `SignatureVerifier` trait, which can be implemented on top of whatever
+//! crypto library an application already uses.
+use super::{extract_bytes, extract_key, ParseTorrentError, Torrent, TryFromBencodingError};
+use crate::bencoding::{Bencoding, DictBuilder};
+
+/// A signature over the info dictionary of a torrent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Signature {
+    /// The identity of whoever made this signature, which is its key in the `signatures`.
+    pub signer: String,
+    /// If present, the certificate of the signer, containing their public key.
+    ///
+    /// Without a certificate, the public key of the signer needs to be known in advance.
+    pub certificate: Option<Vec<u8>>,
+    /// If present, additional information covered by this signature.
+    pub info: Option<Bencoding>,
+    /// The bytes of the signature itself.
+    pub signature: Vec<u8>,
+}
+
+/// Something which can check signatures made by a given public key.
+///
+/// BEP 35 uses RSA signatures over the SHA-1 hash of the signed data, but this trait
+/// leaves the choice of algorithm up to the implementation.
+pub trait SignatureVerifier {
+    /// Check that a signature over some data was made by the key of this verifier.
+    fn verify(&self, data: &[u8], signature: &[u8]) -> bool;
+}
+
+impl<F: Fn(&[u8], &[u8]) -> bool> SignatureVerifier for F {
+    fn verify(&self, data: &[u8], signature: &[u8]) -> bool {
+        self(data, signature)
+    }
+}
+
+impl Signature {
+    /// The data this signature covers, given the bytes of the info dictionary it signs.
+    ///
+    /// This is the info dictionary, followed by the encoded `info` of this signature,
+    /// if it has one. See `Torrent::info_bytes` for the bytes of a torrent's info dictionary.
+    pub fn signed_data(&self, info_bytes: &[u8]) -> Vec<u8> {
+        let mut data = info_bytes.to_vec();
+        if let Some(info) = &self.info {
+            data.extend_from_slice(&info.encode_in_order());
+        }
+        data
+    }
+
+    /// Check this signature over an info dictionary, with a verifier for the key of its signer.
+    pub fn verify(&self, info_bytes: &[u8], verifier: &impl SignatureVerifier) -> bool {
+        verifier.verify(&self.signed_data(info_bytes), &self.signature)
+    }
+}
+
+pub(super) fn extract_signatures<'b>(
+    bencoding: &'b Bencoding,
+) -> Result<Box<[Signature]>, ParseTorrentError<'b>> {
+    let signatures = match extract_key(bencoding, "signatures") {
+        Err(_) => return Ok(Box::new([])),
+        Ok(Bencoding::Dict(signatures)) => signatures,
+        Ok(other) => return Err(TryFromBencodingError::ExpectedDict(other).into()),
+    };
+    let mut out = Vec::with_capacity(signatures.len());
+    for (signer, entry) in signatures.iter_sorted() {
+        let certificate = extract_key(entry, "certificate")
+            .ok()
+            .map(|inner| extract_bytes(inner).map(Vec::from))
+            .transpose()?;
+        let info = extract_key(entry, "info").ok().cloned();
+        let signature = extract_bytes(extract_key(entry, "signature")?)?.to_vec();
+        out.push(Signature {
+            signer: String::from_utf8_lossy(signer).into_owned(),
+            certificate,
+            info,
+            signature,
+        });
+    }
+    Ok(out.into_boxed_slice())
+}
+
+pub(super) fn signatures_to_bencoding(signatures: &[Signature]) -> Bencoding {
+    let mut dict = DictBuilder::new();
+    for signature in signatures {
+        let mut entry = DictBuilder::new().insert("signature", signature.signature.clone());
+        if let Some(certificate) = &signature.certificate {
+            entry = entry.insert("certificate", certificate.clone());
+        }
+        if let Some(info) = &signature.info {
+            entry = entry.insert("info", info.clone());
+        }
+        dict = dict.insert(&signature.signer, entry);
+    }
+    dict.build()
+}
+
+impl Torrent {
+    /// Find the signature made by a given signer, if this torrent has one.
+    pub fn signature(&self, signer: &str) -> Option<&Signature> {
+        self.signatures.iter().find(|s| s.signer == signer)
+    }
+
+    /// Check that a signer signed this torrent, using a verifier for their public key.
+    ///
+    /// This returns false if the signer didn't sign this torrent at all.
+    pub fn verify_signature(&self, signer: &str, verifier: &impl SignatureVerifier) -> bool {
+        self.signature(signer)
+            .is_some_and(|signature| signature.verify(&self.info_bytes(), verifier))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hash::{Hasher, Sha1};
+    use std::convert::TryFrom;
+
+    // A stand in for a real signature scheme, where the key is prepended to the data.
+    fn sign(key: &[u8], data: &[u8]) -> Vec<u8> {
+        Sha1::digest(&[key, data].concat()).to_vec()
+    }
+
+    #[test]
+    fn verifying_signatures_works() {
+        let info = b"d6:lengthi5e4:name1:f12:piece lengthi16e6:pieces0:e";
+        let extra = b"d7:expiresi1ee";
+        let signature = sign(b"key", &[&info[..], &extra[..]].concat());
+        let mut input = b"d4:info".to_vec();
+        input.extend_from_slice(info);
+        input.extend_from_slice(b"10:signaturesd9:publisherd4:info");
+        input.extend_from_slice(extra);
+        input.extend_from_slice(b"9:signature20:");
+        input.extend_from_slice(&signature);
+        input.extend_from_slice(b"eee");
+        let torrent = Torrent::try_from(&Bencoding::decode(&input).unwrap()).unwrap();
+        let verifier = |key: &'static [u8]| move |data: &[u8], sig: &[u8]| sign(key, data) == sig;
+        assert!(torrent.verify_signature("publisher", &verifier(b"key")));
+        assert!(!torrent.verify_signature("publisher", &verifier(b"other")));
+        assert!(!torrent.verify_signature("someone", &verifier(b"key")));
+        assert_eq!(input, torrent.to_bytes());
+        // Changing the info dictionary invalidates the signature
+        let retagged = torrent.retag(Some("A"));
+        assert!(!retagged.verify_signature("publisher", &verifier(b"key")));
+    }
+
+    #[test]
+    fn parsing_bad_signatures_fails() {
+        let input = b"d4:infod6:lengthi5e4:name1:f12:piece lengthi16e6:pieces0:e10:signaturesi1ee";
+        let bencoding = Bencoding::decode(input).unwrap();
+        assert!(Torrent::try_from(&bencoding).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+//! This module contains statistics derived from the metadata of a torrent.
+//!
+//! None of this is stored in the torrent itself, but indexers and the CLI often want
+//! to know things like which kinds of files a torrent contains, or how much of it is
+//! padding, so we compute all of it in one pass over the files and trackers.
+use super::{format_size, FileInfo, Torrent, TrackerAddr};
+use std::{collections::BTreeMap, fmt};
+
+/// How many files in a torrent share an extension, and how many bytes they contain.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExtensionStats {
+    /// The number of files with this extension.
+    pub files: usize,
+    /// The total number of bytes in these files.
+    pub size: usize,
+}
+
+/// How many trackers of a torrent use each protocol.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TrackerCounts {
+    /// The number of UDP trackers.
+    pub udp: usize,
+    /// The number of HTTP or HTTPS trackers.
+    pub http: usize,
+    /// The number of trackers using other protocols, like websockets.
+    pub other: usize,
+}
+
+/// Statistics about a torrent, as returned by `Torrent::stats`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TorrentStats {
+    /// The total number of bytes in this torrent, including padding files.
+    pub total_size: usize,
+    /// The number of pieces the files of this torrent are split into.
+    pub num_pieces: usize,
+    /// The number of files in this torrent, without padding files.
+    pub num_files: usize,
+    /// The number of files and bytes for each extension, without padding files.
+    ///
+    /// Extensions are lowercased, and files without an extension use an empty string.
+    pub extensions: BTreeMap<String, ExtensionStats>,
+    /// The largest file in this torrent, without counting padding files.
+    pub largest_file: Option<FileInfo>,
+    /// The smallest file in this torrent, without counting padding files.
+    pub smallest_file: Option<FileInfo>,
+    /// The number of padding files in this torrent.
+    pub padding_files: usize,
+    /// The number of bytes taken up by padding files.
+    pub padding_size: usize,
+    /// How many trackers use each protocol.
+    pub trackers: TrackerCounts,
+}
+
+impl Torrent {
+    /// Compute statistics about the files and trackers of this torrent.
+    ///
+    /// When several files are tied for largest or smallest, this picks the first one.
+    pub fn stats(&self) -> TorrentStats {
+        let mut extensions = BTreeMap::<String, ExtensionStats>::new();
+        let mut largest_file: Option<&FileInfo> = None;
+        let mut smallest_file: Option<&FileInfo> = None;
+        let (mut num_files, mut padding_files, mut padding_size) = (0, 0, 0usize);
+        for file in self.files.iter() {
+            if file.attributes.padding {
+                padding_files += 1;
+                padding_size = padding_size.saturating_add(file.length);
+                continue;
+            }
+            num_files += 1;
+            let extension = file
+                .name
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let entry = extensions.entry(extension).or_default();
+            entry.files += 1;
+            entry.size = entry.size.saturating_add(file.length);
+            if largest_file.is_none_or(|largest| file.length > largest.length) {
+                largest_file = Some(file);
+            }
+            if smallest_file.is_none_or(|smallest| file.length < smallest.length) {
+                smallest_file = Some(file);
+            }
+        }
+        let mut trackers = TrackerCounts::default();
+        for (_, tracker) in self.trackers.iter() {
+            match tracker {
+                TrackerAddr::UDP(_) => trackers.udp += 1,
+                TrackerAddr::HTTP(_) => trackers.http += 1,
+                TrackerAddr::Unknown(_) => trackers.other += 1,
+            }
+        }
+        TorrentStats {
+            total_size: self.total_size(),
+            num_pieces: self.num_pieces(),
+            num_files,
+            extensions,
+            largest_file: largest_file.cloned(),
+            smallest_file: smallest_file.cloned(),
+            padding_files,
+            padding_size,
+            trackers,
+        }
+    }
+}
+
+impl fmt::Display for TorrentStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "size: {}", format_size(self.total_size))?;
+        writeln!(f, "pieces: {}", self.num_pieces)?;
+        writeln!(f, "files: {}", self.num_files)?;
+        if let Some(file) = &self.largest_file {
+            let size = format_size(file.length);
+            writeln!(f, "largest file: {} ({})", file.name.display(), size)?;
+        }
+        if let Some(file) = &self.smallest_file {
+            let size = format_size(file.length);
+            writeln!(f, "smallest file: {} ({})", file.name.display(), size)?;
+        }
+        if self.padding_files > 0 {
+            writeln!(
+                f,
+                "padding: {} in {} files",
+                format_size(self.padding_size),
+                self.padding_files
+            )?;
+        }
+        let TrackerCounts { udp, http, other } = self.trackers;
+        writeln!(f, "trackers: {} udp, {} http, {} other", udp, http, other)?;
+        for (extension, stats) in &self.extensions {
+            let extension = if extension.is_empty() {
+                "<none>"
+            } else {
+                extension
+            };
+            writeln!(
+                f,
+                "  .{}: {} in {} files",
+                extension,
+                format_size(stats.size),
+                stats.files
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bencoding::Bencoding;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn computing_stats_works() {
+        let input = b"d8:announce9:udp://a:113:announce-listll9:udp://a:1el8:http://bel7:wss://cee\
+            4:infod5:filesld6:lengthi10e4:pathl5:a.MKVeed4:attr1:p6:lengthi6e4:pathl4:.padeed\
+            6:lengthi2e4:pathl5:b.srteed6:lengthi30e4:pathl5:c.mkveed6:lengthi2e4:pathl6:READMEeee\
+            4:name3:dir12:piece lengthi16e6:pieces0:ee";
+        let torrent = Torrent::try_from(&Bencoding::decode(input).unwrap()).unwrap();
+        let stats = torrent.stats();
+        assert_eq!(50, stats.total_size);
+        assert_eq!(4, stats.num_pieces);
+        assert_eq!(4, stats.num_files);
+        assert_eq!(1, stats.padding_files);
+        assert_eq!(6, stats.padding_size);
+        assert_eq!(
+            Some(ExtensionStats { files: 2, size: 40 }),
+            stats.extensions.get("mkv").copied()
+        );
+        assert_eq!(
+            Some(ExtensionStats { files: 1, size: 2 }),
+            stats.extensions.get("").copied()
+        );
+        assert_eq!(30, stats.largest_file.unwrap().length);
+        assert!(stats.smallest_file.unwrap().name.ends_with("b.srt"));
+        assert_eq!(
+            TrackerCounts {
+                udp: 1,
+                http: 1,
+                other: 1
+            },
+            stats.trackers
+        );
+    }
+}
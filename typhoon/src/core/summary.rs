@@ -0,0 +1,144 @@
+//! This module contains a human readable summary of torrents.
+//!
+//! This is what a user wants to see when looking at a torrent: how big it is,
+//! what it contains, and where it came from. All of the details are available through
+//! `Debug` instead.
+use super::{unix_from_time, write_hex, Torrent, TorrentVersion};
+use std::{fmt, time};
+
+/// Format a number of bytes with binary units, like `1.5 MiB`.
+pub fn format_size(bytes: usize) -> String {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Format a point in time as a UTC date, like `2020-01-31 12:00:00 UTC`.
+pub fn format_time(time: time::SystemTime) -> String {
+    let seconds = unix_from_time(time);
+    let (days, rest) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+    // Convert days since the epoch into a date, following Howard Hinnant's `civil_from_days`
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        rest / 3600,
+        rest % 3600 / 60,
+        rest % 60
+    )
+}
+
+impl fmt::Display for Torrent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "name: {}", self.name().unwrap_or("<unknown>"))?;
+        write!(f, "info hash: ")?;
+        match (self.version, self.info_hash_v2) {
+            (TorrentVersion::V2, Some(hash)) => write_hex(f, hash.as_bytes())?,
+            _ => write_hex(f, self.info_hash.as_bytes())?,
+        }
+        writeln!(f)?;
+        writeln!(f, "size: {}", format_size(self.total_size()))?;
+        writeln!(
+            f,
+            "pieces: {} of {}",
+            self.num_pieces(),
+            format_size(self.piece_length)
+        )?;
+        writeln!(f, "files: {}", self.content_files().len())?;
+        if self.private {
+            writeln!(f, "private: yes")?;
+        }
+        if let Some(source) = &self.source {
+            writeln!(f, "source: {}", source)?;
+        }
+        if let Some(creation) = self.creation {
+            writeln!(f, "created: {}", format_time(creation))?;
+        }
+        if let Some(created_by) = &self.created_by {
+            writeln!(f, "created by: {}", created_by)?;
+        }
+        if let Some(comment) = &self.comment {
+            writeln!(f, "comment: {}", comment)?;
+        }
+        write!(f, "trackers:")?;
+        if self.trackers.is_empty() {
+            write!(f, " none")?;
+        }
+        for (tier, tracker) in self.trackers.iter() {
+            write!(f, "\n  [{}] {}", tier, tracker)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bencoding::Bencoding;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn formatting_sizes_works() {
+        assert_eq!("0 B", format_size(0));
+        assert_eq!("1023 B", format_size(1023));
+        assert_eq!("1.0 KiB", format_size(1024));
+        assert_eq!("1.5 MiB", format_size(3 << 19));
+    }
+
+    #[test]
+    fn formatting_times_works() {
+        let at = |secs| time::UNIX_EPOCH + time::Duration::from_secs(secs);
+        assert_eq!("1970-01-01 00:00:00 UTC", format_time(at(0)));
+        assert_eq!("2000-02-29 12:34:56 UTC", format_time(at(951_827_696)));
+        assert_eq!("2021-12-31 23:59:59 UTC", format_time(at(1_640_995_199)));
+        let before = time::UNIX_EPOCH - time::Duration::from_secs(86_401);
+        assert_eq!("1969-12-30 23:59:59 UTC", format_time(before));
+    }
+
+    #[test]
+    fn summarizing_torrents_works() {
+        let mut input = b"d13:announce-listll9:udp://a:1el9:udp://b:1ee7:comment2:hi".to_vec();
+        input.extend_from_slice(b"13:creation datei0e4:infod5:filesl");
+        input.extend_from_slice(b"d6:lengthi20e4:pathl1:aeed6:lengthi5e4:pathl1:beee");
+        input.extend_from_slice(b"4:name3:dir12:piece lengthi16e6:pieces40:");
+        input.extend_from_slice(&[0; 40]);
+        input.extend_from_slice(b"ee");
+        let torrent = Torrent::try_from(&Bencoding::decode(&input).unwrap()).unwrap();
+        let summary = torrent.to_string();
+        let lines: Vec<_> = summary.lines().collect();
+        assert_eq!("name: dir", lines[0]);
+        assert!(lines[1].starts_with("info hash: "));
+        assert_eq!(
+            vec![
+                "size: 25 B",
+                "pieces: 2 of 16 B",
+                "files: 2",
+                "created: 1970-01-01 00:00:00 UTC",
+                "comment: hi",
+                "trackers:",
+                "  [0] udp://a:1",
+                "  [1] udp://b:1",
+            ],
+            lines[2..]
+        );
+        assert!(format!("{:?}", torrent).contains("PieceHash(0000"));
+    }
+}
@@ -0,0 +1,228 @@
+//! This module parses the URLs of trackers into their components.
+//!
+//! Tracker URLs are much simpler than URLs in general: we only care about the scheme,
+//! the host, the port, and whatever comes after, which we pass along as is. This lets
+//! us catch garbage in the announce list of a torrent long before we try to contact a tracker.
+use super::TrackerAddr;
+use std::{
+    error, fmt,
+    net::{Ipv4Addr, Ipv6Addr},
+    str,
+};
+
+/// The host part of a tracker URL.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Host {
+    /// A domain name, which needs to be resolved.
+    Domain(String),
+    /// An IPv4 address.
+    Ipv4(Ipv4Addr),
+    /// An IPv6 address, which was written in brackets.
+    Ipv6(Ipv6Addr),
+}
+
+impl fmt::Display for Host {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Host::Domain(domain) => write!(f, "{}", domain),
+            Host::Ipv4(ip) => write!(f, "{}", ip),
+            Host::Ipv6(ip) => write!(f, "[{}]", ip),
+        }
+    }
+}
+
+/// An error that can occur when parsing the URL of a tracker.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ParseTrackerUrlError {
+    /// The URL doesn't start with a scheme, like `udp://`.
+    MissingScheme,
+    /// The host of the URL is empty, or contains characters a host can't.
+    InvalidHost(String),
+    /// The port of the URL isn't a number between 1 and 65535.
+    InvalidPort(String),
+    /// The URL has no port, and its scheme has no default port.
+    MissingPort,
+}
+
+impl fmt::Display for ParseTrackerUrlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ParseTrackerUrlError::*;
+        match self {
+            MissingScheme => write!(f, "tracker URL has no scheme"),
+            InvalidHost(host) => write!(f, "tracker URL has invalid host {:?}", host),
+            InvalidPort(port) => write!(f, "tracker URL has invalid port {:?}", port),
+            MissingPort => write!(f, "tracker URL has no port"),
+        }
+    }
+}
+
+impl error::Error for ParseTrackerUrlError {}
+
+/// The URL of a tracker, split into its components.
+///
+/// ```
+/// use typhoon::core::{Host, TrackerUrl};
+///
+/// let url: TrackerUrl = "udp://[::1]:6969/announce".parse().unwrap();
+/// assert_eq!("udp", url.scheme);
+/// assert_eq!(Host::Ipv6("::1".parse().unwrap()), url.host);
+/// assert_eq!(6969, url.port);
+/// assert_eq!("/announce", url.path);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TrackerUrl {
+    /// The scheme of this URL, in lowercase, like `udp` or `https`.
+    pub scheme: String,
+    /// The host to contact.
+    pub host: Host,
+    /// The port to contact, filled in from the scheme if the URL didn't have one.
+    pub port: u16,
+    /// Everything after the host and port, including the query, like `/announce?key=1`.
+    ///
+    /// This is empty if the URL ended after its host or port.
+    pub path: String,
+}
+
+impl TrackerUrl {
+    // The port used by a scheme when a URL doesn't mention one.
+    fn default_port(scheme: &str) -> Option<u16> {
+        match scheme {
+            "http" | "ws" => Some(80),
+            "https" | "wss" => Some(443),
+            _ => None,
+        }
+    }
+}
+
+fn parse_host(host: &str) -> Result<Host, ParseTrackerUrlError> {
+    let invalid = || ParseTrackerUrlError::InvalidHost(host.to_owned());
+    if let Some(inner) = host.strip_prefix('[') {
+        let inner = inner.strip_suffix(']').ok_or_else(invalid)?;
+        return inner.parse().map(Host::Ipv6).map_err(|_| invalid());
+    }
+    if let Ok(ip) = host.parse() {
+        return Ok(Host::Ipv4(ip));
+    }
+    let valid = !host.is_empty()
+        && host
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'.' || c == b'-' || c == b'_');
+    if !valid {
+        return Err(invalid());
+    }
+    Ok(Host::Domain(host.to_ascii_lowercase()))
+}
+
+impl str::FromStr for TrackerUrl {
+    type Err = ParseTrackerUrlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = s
+            .split_once("://")
+            .filter(|(scheme, _)| {
+                let mut chars = scheme.chars();
+                chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+                    && chars.all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+            })
+            .ok_or(ParseTrackerUrlError::MissingScheme)?;
+        let scheme = scheme.to_ascii_lowercase();
+        let split = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+        let (authority, path) = rest.split_at(split);
+        // The port comes after the last colon, unless that colon is inside of an IPv6 literal
+        let (host, port) = match authority.rfind(':') {
+            Some(colon) if !authority[colon..].contains(']') => {
+                (&authority[..colon], Some(&authority[colon + 1..]))
+            }
+            _ => (authority, None),
+        };
+        let host = parse_host(host)?;
+        let port = match port {
+            Some(port) => port
+                .parse()
+                .ok()
+                .filter(|&port| port != 0)
+                .ok_or_else(|| ParseTrackerUrlError::InvalidPort(port.to_owned()))?,
+            None => TrackerUrl::default_port(&scheme).ok_or(ParseTrackerUrlError::MissingPort)?,
+        };
+        Ok(TrackerUrl {
+            scheme,
+            host,
+            port,
+            path: path.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for TrackerUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}://{}:{}{}",
+            self.scheme, self.host, self.port, self.path
+        )
+    }
+}
+
+impl TrackerAddr {
+    /// Parse the address of this tracker into its components.
+    pub fn url(&self) -> Result<TrackerUrl, ParseTrackerUrlError> {
+        self.to_string().parse()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parsing_tracker_urls_works() {
+        let url: TrackerUrl = "HTTP://Tracker.Example.com/announce?passkey=x"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            TrackerUrl {
+                scheme: "http".to_owned(),
+                host: Host::Domain("tracker.example.com".to_owned()),
+                port: 80,
+                path: "/announce?passkey=x".to_owned(),
+            },
+            url
+        );
+        assert_eq!(
+            "http://tracker.example.com:80/announce?passkey=x",
+            url.to_string()
+        );
+        let url: TrackerUrl = "udp://10.0.0.1:1337".parse().unwrap();
+        assert_eq!(Host::Ipv4(Ipv4Addr::new(10, 0, 0, 1)), url.host);
+        assert_eq!((1337, ""), (url.port, url.path.as_str()));
+        let url: TrackerUrl = "https://[2001:db8::1]/a".parse().unwrap();
+        assert_eq!(Host::Ipv6("2001:db8::1".parse().unwrap()), url.host);
+        assert_eq!(443, url.port);
+        assert_eq!(Ok(url.clone()), url.to_string().parse());
+    }
+
+    #[test]
+    fn parsing_garbage_tracker_urls_fails() {
+        use ParseTrackerUrlError::*;
+        let cases = vec![
+            ("tracker.example.com:80", MissingScheme),
+            ("://a:1", MissingScheme),
+            ("udp://:1", InvalidHost("".to_owned())),
+            ("udp://a b:1", InvalidHost("a b".to_owned())),
+            ("udp://[::1:1", InvalidHost("[::1".to_owned())),
+            ("udp://a:99999", InvalidPort("99999".to_owned())),
+            ("udp://a:0/announce", InvalidPort("0".to_owned())),
+            ("udp://a/announce", MissingPort),
+        ];
+        for (url, error) in cases {
+            assert_eq!(Err(error), url.parse::<TrackerUrl>(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn tracker_addrs_convert_to_urls() {
+        let addr = TrackerAddr::from("udp://tracker.example.com:6969/announce");
+        let url = addr.url().unwrap();
+        assert_eq!(("udp", 6969), (url.scheme.as_str(), url.port));
+    }
+}
@@ -0,0 +1,260 @@
+//! This module checks that parsed torrents make sense.
+//!
+//! Parsing only makes sure that a torrent has the right shape. A torrent can have that
+//! shape while still being broken, like having fewer piece hashes than it has pieces,
+//! or suspicious, like having files that would get written outside of the download directory.
+use super::{FileInfo, ParseTrackerUrlError, Torrent};
+use std::{
+    fmt,
+    path::{Component, Path, PathBuf},
+};
+
+// Pieces smaller than this make for huge torrent files, and v2 doesn't allow them at all.
+const MIN_PIECE_LENGTH: usize = 16 * 1024;
+// Pieces larger than this take a long time to download before they can be verified.
+const MAX_PIECE_LENGTH: usize = 64 * 1024 * 1024;
+// No reasonable file tree is this deep, but it's enough to trip up some file systems.
+const MAX_PATH_DEPTH: usize = 64;
+
+/// How serious a problem found while validating a torrent is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// The torrent is usable, but something about it is unusual.
+    Warning,
+    /// The torrent is broken, and won't work correctly.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A single problem found while validating a torrent.
+///
+/// Negative lengths don't appear here, since they already make parsing fail.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ValidationIssue {
+    /// The torrent has a piece length of 0, so its files can't be split into pieces.
+    ZeroPieceLength,
+    /// The piece length isn't a power of two, or is unreasonably small or large.
+    UnusualPieceLength(usize),
+    /// The number of piece hashes doesn't match the number of pieces the files need.
+    PieceCountMismatch {
+        /// How many piece hashes the torrent contains.
+        hashes: usize,
+        /// How many pieces the files of the torrent are split into.
+        pieces: usize,
+    },
+    /// The torrent doesn't contain any files.
+    NoFiles,
+    /// The path of a file is empty, absolute, or escapes the download directory.
+    SuspiciousPath(PathBuf),
+    /// A symlink points to a path outside of the download directory.
+    SuspiciousSymlink {
+        /// The path of the symlink itself.
+        path: PathBuf,
+        /// The path the symlink points to.
+        target: PathBuf,
+    },
+    /// The path of a file is nested unreasonably deep.
+    PathTooDeep(PathBuf),
+    /// The URL of a tracker is malformed, so we won't be able to contact it.
+    InvalidTracker {
+        /// The URL of the tracker, as it appears in the torrent.
+        url: String,
+        /// What's wrong with that URL.
+        error: ParseTrackerUrlError,
+    },
+}
+
+impl ValidationIssue {
+    /// How serious this issue is.
+    pub fn severity(&self) -> Severity {
+        use ValidationIssue::*;
+        match self {
+            ZeroPieceLength | PieceCountMismatch { .. } | NoFiles => Severity::Error,
+            UnusualPieceLength(_)
+            | SuspiciousPath(_)
+            | SuspiciousSymlink { .. }
+            | PathTooDeep(_)
+            | InvalidTracker { .. } => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ValidationIssue::*;
+        match self {
+            ZeroPieceLength => write!(f, "piece length is 0"),
+            UnusualPieceLength(length) if length.is_power_of_two() => {
+                write!(f, "piece length {} is unreasonable", length)
+            }
+            UnusualPieceLength(length) => {
+                write!(f, "piece length {} is not a power of two", length)
+            }
+            PieceCountMismatch { hashes, pieces } => write!(
+                f,
+                "torrent has {} piece hashes, but its files need {} pieces",
+                hashes, pieces
+            ),
+            NoFiles => write!(f, "torrent contains no files"),
+            SuspiciousPath(path) => write!(f, "file path {} is suspicious", path.display()),
+            SuspiciousSymlink { path, target } => write!(
+                f,
+                "file {} links to suspicious path {}",
+                path.display(),
+                target.display()
+            ),
+            PathTooDeep(path) => write!(f, "file path {} is too deep", path.display()),
+            InvalidTracker { url, error } => write!(f, "tracker {}: {}", url, error),
+        }
+    }
+}
+
+// Check whether a path could end up outside of the download directory.
+fn is_suspicious(path: &Path) -> bool {
+    path.as_os_str().is_empty()
+        || path.components().any(|c| match c {
+            Component::Normal(part) => part.is_empty(),
+            Component::CurDir => false,
+            _ => true,
+        })
+}
+
+fn validate_file(file: &FileInfo, issues: &mut Vec<ValidationIssue>) {
+    if is_suspicious(&file.name) {
+        issues.push(ValidationIssue::SuspiciousPath(file.name.clone()));
+    } else if file.name.components().count() > MAX_PATH_DEPTH {
+        issues.push(ValidationIssue::PathTooDeep(file.name.clone()));
+    }
+    // Following a symlink like this would let other files get written outside the download
+    if let Some(target) = file.symlink_path.as_ref().filter(|t| is_suspicious(t)) {
+        issues.push(ValidationIssue::SuspiciousSymlink {
+            path: file.name.clone(),
+            target: target.clone(),
+        });
+    }
+}
+
+impl Torrent {
+    /// Check that this torrent makes sense, returning all of the problems we find.
+    ///
+    /// An empty list means that the torrent is fine. Issues with an `Error` severity
+    /// mean that the torrent won't work, while warnings point out oddities.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        if self.piece_length == 0 {
+            issues.push(ValidationIssue::ZeroPieceLength);
+        } else {
+            let reasonable = (MIN_PIECE_LENGTH..=MAX_PIECE_LENGTH).contains(&self.piece_length);
+            if !self.piece_length.is_power_of_two() || !reasonable {
+                issues.push(ValidationIssue::UnusualPieceLength(self.piece_length));
+            }
+            if !self.has_consistent_pieces() {
+                issues.push(ValidationIssue::PieceCountMismatch {
+                    hashes: self.piece_hashes.len(),
+                    pieces: self.num_pieces(),
+                });
+            }
+        }
+        if self.files.is_empty() {
+            issues.push(ValidationIssue::NoFiles);
+        }
+        for file in self.files.iter() {
+            validate_file(file, &mut issues);
+        }
+        for (_, tracker) in self.trackers.iter() {
+            if let Err(error) = tracker.url() {
+                issues.push(ValidationIssue::InvalidTracker {
+                    url: tracker.to_string(),
+                    error,
+                });
+            }
+        }
+        issues
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        bencoding::Bencoding,
+        core::{PieceHash, TrackerAddr},
+    };
+    use std::convert::TryFrom;
+
+    fn torrent(piece_length: usize, files: Vec<FileInfo>, hashes: usize) -> Torrent {
+        let input = b"d13:announce-listll9:udp://a:1ee4:infod6:lengthi0e4:name1:f12:piece lengthi16384e6:pieces0:ee";
+        let mut torrent = Torrent::try_from(&Bencoding::decode(input).unwrap()).unwrap();
+        torrent.piece_length = piece_length;
+        torrent.files = files.into_boxed_slice();
+        torrent.piece_hashes = vec![PieceHash([0; 20]); hashes].into_boxed_slice();
+        torrent
+    }
+
+    #[test]
+    fn valid_torrents_have_no_issues() {
+        let files = vec![FileInfo::new("dir/a", 40000), FileInfo::new("dir/b", 0)];
+        assert_eq!(
+            Vec::<ValidationIssue>::new(),
+            torrent(16384, files, 3).validate()
+        );
+    }
+
+    #[test]
+    fn validating_finds_issues() {
+        let deep: PathBuf = (0..100).map(|i| i.to_string()).collect();
+        let files = vec![
+            FileInfo::new("../a", 10),
+            FileInfo::new(deep.clone(), 0),
+            FileInfo {
+                symlink_path: Some(PathBuf::from("/etc")),
+                ..FileInfo::new("link", 0)
+            },
+        ];
+        let mut invalid = torrent(1000, files, 0);
+        invalid.trackers = vec![(0, TrackerAddr::from("udp://a"))].into_boxed_slice();
+        let issues = invalid.validate();
+        assert_eq!(
+            vec![
+                ValidationIssue::UnusualPieceLength(1000),
+                ValidationIssue::PieceCountMismatch {
+                    hashes: 0,
+                    pieces: 1
+                },
+                ValidationIssue::SuspiciousPath(PathBuf::from("../a")),
+                ValidationIssue::PathTooDeep(deep),
+                ValidationIssue::SuspiciousSymlink {
+                    path: PathBuf::from("link"),
+                    target: PathBuf::from("/etc")
+                },
+                ValidationIssue::InvalidTracker {
+                    url: "udp://a".to_owned(),
+                    error: ParseTrackerUrlError::MissingPort
+                },
+            ],
+            issues
+        );
+        let severities: Vec<_> = issues.iter().map(|i| i.severity()).collect();
+        assert_eq!(Severity::Error, severities[1]);
+        assert_eq!(
+            vec![ValidationIssue::ZeroPieceLength, ValidationIssue::NoFiles],
+            torrent(0, Vec::new(), 0).validate()
+        );
+        assert_eq!(
+            vec![ValidationIssue::UnusualPieceLength(1 << 30)],
+            torrent(1 << 30, Vec::new(), 0)
+                .validate()
+                .into_iter()
+                .take(1)
+                .collect::<Vec<_>>()
+        );
+    }
+}
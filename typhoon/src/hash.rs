@@ -0,0 +1,429 @@
+//! This module contains the hash functions used throughout Bittorrent.
+//!
+//! Version 1 of the protocol uses SHA-1 for piece hashes and info hashes,
+//! while version 2 moved to SHA-256. Both of these are exposed behind the
+//! `Hasher` trait, so that code verifying data doesn't need to care about which
+//! implementation is doing the work. MD5 is also included, since some torrents
+//! contain MD5 checksums of their files.
+//!
+//! Everything in the crate that hashes data, like `InfoHash::of` or `TorrentBuilder::build`,
+//! has a `_with` variant, which takes another implementation of `Hasher` to use instead,
+//! such as one with hardware acceleration.
+//!
+//! The implementations in this module are written in pure Rust, without any
+//! platform specific acceleration.
+
+/// Represents an incremental hash function.
+///
+/// Data is fed in with `update`, possibly over multiple calls, and then
+/// `finish` produces the final digest.
+pub trait Hasher: Default {
+    /// The digest produced by this hash function.
+    type Output: AsRef<[u8]>;
+
+    /// Feed more data into the hash function.
+    fn update(&mut self, data: &[u8]);
+
+    /// Consume the hasher, producing the digest of all of the data fed so far.
+    fn finish(self) -> Self::Output;
+
+    /// Hash a single sequence of bytes in one go.
+    fn digest(data: &[u8]) -> Self::Output {
+        let mut hasher = Self::default();
+        hasher.update(data);
+        hasher.finish()
+    }
+}
+
+// SHA-1, SHA-256, and MD5 all process data in blocks of 64 bytes.
+const BLOCK_SIZE: usize = 64;
+
+// Buffers data into whole blocks, and handles the padding at the end of a message.
+#[derive(Clone)]
+struct BlockBuffer {
+    block: [u8; BLOCK_SIZE],
+    filled: usize,
+    // The total number of bytes fed into the hash function.
+    length: u64,
+}
+
+impl Default for BlockBuffer {
+    fn default() -> Self {
+        BlockBuffer {
+            block: [0; BLOCK_SIZE],
+            filled: 0,
+            length: 0,
+        }
+    }
+}
+
+impl BlockBuffer {
+    fn update(&mut self, mut data: &[u8], mut compress: impl FnMut(&[u8; BLOCK_SIZE])) {
+        self.length = self.length.wrapping_add(data.len() as u64);
+        if self.filled > 0 {
+            let amount = (BLOCK_SIZE - self.filled).min(data.len());
+            self.block[self.filled..self.filled + amount].copy_from_slice(&data[..amount]);
+            self.filled += amount;
+            data = &data[amount..];
+            if self.filled < BLOCK_SIZE {
+                return;
+            }
+            compress(&self.block);
+            self.filled = 0;
+        }
+        let mut chunks = data.chunks_exact(BLOCK_SIZE);
+        for chunk in &mut chunks {
+            let mut block = [0; BLOCK_SIZE];
+            block.copy_from_slice(chunk);
+            compress(&block);
+        }
+        let rest = chunks.remainder();
+        self.block[..rest.len()].copy_from_slice(rest);
+        self.filled = rest.len();
+    }
+
+    // All of these hash functions pad with a 1 bit, zeros, and then the message length in bits.
+    //
+    // MD5 writes that length in little endian, unlike the others.
+    fn finish(mut self, little_endian: bool, mut compress: impl FnMut(&[u8; BLOCK_SIZE])) {
+        let bit_length = self.length.wrapping_mul(8);
+        self.block[self.filled] = 0x80;
+        for b in &mut self.block[self.filled + 1..] {
+            *b = 0;
+        }
+        if self.filled + 1 > BLOCK_SIZE - 8 {
+            compress(&self.block);
+            self.block = [0; BLOCK_SIZE];
+        }
+        let bytes = if little_endian {
+            bit_length.to_le_bytes()
+        } else {
+            bit_length.to_be_bytes()
+        };
+        self.block[BLOCK_SIZE - 8..].copy_from_slice(&bytes);
+        compress(&self.block);
+    }
+}
+
+/// The SHA-1 hash function, used for piece and info hashes in version 1 torrents.
+#[derive(Clone)]
+pub struct Sha1 {
+    state: [u32; 5],
+    buffer: BlockBuffer,
+}
+
+impl Default for Sha1 {
+    fn default() -> Self {
+        Sha1 {
+            state: [
+                0x6745_2301,
+                0xEFCD_AB89,
+                0x98BA_DCFE,
+                0x1032_5476,
+                0xC3D2_E1F0,
+            ],
+            buffer: BlockBuffer::default(),
+        }
+    }
+}
+
+fn sha1_compress(state: &mut [u32; 5], block: &[u8; BLOCK_SIZE]) {
+    let mut w = [0u32; 80];
+    for (i, word) in block.chunks_exact(4).enumerate() {
+        w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+    }
+    for i in 16..80 {
+        w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e] = *state;
+    for (i, &word) in w.iter().enumerate() {
+        let (f, k) = match i {
+            0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+            20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+            40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+            _ => (b ^ c ^ d, 0xCA62_C1D6),
+        };
+        let temp = a
+            .rotate_left(5)
+            .wrapping_add(f)
+            .wrapping_add(e)
+            .wrapping_add(k)
+            .wrapping_add(word);
+        e = d;
+        d = c;
+        c = b.rotate_left(30);
+        b = a;
+        a = temp;
+    }
+    for (s, v) in state.iter_mut().zip([a, b, c, d, e].iter()) {
+        *s = s.wrapping_add(*v);
+    }
+}
+
+impl Hasher for Sha1 {
+    type Output = [u8; 20];
+
+    fn update(&mut self, data: &[u8]) {
+        let state = &mut self.state;
+        self.buffer
+            .update(data, |block| sha1_compress(state, block));
+    }
+
+    fn finish(mut self) -> Self::Output {
+        let state = &mut self.state;
+        self.buffer
+            .finish(false, |block| sha1_compress(state, block));
+        let mut out = [0; 20];
+        for (chunk, word) in out.chunks_exact_mut(4).zip(self.state.iter()) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        out
+    }
+}
+
+/// The SHA-256 hash function, used for piece and info hashes in version 2 torrents.
+#[derive(Clone)]
+pub struct Sha256 {
+    state: [u32; 8],
+    buffer: BlockBuffer,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Sha256 {
+            state: [
+                0x6a09_e667,
+                0xbb67_ae85,
+                0x3c6e_f372,
+                0xa54f_f53a,
+                0x510e_527f,
+                0x9b05_688c,
+                0x1f83_d9ab,
+                0x5be0_cd19,
+            ],
+            buffer: BlockBuffer::default(),
+        }
+    }
+}
+
+#[rustfmt::skip]
+const SHA256_K: [u32; 64] = [
+    0x428a_2f98, 0x7137_4491, 0xb5c0_fbcf, 0xe9b5_dba5, 0x3956_c25b, 0x59f1_11f1, 0x923f_82a4,
+    0xab1c_5ed5, 0xd807_aa98, 0x1283_5b01, 0x2431_85be, 0x550c_7dc3, 0x72be_5d74, 0x80de_b1fe,
+    0x9bdc_06a7, 0xc19b_f174, 0xe49b_69c1, 0xefbe_4786, 0x0fc1_9dc6, 0x240c_a1cc, 0x2de9_2c6f,
+    0x4a74_84aa, 0x5cb0_a9dc, 0x76f9_88da, 0x983e_5152, 0xa831_c66d, 0xb003_27c8, 0xbf59_7fc7,
+    0xc6e0_0bf3, 0xd5a7_9147, 0x06ca_6351, 0x1429_2967, 0x27b7_0a85, 0x2e1b_2138, 0x4d2c_6dfc,
+    0x5338_0d13, 0x650a_7354, 0x766a_0abb, 0x81c2_c92e, 0x9272_2c85, 0xa2bf_e8a1, 0xa81a_664b,
+    0xc24b_8b70, 0xc76c_51a3, 0xd192_e819, 0xd699_0624, 0xf40e_3585, 0x106a_a070, 0x19a4_c116,
+    0x1e37_6c08, 0x2748_774c, 0x34b0_bcb5, 0x391c_0cb3, 0x4ed8_aa4a, 0x5b9c_ca4f, 0x682e_6ff3,
+    0x748f_82ee, 0x78a5_636f, 0x84c8_7814, 0x8cc7_0208, 0x90be_fffa, 0xa450_6ceb, 0xbef9_a3f7,
+    0xc671_78f2,
+];
+
+fn sha256_compress(state: &mut [u32; 8], block: &[u8; BLOCK_SIZE]) {
+    let mut w = [0u32; 64];
+    for (i, word) in block.chunks_exact(4).enumerate() {
+        w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for (&k, &word) in SHA256_K.iter().zip(w.iter()) {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let temp1 = h
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(k)
+            .wrapping_add(word);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let temp2 = s0.wrapping_add(maj);
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(temp1);
+        d = c;
+        c = b;
+        b = a;
+        a = temp1.wrapping_add(temp2);
+    }
+    for (s, v) in state.iter_mut().zip([a, b, c, d, e, f, g, h].iter()) {
+        *s = s.wrapping_add(*v);
+    }
+}
+
+impl Hasher for Sha256 {
+    type Output = [u8; 32];
+
+    fn update(&mut self, data: &[u8]) {
+        let state = &mut self.state;
+        self.buffer
+            .update(data, |block| sha256_compress(state, block));
+    }
+
+    fn finish(mut self) -> Self::Output {
+        let state = &mut self.state;
+        self.buffer
+            .finish(false, |block| sha256_compress(state, block));
+        let mut out = [0; 32];
+        for (chunk, word) in out.chunks_exact_mut(4).zip(self.state.iter()) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        out
+    }
+}
+
+/// The MD5 hash function, used for the optional checksums of files in some torrents.
+///
+/// MD5 is broken, so this is only useful to catch accidental corruption.
+#[derive(Clone)]
+pub struct Md5 {
+    state: [u32; 4],
+    buffer: BlockBuffer,
+}
+
+impl Default for Md5 {
+    fn default() -> Self {
+        Md5 {
+            state: [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476],
+            buffer: BlockBuffer::default(),
+        }
+    }
+}
+
+#[rustfmt::skip]
+const MD5_SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+
+// The integer parts of |sin(i + 1)| * 2^32, for each round.
+#[rustfmt::skip]
+const MD5_K: [u32; 64] = [
+    0xd76a_a478, 0xe8c7_b756, 0x2420_70db, 0xc1bd_ceee, 0xf57c_0faf, 0x4787_c62a, 0xa830_4613,
+    0xfd46_9501, 0x6980_98d8, 0x8b44_f7af, 0xffff_5bb1, 0x895c_d7be, 0x6b90_1122, 0xfd98_7193,
+    0xa679_438e, 0x49b4_0821, 0xf61e_2562, 0xc040_b340, 0x265e_5a51, 0xe9b6_c7aa, 0xd62f_105d,
+    0x0244_1453, 0xd8a1_e681, 0xe7d3_fbc8, 0x21e1_cde6, 0xc337_07d6, 0xf4d5_0d87, 0x455a_14ed,
+    0xa9e3_e905, 0xfcef_a3f8, 0x676f_02d9, 0x8d2a_4c8a, 0xfffa_3942, 0x8771_f681, 0x6d9d_6122,
+    0xfde5_380c, 0xa4be_ea44, 0x4bde_cfa9, 0xf6bb_4b60, 0xbebf_bc70, 0x289b_7ec6, 0xeaa1_27fa,
+    0xd4ef_3085, 0x0488_1d05, 0xd9d4_d039, 0xe6db_99e5, 0x1fa2_7cf8, 0xc4ac_5665, 0xf429_2244,
+    0x432a_ff97, 0xab94_23a7, 0xfc93_a039, 0x655b_59c3, 0x8f0c_cc92, 0xffef_f47d, 0x8584_5dd1,
+    0x6fa8_7e4f, 0xfe2c_e6e0, 0xa301_4314, 0x4e08_11a1, 0xf753_7e82, 0xbd3a_f235, 0x2ad7_d2bb,
+    0xeb86_d391,
+];
+
+fn md5_compress(state: &mut [u32; 4], block: &[u8; BLOCK_SIZE]) {
+    let mut m = [0u32; 16];
+    for (i, word) in block.chunks_exact(4).enumerate() {
+        m[i] = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+    }
+    let [mut a, mut b, mut c, mut d] = *state;
+    for i in 0..64 {
+        let (f, g) = match i {
+            0..=15 => ((b & c) | (!b & d), i),
+            16..=31 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+            32..=47 => (b ^ c ^ d, (3 * i + 5) % 16),
+            _ => (c ^ (b | !d), (7 * i) % 16),
+        };
+        let shift = MD5_SHIFTS[(i / 16) * 4 + i % 4];
+        let rotated = a
+            .wrapping_add(f)
+            .wrapping_add(MD5_K[i])
+            .wrapping_add(m[g])
+            .rotate_left(shift);
+        a = d;
+        d = c;
+        c = b;
+        b = b.wrapping_add(rotated);
+    }
+    for (s, v) in state.iter_mut().zip([a, b, c, d].iter()) {
+        *s = s.wrapping_add(*v);
+    }
+}
+
+impl Hasher for Md5 {
+    type Output = [u8; 16];
+
+    fn update(&mut self, data: &[u8]) {
+        let state = &mut self.state;
+        self.buffer.update(data, |block| md5_compress(state, block));
+    }
+
+    fn finish(mut self) -> Self::Output {
+        let state = &mut self.state;
+        self.buffer.finish(true, |block| md5_compress(state, block));
+        let mut out = [0; 16];
+        for (chunk, word) in out.chunks_exact_mut(4).zip(self.state.iter()) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn sha1_matches_known_digests() {
+        assert_eq!(
+            "da39a3ee5e6b4b0d3255bfef95601890afd80709",
+            hex(&Sha1::digest(b""))
+        );
+        assert_eq!(
+            "a9993e364706816aba3e25717850c26c9cd0d89d",
+            hex(&Sha1::digest(b"abc"))
+        );
+        assert_eq!(
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1",
+            hex(&Sha1::digest(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            ))
+        );
+    }
+
+    #[test]
+    fn sha256_matches_known_digests() {
+        assert_eq!(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            hex(&Sha256::digest(b""))
+        );
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            hex(&Sha256::digest(b"abc"))
+        );
+    }
+
+    #[test]
+    fn md5_matches_known_digests() {
+        assert_eq!("d41d8cd98f00b204e9800998ecf8427e", hex(&Md5::digest(b"")));
+        assert_eq!(
+            "900150983cd24fb0d6963f7d28e17f72",
+            hex(&Md5::digest(b"abc"))
+        );
+        assert_eq!(
+            "57edf4a22be3c955ac49da2e2107b67a",
+            hex(&Md5::digest(
+                b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"
+            ))
+        );
+    }
+
+    #[test]
+    fn incremental_hashing_matches_one_shot() {
+        let data: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        let mut hasher = Sha256::default();
+        for chunk in data.chunks(37) {
+            hasher.update(chunk);
+        }
+        assert_eq!(Sha256::digest(&data), hasher.finish());
+    }
+}
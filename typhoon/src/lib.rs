@@ -1,2 +1,7 @@
 pub mod bencoding;
 pub mod core;
+pub mod hash;
+#[cfg(feature = "fs")]
+pub mod library;
+#[cfg(feature = "tracker")]
+pub mod tracker;
@@ -0,0 +1,282 @@
+//! This module helps manage large collections of `.torrent` files.
+//!
+//! A library is built by scanning a directory tree, parsing every torrent
+//! inside of it. We can then look for torrents that are exact duplicates of each other,
+//! torrents that describe the same content under different metadata, as well as
+//! torrents that share some of their files.
+use crate::{
+    bencoding::Bencoding,
+    core::{FileInfo, InfoHash, Torrent},
+};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Represents a single torrent file found while scanning a library.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LibraryEntry {
+    /// Where this torrent file lives on disk.
+    pub path: PathBuf,
+    /// The SHA1 hash of the info dictionary of this torrent.
+    ///
+    /// Two torrents with the same info hash belong to the same swarm.
+    pub info_hash: InfoHash,
+    /// The metadata parsed from this torrent file.
+    pub torrent: Torrent,
+}
+
+/// Represents a collection of parsed torrent files.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Library {
+    /// The torrents we managed to parse.
+    pub entries: Vec<LibraryEntry>,
+    /// The files we found that we failed to parse, along with the reason why.
+    pub failures: Vec<(PathBuf, String)>,
+}
+
+/// Two torrents that share some of their files.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Overlap<'l> {
+    pub first: &'l LibraryEntry,
+    pub second: &'l LibraryEntry,
+    /// The files, as seen in the first torrent, which also appear in the second.
+    pub files: Vec<&'l FileInfo>,
+}
+
+impl Library {
+    /// Scan a directory tree for files ending in `.torrent`, and parse them.
+    ///
+    /// Torrent files that can't be read or parsed are recorded in `failures`, rather than
+    /// stopping the scan. Errors reading the directories themselves are returned.
+    /// Symlinks to directories aren't followed, so that links can't send us in circles.
+    pub fn scan(root: &Path) -> io::Result<Self> {
+        let mut library = Library::default();
+        let mut pending = vec![root.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            let mut paths = fs::read_dir(&dir)?
+                .map(|entry| entry.and_then(|e| Ok((e.path(), e.file_type()?))))
+                .collect::<io::Result<Vec<_>>>()?;
+            paths.sort_by(|(a, _), (b, _)| a.cmp(b));
+            for (path, file_type) in paths {
+                if file_type.is_dir() {
+                    pending.push(path);
+                } else if path.extension().is_some_and(|ext| ext == "torrent") {
+                    match fs::read(&path) {
+                        Ok(bytes) => library.add(path, &bytes),
+                        Err(e) => library.failures.push((path, e.to_string())),
+                    }
+                }
+            }
+        }
+        Ok(library)
+    }
+
+    /// Add the bytes of a torrent file to this library.
+    pub fn add(&mut self, path: PathBuf, bytes: &[u8]) {
+        let bencoding = match Bencoding::decode(bytes) {
+            Ok(b) => b,
+            Err(e) => return self.failures.push((path, e.to_string())),
+        };
+        let torrent = match Torrent::try_from(&bencoding) {
+            Ok(t) => t,
+            Err(e) => return self.failures.push((path, e.to_string())),
+        };
+        self.entries.push(LibraryEntry {
+            path,
+            info_hash: torrent.info_hash(),
+            torrent,
+        });
+    }
+
+    /// Find groups of torrents with the same info hash.
+    pub fn duplicates(&self) -> Vec<Vec<&LibraryEntry>> {
+        group_by(&self.entries, |e| e.info_hash)
+    }
+
+    /// Find groups of torrents describing the same content, but with different info hashes.
+    ///
+    /// This happens when the same files are re-uploaded to different trackers, with
+    /// a different private flag, or other metadata in the info dictionary changed.
+    /// Torrents are compared by the names and lengths of their files, ignoring padding,
+    /// so that torrents created again with a different piece length are variants too.
+    /// Since v1 piece hashes depend on the piece length, only the roots of v2 files, when
+    /// present, can tell apart files with the same name and length but different contents.
+    pub fn variants(&self) -> Vec<Vec<&LibraryEntry>> {
+        group_by(&self.entries, |e| {
+            let files: Vec<_> = e
+                .torrent
+                .content_files()
+                .into_iter()
+                .map(|file| (file.name, file.length))
+                .collect();
+            let roots: Option<Vec<_>> = e
+                .torrent
+                .v2
+                .as_ref()
+                .map(|v2| v2.files.iter().map(|file| file.pieces_root).collect());
+            (files, roots)
+        })
+        .into_iter()
+        .filter(|group| group.iter().any(|e| e.info_hash != group[0].info_hash))
+        .collect()
+    }
+
+    /// Find pairs of distinct torrents that share some files, with the same path and length.
+    pub fn overlaps(&self) -> Vec<Overlap<'_>> {
+        let mut owners: HashMap<&FileInfo, Vec<usize>> = HashMap::new();
+        for (index, entry) in self.entries.iter().enumerate() {
+            for file in entry.torrent.files.iter() {
+                owners.entry(file).or_default().push(index);
+            }
+        }
+        let mut shared: HashMap<(usize, usize), Vec<&FileInfo>> = HashMap::new();
+        for (file, indices) in owners {
+            for (i, &a) in indices.iter().enumerate() {
+                for &b in &indices[i + 1..] {
+                    if self.entries[a].info_hash != self.entries[b].info_hash {
+                        shared.entry((a, b)).or_default().push(file);
+                    }
+                }
+            }
+        }
+        let mut overlaps: Vec<_> = shared
+            .into_iter()
+            .map(|((a, b), mut files)| {
+                files.sort_by(|x, y| x.name.cmp(&y.name));
+                Overlap {
+                    first: &self.entries[a],
+                    second: &self.entries[b],
+                    files,
+                }
+            })
+            .collect();
+        overlaps
+            .sort_by(|x, y| (&x.first.path, &x.second.path).cmp(&(&y.first.path, &y.second.path)));
+        overlaps
+    }
+}
+
+// Group entries sharing the same key, only keeping groups with more than one entry.
+fn group_by<K, F>(entries: &[LibraryEntry], key: F) -> Vec<Vec<&LibraryEntry>>
+where
+    K: Eq + std::hash::Hash,
+    F: Fn(&LibraryEntry) -> K,
+{
+    let mut groups: HashMap<K, Vec<&LibraryEntry>> = HashMap::new();
+    let mut order = Vec::new();
+    for entry in entries {
+        let group = groups.entry(key(entry)).or_default();
+        if group.is_empty() {
+            order.push(entry);
+        }
+        group.push(entry);
+    }
+    order
+        .into_iter()
+        .filter_map(|first| groups.remove(&key(first)))
+        .filter(|group| group.len() > 1)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn torrent_bytes(name: &str, private: bool, files: &[(&str, usize)]) -> Vec<u8> {
+        with_piece_length(name, private, files, 16)
+    }
+
+    fn with_piece_length(
+        name: &str,
+        private: bool,
+        files: &[(&str, usize)],
+        piece_length: usize,
+    ) -> Vec<u8> {
+        let mut out = b"d13:announce-listll9:udp://a:1ee4:infod5:filesl".to_vec();
+        for (path, length) in files {
+            out.extend_from_slice(
+                format!("d6:lengthi{}e4:pathl{}:{}ee", length, path.len(), path).as_bytes(),
+            );
+        }
+        out.extend_from_slice(format!("e4:name{}:{}", name.len(), name).as_bytes());
+        out.extend_from_slice(format!("12:piece lengthi{}e", piece_length).as_bytes());
+        out.extend_from_slice(b"6:pieces20:AAAAAAAAAAAAAAAAAAAA");
+        if private {
+            out.extend_from_slice(b"7:privatei1e");
+        }
+        out.extend_from_slice(b"ee");
+        out
+    }
+
+    #[test]
+    fn finding_duplicates_and_variants_works() {
+        let mut library = Library::default();
+        let a = torrent_bytes("show", false, &[("e1", 5)]);
+        library.add(PathBuf::from("a.torrent"), &a);
+        library.add(PathBuf::from("b.torrent"), &a);
+        library.add(
+            PathBuf::from("c.torrent"),
+            &torrent_bytes("show", true, &[("e1", 5)]),
+        );
+        library.add(PathBuf::from("d.torrent"), b"not a torrent");
+        library.add(
+            PathBuf::from("e.torrent"),
+            &with_piece_length("show", false, &[("e1", 5)], 32),
+        );
+        library.add(
+            PathBuf::from("f.torrent"),
+            &torrent_bytes("show", false, &[("e1", 6)]),
+        );
+
+        let duplicates = library.duplicates();
+        assert_eq!(1, duplicates.len());
+        assert_eq!(2, duplicates[0].len());
+        let variants = library.variants();
+        assert_eq!(1, variants.len());
+        assert_eq!(4, variants[0].len());
+        assert_eq!(1, library.failures.len());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn scanning_skips_symlinked_dirs_and_records_unreadable_files() {
+        use std::{env, os::unix::fs::symlink};
+
+        let root = env::temp_dir().join(format!("typhoon-library-{}", std::process::id()));
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::write(
+            root.join("dir").join("a.torrent"),
+            torrent_bytes("show", false, &[("e1", 5)]),
+        )
+        .unwrap();
+        // Following this link would find the same torrent again, forever
+        symlink(&root, root.join("dir").join("loop")).unwrap();
+        symlink(root.join("missing"), root.join("broken.torrent")).unwrap();
+
+        let library = Library::scan(&root).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+        let paths: Vec<_> = library.entries.iter().map(|e| e.path.clone()).collect();
+        assert_eq!(vec![root.join("dir").join("a.torrent")], paths);
+        assert_eq!(1, library.failures.len());
+        assert_eq!(root.join("broken.torrent"), library.failures[0].0);
+    }
+
+    #[test]
+    fn finding_overlaps_works() {
+        let mut library = Library::default();
+        library.add(
+            PathBuf::from("a.torrent"),
+            &torrent_bytes("show", false, &[("e1", 5), ("e2", 6)]),
+        );
+        library.add(
+            PathBuf::from("b.torrent"),
+            &torrent_bytes("show", false, &[("e2", 6), ("e3", 7)]),
+        );
+        let overlaps = library.overlaps();
+        assert_eq!(1, overlaps.len());
+        assert_eq!(PathBuf::from("show/e2"), overlaps[0].files[0].name);
+    }
+}
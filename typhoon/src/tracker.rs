@@ -0,0 +1,598 @@
+//! This module contains clients for the trackers of a torrent.
+//!
+//! Trackers keep track of the peers in each swarm. We announce ourselves to a tracker,
+//! telling it how much of a torrent we have, and it responds with other peers we can
+//! connect to, along with how long to wait before announcing again.
+//!
+//! We don't depend on any particular async runtime or network library. Clients are
+//! async, and send their requests through a small trait, which can be implemented on top
+//! of whatever an application already uses. Simple implementations using the standard
+//! library are included.
+//!
+//! Clients are enabled by the `tracker` feature, and the servers in `server` by the
+//! `tracker-server` feature. Both are enabled by default.
+mod events;
+mod http;
+mod peer;
+mod proxy;
+mod schedule;
+#[cfg(feature = "tracker-server")]
+pub mod server;
+mod set;
+mod udp;
+#[cfg(feature = "websocket")]
+mod websocket;
+
+pub use events::{flush_stopped, AnnounceEvents};
+pub use http::{BlockingHttpClient, HttpClient, HttpTracker};
+pub use peer::Peer;
+pub use proxy::{Proxy, ProxyAuth};
+pub use schedule::{AnnounceScheduler, DEFAULT_MIN_INTERVAL};
+pub use set::TrackerSet;
+pub use udp::{StdUdpClient, UdpClient, UdpTracker};
+#[cfg(feature = "websocket")]
+pub use websocket::{StdWebSocketClient, WebSocketClient, WebSocketTracker};
+
+use crate::{
+    bencoding::{Bencoding, BencodingError},
+    core::{
+        extract_bytes, extract_key, extract_string, extract_usize, url_encode, InfoHash,
+        OwnedParseError, TryFromBencodingError,
+    },
+};
+use std::{
+    collections::{hash_map::RandomState, BTreeMap},
+    convert::TryFrom,
+    error, fmt,
+    hash::{BuildHasher, Hasher},
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    thread,
+    time::Duration,
+};
+
+/// The identifier we use for ourselves when talking to trackers and peers.
+///
+/// By convention, this starts with a short prefix identifying the client and its version,
+/// like `-TY0100-`, followed by random bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PeerId([u8; 20]);
+
+impl PeerId {
+    /// Create a peer id from its raw bytes.
+    pub fn new(bytes: [u8; 20]) -> Self {
+        PeerId(bytes)
+    }
+
+    /// Get the raw bytes of this peer id.
+    pub fn as_bytes(&self) -> &[u8; 20] {
+        &self.0
+    }
+
+    /// Percent-encode this peer id, for use in the query string of an announce.
+    pub fn url_encode(&self) -> String {
+        url_encode(&self.0)
+    }
+}
+
+/// The event an announce reports to a tracker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AnnounceEvent {
+    /// A regular announce, sent at the interval the tracker asked for.
+    None,
+    /// We just started downloading or seeding this torrent.
+    Started,
+    /// We just finished downloading this torrent.
+    Completed,
+    /// We stopped downloading or seeding this torrent.
+    Stopped,
+}
+
+impl AnnounceEvent {
+    /// The value of the `event` parameter for this event, if it needs one.
+    pub fn as_param(&self) -> Option<&'static str> {
+        match self {
+            AnnounceEvent::None => None,
+            AnnounceEvent::Started => Some("started"),
+            AnnounceEvent::Completed => Some("completed"),
+            AnnounceEvent::Stopped => Some("stopped"),
+        }
+    }
+}
+
+/// The information we send to a tracker when announcing ourselves.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnnounceRequest {
+    /// The info hash of the torrent we're announcing.
+    pub info_hash: InfoHash,
+    /// Our own peer id.
+    pub peer_id: PeerId,
+    /// The port we accept connections from other peers on.
+    pub port: u16,
+    /// How many bytes we've uploaded since starting.
+    pub uploaded: u64,
+    /// How many bytes we've downloaded since starting.
+    pub downloaded: u64,
+    /// How many bytes we still need to download.
+    pub left: u64,
+    /// The event this announce reports, if any.
+    pub event: AnnounceEvent,
+    /// Whether to ask for the compact list of peers, which nearly every tracker supports.
+    pub compact: bool,
+    /// How many peers we'd like to receive, if we don't want the default.
+    pub num_want: Option<u32>,
+    /// Our IPv4 address, if the tracker can't see it, like when we announce over IPv6.
+    pub ip: Option<Ipv4Addr>,
+    /// Our IPv6 address, so that the tracker can hand it out to IPv6 peers, from BEP 7.
+    ///
+    /// This should only be set to a routable address, see `routable_ipv6`.
+    pub ipv6: Option<Ipv6Addr>,
+    /// The id the tracker gave us in its last response, if any.
+    pub tracker_id: Option<String>,
+}
+
+impl AnnounceRequest {
+    /// Create a regular announce, asking for a compact list of peers.
+    pub fn new(info_hash: InfoHash, peer_id: PeerId, port: u16, left: u64) -> Self {
+        AnnounceRequest {
+            info_hash,
+            peer_id,
+            port,
+            uploaded: 0,
+            downloaded: 0,
+            left,
+            event: AnnounceEvent::None,
+            compact: true,
+            num_want: None,
+            ip: None,
+            ipv6: None,
+            tracker_id: None,
+        }
+    }
+}
+
+/// Check whether an IPv6 address can be reached from the internet.
+///
+/// Only global unicast addresses, in `2000::/3`, are routable. Link-local, unique local,
+/// loopback, and documentation addresses aren't worth telling a tracker about.
+pub fn is_routable_ipv6(ip: &Ipv6Addr) -> bool {
+    const DOCUMENTATION: [u16; 2] = [0x2001, 0x0db8];
+    let segments = ip.segments();
+    segments[0] & 0xe000 == 0x2000 && segments[..2] != DOCUMENTATION
+}
+
+/// Find the routable IPv6 address of this machine, if it has one.
+///
+/// This asks the OS which address it would use to reach a public IPv6 address.
+/// No packets get sent, since connecting a UDP socket only picks a route.
+pub fn routable_ipv6() -> Option<Ipv6Addr> {
+    let socket = UdpSocket::bind("[::]:0").ok()?;
+    // The address of a public DNS resolver, which any route to the internet can reach
+    socket.connect("[2001:4860:4860::8888]:53").ok()?;
+    match socket.local_addr().ok()? {
+        SocketAddr::V6(addr) if is_routable_ipv6(addr.ip()) => Some(*addr.ip()),
+        _ => None,
+    }
+}
+
+/// What a tracker tells us in response to an announce.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AnnounceResponse {
+    /// How long we should wait before announcing again.
+    pub interval: Duration,
+    /// If present, we shouldn't announce again before this much time has passed.
+    pub min_interval: Option<Duration>,
+    /// The peers we can connect to, with IPv6 peers coming after the others.
+    pub peers: Vec<Peer>,
+    /// A warning from the tracker, which didn't keep it from answering our announce.
+    pub warning: Option<String>,
+    /// An id the tracker wants us to send back in our next announces.
+    pub tracker_id: Option<String>,
+    /// The number of peers with the entire torrent, if the tracker told us.
+    pub seeders: Option<u32>,
+    /// The number of peers still downloading the torrent, if the tracker told us.
+    pub leechers: Option<u32>,
+    /// Our IP address, as seen by the tracker, from BEP 24.
+    pub external_ip: Option<IpAddr>,
+}
+
+fn extract_duration(bencoding: &Bencoding) -> Result<Duration, TryFromBencodingError<'_>> {
+    extract_usize(bencoding).map(|secs| Duration::from_secs(secs as u64))
+}
+
+// Extract an optional key, with some function for its value.
+fn extract_optional<'b, T>(
+    bencoding: &'b Bencoding,
+    key: &'static str,
+    extract: impl FnOnce(&'b Bencoding) -> Result<T, TryFromBencodingError<'b>>,
+) -> Result<Option<T>, TryFromBencodingError<'b>> {
+    extract_key(bencoding, key).ok().map(extract).transpose()
+}
+
+// Extract an IP address, written as 4 or 16 bytes in big endian.
+fn extract_ip(bencoding: &Bencoding) -> Result<Option<IpAddr>, TryFromBencodingError<'_>> {
+    let bytes = extract_bytes(bencoding)?;
+    let ip = match bytes.len() {
+        4 => <[u8; 4]>::try_from(bytes).ok().map(IpAddr::from),
+        16 => <[u8; 16]>::try_from(bytes).ok().map(IpAddr::from),
+        _ => None,
+    };
+    Ok(ip)
+}
+
+impl<'b> TryFrom<&'b Bencoding> for AnnounceResponse {
+    type Error = TryFromBencodingError<'b>;
+
+    fn try_from(bencoding: &'b Bencoding) -> Result<Self, Self::Error> {
+        let interval = extract_duration(extract_key(bencoding, "interval")?)?;
+        let min_interval = extract_optional(bencoding, "min interval", extract_duration)?;
+        let peers = peer::extract_peers(bencoding)?;
+        let warning = extract_optional(bencoding, "warning message", extract_string)?;
+        let tracker_id = extract_optional(bencoding, "tracker id", extract_string)?;
+        Ok(AnnounceResponse {
+            interval,
+            min_interval,
+            peers,
+            warning: warning.map(String::from),
+            tracker_id: tracker_id.map(String::from),
+            seeders: extract_optional(bencoding, "complete", extract_u32)?,
+            leechers: extract_optional(bencoding, "incomplete", extract_u32)?,
+            external_ip: extract_optional(bencoding, "external ip", extract_ip)?.flatten(),
+        })
+    }
+}
+
+// Check whether a response is the tracker refusing our request.
+fn check_failure(bencoding: &Bencoding) -> Result<(), TrackerError> {
+    match extract_key(bencoding, "failure reason") {
+        Ok(reason) => {
+            let reason = String::from_utf8_lossy(extract_bytes(reason).unwrap_or_default());
+            Err(TrackerError::Failure(reason.into_owned()))
+        }
+        Err(_) => Ok(()),
+    }
+}
+
+impl AnnounceResponse {
+    /// Decode the body of a response from a tracker.
+    ///
+    /// Responses with a `failure reason` become `TrackerError::Failure`.
+    ///
+    /// ```
+    /// use typhoon::tracker::{AnnounceResponse, TrackerError};
+    ///
+    /// let response = AnnounceResponse::decode(b"d14:failure reason6:bannede");
+    /// assert!(matches!(response, Err(TrackerError::Failure(reason)) if reason == "banned"));
+    /// ```
+    pub fn decode(body: &[u8]) -> Result<Self, TrackerError> {
+        let bencoding = Bencoding::decode(body)?;
+        check_failure(&bencoding)?;
+        AnnounceResponse::try_from(&bencoding)
+            .map_err(|e| TrackerError::InvalidResponse(e.into_owned(&bencoding)))
+    }
+}
+
+/// How many peers a tracker knows about in the swarm of a torrent.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ScrapeStats {
+    /// The number of peers with the entire torrent.
+    pub seeders: u32,
+    /// The number of peers still downloading the torrent.
+    pub leechers: u32,
+    /// The number of times a peer finished downloading the torrent.
+    pub completed: u32,
+}
+
+/// What a tracker tells us in response to a scrape.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScrapeResponse {
+    /// The stats of each torrent we asked about, which the tracker knows about.
+    pub files: BTreeMap<InfoHash, ScrapeStats>,
+}
+
+fn extract_u32(bencoding: &Bencoding) -> Result<u32, TryFromBencodingError<'_>> {
+    let int = extract_usize(bencoding)?;
+    u32::try_from(int).map_err(|_| TryFromBencodingError::InvalidSize(int as i64))
+}
+
+impl<'b> TryFrom<&'b Bencoding> for ScrapeResponse {
+    type Error = TryFromBencodingError<'b>;
+
+    fn try_from(bencoding: &'b Bencoding) -> Result<Self, Self::Error> {
+        let files = match extract_key(bencoding, "files")? {
+            Bencoding::Dict(files) => files,
+            other => return Err(TryFromBencodingError::ExpectedDict(other)),
+        };
+        let mut out = BTreeMap::new();
+        for (hash, stats) in files.iter() {
+            // Skip keys that aren't info hashes, rather than rejecting the entire response
+            let hash = match <[u8; 20]>::try_from(hash) {
+                Ok(hash) => InfoHash::new(hash),
+                Err(_) => continue,
+            };
+            let stats = ScrapeStats {
+                seeders: extract_u32(extract_key(stats, "complete")?)?,
+                leechers: extract_u32(extract_key(stats, "incomplete")?)?,
+                completed: extract_u32(extract_key(stats, "downloaded")?)?,
+            };
+            out.insert(hash, stats);
+        }
+        Ok(ScrapeResponse { files: out })
+    }
+}
+
+impl ScrapeResponse {
+    /// Decode the body of a response from a tracker.
+    pub fn decode(body: &[u8]) -> Result<Self, TrackerError> {
+        let bencoding = Bencoding::decode(body)?;
+        check_failure(&bencoding)?;
+        ScrapeResponse::try_from(&bencoding)
+            .map_err(|e| TrackerError::InvalidResponse(e.into_owned(&bencoding)))
+    }
+}
+
+/// How the standard library clients deal with slow and unreliable networks.
+///
+/// Requests which fail because of the network, like timing out or having their connection
+/// reset, get retried, waiting longer and longer between attempts. Requests the tracker
+/// refuses, or with invalid responses, don't get retried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TrackerConfig {
+    /// How long to wait for a TCP connection, or for a UDP tracker to give us a connection id.
+    pub connect_timeout: Duration,
+    /// How long to wait for the response to a request.
+    pub request_timeout: Duration,
+    /// How many times to retry a request, after the first attempt.
+    pub max_retries: u32,
+    /// How long to wait before the first retry, which doubles after each retry.
+    pub backoff: Duration,
+}
+
+impl Default for TrackerConfig {
+    /// The timeouts and backoff suggested by BEP 15, with at most 3 retries.
+    fn default() -> Self {
+        TrackerConfig {
+            connect_timeout: Duration::from_secs(15),
+            request_timeout: Duration::from_secs(15),
+            max_retries: 3,
+            backoff: Duration::from_secs(15),
+        }
+    }
+}
+
+impl TrackerConfig {
+    /// How long to wait before retrying a request, after some number of retries.
+    ///
+    /// This doubles after each retry, and gets randomly cut by up to half, so that
+    /// clients which failed at the same time don't all retry at the same time.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use typhoon::tracker::TrackerConfig;
+    ///
+    /// let config = TrackerConfig::default();
+    /// let delay = config.retry_delay(2);
+    /// assert!(delay > Duration::from_secs(30) && delay <= Duration::from_secs(60));
+    /// ```
+    pub fn retry_delay(&self, retries: u32) -> Duration {
+        let delay = self.backoff.saturating_mul(1 << retries.min(16));
+        let jitter = (random_u64() % 1024) as u32;
+        delay - delay / 2 * jitter / 1024
+    }
+
+    // Run a request, retrying it after network failures.
+    pub(crate) fn retry<T>(&self, mut request: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        let mut retries = 0;
+        loop {
+            match request() {
+                Err(e) if retries < self.max_retries && is_transient(&e) => {
+                    thread::sleep(self.retry_delay(retries));
+                    retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+// Check whether an error came from the network, and might go away by trying again.
+fn is_transient(error: &io::Error) -> bool {
+    use io::ErrorKind::*;
+    matches!(
+        error.kind(),
+        TimedOut
+            | WouldBlock
+            | Interrupted
+            | ConnectionRefused
+            | ConnectionReset
+            | ConnectionAborted
+            | UnexpectedEof
+    )
+}
+
+/// An error that can occur when talking to a tracker.
+#[derive(Debug)]
+pub enum TrackerError {
+    /// We couldn't reach the tracker, or it didn't respond successfully.
+    Io(io::Error),
+    /// The URL of the tracker isn't one this client can contact.
+    UnsupportedUrl(String),
+    /// The response of the tracker wasn't valid bencoding.
+    Bencoding(BencodingError),
+    /// The response of the tracker didn't contain what it should.
+    InvalidResponse(OwnedParseError),
+    /// The tracker sent a packet which doesn't follow the UDP tracker protocol.
+    InvalidPacket(String),
+    /// The tracker refused our request, with a message explaining why.
+    Failure(String),
+    /// The tracker doesn't support scraping.
+    ScrapeUnsupported,
+}
+
+impl fmt::Display for TrackerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use TrackerError::*;
+        match self {
+            Io(error) => write!(f, "failed to contact tracker: {}", error),
+            UnsupportedUrl(url) => write!(f, "tracker URL {} is not supported", url),
+            Bencoding(error) => write!(f, "tracker response is not bencoding: {}", error),
+            InvalidResponse(error) => write!(f, "invalid tracker response {}", error),
+            InvalidPacket(reason) => write!(f, "invalid tracker packet: {}", reason),
+            Failure(reason) => write!(f, "tracker refused request: {}", reason),
+            ScrapeUnsupported => write!(f, "tracker doesn't support scraping"),
+        }
+    }
+}
+
+impl error::Error for TrackerError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            TrackerError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TrackerError {
+    fn from(error: io::Error) -> Self {
+        TrackerError::Io(error)
+    }
+}
+
+impl From<BencodingError> for TrackerError {
+    fn from(error: BencodingError) -> Self {
+        TrackerError::Bencoding(error)
+    }
+}
+
+// A random number, taken from the keys the standard library seeds hash maps with.
+pub(crate) fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+// Run a future to completion on the current thread, for futures that never wait on anything.
+#[cfg(test)]
+pub(crate) fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    use std::task::{Context, Poll, Waker};
+
+    let mut future = std::pin::pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decoding_announce_responses_works() {
+        let body = b"d8:intervali1800e12:min intervali60e5:peers12:\x7f\x00\x00\x01\x1a\xe1\x0a\x00\x00\x02\x00\x50e";
+        let response = AnnounceResponse::decode(body).unwrap();
+        assert_eq!(Duration::from_secs(1800), response.interval);
+        assert_eq!(Some(Duration::from_secs(60)), response.min_interval);
+        let addrs: Vec<_> = response.peers.iter().map(|p| p.addr.to_string()).collect();
+        assert_eq!(vec!["127.0.0.1:6881", "10.0.0.2:80"], addrs);
+    }
+
+    #[test]
+    fn decoding_optional_announce_fields_works() {
+        let body = b"d8:completei5e11:external ip4:\x01\x02\x03\x0410:incompletei7e\
+            8:intervali900e5:peers0:10:tracker id3:abc15:warning message4:slowe";
+        let response = AnnounceResponse::decode(body).unwrap();
+        assert_eq!(
+            AnnounceResponse {
+                interval: Duration::from_secs(900),
+                warning: Some("slow".to_owned()),
+                tracker_id: Some("abc".to_owned()),
+                seeders: Some(5),
+                leechers: Some(7),
+                external_ip: Some(IpAddr::from([1, 2, 3, 4])),
+                ..AnnounceResponse::default()
+            },
+            response
+        );
+        match AnnounceResponse::decode(b"d14:failure reason3:bad8:intervali1ee") {
+            Err(TrackerError::Failure(reason)) => assert_eq!("bad", reason),
+            other => panic!("unexpected response {:?}", other),
+        }
+        assert!(matches!(
+            ScrapeResponse::decode(b"d14:failure reason3:bade"),
+            Err(TrackerError::Failure(_))
+        ));
+    }
+
+    #[test]
+    fn decoding_scrape_responses_works() {
+        let mut body = b"d5:filesd20:".to_vec();
+        body.extend_from_slice(&[7; 20]);
+        body.extend_from_slice(b"d8:completei5e10:downloadedi50e10:incompletei10eeee");
+        let response = ScrapeResponse::decode(&body).unwrap();
+        let stats = ScrapeStats {
+            seeders: 5,
+            leechers: 10,
+            completed: 50,
+        };
+        assert_eq!(Some(&stats), response.files.get(&InfoHash::new([7; 20])));
+        assert!(ScrapeResponse::decode(b"d5:filesi1ee").is_err());
+    }
+
+    #[test]
+    fn retrying_requests_works() {
+        let config = TrackerConfig {
+            max_retries: 2,
+            backoff: Duration::from_millis(1),
+            ..TrackerConfig::default()
+        };
+        let mut attempts = 0;
+        let result: io::Result<()> = config.retry(|| {
+            attempts += 1;
+            Err(io::ErrorKind::TimedOut.into())
+        });
+        assert!(result.is_err());
+        assert_eq!(3, attempts);
+        attempts = 0;
+        let result = config.retry(|| {
+            attempts += 1;
+            match attempts {
+                1 => Err(io::ErrorKind::ConnectionReset.into()),
+                _ => Ok(attempts),
+            }
+        });
+        assert_eq!(2, result.unwrap());
+        let result: io::Result<()> = config.retry(|| Err(io::ErrorKind::InvalidData.into()));
+        assert!(result.is_err());
+        for retries in 0..20 {
+            let delay = config.retry_delay(retries);
+            let max = Duration::from_millis(1 << retries.min(16));
+            assert!(delay <= max && delay >= max / 2);
+        }
+    }
+
+    #[test]
+    fn checking_routable_ipv6_addresses_works() {
+        let routable = |ip: &str| is_routable_ipv6(&ip.parse().unwrap());
+        assert!(routable("2a01:4f8::1"));
+        assert!(!routable("::1"));
+        assert!(!routable("fe80::1"));
+        assert!(!routable("fd00::1"));
+        assert!(!routable("2001:db8::1"));
+    }
+
+    #[test]
+    fn decoding_bad_announce_responses_fails() {
+        assert!(matches!(
+            AnnounceResponse::decode(b"d8:interval"),
+            Err(TrackerError::Bencoding(_))
+        ));
+        match AnnounceResponse::decode(b"d8:interval1:x5:peers0:e") {
+            Err(TrackerError::InvalidResponse(error)) => {
+                assert_eq!(Some("interval".to_owned()), error.path)
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
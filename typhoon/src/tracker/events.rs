@@ -0,0 +1,214 @@
+//! This module keeps track of which events each torrent still needs to announce.
+//!
+//! Trackers count peers using the events of announces. The first announce of a torrent
+//! needs to be `started`, and our last one `stopped`, so that the tracker can forget us.
+//! Finishing a download gets announced with `completed`, which trackers use to count
+//! downloads, so this needs to happen exactly once, and never for torrents we added
+//! already complete. Events only count as sent once the tracker responds, so that a
+//! failed announce gets retried with the same event.
+use super::{AnnounceEvent, AnnounceRequest, AnnounceResponse, TrackerError};
+use crate::core::InfoHash;
+use std::{collections::HashMap, future::Future};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct State {
+    started: bool,
+    // Whether we finished downloading, and haven't announced it yet
+    completed_pending: bool,
+    // Whether completion was announced, or doesn't need to be
+    completed_done: bool,
+    stopping: bool,
+}
+
+/// The announce events of many torrents.
+#[derive(Clone, Debug, Default)]
+pub struct AnnounceEvents {
+    states: HashMap<InfoHash, State>,
+}
+
+impl AnnounceEvents {
+    /// Create an empty set of torrents.
+    pub fn new() -> Self {
+        AnnounceEvents::default()
+    }
+
+    /// Start keeping track of the events of a torrent.
+    ///
+    /// Torrents added already complete never get announced as `completed`.
+    pub fn add(&mut self, info_hash: InfoHash, complete: bool) {
+        self.states.entry(info_hash).or_insert(State {
+            completed_done: complete,
+            ..State::default()
+        });
+    }
+
+    /// The event the next announce of a torrent should report, if we know about it.
+    ///
+    /// ```
+    /// use typhoon::{core::InfoHash, tracker::{AnnounceEvent, AnnounceEvents}};
+    ///
+    /// let hash = InfoHash::new([0; 20]);
+    /// let mut events = AnnounceEvents::new();
+    /// events.add(hash, false);
+    /// assert_eq!(Some(AnnounceEvent::Started), events.next_event(&hash));
+    /// events.announced(&hash, AnnounceEvent::Started);
+    /// events.finished(&hash);
+    /// assert_eq!(Some(AnnounceEvent::Completed), events.next_event(&hash));
+    /// ```
+    pub fn next_event(&self, info_hash: &InfoHash) -> Option<AnnounceEvent> {
+        let state = self.states.get(info_hash)?;
+        let event = if state.stopping {
+            AnnounceEvent::Stopped
+        } else if !state.started {
+            AnnounceEvent::Started
+        } else if state.completed_pending {
+            AnnounceEvent::Completed
+        } else {
+            AnnounceEvent::None
+        };
+        Some(event)
+    }
+
+    /// Record that a torrent finished downloading.
+    pub fn finished(&mut self, info_hash: &InfoHash) {
+        if let Some(state) = self.states.get_mut(info_hash) {
+            if !state.completed_done {
+                state.completed_pending = true;
+            }
+        }
+    }
+
+    /// Record that a tracker responded to an announce with some event.
+    ///
+    /// Once `stopped` has been announced, we forget about the torrent.
+    pub fn announced(&mut self, info_hash: &InfoHash, event: AnnounceEvent) {
+        let state = match self.states.get_mut(info_hash) {
+            Some(state) => state,
+            None => return,
+        };
+        match event {
+            AnnounceEvent::None => {}
+            AnnounceEvent::Started => state.started = true,
+            AnnounceEvent::Completed => {
+                state.completed_pending = false;
+                state.completed_done = true;
+            }
+            AnnounceEvent::Stopped => {
+                self.states.remove(info_hash);
+            }
+        }
+    }
+
+    /// Stop a torrent, returning whether it still needs to announce `stopped`.
+    ///
+    /// Torrents which never got `started` through to a tracker are forgotten right away.
+    pub fn stop(&mut self, info_hash: &InfoHash) -> bool {
+        match self.states.get_mut(info_hash) {
+            Some(state) if state.started => {
+                state.stopping = true;
+                true
+            }
+            Some(_) => {
+                self.states.remove(info_hash);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Stop every torrent, returning the ones which still need to announce `stopped`.
+    pub fn shutdown(&mut self) -> Vec<InfoHash> {
+        let hashes: Vec<_> = self.states.keys().copied().collect();
+        hashes.into_iter().filter(|hash| self.stop(hash)).collect()
+    }
+}
+
+/// Announce `stopped` for each request, carrying on when some announces fail.
+///
+/// This is meant for shutting down gracefully, where we don't want one unreachable tracker
+/// to keep us from telling the others that we're leaving. The event of each request is
+/// replaced with `stopped`, and the info hashes of the announces that got through are
+/// returned, to pass along to `AnnounceEvents::announced`.
+///
+/// Announces are sent one after the other, so the time this takes is bounded by the
+/// timeouts of the client used to send them.
+pub async fn flush_stopped<F, Fut>(
+    requests: impl IntoIterator<Item = AnnounceRequest>,
+    mut announce: F,
+) -> Vec<InfoHash>
+where
+    F: FnMut(AnnounceRequest) -> Fut,
+    Fut: Future<Output = Result<AnnounceResponse, TrackerError>>,
+{
+    let mut sent = Vec::new();
+    for mut request in requests {
+        request.event = AnnounceEvent::Stopped;
+        let info_hash = request.info_hash;
+        if announce(request).await.is_ok() {
+            sent.push(info_hash);
+        }
+    }
+    sent
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tracker::{block_on, PeerId};
+    use std::{io, time::Duration};
+
+    #[test]
+    fn completed_is_announced_once() {
+        let hash = InfoHash::new([0; 20]);
+        let mut events = AnnounceEvents::new();
+        events.add(hash, false);
+        // Started gets retried until a tracker responds
+        assert_eq!(Some(AnnounceEvent::Started), events.next_event(&hash));
+        events.finished(&hash);
+        assert_eq!(Some(AnnounceEvent::Started), events.next_event(&hash));
+        events.announced(&hash, AnnounceEvent::Started);
+        assert_eq!(Some(AnnounceEvent::Completed), events.next_event(&hash));
+        events.announced(&hash, AnnounceEvent::Completed);
+        events.finished(&hash);
+        assert_eq!(Some(AnnounceEvent::None), events.next_event(&hash));
+        let seeding = InfoHash::new([1; 20]);
+        events.add(seeding, true);
+        events.announced(&seeding, AnnounceEvent::Started);
+        events.finished(&seeding);
+        assert_eq!(Some(AnnounceEvent::None), events.next_event(&seeding));
+    }
+
+    #[test]
+    fn stopping_only_announces_started_torrents() {
+        let started = InfoHash::new([0; 20]);
+        let unannounced = InfoHash::new([1; 20]);
+        let mut events = AnnounceEvents::new();
+        events.add(started, false);
+        events.add(unannounced, false);
+        events.announced(&started, AnnounceEvent::Started);
+        assert_eq!(vec![started], events.shutdown());
+        assert_eq!(None, events.next_event(&unannounced));
+        assert_eq!(Some(AnnounceEvent::Stopped), events.next_event(&started));
+        events.announced(&started, AnnounceEvent::Stopped);
+        assert_eq!(None, events.next_event(&started));
+    }
+
+    #[test]
+    fn flushing_carries_on_after_failures() {
+        let peer_id = PeerId::new([0; 20]);
+        let requests: Vec<_> = (0..3)
+            .map(|i| AnnounceRequest::new(InfoHash::new([i; 20]), peer_id, 6881, 0))
+            .collect();
+        let sent = block_on(flush_stopped(requests, |request| async move {
+            assert_eq!(AnnounceEvent::Stopped, request.event);
+            if request.info_hash == InfoHash::new([1; 20]) {
+                return Err(TrackerError::Io(io::ErrorKind::TimedOut.into()));
+            }
+            Ok(AnnounceResponse {
+                interval: Duration::from_secs(900),
+                ..AnnounceResponse::default()
+            })
+        }));
+        assert_eq!(vec![InfoHash::new([0; 20]), InfoHash::new([2; 20])], sent);
+    }
+}
@@ -0,0 +1,406 @@
+//! This module contains the client for trackers speaking HTTP, from BEP 3.
+//!
+//! Announcing to an HTTP tracker is a single GET request, with the details of the announce
+//! in the query string, and a bencoded dictionary as the body of the response.
+//!
+//! Scraping uses a different URL, found by replacing `announce` with `scrape` in the last
+//! segment of the path of the announce URL, from BEP 48. Trackers whose announce URLs
+//! don't follow this convention don't support scraping.
+use super::{
+    proxy::{self, Proxy},
+    AnnounceRequest, AnnounceResponse, ScrapeResponse, TrackerConfig, TrackerError,
+};
+use crate::core::{url_encode, InfoHash, TrackerUrl};
+use std::{
+    future::Future,
+    io::{self, Read, Write},
+    str,
+};
+
+// The largest response we accept, which is far more than any announce or scrape needs.
+const MAX_RESPONSE_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Something which can make HTTP GET requests, for a tracker client to send its requests with.
+///
+/// This can be implemented on top of whatever HTTP library an application already uses.
+pub trait HttpClient {
+    /// Make a GET request to a URL, returning the body of the response.
+    ///
+    /// Responses without a successful status should be turned into errors.
+    fn get(&self, url: &str) -> impl Future<Output = io::Result<Vec<u8>>>;
+}
+
+/// A client for a tracker speaking HTTP or HTTPS.
+///
+/// Which of these are supported depends on the `HttpClient` used to send requests.
+#[derive(Clone, Debug)]
+pub struct HttpTracker<C> {
+    url: String,
+    client: C,
+}
+
+impl<C: HttpClient> HttpTracker<C> {
+    /// Create a client for the tracker at a given announce URL.
+    pub fn new(url: impl Into<String>, client: C) -> Self {
+        HttpTracker {
+            url: url.into(),
+            client,
+        }
+    }
+
+    /// The announce URL of this tracker.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Build the URL to send a given announce to.
+    ///
+    /// The parameters of the announce are added to the query string, after any parameters
+    /// the announce URL already has, like the passkeys of private trackers.
+    pub fn announce_url(&self, request: &AnnounceRequest) -> String {
+        let separator = if self.url.contains('?') { '&' } else { '?' };
+        let mut url = format!(
+            "{}{}info_hash={}&peer_id={}&port={}&uploaded={}&downloaded={}&left={}&compact={}",
+            self.url,
+            separator,
+            request.info_hash.url_encode(),
+            request.peer_id.url_encode(),
+            request.port,
+            request.uploaded,
+            request.downloaded,
+            request.left,
+            request.compact as u8
+        );
+        if let Some(event) = request.event.as_param() {
+            url.push_str("&event=");
+            url.push_str(event);
+        }
+        if let Some(num_want) = request.num_want {
+            url.push_str(&format!("&numwant={}", num_want));
+        }
+        if let Some(ip) = request.ip {
+            url.push_str(&format!("&ip={}", ip));
+        }
+        if let Some(ipv6) = request.ipv6 {
+            url.push_str("&ipv6=");
+            url.push_str(&url_encode(ipv6.to_string().as_bytes()));
+        }
+        if let Some(tracker_id) = &request.tracker_id {
+            url.push_str("&trackerid=");
+            url.push_str(&url_encode(tracker_id.as_bytes()));
+        }
+        url
+    }
+
+    /// Build the URL to scrape some torrents with, if this tracker supports scraping.
+    ///
+    /// ```
+    /// use typhoon::{core::InfoHash, tracker::{BlockingHttpClient, HttpTracker}};
+    ///
+    /// let tracker = HttpTracker::new("http://t.example.com/announce.php?pk=1", BlockingHttpClient::default());
+    /// let url = tracker.scrape_url(&[InfoHash::new([0x41; 20])]).unwrap();
+    /// assert_eq!("http://t.example.com/scrape.php?pk=1&info_hash=AAAAAAAAAAAAAAAAAAAA", url);
+    /// assert_eq!(None, HttpTracker::new("http://t.example.com/a", BlockingHttpClient::default()).scrape_url(&[]));
+    /// ```
+    pub fn scrape_url(&self, info_hashes: &[InfoHash]) -> Option<String> {
+        let (base, query) = match self.url.find('?') {
+            Some(i) => self.url.split_at(i),
+            None => (self.url.as_str(), ""),
+        };
+        let slash = base.rfind('/')?;
+        let last = &base[slash + 1..];
+        if !last.starts_with("announce") {
+            return None;
+        }
+        let mut url = format!("{}/scrape{}{}", &base[..slash], &last[8..], query);
+        for (i, hash) in info_hashes.iter().enumerate() {
+            let separator = if i == 0 && query.is_empty() { '?' } else { '&' };
+            url.push(separator);
+            url.push_str("info_hash=");
+            url.push_str(&hash.url_encode());
+        }
+        Some(url)
+    }
+
+    fn check_scheme(&self) -> Result<(), TrackerError> {
+        if !self.url.starts_with("http://") && !self.url.starts_with("https://") {
+            return Err(TrackerError::UnsupportedUrl(self.url.clone()));
+        }
+        Ok(())
+    }
+
+    /// Announce ourselves to this tracker, getting back peers in the swarm.
+    pub async fn announce(
+        &self,
+        request: &AnnounceRequest,
+    ) -> Result<AnnounceResponse, TrackerError> {
+        self.check_scheme()?;
+        let body = self.client.get(&self.announce_url(request)).await?;
+        AnnounceResponse::decode(&body)
+    }
+
+    /// Ask this tracker how many peers are in the swarms of some torrents.
+    ///
+    /// Trackers may leave out torrents they don't know about, or limit how many torrents
+    /// can be scraped at once.
+    pub async fn scrape(&self, info_hashes: &[InfoHash]) -> Result<ScrapeResponse, TrackerError> {
+        self.check_scheme()?;
+        let url = self
+            .scrape_url(info_hashes)
+            .ok_or(TrackerError::ScrapeUnsupported)?;
+        let body = self.client.get(&url).await?;
+        ScrapeResponse::decode(&body)
+    }
+}
+
+/// A blocking HTTP client built on the standard library, supporting plain HTTP only.
+///
+/// The futures returned by this client do all of their network I/O the first time they're
+/// polled, blocking the thread polling them until the request completes. This is fine for
+/// tools making a few requests with `block_on`, but would stall an async executor.
+/// Applications with an async runtime, or needing HTTPS, should implement `HttpClient`
+/// on top of the HTTP library of that runtime instead. URLs using any scheme other than
+/// `http` fail with `io::ErrorKind::Unsupported`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlockingHttpClient {
+    config: TrackerConfig,
+    proxy: Option<Proxy>,
+}
+
+impl BlockingHttpClient {
+    /// Create a client with a given configuration for timeouts and retries.
+    pub fn new(config: TrackerConfig) -> Self {
+        BlockingHttpClient {
+            config,
+            proxy: None,
+        }
+    }
+
+    /// Send every request through a proxy.
+    pub fn with_proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Make a GET request to a URL, blocking until the body of the response arrives.
+    pub fn get_blocking(&self, url: &str) -> io::Result<Vec<u8>> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let parsed: TrackerUrl = url.parse().map_err(|e| invalid(format!("{}", e)))?;
+        if parsed.scheme != "http" {
+            let message = format!("scheme {} is not supported", parsed.scheme);
+            return Err(io::Error::new(io::ErrorKind::Unsupported, message));
+        }
+        let host = parsed.host.to_string();
+        let path = if parsed.path.is_empty() {
+            "/"
+        } else {
+            &parsed.path
+        };
+        let response = self.config.retry(|| {
+            let proxy = self.proxy.as_ref();
+            let mut stream = proxy::connect(&parsed.host, parsed.port, proxy, &self.config)?;
+            // HTTP/1.0 keeps servers from using chunked encoding, and closes the connection
+            write!(
+                stream,
+                "GET {} HTTP/1.0\r\nHost: {}:{}\r\nUser-Agent: typhoon\r\n\r\n",
+                path, host, parsed.port
+            )?;
+            read_limited(&mut stream, MAX_RESPONSE_SIZE)
+        })?;
+        parse_response(response)
+    }
+}
+
+impl HttpClient for BlockingHttpClient {
+    fn get(&self, url: &str) -> impl Future<Output = io::Result<Vec<u8>>> {
+        let url = url.to_owned();
+        let client = self.clone();
+        async move { client.get_blocking(&url) }
+    }
+}
+
+// Read until the end of a stream, failing if that's more than some number of bytes.
+fn read_limited(stream: impl Read, limit: u64) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    stream.take(limit.saturating_add(1)).read_to_end(&mut out)?;
+    if out.len() as u64 > limit {
+        let message = format!("response is larger than {} bytes", limit);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    Ok(out)
+}
+
+// Split the body out of a raw HTTP response, checking that its status is successful.
+fn parse_response(mut response: Vec<u8>) -> io::Result<Vec<u8>> {
+    let malformed = || io::Error::new(io::ErrorKind::InvalidData, "malformed HTTP response");
+    let end = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(malformed)?;
+    let head = str::from_utf8(&response[..end]).map_err(|_| malformed())?;
+    let status = head
+        .lines()
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(malformed)?;
+    if !(200..300).contains(&status) {
+        let message = format!("HTTP request failed with status {}", status);
+        return Err(io::Error::other(message));
+    }
+    Ok(response.split_off(end + 4))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        core::InfoHash,
+        tracker::{block_on, AnnounceEvent, Peer, PeerId},
+    };
+    use std::{
+        cell::RefCell,
+        future,
+        net::{SocketAddrV4, TcpListener},
+        thread,
+        time::Duration,
+    };
+
+    // A client answering every request with the same body, remembering the URLs it got.
+    struct FixedClient {
+        body: Vec<u8>,
+        urls: RefCell<Vec<String>>,
+    }
+
+    impl HttpClient for FixedClient {
+        fn get(&self, url: &str) -> impl Future<Output = io::Result<Vec<u8>>> {
+            self.urls.borrow_mut().push(url.to_owned());
+            future::ready(Ok(self.body.clone()))
+        }
+    }
+
+    fn request() -> AnnounceRequest {
+        let mut request = AnnounceRequest::new(
+            InfoHash::new([0xAB; 20]),
+            PeerId::new(*b"-TY0100-abcdefghijkl"),
+            6881,
+            100,
+        );
+        request.event = AnnounceEvent::Started;
+        request
+    }
+
+    #[test]
+    fn building_announce_urls_works() {
+        let tracker = HttpTracker::new(
+            "http://t.example.com/announce?pk=1",
+            BlockingHttpClient::default(),
+        );
+        let mut request = request();
+        request.num_want = Some(50);
+        request.ipv6 = Some("2a01:4f8::1".parse().unwrap());
+        request.tracker_id = Some("x y".to_owned());
+        assert_eq!(
+            format!(
+                "http://t.example.com/announce?pk=1&info_hash={}&peer_id=-TY0100-abcdefghijkl\
+                &port=6881&uploaded=0&downloaded=0&left=100&compact=1&event=started&numwant=50\
+                &ipv6=2a01%3A4f8%3A%3A1&trackerid=x%20y",
+                "%AB".repeat(20)
+            ),
+            tracker.announce_url(&request)
+        );
+    }
+
+    #[test]
+    fn announcing_works() {
+        let client = FixedClient {
+            body: b"d8:intervali900e5:peers6:\x0a\x00\x00\x01\x1a\xe1e".to_vec(),
+            urls: RefCell::new(Vec::new()),
+        };
+        let tracker = HttpTracker::new("http://t.example.com/announce", client);
+        let response = block_on(tracker.announce(&request())).unwrap();
+        assert_eq!(Duration::from_secs(900), response.interval);
+        let peer: SocketAddrV4 = "10.0.0.1:6881".parse().unwrap();
+        assert_eq!(vec![Peer::new(peer)], response.peers);
+        assert_eq!(1, tracker.client.urls.borrow().len());
+        let tracker = HttpTracker::new("udp://t.example.com:80", tracker.client);
+        assert!(matches!(
+            block_on(tracker.announce(&request())),
+            Err(TrackerError::UnsupportedUrl(_))
+        ));
+    }
+
+    #[test]
+    fn scraping_works() {
+        let mut body = b"d5:filesd20:".to_vec();
+        body.extend_from_slice(&[0xAB; 20]);
+        body.extend_from_slice(b"d8:completei1e10:downloadedi2e10:incompletei3eeee");
+        let client = FixedClient {
+            body,
+            urls: RefCell::new(Vec::new()),
+        };
+        let tracker = HttpTracker::new("http://t.example.com/x/announce", client);
+        let hashes = [InfoHash::new([0xAB; 20]), InfoHash::new([0x41; 20])];
+        let response = block_on(tracker.scrape(&hashes)).unwrap();
+        assert_eq!(1, response.files[&hashes[0]].seeders);
+        assert_eq!(
+            vec![format!(
+                "http://t.example.com/x/scrape?info_hash={}&info_hash={}",
+                "%AB".repeat(20),
+                "A".repeat(20)
+            )],
+            *tracker.client.urls.borrow()
+        );
+        let tracker = HttpTracker::new("http://t.example.com/announce/x", tracker.client);
+        assert!(matches!(
+            block_on(tracker.scrape(&hashes)),
+            Err(TrackerError::ScrapeUnsupported)
+        ));
+    }
+
+    #[test]
+    fn std_clients_make_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..read]);
+            }
+            stream
+                .write_all(b"HTTP/1.0 200 OK\r\nContent-Length: 3\r\n\r\nabc")
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        let url = format!("http://127.0.0.1:{}/announce?a=b", port);
+        assert_eq!(
+            b"abc".to_vec(),
+            block_on(BlockingHttpClient::default().get(&url)).unwrap()
+        );
+        assert!(server
+            .join()
+            .unwrap()
+            .starts_with("GET /announce?a=b HTTP/1.0\r\n"));
+    }
+
+    #[test]
+    fn parsing_http_responses_works() {
+        let ok = b"HTTP/1.1 200 OK\r\nA: b\r\n\r\nbody".to_vec();
+        assert_eq!(b"body".to_vec(), parse_response(ok).unwrap());
+        let missing = b"HTTP/1.1 404 Not Found\r\n\r\n".to_vec();
+        assert!(parse_response(missing).is_err());
+        assert!(parse_response(b"garbage".to_vec()).is_err());
+        let https = block_on(BlockingHttpClient::default().get("https://t.example.com/announce"));
+        assert_eq!(io::ErrorKind::Unsupported, https.unwrap_err().kind());
+    }
+
+    #[test]
+    fn reading_responses_is_limited() {
+        assert_eq!(b"abcd".to_vec(), read_limited(&b"abcd"[..], 4).unwrap());
+        let error = read_limited(&b"abcde"[..], 4).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+    }
+}
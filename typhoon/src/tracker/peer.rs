@@ -0,0 +1,167 @@
+//! This module contains the peers trackers send back to us.
+//!
+//! Trackers can list peers in two ways. The original way is a list of dictionaries, each
+//! with the address, port, and id of a peer. Nearly every tracker instead sends the compact
+//! form from BEP 23, a single byte string with 6 bytes for each peer, which is much smaller.
+//! IPv6 peers get their own compact list, `peers6`, with 18 bytes for each peer, from BEP 7.
+use super::PeerId;
+use crate::{
+    bencoding::Bencoding,
+    core::{extract_bytes, extract_int, extract_key, extract_string, TryFromBencodingError},
+};
+use std::{
+    convert::TryFrom,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+};
+
+/// A peer in the swarm of a torrent, as told to us by a tracker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Peer {
+    /// The address to connect to this peer at.
+    pub addr: SocketAddr,
+    /// The id of this peer, if the tracker told us about it.
+    ///
+    /// Compact responses never contain the ids of peers.
+    pub peer_id: Option<PeerId>,
+}
+
+impl Peer {
+    /// Create a peer we only know the address of.
+    pub fn new(addr: impl Into<SocketAddr>) -> Self {
+        Peer {
+            addr: addr.into(),
+            peer_id: None,
+        }
+    }
+
+    /// Parse a compact list of IPv4 peers, with 6 bytes for each peer.
+    ///
+    /// Each peer is 4 bytes of IP address followed by 2 bytes of port, in big endian.
+    /// Trailing bytes not making up an entire peer are ignored.
+    ///
+    /// ```
+    /// use typhoon::tracker::Peer;
+    ///
+    /// let peers = Peer::parse_compact(b"\x7f\x00\x00\x01\x1a\xe1");
+    /// assert_eq!("127.0.0.1:6881".parse(), Ok(peers[0].addr));
+    /// ```
+    pub fn parse_compact(bytes: &[u8]) -> Vec<Peer> {
+        bytes
+            .chunks_exact(6)
+            .map(|chunk| {
+                let ip = Ipv4Addr::new(chunk[0], chunk[1], chunk[2], chunk[3]);
+                Peer::new(SocketAddrV4::new(
+                    ip,
+                    u16::from_be_bytes([chunk[4], chunk[5]]),
+                ))
+            })
+            .collect()
+    }
+
+    /// Parse a compact list of IPv6 peers, with 18 bytes for each peer.
+    ///
+    /// Each peer is 16 bytes of IP address followed by 2 bytes of port, in big endian.
+    /// Trailing bytes not making up an entire peer are ignored.
+    pub fn parse_compact_v6(bytes: &[u8]) -> Vec<Peer> {
+        bytes
+            .chunks_exact(18)
+            .map(|chunk| {
+                let mut ip = [0; 16];
+                ip.copy_from_slice(&chunk[..16]);
+                let port = u16::from_be_bytes([chunk[16], chunk[17]]);
+                Peer::new(SocketAddrV6::new(Ipv6Addr::from(ip), port, 0, 0))
+            })
+            .collect()
+    }
+}
+
+// Parse a peer in the dictionary form, which is `None` if its address isn't an IP.
+//
+// Trackers are allowed to send DNS names instead of IPs, but nobody does in practice,
+// so we skip those peers instead of resolving them.
+fn extract_dict_peer(bencoding: &Bencoding) -> Result<Option<Peer>, TryFromBencodingError<'_>> {
+    let port = extract_int(extract_key(bencoding, "port")?)?;
+    let port = u16::try_from(port).map_err(|_| TryFromBencodingError::InvalidSize(port))?;
+    let ip = match extract_string(extract_key(bencoding, "ip")?)?.parse::<IpAddr>() {
+        Ok(ip) => ip,
+        Err(_) => return Ok(None),
+    };
+    let peer_id = extract_key(bencoding, "peer id")
+        .ok()
+        .map(extract_bytes)
+        .transpose()?
+        .and_then(|id| <[u8; 20]>::try_from(id).ok())
+        .map(PeerId::new);
+    Ok(Some(Peer {
+        addr: SocketAddr::new(ip, port),
+        peer_id,
+    }))
+}
+
+// Extract the peers of a tracker response, in either the compact or dictionary form.
+//
+// IPv6 peers from `peers6` come after the others.
+pub(super) fn extract_peers(bencoding: &Bencoding) -> Result<Vec<Peer>, TryFromBencodingError<'_>> {
+    let mut out = match extract_key(bencoding, "peers")? {
+        Bencoding::ByteString(bytes) => Peer::parse_compact(bytes),
+        Bencoding::List(list) => {
+            let mut out = Vec::with_capacity(list.len());
+            for peer in list.iter() {
+                out.extend(extract_dict_peer(peer)?);
+            }
+            out
+        }
+        other => return Err(TryFromBencodingError::ExpectedList(other)),
+    };
+    if let Ok(peers6) = extract_key(bencoding, "peers6") {
+        out.extend(Peer::parse_compact_v6(extract_bytes(peers6)?));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parsing_compact_peers_works() {
+        let peers = Peer::parse_compact(b"\x0a\x00\x00\x01\x00\x50\xc0\xa8\x01\x02\x1a\xe1\xff");
+        assert_eq!(
+            vec![
+                Peer::new("10.0.0.1:80".parse::<SocketAddr>().unwrap()),
+                Peer::new("192.168.1.2:6881".parse::<SocketAddr>().unwrap())
+            ],
+            peers
+        );
+    }
+
+    #[test]
+    fn parsing_compact_ipv6_peers_works() {
+        let mut input = b"d5:peers6:\x0a\x00\x00\x01\x00\x506:peers618:".to_vec();
+        input.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        input.extend_from_slice(b"\x1a\xe1e");
+        let bencoding = Bencoding::decode(&input).unwrap();
+        let addrs: Vec<_> = extract_peers(&bencoding)
+            .unwrap()
+            .iter()
+            .map(|p| p.addr.to_string())
+            .collect();
+        assert_eq!(vec!["10.0.0.1:80", "[::1]:6881"], addrs);
+    }
+
+    #[test]
+    fn parsing_dictionary_peers_works() {
+        let input = b"d5:peersld2:ip8:10.0.0.17:peer id20:-TY0100-abcdefghijkl4:porti80eed2:ip\
+            13:t.example.com4:porti80eed2:ip3:::14:porti6881eeee";
+        let bencoding = Bencoding::decode(input).unwrap();
+        let peers = extract_peers(&bencoding).unwrap();
+        assert_eq!(2, peers.len());
+        assert_eq!(
+            Some(PeerId::new(*b"-TY0100-abcdefghijkl")),
+            peers[0].peer_id
+        );
+        assert_eq!("[::1]:6881".parse::<SocketAddr>().unwrap(), peers[1].addr);
+        let bad_port = Bencoding::decode(b"d5:peersld2:ip3:::14:porti70000eeee").unwrap();
+        assert!(extract_peers(&bad_port).is_err());
+    }
+}
@@ -0,0 +1,319 @@
+//! This module opens TCP connections for the standard library clients, possibly through a proxy.
+//!
+//! SOCKS5 proxies, from RFC 1928, get asked to connect to hosts by name, so that DNS
+//! lookups happen on the proxy's side, and don't leak which trackers we're using. HTTP
+//! proxies get asked to open a tunnel with `CONNECT`. Neither kind of proxy can carry
+//! UDP trackers for us, since SOCKS5 relays UDP from a separate port that often isn't
+//! reachable, so UDP clients with a proxy refuse to send anything instead.
+use super::TrackerConfig;
+use crate::core::Host;
+use std::{
+    convert::TryFrom,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    str,
+};
+
+/// The credentials to log into a proxy with.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ProxyAuth {
+    /// The name of the user to log in as.
+    pub username: String,
+    /// The password of that user.
+    pub password: String,
+}
+
+/// A proxy to send requests to trackers through.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Proxy {
+    /// A SOCKS5 proxy, at an address like `127.0.0.1:1080`.
+    Socks5 {
+        /// The address of the proxy.
+        addr: String,
+        /// The credentials to log in with, if the proxy needs them.
+        auth: Option<ProxyAuth>,
+    },
+    /// An HTTP proxy supporting `CONNECT`, at an address like `proxy.example.com:8080`.
+    Http {
+        /// The address of the proxy.
+        addr: String,
+        /// The credentials to log in with, using basic authentication.
+        auth: Option<ProxyAuth>,
+    },
+}
+
+pub(super) fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+// Connect to the first address of a host which accepts our connection.
+fn connect_direct(addr: impl ToSocketAddrs, config: &TrackerConfig) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no address for host");
+    for addr in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, config.connect_timeout) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(config.request_timeout))?;
+                stream.set_write_timeout(Some(config.request_timeout))?;
+                return Ok(stream);
+            }
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+fn proxy_error(message: impl Into<String>) -> io::Error {
+    io::Error::other(message.into())
+}
+
+fn socks5_connect(
+    stream: &mut TcpStream,
+    host: &Host,
+    port: u16,
+    auth: Option<&ProxyAuth>,
+) -> io::Result<()> {
+    let method = if auth.is_some() { 2 } else { 0 };
+    stream.write_all(&[5, 1, method])?;
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply)?;
+    if reply != [5, method] {
+        return Err(proxy_error(
+            "SOCKS5 proxy refused our authentication method",
+        ));
+    }
+    if let Some(auth) = auth {
+        let mut login = vec![1];
+        for field in &[&auth.username, &auth.password] {
+            let len = u8::try_from(field.len())
+                .map_err(|_| proxy_error("SOCKS5 credentials are too long"))?;
+            login.push(len);
+            login.extend_from_slice(field.as_bytes());
+        }
+        stream.write_all(&login)?;
+        stream.read_exact(&mut reply)?;
+        if reply[1] != 0 {
+            return Err(proxy_error("SOCKS5 proxy rejected our credentials"));
+        }
+    }
+    let mut request = vec![5, 1, 0];
+    match host {
+        Host::Domain(domain) => {
+            let len = u8::try_from(domain.len()).map_err(|_| proxy_error("domain is too long"))?;
+            request.push(3);
+            request.push(len);
+            request.extend_from_slice(domain.as_bytes());
+        }
+        Host::Ipv4(ip) => {
+            request.push(1);
+            request.extend_from_slice(&ip.octets());
+        }
+        Host::Ipv6(ip) => {
+            request.push(4);
+            request.extend_from_slice(&ip.octets());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request)?;
+    let mut head = [0; 4];
+    stream.read_exact(&mut head)?;
+    if head[1] != 0 {
+        let message = format!("SOCKS5 proxy couldn't connect, with error {}", head[1]);
+        return Err(proxy_error(message));
+    }
+    // The proxy tells us which address it connected from, which we don't need
+    let addr_len = match head[3] {
+        1 => 4,
+        4 => 16,
+        3 => {
+            let mut len = [0];
+            stream.read_exact(&mut len)?;
+            usize::from(len[0])
+        }
+        _ => return Err(proxy_error("SOCKS5 proxy sent an invalid address")),
+    };
+    let mut bound = vec![0; addr_len + 2];
+    stream.read_exact(&mut bound)?;
+    Ok(())
+}
+
+fn http_connect(
+    stream: &mut TcpStream,
+    host: &Host,
+    port: u16,
+    auth: Option<&ProxyAuth>,
+) -> io::Result<()> {
+    let target = format!("{}:{}", host, port);
+    let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", target, target);
+    if let Some(auth) = auth {
+        let credentials = format!("{}:{}", auth.username, auth.password);
+        request.push_str("Proxy-Authorization: Basic ");
+        request.push_str(&base64(credentials.as_bytes()));
+        request.push_str("\r\n");
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes())?;
+    // Read byte by byte, so that we don't read past the end of the proxy's response
+    let mut head = Vec::new();
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte)?;
+        head.push(byte[0]);
+    }
+    let status = str::from_utf8(&head)
+        .ok()
+        .and_then(|head| head.split(' ').nth(1))
+        .unwrap_or_default();
+    if !status.starts_with('2') {
+        let message = format!("HTTP proxy refused to connect, with status {}", status);
+        return Err(proxy_error(message));
+    }
+    Ok(())
+}
+
+// Open a connection to a host, through a proxy if there is one.
+pub(super) fn connect(
+    host: &Host,
+    port: u16,
+    proxy: Option<&Proxy>,
+    config: &TrackerConfig,
+) -> io::Result<TcpStream> {
+    match proxy {
+        None => match host {
+            Host::Domain(domain) => connect_direct((domain.as_str(), port), config),
+            Host::Ipv4(ip) => connect_direct(SocketAddr::from((*ip, port)), config),
+            Host::Ipv6(ip) => connect_direct(SocketAddr::from((*ip, port)), config),
+        },
+        Some(Proxy::Socks5 { addr, auth }) => {
+            let mut stream = connect_direct(addr.as_str(), config)?;
+            socks5_connect(&mut stream, host, port, auth.as_ref())?;
+            Ok(stream)
+        }
+        Some(Proxy::Http { addr, auth }) => {
+            let mut stream = connect_direct(addr.as_str(), config)?;
+            http_connect(&mut stream, host, port, auth.as_ref())?;
+            Ok(stream)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{net::TcpListener, thread};
+
+    fn auth() -> ProxyAuth {
+        ProxyAuth {
+            username: "user".to_owned(),
+            password: "pass".to_owned(),
+        }
+    }
+
+    // Run a proxy accepting a single connection, with a function handling its handshake.
+    fn proxy<F>(handshake: F) -> (String, thread::JoinHandle<Vec<u8>>)
+    where
+        F: FnOnce(&mut TcpStream) -> Vec<u8> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let received = handshake(&mut stream);
+            stream.write_all(b"tunnel").unwrap();
+            received
+        });
+        (addr, handle)
+    }
+
+    fn read_tunnel(mut stream: TcpStream) -> Vec<u8> {
+        let mut out = Vec::new();
+        stream.read_to_end(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn connecting_through_socks5_works() {
+        let (addr, server) = proxy(|stream| {
+            let mut received = vec![0; 3 + 11 + 20];
+            stream.read_exact(&mut received[..3]).unwrap();
+            stream.write_all(&[5, 2]).unwrap();
+            stream.read_exact(&mut received[3..14]).unwrap();
+            stream.write_all(&[1, 0]).unwrap();
+            stream.read_exact(&mut received[14..]).unwrap();
+            stream.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+            received
+        });
+        let proxy = Proxy::Socks5 {
+            addr,
+            auth: Some(auth()),
+        };
+        let host = Host::Domain("t.example.com".to_owned());
+        let config = TrackerConfig::default();
+        let stream = connect(&host, 80, Some(&proxy), &config).unwrap();
+        assert_eq!(b"tunnel".to_vec(), read_tunnel(stream));
+        let mut expected = vec![5, 1, 2, 1, 4];
+        expected.extend_from_slice(b"user\x04pass\x05\x01\x00\x03\x0dt.example.com\x00\x50");
+        assert_eq!(expected, server.join().unwrap());
+    }
+
+    #[test]
+    fn connecting_through_http_proxies_works() {
+        let (addr, server) = proxy(|stream| {
+            let mut head = Vec::new();
+            let mut byte = [0];
+            while !head.ends_with(b"\r\n\r\n") {
+                stream.read_exact(&mut byte).unwrap();
+                head.push(byte[0]);
+            }
+            stream.write_all(b"HTTP/1.1 200 OK\r\n\r\n").unwrap();
+            head
+        });
+        let proxy = Proxy::Http {
+            addr,
+            auth: Some(auth()),
+        };
+        let host = Host::Ipv6("::1".parse().unwrap());
+        let config = TrackerConfig::default();
+        let stream = connect(&host, 6969, Some(&proxy), &config).unwrap();
+        assert_eq!(b"tunnel".to_vec(), read_tunnel(stream));
+        let head = String::from_utf8(server.join().unwrap()).unwrap();
+        assert!(head.starts_with("CONNECT [::1]:6969 HTTP/1.1\r\n"));
+        assert!(head.contains("Proxy-Authorization: Basic dXNlcjpwYXNz\r\n"));
+    }
+
+    #[test]
+    fn refused_connections_are_errors() {
+        let (addr, server) = proxy(|stream| {
+            let mut greeting = [0; 3];
+            stream.read_exact(&mut greeting).unwrap();
+            stream.write_all(&[5, 0xFF]).unwrap();
+            Vec::new()
+        });
+        let proxy = Proxy::Socks5 { addr, auth: None };
+        let host = Host::Ipv4("10.0.0.1".parse().unwrap());
+        assert!(connect(&host, 80, Some(&proxy), &TrackerConfig::default()).is_err());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn encoding_base64_works() {
+        assert_eq!("", base64(b""));
+        assert_eq!("Zm9vYmFy", base64(b"foobar"));
+        assert_eq!("Zm9vYg==", base64(b"foob"));
+        assert_eq!("Zm9vYmE=", base64(b"fooba"));
+    }
+}
@@ -0,0 +1,230 @@
+//! This module decides when to announce each torrent to its trackers.
+//!
+//! Trackers tell us how long to wait between regular announces, and sometimes a minimum
+//! interval, which we shouldn't announce more often than even when we have a reason to,
+//! like running low on peers. When many torrents are added at once, we space out their
+//! first announces, so that they don't all hit the trackers in one burst, and keep
+//! getting announced at different times afterwards.
+//!
+//! The scheduler doesn't keep time itself, every method takes the current time instead.
+use super::AnnounceResponse;
+use crate::core::InfoHash;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// The minimum interval we use for trackers that don't give us one.
+pub const DEFAULT_MIN_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Schedule {
+    next: Instant,
+    // When we last got a response, for forced announces to respect the minimum interval
+    last: Option<Instant>,
+    min_interval: Duration,
+    in_flight: bool,
+}
+
+/// A scheduler for the announces of many torrents.
+#[derive(Clone, Debug)]
+pub struct AnnounceScheduler {
+    stagger: Duration,
+    schedules: HashMap<InfoHash, Schedule>,
+    // The latest time a newly added torrent was scheduled at
+    last_slot: Option<Instant>,
+}
+
+impl AnnounceScheduler {
+    /// Create a scheduler, leaving at least `stagger` between the first announces of torrents.
+    pub fn new(stagger: Duration) -> Self {
+        AnnounceScheduler {
+            stagger,
+            schedules: HashMap::new(),
+            last_slot: None,
+        }
+    }
+
+    /// Start scheduling announces for a torrent, returning when its first announce is due.
+    ///
+    /// Adding a torrent that's already scheduled leaves its schedule alone.
+    pub fn add(&mut self, info_hash: InfoHash, now: Instant) -> Instant {
+        if let Some(schedule) = self.schedules.get(&info_hash) {
+            return schedule.next;
+        }
+        let next = match self.last_slot {
+            Some(slot) if slot + self.stagger > now => slot + self.stagger,
+            _ => now,
+        };
+        self.last_slot = Some(next);
+        let schedule = Schedule {
+            next,
+            last: None,
+            min_interval: DEFAULT_MIN_INTERVAL,
+            in_flight: false,
+        };
+        self.schedules.insert(info_hash, schedule);
+        next
+    }
+
+    /// Stop scheduling announces for a torrent.
+    pub fn remove(&mut self, info_hash: &InfoHash) {
+        self.schedules.remove(info_hash);
+    }
+
+    /// The number of torrents being scheduled.
+    pub fn len(&self) -> usize {
+        self.schedules.len()
+    }
+
+    /// Check whether no torrents are being scheduled.
+    pub fn is_empty(&self) -> bool {
+        self.schedules.is_empty()
+    }
+
+    /// The torrents due for an announce, earliest first.
+    ///
+    /// These won't be returned again until their announce is reported as done with
+    /// `announced` or `failed`.
+    pub fn due(&mut self, now: Instant) -> Vec<InfoHash> {
+        let mut due: Vec<_> = self
+            .schedules
+            .iter_mut()
+            .filter(|(_, schedule)| !schedule.in_flight && schedule.next <= now)
+            .map(|(hash, schedule)| {
+                schedule.in_flight = true;
+                (schedule.next, *hash)
+            })
+            .collect();
+        due.sort_unstable();
+        due.into_iter().map(|(_, hash)| hash).collect()
+    }
+
+    /// The next time a torrent will be due for an announce, if any.
+    ///
+    /// This is how long to sleep for, before calling `due` again.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.schedules
+            .values()
+            .filter(|schedule| !schedule.in_flight)
+            .map(|schedule| schedule.next)
+            .min()
+    }
+
+    /// Record the response to an announce, scheduling the next one after its interval.
+    pub fn announced(&mut self, info_hash: &InfoHash, now: Instant, response: &AnnounceResponse) {
+        if let Some(schedule) = self.schedules.get_mut(info_hash) {
+            schedule.min_interval = response.min_interval.unwrap_or(DEFAULT_MIN_INTERVAL);
+            schedule.next = now + response.interval.max(schedule.min_interval);
+            schedule.last = Some(now);
+            schedule.in_flight = false;
+        }
+    }
+
+    /// Record that an announce failed, trying again after some delay.
+    pub fn failed(&mut self, info_hash: &InfoHash, now: Instant, retry_after: Duration) {
+        if let Some(schedule) = self.schedules.get_mut(info_hash) {
+            schedule.next = now + retry_after;
+            schedule.in_flight = false;
+        }
+    }
+
+    /// Ask for an announce as soon as possible, returning when it will be due.
+    ///
+    /// The announce still waits until the minimum interval has passed since the last one,
+    /// and forcing a torrent that isn't scheduled does nothing.
+    ///
+    /// ```
+    /// use std::time::{Duration, Instant};
+    /// use typhoon::{core::InfoHash, tracker::{AnnounceResponse, AnnounceScheduler}};
+    ///
+    /// let hash = InfoHash::new([0; 20]);
+    /// let now = Instant::now();
+    /// let mut scheduler = AnnounceScheduler::new(Duration::from_secs(1));
+    /// scheduler.add(hash, now);
+    /// let response = AnnounceResponse {
+    ///     interval: Duration::from_secs(1800),
+    ///     min_interval: Some(Duration::from_secs(60)),
+    ///     ..AnnounceResponse::default()
+    /// };
+    /// scheduler.announced(&hash, now, &response);
+    /// assert_eq!(Some(now + Duration::from_secs(60)), scheduler.force(&hash, now));
+    /// ```
+    pub fn force(&mut self, info_hash: &InfoHash, now: Instant) -> Option<Instant> {
+        let schedule = self.schedules.get_mut(info_hash)?;
+        let earliest = match schedule.last {
+            Some(last) => now.max(last + schedule.min_interval),
+            None => now,
+        };
+        schedule.next = schedule.next.min(earliest);
+        Some(schedule.next)
+    }
+}
+
+impl Default for AnnounceScheduler {
+    /// A scheduler leaving a second between the first announces of torrents.
+    fn default() -> Self {
+        AnnounceScheduler::new(Duration::from_secs(1))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn response(interval: u64, min_interval: Option<u64>) -> AnnounceResponse {
+        AnnounceResponse {
+            interval: Duration::from_secs(interval),
+            min_interval: min_interval.map(Duration::from_secs),
+            ..AnnounceResponse::default()
+        }
+    }
+
+    #[test]
+    fn added_torrents_are_staggered() {
+        let now = Instant::now();
+        let second = Duration::from_secs(1);
+        let mut scheduler = AnnounceScheduler::new(second);
+        let hashes: Vec<_> = (0..3).map(|i| InfoHash::new([i; 20])).collect();
+        for (i, hash) in hashes.iter().enumerate() {
+            assert_eq!(now + second * i as u32, scheduler.add(*hash, now));
+        }
+        assert_eq!(now, scheduler.add(hashes[0], now + second * 5));
+        assert_eq!(vec![hashes[0], hashes[1]], scheduler.due(now + second));
+        // Torrents in flight aren't due again
+        assert_eq!(vec![hashes[2]], scheduler.due(now + second * 2));
+        assert_eq!(None, scheduler.next_deadline());
+        // Once the burst is over, new torrents are announced right away
+        let later = now + second * 10;
+        assert_eq!(later, scheduler.add(InfoHash::new([9; 20]), later));
+    }
+
+    #[test]
+    fn intervals_are_respected() {
+        let now = Instant::now();
+        let hash = InfoHash::new([0; 20]);
+        let mut scheduler = AnnounceScheduler::default();
+        scheduler.add(hash, now);
+        assert_eq!(vec![hash], scheduler.due(now));
+        scheduler.announced(&hash, now, &response(900, Some(120)));
+        assert_eq!(
+            Some(now + Duration::from_secs(900)),
+            scheduler.next_deadline()
+        );
+        assert!(scheduler.due(now + Duration::from_secs(899)).is_empty());
+        let forced = scheduler.force(&hash, now + Duration::from_secs(10));
+        assert_eq!(Some(now + Duration::from_secs(120)), forced);
+        assert_eq!(vec![hash], scheduler.due(now + Duration::from_secs(120)));
+        // Intervals shorter than the minimum interval are raised to it
+        scheduler.announced(&hash, now, &response(5, None));
+        assert_eq!(Some(now + DEFAULT_MIN_INTERVAL), scheduler.next_deadline());
+        scheduler.due(now + DEFAULT_MIN_INTERVAL);
+        scheduler.failed(&hash, now, Duration::from_secs(15));
+        assert_eq!(
+            Some(now + Duration::from_secs(15)),
+            scheduler.next_deadline()
+        );
+        scheduler.remove(&hash);
+        assert!(scheduler.is_empty());
+    }
+}
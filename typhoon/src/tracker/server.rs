@@ -0,0 +1,14 @@
+//! This module contains servers for running a tracker of our own.
+//!
+//! Swarms live in a `SwarmStore`, which does all the bookkeeping, while each server only
+//! speaks its protocol, so that an HTTP and a UDP server can share the same swarms.
+//! Servers are built on the standard library, but the handling of each request is also
+//! exposed on its own, taking the current time, so that servers can be run on top of
+//! another network library.
+mod http;
+mod swarm;
+mod udp;
+
+pub use http::HttpTrackerServer;
+pub use swarm::{ServerAnnounce, SwarmResponse, SwarmStore, DEFAULT_NUM_WANT, MAX_NUM_WANT};
+pub use udp::UdpTrackerServer;
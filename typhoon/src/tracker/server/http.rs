@@ -0,0 +1,451 @@
+//! This module contains a tracker server speaking HTTP.
+//!
+//! Announces and scrapes are GET requests to any path ending in `/announce` or `/scrape`,
+//! so that passkeys can be put in the path, like `/abcdef/announce`. Responses are always
+//! sent with a 200 status, with refusals explained by a `failure reason`, since that's
+//! what clients expect.
+use super::{ServerAnnounce, SwarmStore, DEFAULT_NUM_WANT};
+use crate::{
+    bencoding::{Bencoding, DictBuilder},
+    core::{url_decode, InfoHash},
+    tracker::{AnnounceEvent, PeerId},
+};
+use std::{
+    convert::TryFrom,
+    io::{self, Read, Write},
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream},
+    str,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+// The longest request head we accept, which is plenty for an announce.
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// A tracker server speaking HTTP, from BEP 3.
+#[derive(Clone, Debug)]
+pub struct HttpTrackerServer {
+    store: Arc<SwarmStore>,
+    max_connections: usize,
+    request_timeout: Duration,
+}
+
+// Counts a connection as being handled, until it gets dropped.
+struct Connection(Arc<AtomicUsize>);
+
+impl Connection {
+    // Start counting a new connection, unless we're already handling as many as we can.
+    fn open(active: &Arc<AtomicUsize>, max: usize) -> Option<Self> {
+        if active.fetch_add(1, Ordering::AcqRel) >= max {
+            active.fetch_sub(1, Ordering::AcqRel);
+            return None;
+        }
+        Some(Connection(Arc::clone(active)))
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+// The parameters of a request, in the order they appeared.
+struct Query(Vec<(String, Vec<u8>)>);
+
+impl Query {
+    fn parse(query: &str) -> Option<Self> {
+        let mut params = Vec::new();
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            params.push((key.to_owned(), url_decode(value, false)?));
+        }
+        Some(Query(params))
+    }
+
+    fn get(&self, key: &str) -> Option<&[u8]> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a [u8]> {
+        self.0
+            .iter()
+            .filter(move |(k, _)| k == key)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    fn get_int<T: str::FromStr>(&self, key: &str) -> Option<T> {
+        str::from_utf8(self.get(key)?).ok()?.parse().ok()
+    }
+}
+
+fn failure(reason: &str) -> Vec<u8> {
+    DictBuilder::new()
+        .insert("failure reason", reason)
+        .build()
+        .encode()
+}
+
+impl HttpTrackerServer {
+    /// Create a server keeping track of peers in a store, which other servers can share.
+    pub fn new(store: Arc<SwarmStore>) -> Self {
+        HttpTrackerServer {
+            store,
+            max_connections: 256,
+            request_timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Handle at most some number of connections at once, closing new ones past that.
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// Give clients some amount of time to send their entire request.
+    ///
+    /// This bounds the whole request, and not each read, so that clients trickling in
+    /// a byte at a time can't hold onto a connection.
+    pub fn with_request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = request_timeout;
+        self
+    }
+
+    /// The store this server keeps track of peers in.
+    pub fn store(&self) -> &Arc<SwarmStore> {
+        &self.store
+    }
+
+    /// Handle a request, with its target, like `/announce?info_hash=...`, returning the body
+    /// of the response, or `None` if the path isn't one we serve.
+    pub fn handle(&self, target: &str, remote: SocketAddr, now: Instant) -> Option<Vec<u8>> {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let query = match Query::parse(query) {
+            Some(query) => query,
+            None => return Some(failure("invalid percent encoding")),
+        };
+        if path.ends_with("/announce") {
+            Some(self.announce(&query, remote, now))
+        } else if path.ends_with("/scrape") {
+            Some(self.scrape(&query, now))
+        } else {
+            None
+        }
+    }
+
+    fn announce(&self, query: &Query, remote: SocketAddr, now: Instant) -> Vec<u8> {
+        let info_hash = query
+            .get("info_hash")
+            .and_then(|h| <[u8; 20]>::try_from(h).ok());
+        let peer_id = query
+            .get("peer_id")
+            .and_then(|id| <[u8; 20]>::try_from(id).ok());
+        let port = query.get_int::<u16>("port");
+        let left = query.get_int::<u64>("left");
+        let (info_hash, peer_id, port, left) = match (info_hash, peer_id, port, left) {
+            (Some(info_hash), Some(peer_id), Some(port), Some(left)) => {
+                (info_hash, peer_id, port, left)
+            }
+            _ => return failure("missing or invalid info_hash, peer_id, port, or left"),
+        };
+        let event = match query.get("event") {
+            Some(b"started") => AnnounceEvent::Started,
+            Some(b"completed") => AnnounceEvent::Completed,
+            Some(b"stopped") => AnnounceEvent::Stopped,
+            _ => AnnounceEvent::None,
+        };
+        // We ignore the ip parameter, so that peers can't add others to swarms
+        let announce = ServerAnnounce {
+            info_hash: InfoHash::new(info_hash),
+            peer_id: PeerId::new(peer_id),
+            addr: SocketAddr::new(remote.ip().to_canonical(), port),
+            left,
+            event,
+            num_want: query.get_int("numwant").unwrap_or(DEFAULT_NUM_WANT),
+        };
+        let response = match self.store.announce(&announce, now) {
+            Ok(response) => response,
+            Err(reason) => return failure(reason),
+        };
+        let mut dict = DictBuilder::new()
+            .insert("interval", self.store.interval().as_secs() as i64)
+            .insert("complete", i64::from(response.stats.seeders))
+            .insert("incomplete", i64::from(response.stats.leechers));
+        if query.get("compact") == Some(b"0") {
+            let peers: Vec<Bencoding> = response
+                .peers
+                .iter()
+                .map(|peer| {
+                    let mut peer_dict = DictBuilder::new()
+                        .insert("ip", peer.addr.ip().to_string())
+                        .insert("port", i64::from(peer.addr.port()));
+                    if let Some(id) = peer.peer_id {
+                        peer_dict = peer_dict.insert("peer id", id.as_bytes().to_vec());
+                    }
+                    peer_dict.build()
+                })
+                .collect();
+            dict = dict.insert("peers", peers);
+        } else {
+            let (mut peers, mut peers6) = (Vec::new(), Vec::new());
+            for peer in &response.peers {
+                let out = match peer.addr.ip() {
+                    IpAddr::V4(ip) => {
+                        peers.extend_from_slice(&ip.octets());
+                        &mut peers
+                    }
+                    IpAddr::V6(ip) => {
+                        peers6.extend_from_slice(&ip.octets());
+                        &mut peers6
+                    }
+                };
+                out.extend_from_slice(&peer.addr.port().to_be_bytes());
+            }
+            dict = dict.insert("peers", peers);
+            if !peers6.is_empty() {
+                dict = dict.insert("peers6", peers6);
+            }
+        }
+        dict.build().encode()
+    }
+
+    fn scrape(&self, query: &Query, now: Instant) -> Vec<u8> {
+        let hashes: Vec<_> = query
+            .get_all("info_hash")
+            .filter_map(|hash| <[u8; 20]>::try_from(hash).ok())
+            .map(InfoHash::new)
+            .collect();
+        if hashes.is_empty() {
+            return failure("scraping every torrent is not supported");
+        }
+        let mut files = DictBuilder::new();
+        for (hash, stats) in self.store.scrape(&hashes, now).files {
+            let stats = DictBuilder::new()
+                .insert("complete", i64::from(stats.seeders))
+                .insert("downloaded", i64::from(stats.completed))
+                .insert("incomplete", i64::from(stats.leechers));
+            files = files.insert(hash.as_bytes(), stats);
+        }
+        DictBuilder::new().insert("files", files).build().encode()
+    }
+
+    fn handle_connection(&self, mut stream: TcpStream) -> io::Result<()> {
+        let deadline = Instant::now() + self.request_timeout;
+        stream.set_write_timeout(Some(self.request_timeout))?;
+        let remote = stream.peer_addr()?;
+        let mut head = Vec::new();
+        let mut buf = [0; 1024];
+        while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+            // Each read only gets whatever time is left for the whole request
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(());
+            }
+            stream.set_read_timeout(Some(left))?;
+            let read = stream.read(&mut buf)?;
+            if read == 0 || head.len() + read > MAX_REQUEST_SIZE {
+                return Ok(());
+            }
+            head.extend_from_slice(&buf[..read]);
+        }
+        let target = str::from_utf8(&head)
+            .ok()
+            .and_then(|head| head.strip_prefix("GET "))
+            .and_then(|line| line.split(' ').next());
+        let (status, body) = match target.map(|t| self.handle(t, remote, Instant::now())) {
+            Some(Some(body)) => ("200 OK", body),
+            Some(None) => ("404 Not Found", Vec::new()),
+            None => ("400 Bad Request", Vec::new()),
+        };
+        write!(
+            stream,
+            "HTTP/1.0 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n",
+            status,
+            body.len()
+        )?;
+        stream.write_all(&body)
+    }
+
+    /// Serve requests coming in on a listener, forever.
+    ///
+    /// Each connection gets handled on its own thread, up to the maximum number of
+    /// connections, past which new connections are closed right away.
+    pub fn serve(&self, listener: TcpListener) {
+        let active = Arc::new(AtomicUsize::new(0));
+        for stream in listener.incoming().flatten() {
+            let connection = match Connection::open(&active, self.max_connections) {
+                Some(connection) => connection,
+                None => continue,
+            };
+            let server = self.clone();
+            thread::spawn(move || {
+                let _connection = connection;
+                server.handle_connection(stream)
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tracker::{
+        block_on, AnnounceRequest, AnnounceResponse, BlockingHttpClient, HttpTracker,
+        ScrapeResponse, TrackerError,
+    };
+
+    fn target(id: u8, extra: &str) -> String {
+        format!(
+            "/announce?info_hash={}&peer_id={}&port=6881&left=10{}",
+            "%AB".repeat(20),
+            format!("%{:02X}", id).repeat(20),
+            extra
+        )
+    }
+
+    fn remote(id: u8) -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, id], 50000))
+    }
+
+    #[test]
+    fn announcing_works() {
+        let server = HttpTrackerServer::new(Arc::new(SwarmStore::default()));
+        let now = Instant::now();
+        server.handle(&target(1, ""), remote(1), now).unwrap();
+        let body = server
+            .handle(&target(2, "&event=started"), remote(2), now)
+            .unwrap();
+        let response = AnnounceResponse::decode(&body).unwrap();
+        assert_eq!(Duration::from_secs(1800), response.interval);
+        assert_eq!(Some(2), response.leechers);
+        let addrs: Vec<_> = response.peers.iter().map(|peer| peer.addr).collect();
+        assert_eq!(vec![SocketAddr::from(([10, 0, 0, 1], 6881))], addrs);
+        let body = server
+            .handle(&target(2, "&compact=0"), remote(2), now)
+            .unwrap();
+        let response = AnnounceResponse::decode(&body).unwrap();
+        assert_eq!(Some(PeerId::new([1; 20])), response.peers[0].peer_id);
+    }
+
+    #[test]
+    fn scraping_works() {
+        let server = HttpTrackerServer::new(Arc::new(SwarmStore::default()));
+        let now = Instant::now();
+        server.handle(&target(1, ""), remote(1), now).unwrap();
+        let body = server
+            .handle(
+                &format!("/pk/scrape?info_hash={}", "%AB".repeat(20)),
+                remote(1),
+                now,
+            )
+            .unwrap();
+        let response = ScrapeResponse::decode(&body).unwrap();
+        assert_eq!(1, response.files[&InfoHash::new([0xAB; 20])].leechers);
+    }
+
+    #[test]
+    fn bad_requests_are_refused() {
+        let store = SwarmStore::default().with_allowlist(|_| false);
+        let server = HttpTrackerServer::new(Arc::new(store));
+        let now = Instant::now();
+        assert_eq!(None, server.handle("/index.html", remote(1), now));
+        let refused = [
+            server.handle(&target(1, ""), remote(1), now),
+            server.handle("/announce?info_hash=abc", remote(1), now),
+            server.handle("/announce?info_hash=%zz", remote(1), now),
+            server.handle(&target(1, "").replace("&left=10", ""), remote(1), now),
+            server.handle(&target(1, "").replace("left=10", "left=-1"), remote(1), now),
+            server.handle("/scrape", remote(1), now),
+        ];
+        for body in refused.iter() {
+            let decoded = AnnounceResponse::decode(body.as_ref().unwrap());
+            assert!(matches!(decoded, Err(TrackerError::Failure(_))));
+        }
+    }
+
+    #[test]
+    fn clients_can_announce_to_servers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/announce", listener.local_addr().unwrap());
+        let server = HttpTrackerServer::new(Arc::new(SwarmStore::default()));
+        thread::spawn(move || server.serve(listener));
+        let tracker = HttpTracker::new(url, BlockingHttpClient::default());
+        let info_hash = InfoHash::new([1; 20]);
+        for id in 1..=2 {
+            let request =
+                AnnounceRequest::new(info_hash, PeerId::new([id; 20]), 6880 + id as u16, 0);
+            let response = block_on(tracker.announce(&request)).unwrap();
+            assert_eq!(Some(id as u32), response.seeders);
+        }
+        let scrape = block_on(tracker.scrape(&[info_hash])).unwrap();
+        assert_eq!(2, scrape.files[&info_hash].seeders);
+    }
+
+    // Start serving on a new port, returning the address to connect to.
+    fn spawn(server: HttpTrackerServer) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || server.serve(listener));
+        addr
+    }
+
+    // Wait for the server to close a connection, returning whatever it sent us.
+    fn read_until_closed(stream: &mut TcpStream) -> Vec<u8> {
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut response = Vec::new();
+        let _ = stream.read_to_end(&mut response);
+        response
+    }
+
+    #[test]
+    fn slow_requests_time_out() {
+        let server = HttpTrackerServer::new(Arc::new(SwarmStore::default()))
+            .with_request_timeout(Duration::from_millis(300));
+        let mut stream = TcpStream::connect(spawn(server)).unwrap();
+        let start = Instant::now();
+        // Trickling in bytes shouldn't extend the deadline
+        for _ in 0..5 {
+            if stream.write_all(b"G").is_err() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        assert!(read_until_closed(&mut stream).is_empty());
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn connections_past_the_limit_are_closed() {
+        let server = HttpTrackerServer::new(Arc::new(SwarmStore::default()))
+            .with_max_connections(1)
+            .with_request_timeout(Duration::from_secs(5));
+        let addr = spawn(server);
+        let mut idle = TcpStream::connect(addr).unwrap();
+        let mut extra = TcpStream::connect(addr).unwrap();
+        let _ = extra.write_all(b"GET /announce HTTP/1.0\r\n\r\n");
+        assert!(read_until_closed(&mut extra).is_empty());
+        // Once the first connection is done, new ones are handled again
+        idle.write_all(b"GET /announce HTTP/1.0\r\n\r\n").unwrap();
+        assert!(!read_until_closed(&mut idle).is_empty());
+        // The connection closes just before it stops being counted, so we might be early
+        let handled = (0..50).any(|_| {
+            let mut next = TcpStream::connect(addr).unwrap();
+            let _ = next.write_all(b"GET /announce HTTP/1.0\r\n\r\n");
+            let handled = !read_until_closed(&mut next).is_empty();
+            if !handled {
+                thread::sleep(Duration::from_millis(20));
+            }
+            handled
+        });
+        assert!(handled);
+    }
+}
@@ -0,0 +1,309 @@
+//! This module contains the swarms a tracker server keeps track of.
+//!
+//! Peers get added to the swarm of a torrent when they announce, and removed when they
+//! announce `stopped`, or once they haven't announced for too long. The store is shared
+//! between the servers for each protocol, so that peers announcing over HTTP and UDP
+//! end up in the same swarms.
+use crate::{
+    core::InfoHash,
+    tracker::{set::shuffle, AnnounceEvent, Peer, PeerId, ScrapeResponse, ScrapeStats},
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    net::SocketAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// The number of peers we hand out when an announce doesn't say how many it wants.
+pub const DEFAULT_NUM_WANT: usize = 50;
+
+/// The most peers we ever hand out in a single response.
+pub const MAX_NUM_WANT: usize = 200;
+
+/// An announce received by a tracker server, from either protocol.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerAnnounce {
+    /// The torrent being announced.
+    pub info_hash: InfoHash,
+    /// The id of the announcing peer.
+    pub peer_id: PeerId,
+    /// The address other peers can reach the announcing peer at.
+    pub addr: SocketAddr,
+    /// How many bytes the peer still needs, which is 0 for seeders.
+    pub left: u64,
+    /// The event this announce reports.
+    pub event: AnnounceEvent,
+    /// How many peers the announcing peer wants.
+    pub num_want: usize,
+}
+
+/// What a tracker server answers an announce with.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SwarmResponse {
+    /// Other peers in the swarm, at most as many as were asked for.
+    pub peers: Vec<Peer>,
+    /// How many times a peer finished downloading the torrent, and the size of the swarm.
+    pub stats: ScrapeStats,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct SwarmPeer {
+    addr: SocketAddr,
+    seeding: bool,
+    last_seen: Instant,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Swarm {
+    peers: HashMap<PeerId, SwarmPeer>,
+    completed: u32,
+}
+
+impl Swarm {
+    fn stats(&self) -> ScrapeStats {
+        let seeders = self.peers.values().filter(|peer| peer.seeding).count() as u32;
+        ScrapeStats {
+            seeders,
+            leechers: self.peers.len() as u32 - seeders,
+            completed: self.completed,
+        }
+    }
+
+    fn expire(&mut self, before: Instant) {
+        self.peers.retain(|_, peer| peer.last_seen >= before);
+    }
+}
+
+type Allowlist = Box<dyn Fn(&InfoHash) -> bool + Send + Sync>;
+
+/// The swarms a tracker server knows about, which can be shared between threads.
+pub struct SwarmStore {
+    interval: Duration,
+    peer_timeout: Duration,
+    allowlist: Option<Allowlist>,
+    swarms: Mutex<HashMap<InfoHash, Swarm>>,
+}
+
+impl fmt::Debug for SwarmStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SwarmStore")
+            .field("interval", &self.interval)
+            .field("peer_timeout", &self.peer_timeout)
+            .field("allowlist", &self.allowlist.is_some())
+            .finish()
+    }
+}
+
+impl Default for SwarmStore {
+    /// A store asking peers to announce every 30 minutes.
+    fn default() -> Self {
+        SwarmStore::new(Duration::from_secs(30 * 60))
+    }
+}
+
+impl SwarmStore {
+    /// Create a store asking peers to announce at some interval.
+    ///
+    /// Peers which miss two announces in a row get removed from their swarm.
+    pub fn new(interval: Duration) -> Self {
+        SwarmStore {
+            interval,
+            peer_timeout: interval * 2,
+            allowlist: None,
+            swarms: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Change how long peers stay in their swarm after their last announce.
+    pub fn with_peer_timeout(mut self, peer_timeout: Duration) -> Self {
+        self.peer_timeout = peer_timeout;
+        self
+    }
+
+    /// Only track the torrents for which a function returns true.
+    ///
+    /// This is how private trackers keep unregistered torrents out. Announces and scrapes
+    /// for other torrents are refused.
+    pub fn with_allowlist(
+        mut self,
+        allowed: impl Fn(&InfoHash) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.allowlist = Some(Box::new(allowed));
+        self
+    }
+
+    /// How often peers are asked to announce.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Check whether a torrent can be tracked here.
+    pub fn is_allowed(&self, info_hash: &InfoHash) -> bool {
+        self.allowlist
+            .as_ref()
+            .is_none_or(|allowed| allowed(info_hash))
+    }
+
+    /// Handle an announce, returning other peers in the swarm, or why we refused it.
+    pub fn announce(
+        &self,
+        announce: &ServerAnnounce,
+        now: Instant,
+    ) -> Result<SwarmResponse, &'static str> {
+        if !self.is_allowed(&announce.info_hash) {
+            return Err("unregistered torrent");
+        }
+        let mut swarms = self.swarms.lock().unwrap_or_else(|e| e.into_inner());
+        let swarm = swarms.entry(announce.info_hash).or_default();
+        swarm.expire(now.checked_sub(self.peer_timeout).unwrap_or(now));
+        if announce.event == AnnounceEvent::Stopped {
+            swarm.peers.remove(&announce.peer_id);
+        } else {
+            let peer = SwarmPeer {
+                addr: announce.addr,
+                seeding: announce.left == 0,
+                last_seen: now,
+            };
+            swarm.peers.insert(announce.peer_id, peer);
+        }
+        if announce.event == AnnounceEvent::Completed {
+            swarm.completed = swarm.completed.saturating_add(1);
+        }
+        let mut peers: Vec<_> = swarm
+            .peers
+            .iter()
+            .filter(|(id, peer)| **id != announce.peer_id && peer.addr != announce.addr)
+            // Seeders don't need other seeders
+            .filter(|(_, peer)| announce.left > 0 || !peer.seeding)
+            .map(|(id, peer)| Peer {
+                addr: peer.addr,
+                peer_id: Some(*id),
+            })
+            .collect();
+        shuffle(&mut peers);
+        peers.truncate(announce.num_want.min(MAX_NUM_WANT));
+        let stats = swarm.stats();
+        if swarm.peers.is_empty() {
+            swarms.remove(&announce.info_hash);
+        }
+        Ok(SwarmResponse { peers, stats })
+    }
+
+    /// Get the stats of some torrents, leaving out the ones we don't know about.
+    pub fn scrape(&self, info_hashes: &[InfoHash], now: Instant) -> ScrapeResponse {
+        let before = now.checked_sub(self.peer_timeout).unwrap_or(now);
+        let mut swarms = self.swarms.lock().unwrap_or_else(|e| e.into_inner());
+        let mut files = BTreeMap::new();
+        for hash in info_hashes.iter().filter(|hash| self.is_allowed(hash)) {
+            if let Some(swarm) = swarms.get_mut(hash) {
+                swarm.expire(before);
+                files.insert(*hash, swarm.stats());
+            }
+        }
+        ScrapeResponse { files }
+    }
+
+    /// Remove every peer which hasn't announced for too long, and swarms left empty.
+    pub fn expire(&self, now: Instant) {
+        let before = now.checked_sub(self.peer_timeout).unwrap_or(now);
+        let mut swarms = self.swarms.lock().unwrap_or_else(|e| e.into_inner());
+        for swarm in swarms.values_mut() {
+            swarm.expire(before);
+        }
+        swarms.retain(|_, swarm| !swarm.peers.is_empty());
+    }
+
+    /// The number of torrents with at least one peer.
+    pub fn len(&self) -> usize {
+        self.swarms.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Check whether no torrent has any peers.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn announce(id: u8, left: u64, event: AnnounceEvent) -> ServerAnnounce {
+        ServerAnnounce {
+            info_hash: InfoHash::new([0xAB; 20]),
+            peer_id: PeerId::new([id; 20]),
+            addr: SocketAddr::from(([10, 0, 0, id], 6881)),
+            left,
+            event,
+            num_want: DEFAULT_NUM_WANT,
+        }
+    }
+
+    #[test]
+    fn swarms_hand_out_other_peers() {
+        let store = SwarmStore::default();
+        let now = Instant::now();
+        let first = store
+            .announce(&announce(1, 0, AnnounceEvent::Started), now)
+            .unwrap();
+        assert!(first.peers.is_empty());
+        let second = store
+            .announce(&announce(2, 100, AnnounceEvent::Started), now)
+            .unwrap();
+        let addrs: Vec<_> = second.peers.iter().map(|peer| peer.addr).collect();
+        assert_eq!(vec![SocketAddr::from(([10, 0, 0, 1], 6881))], addrs);
+        assert_eq!((1, 1), (second.stats.seeders, second.stats.leechers));
+        // Seeders only get leechers
+        let third = store
+            .announce(&announce(3, 0, AnnounceEvent::Completed), now)
+            .unwrap();
+        assert_eq!(1, third.peers.len());
+        assert_eq!(1, third.stats.completed);
+        store
+            .announce(&announce(3, 0, AnnounceEvent::Stopped), now)
+            .unwrap();
+        let hash = InfoHash::new([0xAB; 20]);
+        let stats = store.scrape(&[hash, InfoHash::new([0; 20])], now).files;
+        assert_eq!(1, stats.len());
+        let expected = ScrapeStats {
+            seeders: 1,
+            leechers: 1,
+            completed: 1,
+        };
+        assert_eq!(expected, stats[&hash]);
+    }
+
+    #[test]
+    fn peers_expire() {
+        let store = SwarmStore::new(Duration::from_secs(60));
+        let now = Instant::now();
+        store
+            .announce(&announce(1, 0, AnnounceEvent::Started), now)
+            .unwrap();
+        store.expire(now + Duration::from_secs(119));
+        assert_eq!(1, store.len());
+        let later = now + Duration::from_secs(121);
+        let response = store
+            .announce(&announce(2, 10, AnnounceEvent::Started), later)
+            .unwrap();
+        assert!(response.peers.is_empty());
+        store.expire(later + Duration::from_secs(121));
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn allowlists_refuse_other_torrents() {
+        let allowed = InfoHash::new([0xAB; 20]);
+        let store = SwarmStore::default().with_allowlist(move |hash| *hash == allowed);
+        let now = Instant::now();
+        assert!(store
+            .announce(&announce(1, 0, AnnounceEvent::None), now)
+            .is_ok());
+        let mut other = announce(1, 0, AnnounceEvent::None);
+        other.info_hash = InfoHash::new([0; 20]);
+        assert_eq!(Err("unregistered torrent"), store.announce(&other, now));
+        assert!(store.scrape(&[other.info_hash], now).files.is_empty());
+    }
+}
@@ -0,0 +1,376 @@
+//! This module contains a tracker server speaking UDP, from BEP 15.
+//!
+//! Connection ids are derived from the IP address of a client, a secret key, and the
+//! current minute, so that we can check them without remembering which ones we handed
+//! out. Ports are left out, since clients may send each request from a new one. An id
+//! stays valid until the end of the minute after the one it was handed out in, which
+//! covers the minute clients keep using it for.
+//!
+//! Every packet counts against a rate limit for the address it came from, and packets
+//! over the limit are dropped without a response. Since the source of a UDP packet can be
+//! spoofed, answering them would let anyone use us to flood someone else.
+use super::{ServerAnnounce, SwarmStore, DEFAULT_NUM_WANT};
+use crate::{
+    core::InfoHash,
+    tracker::{
+        udp::{
+            read_u32, read_u64, ACTION_ANNOUNCE, ACTION_CONNECT, ACTION_ERROR, ACTION_SCRAPE,
+            CONNECTION_LIFETIME, PROTOCOL_ID,
+        },
+        AnnounceEvent, PeerId,
+    },
+};
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    convert::TryFrom,
+    hash::BuildHasher,
+    net::{IpAddr, SocketAddr, UdpSocket},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// The most torrents a single scrape can ask about, which is what fits in a packet.
+const MAX_SCRAPE_HASHES: usize = 74;
+
+// How often a running server removes expired peers and rate limits.
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// A tracker server speaking UDP, from BEP 15.
+///
+/// By default, each address can send 50 packets every 10 seconds, which leaves plenty
+/// of room for clients announcing many torrents at once.
+#[derive(Debug)]
+pub struct UdpTrackerServer {
+    store: Arc<SwarmStore>,
+    keys: RandomState,
+    started: Instant,
+    rate_limit: Option<(u32, Duration)>,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+fn error(transaction: u32, message: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(8 + message.len());
+    out.extend_from_slice(&ACTION_ERROR.to_be_bytes());
+    out.extend_from_slice(&transaction.to_be_bytes());
+    out.extend_from_slice(message.as_bytes());
+    out
+}
+
+impl UdpTrackerServer {
+    /// Create a server keeping track of peers in a store, which other servers can share.
+    pub fn new(store: Arc<SwarmStore>) -> Self {
+        UdpTrackerServer {
+            store,
+            keys: RandomState::new(),
+            started: Instant::now(),
+            rate_limit: Some((50, Duration::from_secs(10))),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Let each address send at most some number of packets over a period.
+    ///
+    /// Packets are allowed to come in bursts, as long as the average rate stays below
+    /// the limit.
+    pub fn with_rate_limit(mut self, packets: u32, period: Duration) -> Self {
+        self.rate_limit = Some((packets, period));
+        self
+    }
+
+    /// Answer every packet, however many an address sends.
+    pub fn without_rate_limit(mut self) -> Self {
+        self.rate_limit = None;
+        self
+    }
+
+    /// The store this server keeps track of peers in.
+    pub fn store(&self) -> &Arc<SwarmStore> {
+        &self.store
+    }
+
+    fn allow(&self, ip: IpAddr, now: Instant) -> bool {
+        let (packets, period) = match self.rate_limit {
+            Some(limit) => limit,
+            None => return true,
+        };
+        let capacity = f64::from(packets);
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated);
+        let refill = capacity * elapsed.as_secs_f64() / period.as_secs_f64();
+        bucket.tokens = (bucket.tokens + refill).min(capacity);
+        bucket.updated = bucket.updated.max(now);
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+
+    fn connection_id(&self, ip: IpAddr, minute: u64) -> u64 {
+        self.keys.hash_one((ip, minute))
+    }
+
+    fn minute(&self, now: Instant) -> u64 {
+        now.saturating_duration_since(self.started).as_secs() / CONNECTION_LIFETIME.as_secs()
+    }
+
+    fn check_connection(&self, connection: u64, ip: IpAddr, now: Instant) -> bool {
+        let minute = self.minute(now);
+        connection == self.connection_id(ip, minute)
+            || (minute > 0 && connection == self.connection_id(ip, minute - 1))
+    }
+
+    /// Handle a packet, returning the packet to answer with, if any.
+    pub fn handle(&self, packet: &[u8], remote: SocketAddr, now: Instant) -> Option<Vec<u8>> {
+        let connection = read_u64(packet, 0).ok()?;
+        let action = read_u32(packet, 8).ok()?;
+        let transaction = read_u32(packet, 12).ok()?;
+        let ip = remote.ip().to_canonical();
+        if !self.allow(ip, now) {
+            return None;
+        }
+        if action == ACTION_CONNECT {
+            if connection != PROTOCOL_ID {
+                return Some(error(transaction, "invalid protocol id"));
+            }
+            let mut out = Vec::with_capacity(16);
+            out.extend_from_slice(&ACTION_CONNECT.to_be_bytes());
+            out.extend_from_slice(&transaction.to_be_bytes());
+            out.extend_from_slice(&self.connection_id(ip, self.minute(now)).to_be_bytes());
+            return Some(out);
+        }
+        if !self.check_connection(connection, ip, now) {
+            return Some(error(transaction, "invalid connection id"));
+        }
+        let response = match action {
+            ACTION_ANNOUNCE => self.announce(packet, ip, now),
+            ACTION_SCRAPE => Ok(self.scrape(packet, now)),
+            _ => Err("unknown action"),
+        };
+        let mut out = match response {
+            Ok(out) => out,
+            Err(message) => return Some(error(transaction, message)),
+        };
+        out[..4].copy_from_slice(&action.to_be_bytes());
+        out[4..8].copy_from_slice(&transaction.to_be_bytes());
+        Some(out)
+    }
+
+    // Handle an announce, leaving room at the start for the action and transaction id.
+    fn announce(&self, packet: &[u8], ip: IpAddr, now: Instant) -> Result<Vec<u8>, &'static str> {
+        if packet.len() < 98 {
+            return Err("packet is too short");
+        }
+        let info_hash = <[u8; 20]>::try_from(&packet[16..36]).unwrap_or_default();
+        let peer_id = <[u8; 20]>::try_from(&packet[36..56]).unwrap_or_default();
+        let event = match read_u32(packet, 80).unwrap_or_default() {
+            1 => AnnounceEvent::Completed,
+            2 => AnnounceEvent::Started,
+            3 => AnnounceEvent::Stopped,
+            _ => AnnounceEvent::None,
+        };
+        let num_want = read_u32(packet, 92).unwrap_or_default() as i32;
+        let port = u16::from_be_bytes([packet[96], packet[97]]);
+        // We ignore the ip field, so that peers can't add others to swarms
+        let announce = ServerAnnounce {
+            info_hash: InfoHash::new(info_hash),
+            peer_id: PeerId::new(peer_id),
+            addr: SocketAddr::new(ip, port),
+            left: read_u64(packet, 64).unwrap_or_default(),
+            event,
+            num_want: usize::try_from(num_want).unwrap_or(DEFAULT_NUM_WANT),
+        };
+        let response = self.store.announce(&announce, now)?;
+        let interval = u32::try_from(self.store.interval().as_secs()).unwrap_or(u32::MAX);
+        let mut out = vec![0; 8];
+        out.extend_from_slice(&interval.to_be_bytes());
+        out.extend_from_slice(&response.stats.leechers.to_be_bytes());
+        out.extend_from_slice(&response.stats.seeders.to_be_bytes());
+        // Clients expect peers of the same family as the address they announced from
+        for peer in &response.peers {
+            match (ip, peer.addr.ip()) {
+                (IpAddr::V4(_), IpAddr::V4(peer_ip)) => out.extend_from_slice(&peer_ip.octets()),
+                (IpAddr::V6(_), IpAddr::V6(peer_ip)) => out.extend_from_slice(&peer_ip.octets()),
+                _ => continue,
+            }
+            out.extend_from_slice(&peer.addr.port().to_be_bytes());
+        }
+        Ok(out)
+    }
+
+    // Handle a scrape, leaving room at the start for the action and transaction id.
+    fn scrape(&self, packet: &[u8], now: Instant) -> Vec<u8> {
+        let hashes: Vec<_> = packet[16..]
+            .chunks_exact(20)
+            .take(MAX_SCRAPE_HASHES)
+            .filter_map(|hash| <[u8; 20]>::try_from(hash).ok())
+            .map(InfoHash::new)
+            .collect();
+        let files = self.store.scrape(&hashes, now).files;
+        let mut out = vec![0; 8];
+        // Stats go in the same order as the hashes, with zeros for torrents we don't know
+        for hash in &hashes {
+            let stats = files.get(hash).copied().unwrap_or_default();
+            out.extend_from_slice(&stats.seeders.to_be_bytes());
+            out.extend_from_slice(&stats.completed.to_be_bytes());
+            out.extend_from_slice(&stats.leechers.to_be_bytes());
+        }
+        out
+    }
+
+    /// Remove expired peers from the store, and forget addresses back under their limit.
+    pub fn expire(&self, now: Instant) {
+        self.store.expire(now);
+        if let Some((_, period)) = self.rate_limit {
+            let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+            buckets.retain(|_, bucket| now.saturating_duration_since(bucket.updated) < period);
+        }
+    }
+
+    /// Serve packets coming in on a socket, forever.
+    ///
+    /// Packets are handled one after the other, on the calling thread, and expired peers
+    /// get removed every minute.
+    pub fn serve(&self, socket: UdpSocket) {
+        let mut buf = vec![0; 2048];
+        let mut last_cleanup = Instant::now();
+        loop {
+            // Errors here come from single packets, like ICMP errors, so we carry on
+            let (read, remote) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(_) => continue,
+            };
+            let now = Instant::now();
+            if let Some(response) = self.handle(&buf[..read], remote, now) {
+                let _ = socket.send_to(&response, remote);
+            }
+            if now.saturating_duration_since(last_cleanup) >= CLEANUP_INTERVAL {
+                self.expire(now);
+                last_cleanup = now;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tracker::{block_on, AnnounceRequest, StdUdpClient, UdpTracker};
+    use std::thread;
+
+    fn remote(id: u8) -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, id], 50000))
+    }
+
+    fn connect(server: &UdpTrackerServer, remote: SocketAddr, now: Instant) -> u64 {
+        let mut packet = PROTOCOL_ID.to_be_bytes().to_vec();
+        packet.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 9]);
+        let response = server.handle(&packet, remote, now).unwrap();
+        assert_eq!(&[0, 0, 0, 0, 0, 0, 0, 9], &response[..8]);
+        read_u64(&response, 8).unwrap()
+    }
+
+    fn announce(connection: u64, id: u8, left: u64) -> Vec<u8> {
+        let mut packet = connection.to_be_bytes().to_vec();
+        packet.extend_from_slice(&ACTION_ANNOUNCE.to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0, 9]);
+        packet.extend_from_slice(&[0xAB; 20]);
+        packet.extend_from_slice(&[id; 20]);
+        packet.extend_from_slice(&[0; 8]);
+        packet.extend_from_slice(&left.to_be_bytes());
+        packet.extend_from_slice(&[0; 8 + 4 + 4 + 4]);
+        packet.extend_from_slice(&(-1i32).to_be_bytes());
+        packet.extend_from_slice(&6881u16.to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn announcing_works() {
+        let server = UdpTrackerServer::new(Arc::new(SwarmStore::default()));
+        let now = Instant::now();
+        for id in 1..=2 {
+            let connection = connect(&server, remote(id), now);
+            let response = server
+                .handle(&announce(connection, id, 10), remote(id), now)
+                .unwrap();
+            assert_eq!(&[0, 0, 0, 1, 0, 0, 0, 9], &response[..8]);
+            assert_eq!(1800, read_u32(&response, 8).unwrap());
+            assert_eq!(u32::from(id), read_u32(&response, 12).unwrap());
+            assert_eq!(6 * usize::from(id - 1), response[20..].len());
+        }
+        let connection = connect(&server, remote(1), now);
+        let mut packet = connection.to_be_bytes().to_vec();
+        packet.extend_from_slice(&ACTION_SCRAPE.to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0, 9]);
+        packet.extend_from_slice(&[0xAB; 20]);
+        packet.extend_from_slice(&[0; 20]);
+        let response = server.handle(&packet, remote(1), now).unwrap();
+        let stats: Vec<_> = (8..response.len())
+            .step_by(4)
+            .map(|at| read_u32(&response, at).unwrap())
+            .collect();
+        assert_eq!(vec![0, 0, 2, 0, 0, 0], stats);
+    }
+
+    #[test]
+    fn connection_ids_are_checked() {
+        let server = UdpTrackerServer::new(Arc::new(SwarmStore::default()));
+        let now = Instant::now();
+        let connection = connect(&server, remote(1), now);
+        let later = now + Duration::from_secs(61);
+        assert_eq!(connection, connect(&server, remote(1), now));
+        assert!(server.check_connection(connection, remote(1).ip(), later));
+        assert!(!server.check_connection(connection, remote(2).ip(), now));
+        let expired = now + Duration::from_secs(121);
+        let response = server
+            .handle(&announce(connection, 1, 0), remote(1), expired)
+            .unwrap();
+        assert_eq!(ACTION_ERROR, read_u32(&response, 0).unwrap());
+        assert_eq!(b"invalid connection id", &response[8..]);
+    }
+
+    #[test]
+    fn packets_over_the_rate_limit_are_dropped() {
+        let store = Arc::new(SwarmStore::default());
+        let server = UdpTrackerServer::new(store).with_rate_limit(2, Duration::from_secs(10));
+        let now = Instant::now();
+        connect(&server, remote(1), now);
+        connect(&server, remote(1), now);
+        let packet = announce(0, 1, 0);
+        assert_eq!(None, server.handle(&packet, remote(1), now));
+        assert!(server.handle(&packet, remote(2), now).is_some());
+        let later = now + Duration::from_secs(5);
+        assert!(server.handle(&packet, remote(1), later).is_some());
+        server.expire(now + Duration::from_secs(20));
+        assert!(server.buckets.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn clients_can_announce_to_servers() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let url = format!("udp://{}", socket.local_addr().unwrap());
+        let server = UdpTrackerServer::new(Arc::new(SwarmStore::default()));
+        thread::spawn(move || server.serve(socket));
+        let tracker = UdpTracker::new(url, StdUdpClient::default());
+        let info_hash = InfoHash::new([1; 20]);
+        for id in 1..=2 {
+            let peer_id = PeerId::new([id; 20]);
+            let request = AnnounceRequest::new(info_hash, peer_id, 6880 + u16::from(id), 10);
+            let response = block_on(tracker.announce(&request)).unwrap();
+            assert_eq!(Some(u32::from(id)), response.leechers);
+            assert_eq!(usize::from(id - 1), response.peers.len());
+        }
+        let scrape = block_on(tracker.scrape(&[info_hash])).unwrap();
+        assert_eq!(2, scrape.files[&info_hash].leechers);
+    }
+}
@@ -0,0 +1,196 @@
+//! This module decides which tracker of a torrent to announce to, from BEP 12.
+//!
+//! Torrents list their trackers in tiers. We try the trackers of the first tier, in a
+//! random order, moving on to the next tier only once every tracker in a tier has failed.
+//! A tracker which responds gets moved to the front of its tier, so that we keep using it.
+use super::random_u64;
+use crate::core::TrackerAddr;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Entry {
+    addr: TrackerAddr,
+    // Whether this tracker failed since we last found a working tracker
+    failed: bool,
+    // How many times in a row this tracker has failed
+    failures: u32,
+}
+
+/// The trackers of a torrent, ordered by which ones to try first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrackerSet {
+    tiers: Vec<Vec<Entry>>,
+}
+
+impl TrackerSet {
+    /// Create a set from trackers with their tiers, like `Torrent::trackers`.
+    ///
+    /// Trackers within each tier get shuffled, spreading load across them.
+    pub fn new(trackers: &[(u8, TrackerAddr)]) -> Self {
+        let mut sorted: Vec<_> = trackers.iter().collect();
+        sorted.sort_by_key(|(tier, _)| *tier);
+        let mut tiers: Vec<Vec<Entry>> = Vec::new();
+        let mut last_tier = None;
+        for (tier, addr) in sorted {
+            if last_tier != Some(*tier) {
+                tiers.push(Vec::new());
+                last_tier = Some(*tier);
+            }
+            let entry = Entry {
+                addr: addr.clone(),
+                failed: false,
+                failures: 0,
+            };
+            // We just pushed a tier, so there's always a last one
+            tiers.last_mut().unwrap().push(entry);
+        }
+        for tier in &mut tiers {
+            shuffle(tier);
+        }
+        TrackerSet { tiers }
+    }
+
+    /// The number of trackers in this set.
+    pub fn len(&self) -> usize {
+        self.tiers.iter().map(Vec::len).sum()
+    }
+
+    /// Check whether this set has no trackers at all.
+    pub fn is_empty(&self) -> bool {
+        self.tiers.is_empty()
+    }
+
+    /// Iterate over the trackers in this set, in the order they'll be tried in.
+    pub fn iter(&self) -> impl Iterator<Item = &TrackerAddr> {
+        self.entries().map(|entry| &entry.addr)
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.tiers.iter().flatten()
+    }
+
+    fn entry_mut(&mut self, addr: &TrackerAddr) -> Option<&mut Entry> {
+        self.tiers
+            .iter_mut()
+            .flatten()
+            .find(|entry| entry.addr == *addr)
+    }
+
+    /// The tracker to announce to next.
+    ///
+    /// This is the first tracker which hasn't failed since we last found a working one.
+    /// Once every tracker has failed, we start over from the first tracker.
+    ///
+    /// ```
+    /// use typhoon::{core::TrackerAddr, tracker::TrackerSet};
+    ///
+    /// let a = TrackerAddr::from("udp://a:1");
+    /// let b = TrackerAddr::from("udp://b:1");
+    /// let mut set = TrackerSet::new(&[(0, a.clone()), (1, b.clone())]);
+    /// assert_eq!(Some(&a), set.next_tracker());
+    /// set.failure(&a);
+    /// assert_eq!(Some(&b), set.next_tracker());
+    /// ```
+    pub fn next_tracker(&mut self) -> Option<&TrackerAddr> {
+        if self.entries().all(|entry| entry.failed) {
+            self.reset_failed();
+        }
+        self.entries()
+            .find(|entry| !entry.failed)
+            .map(|entry| &entry.addr)
+    }
+
+    fn reset_failed(&mut self) {
+        for entry in self.tiers.iter_mut().flatten() {
+            entry.failed = false;
+        }
+    }
+
+    /// Record that a tracker responded, moving it to the front of its tier.
+    pub fn success(&mut self, addr: &TrackerAddr) {
+        for tier in &mut self.tiers {
+            if let Some(i) = tier.iter().position(|entry| entry.addr == *addr) {
+                let mut entry = tier.remove(i);
+                entry.failures = 0;
+                tier.insert(0, entry);
+            }
+        }
+        // Trackers that failed get another chance if this one stops working
+        self.reset_failed();
+    }
+
+    /// Record that a tracker couldn't be reached, or refused our announce.
+    pub fn failure(&mut self, addr: &TrackerAddr) {
+        if let Some(entry) = self.entry_mut(addr) {
+            entry.failed = true;
+            entry.failures += 1;
+        }
+    }
+
+    /// How many times in a row a tracker has failed, which is 0 for unknown trackers.
+    pub fn failures(&self, addr: &TrackerAddr) -> u32 {
+        self.entries()
+            .find(|entry| entry.addr == *addr)
+            .map_or(0, |entry| entry.failures)
+    }
+}
+
+// Shuffle a slice, with the Fisher-Yates algorithm.
+pub(super) fn shuffle<T>(slice: &mut [T]) {
+    for i in (1..slice.len()).rev() {
+        let j = (random_u64() % (i as u64 + 1)) as usize;
+        slice.swap(i, j);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn addr(name: &str) -> TrackerAddr {
+        TrackerAddr::from(format!("udp://{}:1", name).as_str())
+    }
+
+    #[test]
+    fn tiers_are_tried_in_order() {
+        let trackers = [(1, addr("c")), (0, addr("a")), (0, addr("b"))];
+        let mut set = TrackerSet::new(&trackers);
+        assert_eq!(3, set.len());
+        let first: Vec<_> = set.iter().take(2).cloned().collect();
+        assert!(first.contains(&addr("a")) && first.contains(&addr("b")));
+        assert_eq!(Some(&first[0]), set.next_tracker());
+        set.failure(&first[0]);
+        assert_eq!(Some(&first[1]), set.next_tracker());
+        set.failure(&first[1]);
+        assert_eq!(Some(&addr("c")), set.next_tracker());
+        set.failure(&addr("c"));
+        // Every tracker failed, so we start over
+        assert_eq!(Some(&first[0]), set.next_tracker());
+        assert_eq!(1, set.failures(&addr("c")));
+    }
+
+    #[test]
+    fn responsive_trackers_are_promoted() {
+        let trackers = [(0, addr("a")), (0, addr("b")), (0, addr("c"))];
+        let mut set = TrackerSet::new(&trackers);
+        let order: Vec<_> = set.iter().cloned().collect();
+        set.failure(&order[0]);
+        set.failure(&order[1]);
+        set.success(&order[2]);
+        assert_eq!(
+            vec![&order[2], &order[0], &order[1]],
+            set.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(Some(&order[2]), set.next_tracker());
+        assert_eq!(0, set.failures(&order[2]));
+        assert_eq!(1, set.failures(&order[0]));
+        assert_eq!(None, TrackerSet::new(&[]).next_tracker());
+    }
+
+    #[test]
+    fn shuffling_keeps_every_element() {
+        let mut items: Vec<_> = (0..50).collect();
+        shuffle(&mut items);
+        items.sort_unstable();
+        assert_eq!((0..50).collect::<Vec<_>>(), items);
+    }
+}
@@ -0,0 +1,437 @@
+//! This module contains the client for trackers speaking UDP, from BEP 15.
+//!
+//! UDP trackers need much less bandwidth than HTTP trackers, with a few small packets in
+//! place of a TCP connection. Since UDP addresses can be spoofed, we first need to get a
+//! connection id from the tracker, which proves we can receive packets at our address,
+//! and send it along with every other request. Connection ids stay valid for a minute.
+use super::{
+    proxy::Proxy, random_u64, AnnounceEvent, AnnounceRequest, AnnounceResponse, Peer,
+    ScrapeResponse, ScrapeStats, TrackerConfig, TrackerError,
+};
+use crate::core::{Host, InfoHash, TrackerUrl};
+use std::{
+    cell::Cell,
+    collections::BTreeMap,
+    convert::TryFrom,
+    future::Future,
+    io,
+    net::{ToSocketAddrs, UdpSocket},
+    time::{Duration, Instant},
+};
+
+// The magic number starting every connect request.
+pub(super) const PROTOCOL_ID: u64 = 0x0417_2710_1980;
+pub(super) const ACTION_CONNECT: u32 = 0;
+pub(super) const ACTION_ANNOUNCE: u32 = 1;
+pub(super) const ACTION_SCRAPE: u32 = 2;
+pub(super) const ACTION_ERROR: u32 = 3;
+// How long a connection id can be used for, after the tracker gives it to us.
+pub(super) const CONNECTION_LIFETIME: Duration = Duration::from_secs(60);
+
+/// Something which can send UDP packets, for a tracker client to send its requests with.
+///
+/// This can be implemented on top of whatever network library an application already uses.
+pub trait UdpClient {
+    /// Send a packet to an address, like `tracker.example.com:6969`, returning the response.
+    ///
+    /// Only packets coming back from the address the packet was sent to should be returned.
+    fn send(&self, addr: &str, packet: &[u8]) -> impl Future<Output = io::Result<Vec<u8>>>;
+}
+
+/// A client for a tracker speaking UDP.
+#[derive(Clone, Debug)]
+pub struct UdpTracker<C> {
+    url: String,
+    client: C,
+    // Sent along with announces, letting the tracker recognize us if our address changes
+    key: u32,
+    connection: Cell<Option<(u64, Instant)>>,
+}
+
+impl<C: UdpClient> UdpTracker<C> {
+    /// Create a client for the tracker at a given announce URL.
+    pub fn new(url: impl Into<String>, client: C) -> Self {
+        UdpTracker {
+            url: url.into(),
+            client,
+            key: random_u64() as u32,
+            connection: Cell::new(None),
+        }
+    }
+
+    /// The announce URL of this tracker.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    // Find the address of this tracker, and whether or not we talk to it over IPv6.
+    fn target(&self) -> Result<(String, bool), TrackerError> {
+        let unsupported = || TrackerError::UnsupportedUrl(self.url.clone());
+        let parsed: TrackerUrl = self.url.parse().map_err(|_| unsupported())?;
+        if parsed.scheme != "udp" {
+            return Err(unsupported());
+        }
+        let ipv6 = matches!(parsed.host, Host::Ipv6(_));
+        Ok((format!("{}:{}", parsed.host, parsed.port), ipv6))
+    }
+
+    // Send a request, checking that the response matches it.
+    async fn request(
+        &self,
+        addr: &str,
+        packet: &[u8],
+        action: u32,
+        transaction: u32,
+    ) -> Result<Vec<u8>, TrackerError> {
+        let response = self.client.send(addr, packet).await?;
+        check_response(&response, action, transaction)?;
+        Ok(response)
+    }
+
+    // Get a connection id, reusing the last one if it's still valid.
+    async fn connect(&self, addr: &str) -> Result<u64, TrackerError> {
+        if let Some((id, since)) = self.connection.get() {
+            if since.elapsed() < CONNECTION_LIFETIME {
+                return Ok(id);
+            }
+        }
+        let transaction = random_u64() as u32;
+        let mut packet = Vec::with_capacity(16);
+        packet.extend_from_slice(&PROTOCOL_ID.to_be_bytes());
+        packet.extend_from_slice(&ACTION_CONNECT.to_be_bytes());
+        packet.extend_from_slice(&transaction.to_be_bytes());
+        let response = self
+            .request(addr, &packet, ACTION_CONNECT, transaction)
+            .await?;
+        let id = read_u64(&response, 8)?;
+        self.connection.set(Some((id, Instant::now())));
+        Ok(id)
+    }
+
+    /// Announce ourselves to this tracker, getting back peers in the swarm.
+    ///
+    /// If we talk to the tracker over IPv6, it sends back IPv6 peers only.
+    pub async fn announce(
+        &self,
+        request: &AnnounceRequest,
+    ) -> Result<AnnounceResponse, TrackerError> {
+        let (addr, ipv6) = self.target()?;
+        let connection = self.connect(&addr).await?;
+        let transaction = random_u64() as u32;
+        let event: u32 = match request.event {
+            AnnounceEvent::None => 0,
+            AnnounceEvent::Completed => 1,
+            AnnounceEvent::Started => 2,
+            AnnounceEvent::Stopped => 3,
+        };
+        let ip = request.ip.map_or(0, u32::from);
+        let num_want = request
+            .num_want
+            .map_or(-1, |n| n.min(i32::MAX as u32) as i32);
+        let mut packet = Vec::with_capacity(98);
+        packet.extend_from_slice(&connection.to_be_bytes());
+        packet.extend_from_slice(&ACTION_ANNOUNCE.to_be_bytes());
+        packet.extend_from_slice(&transaction.to_be_bytes());
+        packet.extend_from_slice(request.info_hash.as_bytes());
+        packet.extend_from_slice(request.peer_id.as_bytes());
+        packet.extend_from_slice(&request.downloaded.to_be_bytes());
+        packet.extend_from_slice(&request.left.to_be_bytes());
+        packet.extend_from_slice(&request.uploaded.to_be_bytes());
+        packet.extend_from_slice(&event.to_be_bytes());
+        packet.extend_from_slice(&ip.to_be_bytes());
+        packet.extend_from_slice(&self.key.to_be_bytes());
+        packet.extend_from_slice(&num_want.to_be_bytes());
+        packet.extend_from_slice(&request.port.to_be_bytes());
+        let response = self
+            .request(&addr, &packet, ACTION_ANNOUNCE, transaction)
+            .await?;
+        let interval = read_u32(&response, 8)?;
+        let leechers = read_u32(&response, 12)?;
+        let seeders = read_u32(&response, 16)?;
+        let peers = if ipv6 {
+            Peer::parse_compact_v6(&response[20..])
+        } else {
+            Peer::parse_compact(&response[20..])
+        };
+        Ok(AnnounceResponse {
+            interval: Duration::from_secs(u64::from(interval)),
+            peers,
+            seeders: Some(seeders),
+            leechers: Some(leechers),
+            ..AnnounceResponse::default()
+        })
+    }
+
+    /// Ask this tracker how many peers are in the swarms of some torrents.
+    ///
+    /// Trackers only answer for as many torrents as fit in a single packet, about 74.
+    pub async fn scrape(&self, info_hashes: &[InfoHash]) -> Result<ScrapeResponse, TrackerError> {
+        let (addr, _) = self.target()?;
+        let connection = self.connect(&addr).await?;
+        let transaction = random_u64() as u32;
+        let mut packet = Vec::with_capacity(16 + 20 * info_hashes.len());
+        packet.extend_from_slice(&connection.to_be_bytes());
+        packet.extend_from_slice(&ACTION_SCRAPE.to_be_bytes());
+        packet.extend_from_slice(&transaction.to_be_bytes());
+        for hash in info_hashes {
+            packet.extend_from_slice(hash.as_bytes());
+        }
+        let response = self
+            .request(&addr, &packet, ACTION_SCRAPE, transaction)
+            .await?;
+        // Stats come back in the same order as the hashes we sent
+        let files: BTreeMap<_, _> = info_hashes
+            .iter()
+            .zip(response[8..].chunks_exact(12))
+            .map(|(hash, chunk)| {
+                let stats = ScrapeStats {
+                    seeders: read_u32(chunk, 0).unwrap_or_default(),
+                    completed: read_u32(chunk, 4).unwrap_or_default(),
+                    leechers: read_u32(chunk, 8).unwrap_or_default(),
+                };
+                (*hash, stats)
+            })
+            .collect();
+        Ok(ScrapeResponse { files })
+    }
+}
+
+pub(super) fn read_u32(bytes: &[u8], at: usize) -> Result<u32, TrackerError> {
+    bytes
+        .get(at..at + 4)
+        .and_then(|b| <[u8; 4]>::try_from(b).ok())
+        .map(u32::from_be_bytes)
+        .ok_or_else(|| TrackerError::InvalidPacket("packet is too short".to_owned()))
+}
+
+pub(super) fn read_u64(bytes: &[u8], at: usize) -> Result<u64, TrackerError> {
+    let high = read_u32(bytes, at)?;
+    let low = read_u32(bytes, at + 4)?;
+    Ok(u64::from(high) << 32 | u64::from(low))
+}
+
+// Check the header of a response, turning errors from the tracker into failures.
+fn check_response(response: &[u8], action: u32, transaction: u32) -> Result<(), TrackerError> {
+    let got_action = read_u32(response, 0)?;
+    if read_u32(response, 4)? != transaction {
+        let message = "transaction id doesn't match request".to_owned();
+        return Err(TrackerError::InvalidPacket(message));
+    }
+    if got_action == ACTION_ERROR {
+        let message = String::from_utf8_lossy(&response[8..]).into_owned();
+        return Err(TrackerError::Failure(message));
+    }
+    if got_action != action {
+        let message = format!("expected action {}, got {}", action, got_action);
+        return Err(TrackerError::InvalidPacket(message));
+    }
+    Ok(())
+}
+
+/// A UDP client built on the standard library.
+///
+/// Requests block the thread polling them until they complete, or until they've timed out
+/// too many times. Requests for connection ids use the connect timeout of the client's
+/// configuration. Domains are contacted over IPv4 if they have an IPv4 address.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StdUdpClient {
+    config: TrackerConfig,
+    proxy: Option<Proxy>,
+}
+
+impl StdUdpClient {
+    /// Create a client with a given configuration for timeouts and retries.
+    pub fn new(config: TrackerConfig) -> Self {
+        StdUdpClient {
+            config,
+            proxy: None,
+        }
+    }
+
+    /// Use a proxy, which UDP trackers can't be reached through.
+    ///
+    /// Every packet fails with `io::ErrorKind::Unsupported`, instead of bypassing the proxy
+    /// and revealing our address to trackers.
+    pub fn with_proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    fn send_blocking(&self, addr: &str, packet: &[u8]) -> io::Result<Vec<u8>> {
+        if self.proxy.is_some() {
+            let message = "UDP trackers can't be reached through a proxy";
+            return Err(io::Error::new(io::ErrorKind::Unsupported, message));
+        }
+        let addrs: Vec<_> = addr.to_socket_addrs()?.collect();
+        let target = addrs
+            .iter()
+            .find(|addr| addr.is_ipv4())
+            .or_else(|| addrs.first())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address for host"))?;
+        let local = if target.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(local)?;
+        // Connecting filters out packets from everywhere else
+        socket.connect(target)?;
+        let timeout = if packet.starts_with(&PROTOCOL_ID.to_be_bytes()) {
+            self.config.connect_timeout
+        } else {
+            self.config.request_timeout
+        };
+        socket.set_read_timeout(Some(timeout))?;
+        let mut buf = vec![0; 65536];
+        let read = self.config.retry(|| {
+            socket.send(packet)?;
+            socket.recv(&mut buf)
+        })?;
+        buf.truncate(read);
+        Ok(buf)
+    }
+}
+
+impl UdpClient for StdUdpClient {
+    fn send(&self, addr: &str, packet: &[u8]) -> impl Future<Output = io::Result<Vec<u8>>> {
+        let addr = addr.to_owned();
+        let packet = packet.to_vec();
+        let client = self.clone();
+        async move { client.send_blocking(&addr, &packet) }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tracker::{block_on, PeerId};
+    use std::{cell::RefCell, future, net::SocketAddr, thread};
+
+    // A tracker answering packets directly, remembering the actions it got.
+    #[derive(Default)]
+    struct FakeTracker {
+        actions: RefCell<Vec<u32>>,
+        refuse: bool,
+    }
+
+    impl FakeTracker {
+        fn respond(&self, packet: &[u8]) -> Vec<u8> {
+            let action = read_u32(packet, 8).unwrap();
+            self.actions.borrow_mut().push(action);
+            let mut out = Vec::new();
+            out.extend_from_slice(&packet[8..16]);
+            if self.refuse && action != ACTION_CONNECT {
+                out[..4].copy_from_slice(&ACTION_ERROR.to_be_bytes());
+                out.extend_from_slice(b"go away");
+                return out;
+            }
+            match action {
+                ACTION_CONNECT => out.extend_from_slice(&7u64.to_be_bytes()),
+                ACTION_ANNOUNCE => {
+                    assert_eq!(98, packet.len());
+                    assert_eq!(7, read_u64(packet, 0).unwrap());
+                    // interval, leechers, seeders, then one peer
+                    out.extend_from_slice(&[0, 0, 3, 132, 0, 0, 0, 1, 0, 0, 0, 2]);
+                    out.extend_from_slice(b"\x0a\x00\x00\x01\x1a\xe1");
+                }
+                _ => {
+                    for _ in packet[16..].chunks(20) {
+                        out.extend_from_slice(&[0, 0, 0, 5, 0, 0, 0, 50, 0, 0, 0, 10]);
+                    }
+                }
+            }
+            out
+        }
+    }
+
+    impl UdpClient for FakeTracker {
+        fn send(&self, _: &str, packet: &[u8]) -> impl Future<Output = io::Result<Vec<u8>>> {
+            future::ready(Ok(self.respond(packet)))
+        }
+    }
+
+    fn request() -> AnnounceRequest {
+        AnnounceRequest::new(
+            InfoHash::new([0xAB; 20]),
+            PeerId::new(*b"-TY0100-abcdefghijkl"),
+            6881,
+            100,
+        )
+    }
+
+    #[test]
+    fn announcing_works() {
+        let tracker = UdpTracker::new("udp://t.example.com:6969/announce", FakeTracker::default());
+        let response = block_on(tracker.announce(&request())).unwrap();
+        assert_eq!(Duration::from_secs(900), response.interval);
+        let peer: SocketAddr = "10.0.0.1:6881".parse().unwrap();
+        assert_eq!(vec![Peer::new(peer)], response.peers);
+        assert_eq!((Some(2), Some(1)), (response.seeders, response.leechers));
+        // The connection id should be reused
+        block_on(tracker.announce(&request())).unwrap();
+        assert_eq!(vec![0, 1, 1], *tracker.client.actions.borrow());
+    }
+
+    #[test]
+    fn scraping_works() {
+        let tracker = UdpTracker::new("udp://t.example.com:6969", FakeTracker::default());
+        let hashes = [InfoHash::new([1; 20]), InfoHash::new([2; 20])];
+        let response = block_on(tracker.scrape(&hashes)).unwrap();
+        let stats = ScrapeStats {
+            seeders: 5,
+            leechers: 10,
+            completed: 50,
+        };
+        assert_eq!(2, response.files.len());
+        assert_eq!(Some(&stats), response.files.get(&hashes[1]));
+    }
+
+    #[test]
+    fn tracker_errors_are_reported() {
+        let client = FakeTracker {
+            refuse: true,
+            ..FakeTracker::default()
+        };
+        let tracker = UdpTracker::new("udp://t.example.com:6969", client);
+        match block_on(tracker.announce(&request())) {
+            Err(TrackerError::Failure(message)) => assert_eq!("go away", message),
+            other => panic!("unexpected response {:?}", other),
+        }
+        let tracker = UdpTracker::new("http://t.example.com/announce", FakeTracker::default());
+        assert!(matches!(
+            block_on(tracker.scrape(&[])),
+            Err(TrackerError::UnsupportedUrl(_))
+        ));
+        assert!(check_response(&[0, 0, 0, 0, 0, 0, 0, 1], 0, 2).is_err());
+        assert!(check_response(&[0, 0, 0, 1, 0, 0, 0, 2], 0, 2).is_err());
+        assert!(check_response(&[0, 0, 0], 0, 2).is_err());
+    }
+
+    #[test]
+    fn std_clients_send_packets() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let mut buf = [0; 64];
+            // Drop the first packet, which should get resent
+            socket.recv_from(&mut buf).unwrap();
+            let (read, from) = socket.recv_from(&mut buf).unwrap();
+            socket.send_to(&buf[..read], from).unwrap();
+        });
+        let config = TrackerConfig {
+            request_timeout: Duration::from_millis(50),
+            backoff: Duration::from_millis(1),
+            ..TrackerConfig::default()
+        };
+        let response = block_on(StdUdpClient::new(config).send(&addr, b"ping")).unwrap();
+        assert_eq!(b"ping".to_vec(), response);
+        server.join().unwrap();
+        let proxy = Proxy::Socks5 {
+            addr: "127.0.0.1:1080".to_owned(),
+            auth: None,
+        };
+        let proxied = StdUdpClient::default().with_proxy(proxy);
+        let error = block_on(proxied.send(&addr, b"ping")).unwrap_err();
+        assert_eq!(io::ErrorKind::Unsupported, error.kind());
+    }
+}
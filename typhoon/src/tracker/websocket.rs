@@ -0,0 +1,493 @@
+//! This module contains the client for WebSocket trackers, used by WebTorrent.
+//!
+//! WebSocket trackers exchange JSON messages instead of bencoding, with info hashes and
+//! peer ids written as strings with one character for each byte. Peers in these swarms
+//! connect to each other over WebRTC, by sending offers through the tracker. We can't make
+//! WebRTC connections, so we announce without any offers: this never gives us peers,
+//! but keeps us counted in the swarm, and lets us scrape wss-only trackers.
+use super::{
+    proxy::{self, base64, Proxy},
+    random_u64, AnnounceRequest, AnnounceResponse, ScrapeResponse, TrackerConfig, TrackerError,
+};
+use crate::{
+    bencoding::{Bencoding, Dict},
+    core::{extract_key, extract_string, InfoHash, TrackerUrl},
+    hash::{Hasher, Sha1},
+};
+use std::{
+    convert::TryFrom,
+    future::Future,
+    io::{self, Read, Write},
+    net::TcpStream,
+    str,
+    time::Duration,
+};
+
+// The interval WebTorrent trackers use, for responses that leave it out.
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(120);
+
+// The largest message we accept, across all of its frames, which leaves plenty of room
+// for the offers and answers WebTorrent trackers relay.
+const MAX_MESSAGE_SIZE: u64 = 1024 * 1024;
+
+/// Something which can talk to WebSocket servers, for a tracker client to send messages with.
+///
+/// This can be implemented on top of whatever WebSocket library an application already uses.
+pub trait WebSocketClient {
+    /// Send a text message to the server at a URL, returning the next text message it sends.
+    fn send(&self, url: &str, message: &str) -> impl Future<Output = io::Result<String>>;
+}
+
+/// A client for a tracker speaking the WebTorrent protocol, over `ws://` or `wss://`.
+#[derive(Clone, Debug)]
+pub struct WebSocketTracker<C> {
+    url: String,
+    client: C,
+}
+
+// Write bytes as a JSON string, with one character for each byte.
+fn write_binary(bytes: &[u8], out: &mut String) {
+    out.push('"');
+    for &b in bytes {
+        match b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7E => out.push(b as char),
+            _ => out.push_str(&format!("\\u{:04x}", b)),
+        }
+    }
+    out.push('"');
+}
+
+// Read back bytes written as a string with one character for each byte.
+fn read_binary(string: &[u8]) -> Option<Vec<u8>> {
+    str::from_utf8(string)
+        .ok()?
+        .chars()
+        .map(|c| u8::try_from(u32::from(c)).ok())
+        .collect()
+}
+
+impl<C: WebSocketClient> WebSocketTracker<C> {
+    /// Create a client for the tracker at a given URL.
+    pub fn new(url: impl Into<String>, client: C) -> Self {
+        WebSocketTracker {
+            url: url.into(),
+            client,
+        }
+    }
+
+    /// The URL of this tracker.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    // Send a message, decoding the response, and checking that it has the right action.
+    async fn request(&self, message: &str, action: &str) -> Result<Bencoding, TrackerError> {
+        if !self.url.starts_with("ws://") && !self.url.starts_with("wss://") {
+            return Err(TrackerError::UnsupportedUrl(self.url.clone()));
+        }
+        let response = self.client.send(&self.url, message).await?;
+        let response = Bencoding::from_json(&response)
+            .map_err(|e| TrackerError::InvalidPacket(format!("invalid JSON {}", e)))?;
+        if let Ok(reason) = extract_key(&response, "failure reason") {
+            let reason = extract_string(reason).unwrap_or("unknown failure");
+            return Err(TrackerError::Failure(reason.to_owned()));
+        }
+        match extract_key(&response, "action").and_then(extract_string) {
+            Ok(got) if got == action => Ok(response),
+            _ => {
+                let message = format!("expected a response to {}", action);
+                Err(TrackerError::InvalidPacket(message))
+            }
+        }
+    }
+
+    /// Announce ourselves to this tracker, without asking for any peers.
+    pub async fn announce(
+        &self,
+        request: &AnnounceRequest,
+    ) -> Result<AnnounceResponse, TrackerError> {
+        let mut message = String::from(r#"{"action":"announce","info_hash":"#);
+        write_binary(request.info_hash.as_bytes(), &mut message);
+        message.push_str(r#","peer_id":"#);
+        write_binary(request.peer_id.as_bytes(), &mut message);
+        message.push_str(&format!(
+            r#","uploaded":{},"downloaded":{},"left":{},"numwant":0,"offers":[]"#,
+            request.uploaded, request.downloaded, request.left
+        ));
+        if let Some(event) = request.event.as_param() {
+            message.push_str(&format!(r#","event":"{}""#, event));
+        }
+        message.push('}');
+        let response = self.request(&message, "announce").await?;
+        let interval = match extract_key(&response, "interval") {
+            Ok(Bencoding::Int(secs)) if *secs >= 0 => Duration::from_secs(*secs as u64),
+            _ => DEFAULT_INTERVAL,
+        };
+        let count = |key| match extract_key(&response, key) {
+            Ok(Bencoding::Int(count)) => u32::try_from(*count).ok(),
+            _ => None,
+        };
+        Ok(AnnounceResponse {
+            interval,
+            seeders: count("complete"),
+            leechers: count("incomplete"),
+            ..AnnounceResponse::default()
+        })
+    }
+
+    /// Ask this tracker how many peers are in the swarms of some torrents.
+    pub async fn scrape(&self, info_hashes: &[InfoHash]) -> Result<ScrapeResponse, TrackerError> {
+        let mut message = String::from(r#"{"action":"scrape","info_hash":["#);
+        for (i, hash) in info_hashes.iter().enumerate() {
+            if i > 0 {
+                message.push(',');
+            }
+            write_binary(hash.as_bytes(), &mut message);
+        }
+        message.push_str("]}");
+        let response = self.request(&message, "scrape").await?;
+        // Turn the info hashes back into bytes, to decode this like a bencoded response
+        let mut files = Dict::new();
+        if let Ok(Bencoding::Dict(dict)) = extract_key(&response, "files") {
+            for (hash, stats) in dict.iter() {
+                if let Some(hash) = read_binary(hash) {
+                    files.insert(hash, stats.clone());
+                }
+            }
+        }
+        let mut root = Dict::new();
+        root.insert(&b"files"[..], Bencoding::Dict(files));
+        let root = Bencoding::Dict(root);
+        ScrapeResponse::try_from(&root)
+            .map_err(|e| TrackerError::InvalidResponse(e.into_owned(&root)))
+    }
+}
+
+// The GUID servers append to our key when accepting a connection, from RFC 6455.
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+// The value a server needs to send back in `Sec-WebSocket-Accept` for a given key.
+fn accept_key(key: &str) -> String {
+    base64(&Sha1::digest(
+        format!("{}{}", key, WEBSOCKET_GUID).as_bytes(),
+    ))
+}
+
+// Write a frame, masked with a random key, as clients have to.
+fn write_frame(stream: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(0x80 | len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(0x80 | 127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    let mask = (random_u64() as u32).to_be_bytes();
+    frame.extend_from_slice(&mask);
+    frame.extend(payload.iter().zip(mask.iter().cycle()).map(|(b, m)| b ^ m));
+    stream.write_all(&frame)
+}
+
+fn too_large() -> io::Error {
+    let message = format!("message is larger than {} bytes", MAX_MESSAGE_SIZE);
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Read a frame, returning its opcode, whether it's the final one, and its payload.
+fn read_frame(stream: &mut impl Read) -> io::Result<(u8, bool, Vec<u8>)> {
+    let mut header = [0; 2];
+    stream.read_exact(&mut header)?;
+    let len = match header[1] & 0x7F {
+        126 => {
+            let mut len = [0; 2];
+            stream.read_exact(&mut len)?;
+            u64::from(u16::from_be_bytes(len))
+        }
+        127 => {
+            let mut len = [0; 8];
+            stream.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => u64::from(len),
+    };
+    // The length comes from the server, so we can't trust it to be reasonable
+    if len > MAX_MESSAGE_SIZE {
+        return Err(too_large());
+    }
+    let mut mask = [0; 4];
+    let masked = header[1] & 0x80 != 0;
+    if masked {
+        stream.read_exact(&mut mask)?;
+    }
+    let mut payload = Vec::new();
+    stream.take(len).read_to_end(&mut payload)?;
+    if payload.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    if masked {
+        for (b, m) in payload.iter_mut().zip(mask.iter().cycle()) {
+            *b ^= m;
+        }
+    }
+    Ok((header[0] & 0x0F, header[0] & 0x80 != 0, payload))
+}
+
+/// A WebSocket client built on the standard library, supporting plain `ws://` only.
+///
+/// Each message opens a new connection, which gets closed once the response arrives.
+/// Requests block the thread polling them until they complete.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StdWebSocketClient {
+    config: TrackerConfig,
+    proxy: Option<Proxy>,
+}
+
+impl StdWebSocketClient {
+    /// Create a client with a given configuration for timeouts and retries.
+    pub fn new(config: TrackerConfig) -> Self {
+        StdWebSocketClient {
+            config,
+            proxy: None,
+        }
+    }
+
+    /// Open every connection through a proxy.
+    pub fn with_proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    fn handshake(stream: &mut TcpStream, url: &TrackerUrl) -> io::Result<()> {
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut nonce = [0; 16];
+        nonce[..8].copy_from_slice(&random_u64().to_be_bytes());
+        nonce[8..].copy_from_slice(&random_u64().to_be_bytes());
+        let key = base64(&nonce);
+        let path = if url.path.is_empty() { "/" } else { &url.path };
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nHost: {}:{}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+            Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            path, url.host, url.port, key
+        )?;
+        let mut head = Vec::new();
+        let mut byte = [0];
+        while !head.ends_with(b"\r\n\r\n") {
+            stream.read_exact(&mut byte)?;
+            head.push(byte[0]);
+        }
+        let head = str::from_utf8(&head).map_err(|_| invalid("malformed handshake"))?;
+        let mut lines = head.lines();
+        if lines.next().and_then(|line| line.split(' ').nth(1)) != Some("101") {
+            return Err(invalid("server refused WebSocket connection"));
+        }
+        let expected = accept_key(&key);
+        let accepted = lines.any(|line| {
+            line.split_once(':').is_some_and(|(name, value)| {
+                name.eq_ignore_ascii_case("sec-websocket-accept") && value.trim() == expected
+            })
+        });
+        if !accepted {
+            return Err(invalid("server sent the wrong accept key"));
+        }
+        Ok(())
+    }
+
+    fn send_blocking(&self, url: &str, message: &str) -> io::Result<String> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let parsed: TrackerUrl = url.parse().map_err(|e| invalid(format!("{}", e)))?;
+        if parsed.scheme != "ws" {
+            let message = format!("scheme {} is not supported", parsed.scheme);
+            return Err(io::Error::new(io::ErrorKind::Unsupported, message));
+        }
+        self.config.retry(|| {
+            let proxy = self.proxy.as_ref();
+            let mut stream = proxy::connect(&parsed.host, parsed.port, proxy, &self.config)?;
+            StdWebSocketClient::handshake(&mut stream, &parsed)?;
+            write_frame(&mut stream, OPCODE_TEXT, message.as_bytes())?;
+            let mut text = Vec::new();
+            loop {
+                match read_frame(&mut stream)? {
+                    (OPCODE_TEXT, fin, payload) | (OPCODE_CONTINUATION, fin, payload) => {
+                        text.extend_from_slice(&payload);
+                        if text.len() as u64 > MAX_MESSAGE_SIZE {
+                            return Err(too_large());
+                        }
+                        if fin {
+                            break;
+                        }
+                    }
+                    (OPCODE_PING, _, payload) => write_frame(&mut stream, OPCODE_PONG, &payload)?,
+                    (OPCODE_CLOSE, _, _) => return Err(io::ErrorKind::ConnectionAborted.into()),
+                    _ => {}
+                }
+            }
+            // Closing politely is best effort, since we already have our response
+            let _ = write_frame(&mut stream, OPCODE_CLOSE, &[]);
+            String::from_utf8(text)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "message isn't UTF-8"))
+        })
+    }
+}
+
+impl WebSocketClient for StdWebSocketClient {
+    fn send(&self, url: &str, message: &str) -> impl Future<Output = io::Result<String>> {
+        let url = url.to_owned();
+        let message = message.to_owned();
+        let client = self.clone();
+        async move { client.send_blocking(&url, &message) }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tracker::{block_on, AnnounceEvent, PeerId};
+    use std::{cell::RefCell, future, net::TcpListener, thread};
+
+    // A client answering every message with the same response, remembering the messages.
+    struct FixedClient {
+        response: String,
+        messages: RefCell<Vec<String>>,
+    }
+
+    impl WebSocketClient for FixedClient {
+        fn send(&self, _: &str, message: &str) -> impl Future<Output = io::Result<String>> {
+            self.messages.borrow_mut().push(message.to_owned());
+            future::ready(Ok(self.response.clone()))
+        }
+    }
+
+    fn tracker(response: &str) -> WebSocketTracker<FixedClient> {
+        let client = FixedClient {
+            response: response.to_owned(),
+            messages: RefCell::new(Vec::new()),
+        };
+        WebSocketTracker::new("wss://t.example.com", client)
+    }
+
+    #[test]
+    fn announcing_works() {
+        let tracker = tracker(r#"{"action":"announce","interval":300,"complete":1}"#);
+        let mut request = AnnounceRequest::new(
+            InfoHash::new([0xAB; 20]),
+            PeerId::new(*b"-TY0100-abcdefghij\"\\"),
+            6881,
+            100,
+        );
+        request.event = AnnounceEvent::Started;
+        let response = block_on(tracker.announce(&request)).unwrap();
+        assert_eq!(Duration::from_secs(300), response.interval);
+        assert_eq!((Some(1), None), (response.seeders, response.leechers));
+        let expected = format!(
+            r#"{{"action":"announce","info_hash":"{}","peer_id":"-TY0100-abcdefghij\"\\","uploaded":0,"downloaded":0,"left":100,"numwant":0,"offers":[],"event":"started"}}"#,
+            r"\u00ab".repeat(20)
+        );
+        assert_eq!(vec![expected], *tracker.client.messages.borrow());
+        let refused = self::tracker(r#"{"failure reason":"unregistered torrent"}"#);
+        assert!(matches!(
+            block_on(refused.announce(&request)),
+            Err(TrackerError::Failure(_))
+        ));
+    }
+
+    #[test]
+    fn scraping_works() {
+        let hash = InfoHash::new([0xAB; 20]);
+        let mut key = String::new();
+        write_binary(hash.as_bytes(), &mut key);
+        let tracker = tracker(&format!(
+            r#"{{"action":"scrape","files":{{{}:{{"complete":2,"incomplete":3,"downloaded":4}}}}}}"#,
+            key
+        ));
+        let response = block_on(tracker.scrape(&[hash])).unwrap();
+        assert_eq!(2, response.files[&hash].seeders);
+        assert_eq!(3, response.files[&hash].leechers);
+        let wrong = self::tracker(r#"{"action":"announce"}"#);
+        assert!(matches!(
+            block_on(wrong.scrape(&[hash])),
+            Err(TrackerError::InvalidPacket(_))
+        ));
+    }
+
+    #[test]
+    fn encoding_works() {
+        // The example from RFC 6455
+        assert_eq!(
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=",
+            accept_key("dGhlIHNhbXBsZSBub25jZQ==")
+        );
+        let bytes: Vec<u8> = (0..=255).collect();
+        let mut string = String::new();
+        write_binary(&bytes, &mut string);
+        let decoded = Bencoding::from_json(&string).unwrap();
+        assert_eq!(
+            Some(bytes),
+            read_binary(crate::core::extract_bytes(&decoded).unwrap())
+        );
+    }
+
+    #[test]
+    fn oversized_frames_are_refused() {
+        let mut frame = vec![0x81, 127];
+        frame.extend_from_slice(&u64::MAX.to_be_bytes());
+        let error = read_frame(&mut &frame[..]).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        let mut frame = vec![0x81, 5];
+        frame.extend_from_slice(b"hello");
+        assert_eq!(
+            (1, true, b"hello".to_vec()),
+            read_frame(&mut &frame[..]).unwrap()
+        );
+    }
+
+    #[test]
+    fn std_clients_send_messages() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut head = Vec::new();
+            let mut byte = [0];
+            while !head.ends_with(b"\r\n\r\n") {
+                stream.read_exact(&mut byte).unwrap();
+                head.push(byte[0]);
+            }
+            let head = String::from_utf8(head).unwrap();
+            let key = head
+                .lines()
+                .find_map(|line| line.strip_prefix("Sec-WebSocket-Key: "))
+                .unwrap();
+            write!(
+                stream,
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                Connection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                accept_key(key)
+            )
+            .unwrap();
+            let (opcode, _, payload) = read_frame(&mut stream).unwrap();
+            assert_eq!(OPCODE_TEXT, opcode);
+            let mut reply = vec![0x80 | OPCODE_TEXT, payload.len() as u8];
+            reply.extend_from_slice(&payload);
+            stream.write_all(&reply).unwrap();
+        });
+        let url = format!("ws://127.0.0.1:{}/announce", port);
+        let client = StdWebSocketClient::default();
+        assert_eq!("hello", block_on(client.send(&url, "hello")).unwrap());
+        server.join().unwrap();
+        let wss = block_on(client.send("wss://t.example.com", "hello"));
+        assert_eq!(io::ErrorKind::Unsupported, wss.unwrap_err().kind());
+    }
+}